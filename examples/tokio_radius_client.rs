@@ -0,0 +1,50 @@
+//! An example on how to use built-in Tokio RADIUS Client
+//!
+//! ```bash
+//! cargo run --example tokio_radius_client --features tokio
+//! ```
+
+
+use radius_rust::client::{ client::Client, retransmission::RetransmissionPolicy, tokio_client::TokioClient };
+use radius_rust::protocol::dictionary::Dictionary;
+use radius_rust::protocol::error::RadiusError;
+use radius_rust::protocol::radius_packet::RadiusMsgType;
+use radius_rust::tools::{ encrypt_data, ipv4_string_to_bytes, integer_to_bytes };
+
+use log::{ debug, LevelFilter };
+use simple_logger::SimpleLogger;
+use std::time::Duration;
+
+
+#[tokio::main]
+async fn main() -> Result<(), RadiusError> {
+    SimpleLogger::new().with_level(LevelFilter::Debug).init().expect("Failed to create new logger");
+    debug!("RADIUS Tokio Client started");
+
+    let dictionary = Dictionary::from_file("./dict_examples/integration_dict")?;
+    let client     = TokioClient::with_client(Client::with_dictionary(dictionary)
+        .set_server(String::from("127.0.0.1"))
+        .set_secret(String::from("secret"))
+        .set_retransmission_policy(RetransmissionPolicy::default().set_initial_rt(Duration::from_millis(500)))
+        .set_port(RadiusMsgType::AUTH, 1812));
+
+    let user_name            = String::from("testing").into_bytes();
+    let nas_ip_addr_bytes    = ipv4_string_to_bytes("192.168.1.10")?;
+    let framed_ip_addr_bytes = ipv4_string_to_bytes("10.0.0.100")?;
+    let mut packet           = client.client().create_auth_packet();
+    let encrypted_password   = encrypt_data("very secure password".as_bytes(), packet.authenticator(), client.client().secret().as_bytes());
+
+    let attributes = vec![
+        client.client().create_attribute_by_name("User-Name",          user_name)?,
+        client.client().create_attribute_by_name("Password",           encrypted_password)?,
+        client.client().create_attribute_by_name("NAS-IP-Address",     nas_ip_addr_bytes)?,
+        client.client().create_attribute_by_name("NAS-Port-Id",        integer_to_bytes(0))?,
+        client.client().create_attribute_by_name("Framed-IP-Address",  framed_ip_addr_bytes)?
+    ];
+    packet.set_attributes(attributes);
+
+    let reply = client.send_and_receive_reply(&mut packet).await?;
+    debug!("Received reply: {:?}", reply);
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+//! An example on how to use built-in Tokio RADIUS Server
+//!
+//! To run Tokio RADIUS Server example
+//! ```bash
+//! cargo run --example tokio_radius_server --features tokio
+//! ```
+
+
+use radius_rust::protocol::dictionary::Dictionary;
+use radius_rust::protocol::error::RadiusError;
+use radius_rust::protocol::radius_packet::{ RadiusMsgType, RadiusPacket, TypeCode };
+use radius_rust::server::{ handler::{ HandlerOutcome, RequestContext }, server::Server, tokio_server::TokioServer, AsyncServerTrait };
+use radius_rust::tools::{ ipv6_string_to_bytes, ipv4_string_to_bytes, integer_to_bytes };
+
+use async_trait::async_trait;
+use log::{ debug, LevelFilter };
+use simple_logger::SimpleLogger;
+
+
+struct CustomHandler {
+    // Only used to create reply attributes
+    base_server: Server
+}
+
+#[async_trait]
+impl AsyncServerTrait for CustomHandler {
+    async fn handle_request(&self, context: &RequestContext, request: &RadiusPacket) -> Result<HandlerOutcome, RadiusError> {
+        debug!("Received {:?} on {} socket", request.code(), context.msg_type());
+
+        let (code, attributes) = match context.msg_type() {
+            RadiusMsgType::AUTH => {
+                let attributes = vec![
+                    self.base_server.create_attribute_by_name("Service-Type",       integer_to_bytes(2))?,
+                    self.base_server.create_attribute_by_name("Framed-IP-Address",  ipv4_string_to_bytes("192.168.0.1")?)?,
+                    self.base_server.create_attribute_by_name("Framed-IPv6-Prefix", ipv6_string_to_bytes("fc66::1/64")?)?
+                ];
+                (TypeCode::AccessAccept, attributes)
+            },
+            RadiusMsgType::ACCT => (TypeCode::AccountingResponse, vec![]),
+            RadiusMsgType::COA  => (TypeCode::CoAACK, vec![self.base_server.create_attribute_by_name("State", String::from("testing").into_bytes())?])
+        };
+
+        let mut reply = RadiusPacket::initialise_packet(code);
+        reply.set_attributes(attributes);
+        Ok(HandlerOutcome::Reply(reply))
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), RadiusError> {
+    SimpleLogger::new().with_level(LevelFilter::Debug).init().expect("Failed to create new logger");
+    debug!("RADIUS Tokio Server started");
+
+    let dictionary = Dictionary::from_file("./dict_examples/integration_dict")?;
+    let server     = Server::with_dictionary(dictionary)
+        .set_server(String::from("127.0.0.1"))
+        .set_secret(String::from("secret"))
+        .set_allowed_hosts(vec![String::from("127.0.0.1")])
+        .set_port(RadiusMsgType::AUTH, 1812)
+        .set_port(RadiusMsgType::ACCT, 1813)
+        .set_port(RadiusMsgType::COA,  3799);
+
+    let handler = CustomHandler { base_server: Server::with_dictionary(Dictionary::from_file("./dict_examples/integration_dict")?) };
+    let server  = TokioServer::with_server(server).await?.set_max_concurrency(256);
+    server.serve(handler).await
+}
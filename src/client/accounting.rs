@@ -0,0 +1,219 @@
+//! NAS-side RADIUS accounting session state (RFC 2866 & RFC 2869)
+
+
+use crate::protocol::error::RadiusError;
+use crate::protocol::radius_packet::{ RadiusAttribute, RadiusPacket };
+use crate::tools::bytes_to_integer;
+
+use std::convert::TryInto;
+use std::time::{ Duration, Instant };
+
+
+const ACCT_INTERIM_INTERVAL_ID: u8  = 85;
+const MIN_INTERIM_INTERVAL:     u64 = 60;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Represents value of Acct-Status-Type attribute
+pub enum AcctStatusType {
+    /// Start            = 1
+    Start,
+    /// Stop             = 2
+    Stop,
+    /// Interim-Update   = 3
+    InterimUpdate,
+    /// Accounting-On    = 7
+    AccountingOn,
+    /// Accounting-Off   = 8
+    AccountingOff
+}
+
+impl AcctStatusType {
+    /// Convert AcctStatusType to integer value
+    pub fn to_u32(&self) -> u32 {
+        match self {
+            AcctStatusType::Start         => 1,
+            AcctStatusType::Stop          => 2,
+            AcctStatusType::InterimUpdate => 3,
+            AcctStatusType::AccountingOn  => 7,
+            AcctStatusType::AccountingOff => 8
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+/// Represents single subscriber session, as seen by NAS
+///
+/// Session is created by [Client](crate::client::client::Client), which assigns it unique
+/// Acct-Session-Id, and is used to build Start, Interim-Update and Stop Accounting-Requests.
+/// Attributes, session is created with (ie User-Name, NAS-IP-Address, Framed-IP-Address), are
+/// sent in every Accounting-Request of the session
+///
+/// Sessions are not tied to NAS's lifecycle: NAS is to send Accounting-On, before the first session
+/// starts, and Accounting-Off, once the last one has stopped
+///
+/// ```no_run
+/// use radius_rust::client::client::Client;
+/// use radius_rust::client::udp_client::UdpClient;
+/// use radius_rust::protocol::dictionary::Dictionary;
+/// use radius_rust::protocol::error::RadiusError;
+///
+/// fn run_nas(client: Client) -> Result<(), RadiusError> {
+///     let mut client = UdpClient::with_client(client)?;
+///     let nas_id     = vec![client.client().create_attribute_by_name("NAS-Identifier", b"nas1".to_vec())?];
+///     client.accounting_on(nas_id.clone())?;
+///
+///     let mut session = client.client().create_acct_session(vec![]);
+///     let mut start   = client.client().create_acct_start_packet(&mut session)?;
+///     client.send_and_receive_reply(&mut start)?;
+///     // ... Interim-Updates ...
+///     let mut stop = client.client().create_acct_stop_packet(&session, None)?;
+///     client.send_and_receive_reply(&mut stop)?;
+///
+///     client.accounting_off(nas_id)?;
+///     Ok(())
+/// }
+/// ```
+pub struct AccountingSession {
+    session_id:       String,
+    attributes:       Vec<RadiusAttribute>,
+    started:          Instant,
+    last_update:      Instant,
+    interim_interval: Option<Duration>,
+    input_octets:     u64,
+    output_octets:    u64,
+    input_packets:    u32,
+    output_packets:   u32
+}
+
+impl AccountingSession {
+    pub(crate) fn new(session_id: String, attributes: Vec<RadiusAttribute>) -> AccountingSession {
+        let now = Instant::now();
+
+        AccountingSession {
+            session_id,
+            attributes,
+            started:          now,
+            last_update:      now,
+            interim_interval: None,
+            input_octets:     0,
+            output_octets:    0,
+            input_packets:    0,
+            output_packets:   0
+        }
+    }
+
+    /// Returns Acct-Session-Id
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Returns attributes, which are sent in every Accounting-Request of the session
+    pub fn attributes(&self) -> &[RadiusAttribute] {
+        &self.attributes
+    }
+
+    /// Returns time at which session has started
+    pub fn started(&self) -> Instant {
+        self.started
+    }
+
+    /// Returns session duration
+    pub fn session_time(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Updates session counters with the current totals
+    pub fn update_counters(&mut self, input_octets: u64, output_octets: u64, input_packets: u32, output_packets: u32) {
+        self.input_octets   = input_octets;
+        self.output_octets  = output_octets;
+        self.input_packets  = input_packets;
+        self.output_packets = output_packets;
+    }
+
+    /// Returns total number of octets received from the subscriber
+    pub fn input_octets(&self) -> u64 {
+        self.input_octets
+    }
+
+    /// Returns total number of octets sent to the subscriber
+    pub fn output_octets(&self) -> u64 {
+        self.output_octets
+    }
+
+    /// Returns total number of packets received from the subscriber
+    pub fn input_packets(&self) -> u32 {
+        self.input_packets
+    }
+
+    /// Returns total number of packets sent to the subscriber
+    pub fn output_packets(&self) -> u32 {
+        self.output_packets
+    }
+
+    /// Sets interval between Interim-Update Accounting-Requests; None disables Interim-Updates
+    pub fn set_interim_interval(&mut self, interim_interval: Option<Duration>) {
+        self.interim_interval = interim_interval;
+    }
+
+    /// Sets interval between Interim-Update Accounting-Requests from Acct-Interim-Interval of
+    /// Access-Accept, if it is present
+    ///
+    /// Interval is never shorter than 60 seconds, as required by RFC 2869 Section 5.16
+    pub fn set_interim_interval_from_reply(&mut self, reply: &RadiusPacket) -> Result<(), RadiusError> {
+        if let Some(attr) = reply.attribute_by_id(ACCT_INTERIM_INTERVAL_ID) {
+            let value: &[u8; 4] = attr.value().try_into().map_err(|_| RadiusError::MalformedAttributeError { error: String::from("invalid Acct-Interim-Interval length") })?;
+            let seconds         = std::cmp::max(bytes_to_integer(value) as u64, MIN_INTERIM_INTERVAL);
+
+            self.interim_interval = Some(Duration::from_secs(seconds));
+        }
+        Ok(())
+    }
+
+    /// Returns interval between Interim-Update Accounting-Requests
+    pub fn interim_interval(&self) -> Option<Duration> {
+        self.interim_interval
+    }
+
+    /// Returns time at which next Interim-Update is due
+    pub fn next_interim(&self) -> Option<Instant> {
+        self.interim_interval.map(|interim_interval| self.last_update + interim_interval)
+    }
+
+    /// Returns true, if Interim-Update is due
+    pub fn is_interim_due(&self, now: Instant) -> bool {
+        self.next_interim().map(|next_interim| now >= next_interim).unwrap_or(false)
+    }
+
+    pub(crate) fn record_update(&mut self, now: Instant) {
+        self.last_update = now;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::radius_packet::TypeCode;
+    use crate::testing::test_dictionary;
+    use crate::tools::integer_to_bytes;
+
+    #[test]
+    fn test_interim_interval_from_reply() {
+        let dictionary  = test_dictionary();
+        let mut session = AccountingSession::new(String::from("session"), vec![]);
+        let mut reply   = RadiusPacket::initialise_packet(TypeCode::AccessAccept);
+
+        session.set_interim_interval_from_reply(&reply).unwrap();
+        assert_eq!(None, session.next_interim());
+        assert!(!session.is_interim_due(Instant::now() + Duration::from_secs(3600)));
+
+        reply.set_attributes(vec![RadiusAttribute::create_by_id(&dictionary, ACCT_INTERIM_INTERVAL_ID, integer_to_bytes(10)).unwrap()]);
+        session.set_interim_interval_from_reply(&reply).unwrap();
+
+        assert_eq!(Some(Duration::from_secs(60)), session.interim_interval());
+        assert!(!session.is_interim_due(session.started() + Duration::from_secs(59)));
+        assert!(session.is_interim_due(session.started() + Duration::from_secs(60)));
+    }
+}
@@ -0,0 +1,145 @@
+//! High-level RADIUS Client authentication outcome
+
+
+use crate::protocol::error::RadiusError;
+use crate::protocol::radius_packet::{ RadiusAttribute, RadiusPacket, TypeCode };
+
+
+const USER_NAME_ID:     u8 = 1;
+const REPLY_MESSAGE_ID: u8 = 18;
+const STATE_ID:         u8 = 24;
+
+
+#[derive(Debug, Clone, PartialEq)]
+/// Represents Access-Challenge, which has to be answered (ie with OTP) to continue authentication
+pub struct Challenge {
+    user_name:  String,
+    state:      Vec<u8>,
+    prompt:     Option<String>,
+    attributes: Vec<RadiusAttribute>
+}
+
+impl Challenge {
+    /// Returns User-Name, which has been challenged
+    pub fn user_name(&self) -> &str {
+        &self.user_name
+    }
+
+    /// Returns State, which has to be sent back with the response
+    pub fn state(&self) -> &[u8] {
+        &self.state
+    }
+
+    /// Returns prompt (Reply-Message), which is to be displayed to the user
+    pub fn prompt(&self) -> Option<&str> {
+        self.prompt.as_deref()
+    }
+
+    /// Returns all attributes of Access-Challenge
+    pub fn attributes(&self) -> &[RadiusAttribute] {
+        &self.attributes
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Represents the outcome of Access-Request
+pub enum AuthOutcome {
+    /// User has been authenticated (Access-Accept)
+    Accept {
+        /// Attributes of Access-Accept
+        attributes: Vec<RadiusAttribute>
+    },
+    /// User has been rejected (Access-Reject)
+    Reject {
+        /// Reply-Message of Access-Reject, if present
+        reply_message: Option<String>
+    },
+    /// User has to answer the challenge (Access-Challenge)
+    Challenge(Challenge)
+}
+
+impl AuthOutcome {
+    /// Interprets verified reply to Access-Request
+    pub fn from_reply(request: &RadiusPacket, reply: &RadiusPacket) -> Result<AuthOutcome, RadiusError> {
+        match reply.code() {
+            TypeCode::AccessAccept    => Ok(AuthOutcome::Accept { attributes: reply.attributes().to_vec() }),
+            TypeCode::AccessReject    => Ok(AuthOutcome::Reject { reply_message: reply_message(reply) }),
+            TypeCode::AccessChallenge => {
+                let state     = reply.attribute_by_id(STATE_ID).ok_or_else(|| RadiusError::ValidationError { error: String::from("Access-Challenge has no State attribute") })?;
+                let user_name = request.attribute_by_id(USER_NAME_ID).map(|attr| String::from_utf8_lossy(attr.value()).into_owned()).unwrap_or_default();
+
+                Ok(AuthOutcome::Challenge(Challenge {
+                    user_name,
+                    state:      state.value().to_vec(),
+                    prompt:     reply_message(reply),
+                    attributes: reply.attributes().to_vec()
+                }))
+            },
+            code                      => Err( RadiusError::ValidationError { error: format!("Unexpected reply to Access-Request: {:?}", code) } )
+        }
+    }
+
+    /// Returns true, if user has been authenticated
+    pub fn is_accept(&self) -> bool {
+        matches!(self, AuthOutcome::Accept { .. })
+    }
+}
+
+/// Returns Reply-Message; multiple Reply-Message attributes are joined in order, one per line
+fn reply_message(reply: &RadiusPacket) -> Option<String> {
+    let messages: Vec<String> = reply.attributes().iter()
+        .filter(|attr| attr.id() == REPLY_MESSAGE_ID)
+        .map(|attr| String::from_utf8_lossy(attr.value()).into_owned())
+        .collect();
+
+    if messages.is_empty() {
+        None
+    } else {
+        Some(messages.join("\n"))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::client::Client;
+    use crate::testing::{ test_dictionary, test_server };
+
+    fn test_reply(code: TypeCode, attributes: Vec<(&str, &str)>) -> (RadiusPacket, RadiusPacket) {
+        let client     = Client::with_dictionary(test_dictionary()).set_secret(String::from("secret"));
+        let server     = test_server("secret");
+
+        let mut request = client.create_pap_packet("testing", "password").unwrap();
+        let mut bytes   = request.to_bytes();
+        let reply_attrs = attributes.into_iter().map(|(name, value)| server.create_attribute_by_name(name, value.as_bytes().to_vec()).unwrap()).collect();
+        let reply       = server.create_reply_packet(code, reply_attrs, &mut bytes);
+
+        (request, reply)
+    }
+
+    #[test]
+    fn test_from_reply() {
+        let (request, reply) = test_reply(TypeCode::AccessAccept, vec![("Reply-Message", "welcome")]);
+        assert!(AuthOutcome::from_reply(&request, &reply).unwrap().is_accept());
+
+        let (request, reply) = test_reply(TypeCode::AccessReject, vec![("Reply-Message", "go"), ("Reply-Message", "away")]);
+        assert_eq!(AuthOutcome::Reject { reply_message: Some(String::from("go\naway")) }, AuthOutcome::from_reply(&request, &reply).unwrap());
+
+        let (request, reply) = test_reply(TypeCode::AccessChallenge, vec![("Reply-Message", "Enter OTP"), ("State", "state-1")]);
+        match AuthOutcome::from_reply(&request, &reply).unwrap() {
+            AuthOutcome::Challenge(challenge) => {
+                assert_eq!("testing",         challenge.user_name());
+                assert_eq!(b"state-1",        challenge.state());
+                assert_eq!(Some("Enter OTP"), challenge.prompt());
+            },
+            _                                 => assert!(false)
+        }
+
+        let (request, reply) = test_reply(TypeCode::AccessChallenge, vec![]);
+        match AuthOutcome::from_reply(&request, &reply) {
+            Err(error) => assert_eq!(String::from("Verification failed for incoming Radius packet: Access-Challenge has no State attribute"), error.to_string()),
+            _          => assert!(false)
+        }
+    }
+}
@@ -1,26 +1,60 @@
 //! RADIUS Generic Client implementation
 
 
+use crate::client::accounting::{ AccountingSession, AcctStatusType };
+use crate::client::auth::Challenge;
+use crate::client::retransmission::RetransmissionPolicy;
 use crate::protocol::dictionary::Dictionary;
-use crate::protocol::error::RadiusError;
+use crate::protocol::error::{ InvalidReplyKind, RadiusError };
 use crate::protocol::host::Host;
 use crate::protocol::radius_packet::{ RadiusAttribute, RadiusPacket, RadiusMsgType, TypeCode };
+use crate::tools::{ encrypt_data, integer_to_bytes, timestamp_to_bytes };
 
 use hmac::{ Hmac, Mac };
 use md5::{ Digest, Md5 };
+use rand::{ thread_rng, Rng };
+use std::sync::atomic::{ AtomicU32, Ordering };
+use std::time::{ Duration, Instant, SystemTime, UNIX_EPOCH };
 
 
 type HmacMd5 = Hmac<Md5>;
 
+const USER_NAME_ID:             u8 = 1;
+const USER_PASSWORD_ID:         u8 = 2;
+const CHAP_PASSWORD_ID:         u8 = 3;
+const STATE_ID:                 u8 = 24;
+const ACCT_STATUS_TYPE_ID:      u8 = 40;
+const ACCT_INPUT_OCTETS_ID:     u8 = 42;
+const ACCT_OUTPUT_OCTETS_ID:    u8 = 43;
+const ACCT_SESSION_ID_ID:       u8 = 44;
+const ACCT_SESSION_TIME_ID:     u8 = 46;
+const ACCT_INPUT_PACKETS_ID:    u8 = 47;
+const ACCT_OUTPUT_PACKETS_ID:   u8 = 48;
+const ACCT_TERMINATE_CAUSE_ID:  u8 = 49;
+const ACCT_INPUT_GIGAWORDS_ID:  u8 = 52;
+const ACCT_OUTPUT_GIGAWORDS_ID: u8 = 53;
+const EVENT_TIMESTAMP_ID:       u8 = 55;
+const EAP_MESSAGE_ID:           u8 = 79;
+const MESSAGE_AUTHENTICATOR_ID: u8 = 80;
+
+/// Port NAS listens on for Disconnect-Requests & CoA-Requests (RFC 5176 Section 3.1)
+///
+/// Client does not default to it: it has to be set with
+/// `set_port(RadiusMsgType::COA, DYNAMIC_AUTHORIZATION_PORT)`
+pub const DYNAMIC_AUTHORIZATION_PORT: u16 = 3799;
+
 
 #[derive(Debug)]
 /// Represents RADIUS Generic Client instance
 pub struct Client {
-    host:           Host,
-    server:         String,
-    secret:         String,
-    retries:        u16,
-    timeout:        u16,
+    host:                  Host,
+    server:                String,
+    secret:                String,
+    retries:               u16,
+    timeout:               u16,
+    retransmission_policy: Option<RetransmissionPolicy>,
+    acct_session_prefix:   u32,
+    acct_session_counter:  AtomicU32
 }
 
 impl Client {
@@ -36,7 +70,10 @@ impl Client {
             server:  String::from(""),
             secret:  String::from(""),
             retries: 1,
-            timeout: 2
+            timeout: 2,
+            retransmission_policy: None,
+            acct_session_prefix:   thread_rng().gen(),
+            acct_session_counter:  AtomicU32::new(0)
         }
     }
 
@@ -79,6 +116,16 @@ impl Client {
         self.timeout = timeout;
         self
     }
+
+    /// **Optional**
+    ///
+    /// Sets retransmission policy, which takes precedence over retries and timeout
+    ///
+    /// If not set, request is transmitted up to `retries` times, every `timeout` seconds
+    pub fn set_retransmission_policy(mut self, retransmission_policy: RetransmissionPolicy) -> Client {
+        self.retransmission_policy = Some(retransmission_policy);
+        self
+    }
     // ===================
 
     /// Returns port of RADIUS server, that receives given type of RADIUS message/packet
@@ -106,6 +153,16 @@ impl Client {
         self.timeout
     }
 
+    /// Returns retransmission policy
+    ///
+    /// If policy was not set explicitly, it is built from retries and timeout
+    pub fn retransmission_policy(&self) -> RetransmissionPolicy {
+        match &self.retransmission_policy {
+            Some(policy) => policy.clone(),
+            None         => RetransmissionPolicy::fixed(Duration::from_secs(self.timeout as u64), std::cmp::max(self.retries, 1) as u32)
+        }
+    }
+
     /// Creates RADIUS packet with any TypeCode without attributes
     ///
     /// You would need to set attributes manually via *set_attributes()* function
@@ -134,6 +191,132 @@ impl Client {
         RadiusPacket::initialise_packet(TypeCode::CoARequest)
     }
 
+    /// Creates RADIUS Access Request packet, which authenticates user with PAP
+    ///
+    /// Packet carries User-Name, User-Password (encrypted with Client's secret) and
+    /// Message-Authenticator
+    pub fn create_pap_packet(&self, user_name: &str, password: &str) -> Result<RadiusPacket, RadiusError> {
+        let packet   = self.create_auth_packet();
+        let password = encrypt_data(password.as_bytes(), packet.authenticator(), self.secret.as_bytes());
+
+        self.finalise_auth_packet(packet, vec![
+            self.create_attribute_by_id(USER_NAME_ID,     user_name.as_bytes().to_vec())?,
+            self.create_attribute_by_id(USER_PASSWORD_ID, password)?
+        ])
+    }
+
+    /// Creates RADIUS Access Request packet, which authenticates user with CHAP
+    ///
+    /// Request Authenticator is used as CHAP challenge (RFC 2865 Section 2.2), so packet carries
+    /// User-Name, CHAP-Password and Message-Authenticator
+    pub fn create_chap_packet(&self, user_name: &str, password: &str) -> Result<RadiusPacket, RadiusError> {
+        let packet         = self.create_auth_packet();
+        let chap_id        = thread_rng().gen::<u8>();
+        let mut md5_hasher = Md5::new();
+
+        md5_hasher.update([chap_id]);
+        md5_hasher.update(password.as_bytes());
+        md5_hasher.update(packet.authenticator());
+
+        let mut chap_password = vec![chap_id];
+        chap_password.extend_from_slice(&md5_hasher.finalize());
+
+        self.finalise_auth_packet(packet, vec![
+            self.create_attribute_by_id(USER_NAME_ID,     user_name.as_bytes().to_vec())?,
+            self.create_attribute_by_id(CHAP_PASSWORD_ID, chap_password)?
+        ])
+    }
+
+    /// Creates RADIUS Access Request packet, which answers Access-Challenge
+    ///
+    /// Response (ie OTP) is sent as User-Password, together with challenge's User-Name and State
+    pub fn create_challenge_response_packet(&self, challenge: &Challenge, response: &str) -> Result<RadiusPacket, RadiusError> {
+        let packet   = self.create_auth_packet();
+        let response = encrypt_data(response.as_bytes(), packet.authenticator(), self.secret.as_bytes());
+
+        self.finalise_auth_packet(packet, vec![
+            self.create_attribute_by_id(USER_NAME_ID,     challenge.user_name().as_bytes().to_vec())?,
+            self.create_attribute_by_id(USER_PASSWORD_ID, response)?,
+            self.create_attribute_by_id(STATE_ID,         challenge.state().to_vec())?
+        ])
+    }
+
+    /// Generates unique Acct-Session-Id
+    ///
+    /// ID consists of random per-Client prefix and per-Client counter, so it is unique across
+    /// Client restarts as well
+    pub fn generate_acct_session_id(&self) -> String {
+        format!("{:08X}{:08X}", self.acct_session_prefix, self.acct_session_counter.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Creates accounting session with unique Acct-Session-Id
+    ///
+    /// Given attributes (ie User-Name, NAS-IP-Address, Framed-IP-Address) are sent in every
+    /// Accounting-Request of the session
+    pub fn create_acct_session(&self, attributes: Vec<RadiusAttribute>) -> AccountingSession {
+        AccountingSession::new(self.generate_acct_session_id(), attributes)
+    }
+
+    /// Creates Accounting-Request, which starts accounting session
+    pub fn create_acct_start_packet(&self, session: &mut AccountingSession) -> Result<RadiusPacket, RadiusError> {
+        session.record_update(Instant::now());
+        self.create_acct_status_packet(AcctStatusType::Start, session.session_id(), session.attributes().to_vec())
+    }
+
+    /// Creates Interim-Update Accounting-Request with session's current counters
+    ///
+    /// Next Interim-Update is scheduled one interim interval after this one
+    pub fn create_acct_interim_packet(&self, session: &mut AccountingSession) -> Result<RadiusPacket, RadiusError> {
+        session.record_update(Instant::now());
+
+        let attributes = self.acct_session_attributes(session, None)?;
+        self.create_acct_status_packet(AcctStatusType::InterimUpdate, session.session_id(), attributes)
+    }
+
+    /// Creates Accounting-Request, which stops accounting session, with session's final counters
+    /// and, if given, Acct-Terminate-Cause
+    pub fn create_acct_stop_packet(&self, session: &AccountingSession, terminate_cause: Option<u32>) -> Result<RadiusPacket, RadiusError> {
+        let attributes = self.acct_session_attributes(session, terminate_cause)?;
+        self.create_acct_status_packet(AcctStatusType::Stop, session.session_id(), attributes)
+    }
+
+    /// Creates Accounting-On Accounting-Request, which is to be sent when NAS starts up
+    ///
+    /// Packet is only built here; [UdpClient::accounting_on](crate::client::udp_client::UdpClient::accounting_on)
+    /// sends it
+    pub fn create_accounting_on_packet(&self, attributes: Vec<RadiusAttribute>) -> Result<RadiusPacket, RadiusError> {
+        self.create_acct_status_packet(AcctStatusType::AccountingOn, &self.generate_acct_session_id(), attributes)
+    }
+
+    /// Creates Accounting-Off Accounting-Request, which is to be sent when NAS shuts down
+    ///
+    /// Packet is only built here; [UdpClient::accounting_off](crate::client::udp_client::UdpClient::accounting_off)
+    /// sends it
+    pub fn create_accounting_off_packet(&self, attributes: Vec<RadiusAttribute>) -> Result<RadiusPacket, RadiusError> {
+        self.create_acct_status_packet(AcctStatusType::AccountingOff, &self.generate_acct_session_id(), attributes)
+    }
+
+    /// Creates Disconnect-Request, which asks NAS to terminate the session(s) identified by given
+    /// session identification attributes (ie User-Name, Acct-Session-Id, NAS-IP-Address)
+    ///
+    /// Packet carries Event-Timestamp; its Request Authenticator is generated on transmission
+    /// (RFC 5176 Section 2.3). Packet is sent to COA port, which is to be set first (ie to
+    /// [DYNAMIC_AUTHORIZATION_PORT])
+    pub fn create_disconnect_packet(&self, session_identifiers: Vec<RadiusAttribute>) -> Result<RadiusPacket, RadiusError> {
+        self.create_dyn_auth_packet(TypeCode::DisconnectRequest, session_identifiers)
+    }
+
+    /// Creates CoA-Request, which asks NAS to change authorization of the session(s) identified by
+    /// given session identification attributes to given attributes
+    ///
+    /// Packet carries Event-Timestamp; its Request Authenticator is generated on transmission
+    /// (RFC 5176 Section 2.3). Packet is sent to COA port, which is to be set first (ie to
+    /// [DYNAMIC_AUTHORIZATION_PORT])
+    pub fn create_change_of_authorization_packet(&self, mut session_identifiers: Vec<RadiusAttribute>, attributes: Vec<RadiusAttribute>) -> Result<RadiusPacket, RadiusError> {
+        session_identifiers.extend(attributes);
+        self.create_dyn_auth_packet(TypeCode::CoARequest, session_identifiers)
+    }
+
     /// Creates RADIUS packet attribute by name, that is defined in dictionary file
     ///
     /// # Examples
@@ -248,11 +431,134 @@ impl Client {
     pub fn verify_packet_attributes(&self, packet: &[u8]) -> Result<(), RadiusError> {
         self.host.verify_packet_attributes(&packet)
     }
+
+    /// Verifies reply to given request and returns it as RadiusPacket
+    ///
+    /// Checks are performed in the following order: reply length, ID, reply code (it has to be a
+    /// valid answer to request code), Response Authenticator, Message-Authenticator (it is
+    /// required, if request carries EAP-Message) and attribute values. Octets past reply's Length
+    /// field are ignored (RFC 2865 Section 3)
+    pub fn process_reply(&self, request: &RadiusPacket, reply: &[u8]) -> Result<RadiusPacket, RadiusError> {
+        if reply.len() < 20 {
+            return Err(invalid_reply(InvalidReplyKind::Malformed, String::from("Reply is shorter than RADIUS header")))
+        }
+        let length = u16::from_be_bytes([reply[2], reply[3]]) as usize;
+        if !(20..=4096).contains(&length) || length > reply.len() {
+            return Err(invalid_reply(InvalidReplyKind::Malformed, format!("Reply Length field [{}] doesn't match reply size [{}]", length, reply.len())))
+        }
+        let reply = &reply[..length];
+
+        if request.id() != reply[1] {
+            return Err(invalid_reply(InvalidReplyKind::IdentifierMismatch, String::from("Packet identifier mismatch")))
+        }
+
+        let code = TypeCode::from_u8(reply[0]).map_err(|error| invalid_reply(InvalidReplyKind::UnexpectedCode, reason(error)))?;
+        if !is_valid_reply_code(request.code(), &code) {
+            return Err(invalid_reply(InvalidReplyKind::UnexpectedCode, format!("{:?} is not a valid reply to {:?}", code, request.code())))
+        }
+
+        self.verify_reply(request, reply).map_err(|error| invalid_reply(InvalidReplyKind::AuthenticatorMismatch, reason(error)))?;
+
+        let reply_packet = self.initialise_packet_from_bytes(reply).map_err(|error| invalid_reply(InvalidReplyKind::Malformed, reason(error)))?;
+        if reply_packet.attribute_by_id(MESSAGE_AUTHENTICATOR_ID).is_some() {
+            // Reply's Message-Authenticator is calculated over reply with Request Authenticator in
+            // place of Response Authenticator (RFC 3579 Section 3.2)
+            let mut reply_with_request_authenticator = reply.to_vec();
+            reply_with_request_authenticator[4..20].copy_from_slice(request.authenticator());
+
+            self.verify_message_authenticator(&reply_with_request_authenticator).map_err(|error| invalid_reply(InvalidReplyKind::MessageAuthenticatorMismatch, reason(error)))?;
+        } else if request.attribute_by_id(EAP_MESSAGE_ID).is_some() {
+            return Err(invalid_reply(InvalidReplyKind::MissingMessageAuthenticator, String::from("Reply to EAP request has no Message-Authenticator")))
+        }
+
+        self.verify_packet_attributes(reply).map_err(|error| invalid_reply(InvalidReplyKind::InvalidAttribute, reason(error)))?;
+        Ok(reply_packet)
+    }
+
+    fn create_acct_status_packet(&self, status_type: AcctStatusType, session_id: &str, mut attributes: Vec<RadiusAttribute>) -> Result<RadiusPacket, RadiusError> {
+        let mut packet = self.create_acct_packet();
+
+        attributes.push(self.create_attribute_by_id(ACCT_STATUS_TYPE_ID, integer_to_bytes(status_type.to_u32()))?);
+        attributes.push(self.create_attribute_by_id(ACCT_SESSION_ID_ID,  session_id.as_bytes().to_vec())?);
+        attributes.push(self.create_event_timestamp_attribute()?);
+
+        packet.set_attributes(attributes);
+        Ok(packet)
+    }
+
+    fn create_dyn_auth_packet(&self, code: TypeCode, mut attributes: Vec<RadiusAttribute>) -> Result<RadiusPacket, RadiusError> {
+        let mut packet = self.create_packet(code);
+
+        attributes.push(self.create_event_timestamp_attribute()?);
+
+        packet.set_attributes(attributes);
+        Ok(packet)
+    }
+
+    fn create_event_timestamp_attribute(&self) -> Result<RadiusAttribute, RadiusError> {
+        let event_timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|timestamp| timestamp.as_secs() as u32).unwrap_or(0);
+        self.create_attribute_by_id(EVENT_TIMESTAMP_ID, timestamp_to_bytes(event_timestamp))
+    }
+
+    fn acct_session_attributes(&self, session: &AccountingSession, terminate_cause: Option<u32>) -> Result<Vec<RadiusAttribute>, RadiusError> {
+        // 64-bit octet counters are split into 32-bit Acct-*-Octets and Acct-*-Gigawords, which
+        // holds the number of times Acct-*-Octets has wrapped (RFC 2869 Section 5.1 & 5.2)
+        let mut attributes = session.attributes().to_vec();
+
+        attributes.push(self.create_attribute_by_id(ACCT_SESSION_TIME_ID,     integer_to_bytes(session.session_time().as_secs() as u32))?);
+        attributes.push(self.create_attribute_by_id(ACCT_INPUT_OCTETS_ID,     integer_to_bytes(session.input_octets() as u32))?);
+        attributes.push(self.create_attribute_by_id(ACCT_INPUT_GIGAWORDS_ID,  integer_to_bytes((session.input_octets() >> 32) as u32))?);
+        attributes.push(self.create_attribute_by_id(ACCT_OUTPUT_OCTETS_ID,    integer_to_bytes(session.output_octets() as u32))?);
+        attributes.push(self.create_attribute_by_id(ACCT_OUTPUT_GIGAWORDS_ID, integer_to_bytes((session.output_octets() >> 32) as u32))?);
+        attributes.push(self.create_attribute_by_id(ACCT_INPUT_PACKETS_ID,    integer_to_bytes(session.input_packets()))?);
+        attributes.push(self.create_attribute_by_id(ACCT_OUTPUT_PACKETS_ID,   integer_to_bytes(session.output_packets()))?);
+
+        if let Some(terminate_cause) = terminate_cause {
+            attributes.push(self.create_attribute_by_id(ACCT_TERMINATE_CAUSE_ID, integer_to_bytes(terminate_cause))?);
+        }
+        Ok(attributes)
+    }
+
+    fn finalise_auth_packet(&self, mut packet: RadiusPacket, mut attributes: Vec<RadiusAttribute>) -> Result<RadiusPacket, RadiusError> {
+        attributes.push(self.create_attribute_by_id(MESSAGE_AUTHENTICATOR_ID, [0; 16].to_vec())?);
+
+        packet.set_attributes(attributes);
+        packet.generate_message_authenticator(&self.secret)?;
+        Ok(packet)
+    }
+}
+
+/// Returns true, if reply code is a valid answer to request code
+fn is_valid_reply_code(request_code: &TypeCode, reply_code: &TypeCode) -> bool {
+    match request_code {
+        TypeCode::AccessRequest     => matches!(reply_code, TypeCode::AccessAccept | TypeCode::AccessReject | TypeCode::AccessChallenge),
+        TypeCode::AccountingRequest => matches!(reply_code, TypeCode::AccountingResponse),
+        // Status-Server sent to authentication port is answered with Access-Accept and the one
+        // sent to accounting port with Accounting-Response (RFC 5997 Section 3)
+        TypeCode::StatusServer      => matches!(reply_code, TypeCode::AccessAccept | TypeCode::AccountingResponse),
+        TypeCode::DisconnectRequest => matches!(reply_code, TypeCode::DisconnectACK | TypeCode::DisconnectNAK),
+        TypeCode::CoARequest        => matches!(reply_code, TypeCode::CoAACK | TypeCode::CoANAK),
+        _                           => false
+    }
+}
+
+fn invalid_reply(kind: InvalidReplyKind, error: String) -> RadiusError {
+    RadiusError::InvalidReplyError { kind, error }
+}
+
+/// Returns message of ValidationError as it is, so it is not prefixed twice once wrapped into
+/// InvalidReplyError
+fn reason(error: RadiusError) -> String {
+    match error {
+        RadiusError::ValidationError { error } => error,
+        error                                  => error.to_string()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::server::server::Server;
     use crate::tools::integer_to_bytes;
 
     #[test]
@@ -449,4 +755,103 @@ mod tests {
 
         assert_eq!(expected_message_autthenticator, generated_message_authenticator)
     }
+
+    #[test]
+    fn test_acct_session_packets() {
+        let dictionary = Dictionary::from_file("./dict_examples/integration_dict").unwrap();
+        let client     = Client::with_dictionary(dictionary).set_secret(String::from("secret"));
+
+        let user_name   = client.create_attribute_by_name("User-Name", String::from("testing").into_bytes()).unwrap();
+        let mut session = client.create_acct_session(vec![user_name]);
+        assert_ne!(session.session_id(), client.create_acct_session(vec![]).session_id());
+
+        let start = client.create_acct_start_packet(&mut session).unwrap();
+        assert_eq!(&TypeCode::AccountingRequest, start.code());
+        assert_eq!(integer_to_bytes(1),                start.attribute_by_name("Acct-Status-Type").unwrap().value());
+        assert_eq!(session.session_id().as_bytes(),    start.attribute_by_name("Acct-Session-Id").unwrap().value());
+        assert_eq!(b"testing",                         start.attribute_by_name("User-Name").unwrap().value());
+
+        session.update_counters((5 << 32) + 10, 20, 1, 2);
+        let stop = client.create_acct_stop_packet(&session, Some(1)).unwrap();
+        assert_eq!(integer_to_bytes(2),  stop.attribute_by_name("Acct-Status-Type").unwrap().value());
+        assert_eq!(integer_to_bytes(10), stop.attribute_by_name("Acct-Input-Octets").unwrap().value());
+        assert_eq!(integer_to_bytes(5),  stop.attribute_by_name("Acct-Input-Gigawords").unwrap().value());
+        assert_eq!(integer_to_bytes(20), stop.attribute_by_name("Acct-Output-Octets").unwrap().value());
+        assert_eq!(integer_to_bytes(0),  stop.attribute_by_name("Acct-Output-Gigawords").unwrap().value());
+        assert_eq!(integer_to_bytes(1),  stop.attribute_by_name("Acct-Terminate-Cause").unwrap().value());
+
+        let accounting_on = client.create_accounting_on_packet(vec![]).unwrap();
+        assert_eq!(integer_to_bytes(7), accounting_on.attribute_by_name("Acct-Status-Type").unwrap().value());
+    }
+
+    #[test]
+    fn test_process_reply() {
+        let dictionary = Dictionary::from_file("./dict_examples/integration_dict").unwrap();
+        let client     = Client::with_dictionary(dictionary).set_secret(String::from("secret"));
+        let dictionary = Dictionary::from_file("./dict_examples/integration_dict").unwrap();
+        let server     = Server::with_dictionary(dictionary).set_secret(String::from("secret"));
+
+        let kind_of = |result: Result<RadiusPacket, RadiusError>| match result {
+            Err(RadiusError::InvalidReplyError { kind, .. }) => Some(kind),
+            Err(error)                                       => panic!("unexpected error: {}", error),
+            Ok(_)                                            => None
+        };
+
+        let sign_reply = |request: &RadiusPacket, reply: &mut Vec<u8>| {
+            let mut md5_hasher = Md5::new();
+            md5_hasher.update(&reply[0..4]);
+            md5_hasher.update(request.authenticator());
+            md5_hasher.update(&reply[20..]);
+            md5_hasher.update(b"secret");
+            reply[4..20].copy_from_slice(&md5_hasher.finalize());
+        };
+
+        // Reply's Message-Authenticator is calculated with Request Authenticator in place of
+        // Response Authenticator
+        let mut request      = client.create_pap_packet("testing", "password").unwrap();
+        let mut bytes        = request.to_bytes();
+        let mut reply_packet = RadiusPacket::initialise_packet(TypeCode::AccessAccept);
+        reply_packet.set_attributes(vec![server.create_attribute_by_id(MESSAGE_AUTHENTICATOR_ID, [0; 16].to_vec()).unwrap()]);
+        reply_packet.override_id(request.id());
+        reply_packet.override_authenticator(request.authenticator().to_vec());
+        reply_packet.generate_message_authenticator("secret").unwrap();
+
+        let mut reply = reply_packet.to_bytes();
+        sign_reply(&request, &mut reply);
+
+        // Octets past Length field are ignored
+        let mut padded_reply = reply.clone();
+        padded_reply.extend_from_slice(&[0; 4]);
+        assert_eq!(&TypeCode::AccessAccept, client.process_reply(&request, &padded_reply).unwrap().code());
+
+        assert_eq!(Some(InvalidReplyKind::Malformed), kind_of(client.process_reply(&request, &reply[..19])));
+        assert_eq!(Some(InvalidReplyKind::Malformed), kind_of(client.process_reply(&request, &reply[..reply.len() - 1])));
+
+        let mut other_request = request.clone();
+        other_request.override_id(request.id().wrapping_add(1));
+        assert_eq!(Some(InvalidReplyKind::IdentifierMismatch), kind_of(client.process_reply(&other_request, &reply)));
+
+        let mut accounting_request = client.create_acct_packet();
+        accounting_request.override_id(request.id());
+        accounting_request.override_authenticator(request.authenticator().to_vec());
+        assert_eq!(Some(InvalidReplyKind::UnexpectedCode), kind_of(client.process_reply(&accounting_request, &reply)));
+
+        let acct_reply = server.create_reply_packet(TypeCode::AccountingResponse, vec![], &mut bytes).to_bytes();
+        assert_eq!(Some(InvalidReplyKind::UnexpectedCode), kind_of(client.process_reply(&request, &acct_reply)));
+
+        let other_client = Client::with_dictionary(Dictionary::from_file("./dict_examples/integration_dict").unwrap()).set_secret(String::from("other-secret"));
+        assert_eq!(Some(InvalidReplyKind::AuthenticatorMismatch), kind_of(other_client.process_reply(&request, &reply)));
+
+        // Tamper with Message-Authenticator and fix up Response Authenticator
+        let last = reply.len() - 1;
+        reply[last] ^= 0xff;
+        sign_reply(&request, &mut reply);
+        assert_eq!(Some(InvalidReplyKind::MessageAuthenticatorMismatch), kind_of(client.process_reply(&request, &reply)));
+
+        let mut eap_request = client.create_auth_packet();
+        eap_request.set_attributes(vec![client.create_attribute_by_id(EAP_MESSAGE_ID, vec![2, 1, 0, 4]).unwrap()]);
+        let mut bytes = eap_request.to_bytes();
+        let eap_reply = server.create_reply_packet(TypeCode::AccessReject, vec![], &mut bytes).to_bytes();
+        assert_eq!(Some(InvalidReplyKind::MissingMessageAuthenticator), kind_of(client.process_reply(&eap_request, &eap_reply)));
+    }
 }
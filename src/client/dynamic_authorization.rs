@@ -0,0 +1,188 @@
+//! High-level Dynamic Authorization (Disconnect & CoA) outcome (RFC 5176)
+
+
+use crate::protocol::error::RadiusError;
+use crate::protocol::radius_packet::{ RadiusAttribute, RadiusPacket, TypeCode };
+use crate::tools::bytes_to_integer;
+
+use std::convert::TryInto;
+
+
+const ERROR_CAUSE_ID: u8 = 101;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Represents value of Error-Cause attribute (RFC 5176 Section 3.5)
+pub enum ErrorCause {
+    /// Residual Session Context Removed           = 201
+    ResidualSessionContextRemoved,
+    /// Invalid EAP Packet (Ignored)               = 202
+    InvalidEapPacket,
+    /// Unsupported Attribute                      = 401
+    UnsupportedAttribute,
+    /// Missing Attribute                          = 402
+    MissingAttribute,
+    /// NAS Identification Mismatch                = 403
+    NasIdentificationMismatch,
+    /// Invalid Request                            = 404
+    InvalidRequest,
+    /// Unsupported Service                        = 405
+    UnsupportedService,
+    /// Unsupported Extension                      = 406
+    UnsupportedExtension,
+    /// Invalid Attribute Value                    = 407
+    InvalidAttributeValue,
+    /// Administratively Prohibited                = 501
+    AdministrativelyProhibited,
+    /// Request Not Routable (Proxy)               = 502
+    RequestNotRoutable,
+    /// Session Context Not Found                  = 503
+    SessionContextNotFound,
+    /// Session Context Not Removable              = 504
+    SessionContextNotRemovable,
+    /// Other Proxy Processing Error               = 505
+    OtherProxyProcessingError,
+    /// Resources Unavailable                      = 506
+    ResourcesUnavailable,
+    /// Request Initiated                          = 507
+    RequestInitiated,
+    /// Multiple Session Selection Unsupported     = 508
+    MultipleSessionSelectionUnsupported,
+    /// Any other value
+    Other(u32)
+}
+
+impl ErrorCause {
+    /// Convert integer(u32) value into corresponding ErrorCause enum
+    pub fn from_u32(value: u32) -> ErrorCause {
+        match value {
+            201 => ErrorCause::ResidualSessionContextRemoved,
+            202 => ErrorCause::InvalidEapPacket,
+            401 => ErrorCause::UnsupportedAttribute,
+            402 => ErrorCause::MissingAttribute,
+            403 => ErrorCause::NasIdentificationMismatch,
+            404 => ErrorCause::InvalidRequest,
+            405 => ErrorCause::UnsupportedService,
+            406 => ErrorCause::UnsupportedExtension,
+            407 => ErrorCause::InvalidAttributeValue,
+            501 => ErrorCause::AdministrativelyProhibited,
+            502 => ErrorCause::RequestNotRoutable,
+            503 => ErrorCause::SessionContextNotFound,
+            504 => ErrorCause::SessionContextNotRemovable,
+            505 => ErrorCause::OtherProxyProcessingError,
+            506 => ErrorCause::ResourcesUnavailable,
+            507 => ErrorCause::RequestInitiated,
+            508 => ErrorCause::MultipleSessionSelectionUnsupported,
+            _   => ErrorCause::Other(value)
+        }
+    }
+
+    /// Convert ErrorCause enum value into corresponding integer(u32)
+    pub fn to_u32(&self) -> u32 {
+        match self {
+            ErrorCause::ResidualSessionContextRemoved       => 201,
+            ErrorCause::InvalidEapPacket                    => 202,
+            ErrorCause::UnsupportedAttribute                => 401,
+            ErrorCause::MissingAttribute                    => 402,
+            ErrorCause::NasIdentificationMismatch           => 403,
+            ErrorCause::InvalidRequest                      => 404,
+            ErrorCause::UnsupportedService                  => 405,
+            ErrorCause::UnsupportedExtension                => 406,
+            ErrorCause::InvalidAttributeValue               => 407,
+            ErrorCause::AdministrativelyProhibited          => 501,
+            ErrorCause::RequestNotRoutable                  => 502,
+            ErrorCause::SessionContextNotFound              => 503,
+            ErrorCause::SessionContextNotRemovable          => 504,
+            ErrorCause::OtherProxyProcessingError           => 505,
+            ErrorCause::ResourcesUnavailable                => 506,
+            ErrorCause::RequestInitiated                    => 507,
+            ErrorCause::MultipleSessionSelectionUnsupported => 508,
+            ErrorCause::Other(value)                        => *value
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Represents the outcome of Disconnect-Request or CoA-Request
+pub enum DynAuthOutcome {
+    /// NAS has fulfilled the request (Disconnect-ACK or CoA-ACK)
+    Ack {
+        /// Attributes of the reply
+        attributes: Vec<RadiusAttribute>
+    },
+    /// NAS has refused the request (Disconnect-NAK or CoA-NAK)
+    Nak {
+        /// Error-Cause of the reply, if present
+        error_cause: Option<ErrorCause>,
+        /// Attributes of the reply
+        attributes:  Vec<RadiusAttribute>
+    }
+}
+
+impl DynAuthOutcome {
+    /// Interprets verified reply to Disconnect-Request or CoA-Request
+    pub fn from_reply(request: &RadiusPacket, reply: &RadiusPacket) -> Result<DynAuthOutcome, RadiusError> {
+        match (request.code(), reply.code()) {
+            (TypeCode::DisconnectRequest, TypeCode::DisconnectACK) |
+            (TypeCode::CoARequest,        TypeCode::CoAACK)        => Ok(DynAuthOutcome::Ack { attributes: reply.attributes().to_vec() }),
+            (TypeCode::DisconnectRequest, TypeCode::DisconnectNAK) |
+            (TypeCode::CoARequest,        TypeCode::CoANAK)        => Ok(DynAuthOutcome::Nak { error_cause: error_cause(reply)?, attributes: reply.attributes().to_vec() }),
+            (request_code, reply_code)                             => Err( RadiusError::ValidationError { error: format!("Unexpected reply to {:?}: {:?}", request_code, reply_code) } )
+        }
+    }
+
+    /// Returns true, if NAS has fulfilled the request
+    pub fn is_ack(&self) -> bool {
+        matches!(self, DynAuthOutcome::Ack { .. })
+    }
+
+    /// Returns Error-Cause of the reply, if present
+    pub fn error_cause(&self) -> Option<ErrorCause> {
+        match self {
+            DynAuthOutcome::Ack { .. }             => None,
+            DynAuthOutcome::Nak { error_cause, .. } => *error_cause
+        }
+    }
+}
+
+fn error_cause(reply: &RadiusPacket) -> Result<Option<ErrorCause>, RadiusError> {
+    match reply.attribute_by_id(ERROR_CAUSE_ID) {
+        Some(attr) => {
+            let value: &[u8; 4] = attr.value().try_into().map_err(|_| RadiusError::MalformedAttributeError { error: String::from("invalid Error-Cause length") })?;
+            Ok(Some(ErrorCause::from_u32(bytes_to_integer(value))))
+        },
+        None       => Ok(None)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::client::Client;
+    use crate::testing::{ test_dictionary, test_server };
+    use crate::tools::integer_to_bytes;
+
+    #[test]
+    fn test_from_reply() {
+        let client     = Client::with_dictionary(test_dictionary()).set_secret(String::from("secret"));
+        let server     = test_server("secret");
+
+        let session_identifiers = vec![client.create_attribute_by_name("Acct-Session-Id", b"session".to_vec()).unwrap()];
+        let mut request         = client.create_disconnect_packet(session_identifiers).unwrap();
+        let mut bytes           = request.to_bytes();
+
+        let reply = server.create_reply_packet(TypeCode::DisconnectACK, vec![], &mut bytes);
+        assert!(DynAuthOutcome::from_reply(&request, &reply).unwrap().is_ack());
+
+        let attributes = vec![server.create_attribute_by_id(ERROR_CAUSE_ID, integer_to_bytes(503)).unwrap()];
+        let reply      = server.create_reply_packet(TypeCode::DisconnectNAK, attributes, &mut bytes);
+        assert_eq!(Some(ErrorCause::SessionContextNotFound), DynAuthOutcome::from_reply(&request, &reply).unwrap().error_cause());
+
+        let reply = server.create_reply_packet(TypeCode::CoAACK, vec![], &mut bytes);
+        match DynAuthOutcome::from_reply(&request, &reply) {
+            Err(error) => assert_eq!(String::from("Verification failed for incoming Radius packet: Unexpected reply to DisconnectRequest: CoAACK"), error.to_string()),
+            _          => assert!(false)
+        }
+    }
+}
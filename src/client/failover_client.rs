@@ -0,0 +1,214 @@
+//! RADIUS Async UDP Client implementation, which fails over across an ordered list of RADIUS
+//! Servers, built on top of Tokio runtime
+
+
+use crate::client::AsyncClientTrait;
+use crate::client::client::Client;
+use crate::client::health::{ is_server_failure, HealthCheckPolicy, ServerStatus };
+use crate::client::home_server::HomeServer;
+use crate::protocol::error::RadiusError;
+use crate::protocol::radius_packet::RadiusPacket;
+
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Instant;
+
+
+#[derive(Debug)]
+/// Represents Async RADIUS Client, which sends requests to the first alive RADIUS Server of an
+/// ordered list of RADIUS Servers (home servers)
+///
+/// Every home server is configured with its own [Client](crate::client::client::Client), so it has
+/// its own address, ports, secret and retransmission policy. When a home server does not answer
+/// (request times out), it is marked dead and request fails over to the next alive home server.
+/// Dead home servers are probed with Status-Server (RFC 5997) on the port of the traffic they have
+/// failed to answer, and are brought back as soon as they answer a probe. If every home server is
+/// dead, request is tried on all of them in order
+///
+/// Probing is lazy: due probes are only sent when request is sent, so without traffic dead home
+/// servers stay dead. Callers with sporadic traffic could drive probes with [probe_due](FailoverClient::probe_due)
+///
+/// Request is expected to be built with the secret of the first home server. Home server with
+/// another secret is sent a copy of request with re-encrypted User-Password and regenerated
+/// Message-Authenticator, so request itself always stays keyed with the secret of the first home server
+pub struct FailoverClient {
+    home_servers: Vec<Arc<HomeServer>>
+}
+
+impl FailoverClient {
+    /// Initialises FailoverClient from the ordered list of configured Clients (one per home server)
+    pub fn with_clients(clients: Vec<Client>) -> FailoverClient {
+        FailoverClient::with_health_check_policy(clients, HealthCheckPolicy::default())
+    }
+
+    /// Initialises FailoverClient from the ordered list of configured Clients (one per home server)
+    /// with given health check policy
+    pub fn with_health_check_policy(clients: Vec<Client>, policy: HealthCheckPolicy) -> FailoverClient {
+        let home_servers = clients.into_iter()
+            .map(|client| Arc::new(HomeServer::new(client, policy.clone())))
+            .collect();
+
+        FailoverClient { home_servers }
+    }
+
+    /// Returns number of home servers
+    pub fn home_servers(&self) -> usize {
+        self.home_servers.len()
+    }
+
+    /// Returns Client of home server with given index
+    pub fn client(&self, index: usize) -> Option<&Client> {
+        self.home_servers.get(index).map(|home_server| home_server.client())
+    }
+
+    /// Returns status of home server with given index
+    pub fn server_status(&self, index: usize) -> Option<ServerStatus> {
+        self.home_servers.get(index).map(|home_server| home_server.status())
+    }
+
+    /// Sends packet to the first alive home server and returns verified reply as RadiusPacket
+    pub async fn send_and_receive_reply(&self, packet: &mut RadiusPacket) -> Result<RadiusPacket, RadiusError> {
+        let (index, reply) = self.transmit(packet).await?;
+        self.home_servers[index].client().initialise_packet_from_bytes(&reply)
+    }
+
+    /// Probes dead home servers, which are due to be probed, with Status-Server in the background
+    ///
+    /// Has to be called from within Tokio runtime, ie periodically from `tokio::time::interval`
+    pub fn probe_due(&self, now: Instant) {
+        for home_server in self.home_servers.iter() {
+            home_server.spawn_probe_if_due(now);
+        }
+    }
+
+    async fn transmit(&self, packet: &mut RadiusPacket) -> Result<(usize, Vec<u8>), RadiusError> {
+        self.probe_due(Instant::now());
+
+        let secret         = self.client(0).map(|client| client.secret().to_string()).ok_or_else(|| RadiusError::SocketInvalidConnectionError { error: String::from("There are no home servers configured") })?;
+        let mut last_error = None;
+
+        let mut candidates: Vec<usize> = (0..self.home_servers.len()).filter(|&index| self.home_servers[index].is_alive()).collect();
+        if candidates.is_empty() {
+            candidates = (0..self.home_servers.len()).collect();
+        }
+
+        for index in candidates {
+            match self.home_servers[index].send_keyed_with(packet, &secret).await {
+                Ok(reply)                               => return Ok((index, reply)),
+                Err(error) if is_server_failure(&error) => last_error = Some(error),
+                Err(error)                              => return Err(error)
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| RadiusError::SocketInvalidConnectionError { error: String::from("There are no home servers configured") }))
+    }
+}
+
+#[async_trait]
+impl AsyncClientTrait for FailoverClient {
+    async fn send_packet(&self, packet: &mut RadiusPacket) -> Result<(), RadiusError> {
+        self.transmit(packet).await.map(|_| ())
+    }
+
+    async fn send_and_receive_packet(&self, packet: &mut RadiusPacket) -> Result<Vec<u8>, RadiusError> {
+        self.transmit(packet).await.map(|(_, reply)| reply)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::retransmission::RetransmissionPolicy;
+    use crate::protocol::radius_packet::{ RadiusMsgType, TypeCode };
+    use crate::testing;
+    use crate::tools::{ decrypt_data, encrypt_data };
+
+    use std::time::Duration;
+    use tokio::net::UdpSocket;
+
+    const USER_PASSWORD_ID: u8 = 2;
+
+    fn test_client(port: u16, secret: &str) -> Client {
+        testing::test_client(port, secret).set_retransmission_policy(RetransmissionPolicy::fixed(Duration::from_millis(100), 2))
+    }
+
+    fn spawn_server(socket: UdpSocket, secret: &str) {
+        let password_secret = secret.to_string();
+
+        testing::spawn_server(socket, secret, move |server, packet, _| {
+            // Decrypted password is echoed back, so test could check it has been re-encrypted
+            let attributes = match packet.attribute_by_id(USER_PASSWORD_ID) {
+                Some(attr) => vec![server.create_attribute_by_name("Reply-Message", decrypt_data(attr.value(), packet.authenticator(), password_secret.as_bytes())).unwrap()],
+                None       => vec![]
+            };
+
+            let reply_code = if packet.code() == &TypeCode::AccountingRequest { TypeCode::AccountingResponse } else { TypeCode::AccessAccept };
+
+            Some((reply_code, attributes))
+        });
+    }
+
+    #[tokio::test]
+    async fn test_failover_and_probe() {
+        let primary_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let primary_port   = primary_socket.local_addr().unwrap().port();
+        let backup_socket  = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let backup_port    = backup_socket.local_addr().unwrap().port();
+        spawn_server(backup_socket, "backup-secret");
+
+        let policy = HealthCheckPolicy::default().set_probe_interval(Duration::from_millis(50));
+        let client = FailoverClient::with_health_check_policy(vec![test_client(primary_port, "secret"), test_client(backup_port, "backup-secret")], policy);
+
+        let mut packet = client.client(0).unwrap().create_auth_packet();
+        let password   = encrypt_data(b"password", packet.authenticator(), b"secret");
+        packet.set_attributes(vec![client.client(0).unwrap().create_attribute_by_id(USER_PASSWORD_ID, password).unwrap()]);
+
+        let reply = client.send_and_receive_reply(&mut packet).await.unwrap();
+        assert_eq!(b"password", reply.attribute_by_name("Reply-Message").unwrap().value());
+        // Backup has been sent rekeyed copy, so packet could be resent with the primary's secret
+        assert_eq!(b"password", &decrypt_data(packet.attribute_by_id(USER_PASSWORD_ID).unwrap().value(), packet.authenticator(), b"secret")[..]);
+        assert_eq!(Some(ServerStatus::Dead),  client.server_status(0));
+        assert_eq!(Some(ServerStatus::Alive), client.server_status(1));
+
+        // Primary comes back and answers Status-Server probe, which is sent with the next request
+        spawn_server(primary_socket, "secret");
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        let mut packet = client.client(0).unwrap().create_auth_packet();
+        client.send_and_receive_reply(&mut packet).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        assert_eq!(Some(ServerStatus::Alive), client.server_status(0));
+    }
+
+    #[tokio::test]
+    async fn test_probe_due_probes_accounting_port() {
+        // Primary's authentication port never answers, so only probe to accounting port succeeds
+        let silent_socket  = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let primary_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let primary_port   = primary_socket.local_addr().unwrap().port();
+        let backup_socket  = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let backup_port    = backup_socket.local_addr().unwrap().port();
+        spawn_server(backup_socket, "secret");
+
+        let primary = test_client(silent_socket.local_addr().unwrap().port(), "secret").set_port(RadiusMsgType::ACCT, primary_port);
+        let backup  = test_client(backup_port, "secret").set_port(RadiusMsgType::ACCT, backup_port);
+        let policy  = HealthCheckPolicy::default().set_probe_interval(Duration::from_millis(50));
+        let client  = FailoverClient::with_health_check_policy(vec![primary, backup], policy);
+
+        let mut packet = client.client(0).unwrap().create_acct_packet();
+        let reply      = client.send_and_receive_reply(&mut packet).await.unwrap();
+        assert_eq!(&TypeCode::AccountingResponse, reply.code());
+        assert_eq!(Some(ServerStatus::Dead), client.server_status(0));
+
+        // Primary comes back and answers Status-Server probe, which is driven without any request
+        spawn_server(primary_socket, "secret");
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        client.probe_due(Instant::now());
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        assert_eq!(Some(ServerStatus::Alive), client.server_status(0));
+    }
+}
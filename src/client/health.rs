@@ -0,0 +1,170 @@
+//! RADIUS Server health tracking, used to detect dead RADIUS Servers and to decide when they
+//! should be probed with Status-Server (RFC 5997)
+//!
+//! Tracking doesn't own any socket or timer, so it could be used with any RADIUS Client transport
+
+
+use crate::protocol::error::RadiusError;
+
+use std::time::{ Duration, Instant };
+
+
+#[derive(Debug, Clone, PartialEq)]
+/// Represents parameters of RADIUS Server health tracking
+pub struct HealthCheckPolicy {
+    max_failures:   u32,
+    probe_interval: Duration
+}
+
+impl Default for HealthCheckPolicy {
+    /// Returns policy, which marks server dead after 1 failed request and probes it every 30 seconds
+    fn default() -> HealthCheckPolicy {
+        HealthCheckPolicy {
+            max_failures:   1,
+            probe_interval: Duration::from_secs(30)
+        }
+    }
+}
+
+impl HealthCheckPolicy {
+    // === Builder for HealthCheckPolicy ===
+    /// Sets number of consecutive failed requests, after which server is marked dead
+    pub fn set_max_failures(mut self, max_failures: u32) -> HealthCheckPolicy {
+        self.max_failures = std::cmp::max(max_failures, 1);
+        self
+    }
+
+    /// Sets interval between Status-Server probes of dead server
+    pub fn set_probe_interval(mut self, probe_interval: Duration) -> HealthCheckPolicy {
+        self.probe_interval = probe_interval;
+        self
+    }
+    // ===================
+
+    /// Returns number of consecutive failed requests, after which server is marked dead
+    pub fn max_failures(&self) -> u32 {
+        self.max_failures
+    }
+
+    /// Returns interval between Status-Server probes of dead server
+    pub fn probe_interval(&self) -> Duration {
+        self.probe_interval
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Represents RADIUS Server status, as seen by RADIUS Client
+pub enum ServerStatus {
+    /// Server answers requests
+    Alive,
+    /// Server stopped answering requests; it only receives Status-Server probes until it answers
+    Dead
+}
+
+#[derive(Debug)]
+/// Represents health of single RADIUS Server
+pub struct ServerHealth {
+    policy:     HealthCheckPolicy,
+    status:     ServerStatus,
+    failures:   u32,
+    next_probe: Option<Instant>
+}
+
+impl ServerHealth {
+    /// Initialises health of alive server
+    pub fn new(policy: HealthCheckPolicy) -> ServerHealth {
+        ServerHealth {
+            policy,
+            status:     ServerStatus::Alive,
+            failures:   0,
+            next_probe: None
+        }
+    }
+
+    /// Returns health check policy
+    pub fn policy(&self) -> &HealthCheckPolicy {
+        &self.policy
+    }
+
+    /// Returns server status
+    pub fn status(&self) -> ServerStatus {
+        self.status
+    }
+
+    /// Returns true, if server is alive
+    pub fn is_alive(&self) -> bool {
+        self.status == ServerStatus::Alive
+    }
+
+    /// Returns number of consecutive failed requests
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Records that server has answered request or Status-Server probe, which brings it back alive
+    pub fn record_success(&mut self) {
+        self.status     = ServerStatus::Alive;
+        self.failures   = 0;
+        self.next_probe = None;
+    }
+
+    /// Records that server has not answered request; server is marked dead once the number of
+    /// consecutive failures reaches policy's limit
+    pub fn record_failure(&mut self, now: Instant) {
+        self.failures = self.failures.saturating_add(1);
+
+        if self.is_alive() && self.failures >= self.policy.max_failures {
+            self.status     = ServerStatus::Dead;
+            self.next_probe = Some(now + self.policy.probe_interval);
+        }
+    }
+
+    /// Returns true, if dead server is due to be probed with Status-Server
+    ///
+    /// Next probe is scheduled straight away, so the same probe is never reported twice
+    pub fn poll_probe(&mut self, now: Instant) -> bool {
+        match self.next_probe {
+            Some(next_probe) if !self.is_alive() && now >= next_probe => {
+                self.next_probe = Some(now + self.policy.probe_interval);
+                true
+            },
+            _                                                          => false
+        }
+    }
+}
+
+/// Returns true, if error means that RADIUS Server did not answer the request (at least not with
+/// a valid reply), so request should be sent to another RADIUS Server or retried later
+pub(crate) fn is_server_failure(error: &RadiusError) -> bool {
+    matches!(error, RadiusError::SocketConnectionError(_) | RadiusError::SocketInvalidConnectionError { .. } | RadiusError::ValidationError { .. } | RadiusError::InvalidReplyError { .. })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_marked_dead_and_probed() {
+        let policy     = HealthCheckPolicy::default().set_max_failures(2).set_probe_interval(Duration::from_secs(10));
+        let mut health = ServerHealth::new(policy);
+        let now        = Instant::now();
+
+        health.record_failure(now);
+        assert_eq!(ServerStatus::Alive, health.status());
+
+        health.record_failure(now);
+        assert_eq!(ServerStatus::Dead, health.status());
+
+        assert!(!health.poll_probe(now + Duration::from_secs(9)));
+        assert!(health.poll_probe(now + Duration::from_secs(10)));
+        assert!(!health.poll_probe(now + Duration::from_secs(11)));
+        assert!(health.poll_probe(now + Duration::from_secs(20)));
+
+        health.record_success();
+        assert!(health.is_alive());
+        assert_eq!(0, health.failures());
+        assert!(!health.poll_probe(now + Duration::from_secs(100)));
+    }
+}
@@ -0,0 +1,186 @@
+//! Shared state of single RADIUS Server (home server) for Async RADIUS Clients, which spread
+//! requests across several RADIUS Servers
+
+
+use crate::client::AsyncClientTrait;
+use crate::client::client::Client;
+use crate::client::health::{ is_server_failure, HealthCheckPolicy, ServerHealth, ServerStatus };
+use crate::client::tokio_client::TokioClient;
+use crate::protocol::error::RadiusError;
+use crate::protocol::radius_packet::{ RadiusPacket, TypeCode };
+use crate::tools::{ decrypt_data, encrypt_data, lock };
+#[cfg(feature = "metrics")]
+use crate::metrics;
+
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicU16, AtomicU64, AtomicUsize, Ordering };
+use std::time::Instant;
+
+
+const USER_PASSWORD_ID:         u8 = 2;
+const MESSAGE_AUTHENTICATOR_ID: u8 = 80;
+
+
+#[derive(Debug)]
+pub(crate) struct HomeServer {
+    client:      TokioClient,
+    health:      Mutex<ServerHealth>,
+    requests:    AtomicU64,
+    replies:     AtomicU64,
+    failures:    AtomicU64,
+    outstanding: AtomicUsize,
+    probe_port:  AtomicU16
+}
+
+/// Keeps request counted as outstanding until it is completed or its future is dropped
+struct OutstandingGuard<'a> {
+    outstanding: &'a AtomicUsize
+}
+
+impl Drop for OutstandingGuard<'_> {
+    fn drop(&mut self) {
+        self.outstanding.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl HomeServer {
+    pub(crate) fn new(client: Client, policy: HealthCheckPolicy) -> HomeServer {
+        let probe_port  = client.port(&TypeCode::StatusServer).unwrap_or_default();
+        let home_server = HomeServer {
+            client:      TokioClient::with_client(client),
+            health:      Mutex::new(ServerHealth::new(policy)),
+            requests:    AtomicU64::new(0),
+            replies:     AtomicU64::new(0),
+            failures:    AtomicU64::new(0),
+            outstanding: AtomicUsize::new(0),
+            probe_port:  AtomicU16::new(probe_port)
+        };
+        #[cfg(feature = "metrics")]
+        home_server.record_status();
+        home_server
+    }
+
+    pub(crate) fn client(&self) -> &Client {
+        self.client.client()
+    }
+
+    pub(crate) fn status(&self) -> ServerStatus {
+        lock(&self.health).status()
+    }
+
+    pub(crate) fn is_alive(&self) -> bool {
+        lock(&self.health).is_alive()
+    }
+
+    pub(crate) fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn replies(&self) -> u64 {
+        self.replies.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn outstanding(&self) -> usize {
+        self.outstanding.load(Ordering::Relaxed)
+    }
+
+    /// Sends request and records its outcome in home server's health and statistics
+    ///
+    /// Only errors, which mean that home server did not answer, count as failures
+    pub(crate) async fn send(&self, packet: &mut RadiusPacket) -> Result<Vec<u8>, RadiusError> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.outstanding.fetch_add(1, Ordering::Relaxed);
+        let _guard = OutstandingGuard { outstanding: &self.outstanding };
+
+        let reply = self.client.send_and_receive_packet(packet).await;
+        match &reply {
+            Ok(_)                                  => {
+                self.replies.fetch_add(1, Ordering::Relaxed);
+                lock(&self.health).record_success();
+                #[cfg(feature = "metrics")]
+                self.record_status();
+            },
+            Err(error) if is_server_failure(error) => {
+                // Server is probed on the port of the traffic, which it has failed to answer
+                if let Some(port) = self.client().port(packet.code()) {
+                    self.probe_port.store(port, Ordering::Relaxed);
+                }
+                self.failures.fetch_add(1, Ordering::Relaxed);
+                lock(&self.health).record_failure(Instant::now());
+                #[cfg(feature = "metrics")]
+                self.record_status();
+            },
+            Err(_)                                 => {}
+        }
+        reply
+    }
+
+    /// Sends request, which has been built with given secret; if home server has another secret,
+    /// rekeyed copy of request is sent instead, so request itself stays keyed with given secret
+    pub(crate) async fn send_keyed_with(&self, packet: &mut RadiusPacket, secret: &str) -> Result<Vec<u8>, RadiusError> {
+        if self.client().secret() == secret {
+            return self.send(packet).await
+        }
+        let mut rekeyed = rekey_request(packet, secret, self.client().secret())?;
+        self.send(&mut rekeyed).await
+    }
+
+    #[cfg(feature = "metrics")]
+    fn record_status(&self) {
+        let up = if self.is_alive() { 1.0 } else { 0.0 };
+        metrics::registry().set_gauge(&metrics::CLIENT_SERVER_UP, &[self.client().server()], up);
+    }
+
+    /// Probes home server with Status-Server in the background, if it is dead and probe is due
+    ///
+    /// Probe is sent to the port of the last request, which home server has failed to answer, so
+    /// home server, which has stopped answering accounting, is brought back only once its
+    /// accounting port answers (RFC 5997 Section 4)
+    pub(crate) fn spawn_probe_if_due(self: &Arc<Self>, now: Instant) {
+        if !lock(&self.health).poll_probe(now) {
+            return
+        }
+        let home_server = Arc::clone(self);
+        let probe_port  = self.probe_port.load(Ordering::Relaxed);
+
+        tokio::spawn(async move {
+            if probe(&home_server.client, probe_port).await.is_ok() {
+                lock(&home_server.health).record_success();
+                #[cfg(feature = "metrics")]
+                home_server.record_status();
+            }
+        });
+    }
+}
+
+/// Returns copy of request, which has been built with one secret, prepared for RADIUS Server with
+/// another secret: User-Password is re-encrypted and Message-Authenticator is regenerated, if they
+/// are present
+fn rekey_request(packet: &RadiusPacket, from_secret: &str, to_secret: &str) -> Result<RadiusPacket, RadiusError> {
+    let mut rekeyed = packet.clone();
+
+    if let Some(encrypted) = packet.attribute_by_id(USER_PASSWORD_ID) {
+        let password = decrypt_data(encrypted.value(), packet.authenticator(), from_secret.as_bytes());
+        let value    = encrypt_data(&password, packet.authenticator(), to_secret.as_bytes());
+
+        rekeyed.override_attribute_by_id(USER_PASSWORD_ID, value)?;
+    }
+    if rekeyed.attribute_by_id(MESSAGE_AUTHENTICATOR_ID).is_some() {
+        rekeyed.generate_message_authenticator(to_secret)?;
+    }
+    Ok(rekeyed)
+}
+
+/// Sends Status-Server probe, which carries Message-Authenticator, as required by RFC 5997
+async fn probe(client: &TokioClient, port: u16) -> Result<(), RadiusError> {
+    let mut packet = client.client().create_packet(TypeCode::StatusServer);
+
+    packet.set_attributes(vec![client.client().create_attribute_by_id(MESSAGE_AUTHENTICATOR_ID, [0; 16].to_vec())?]);
+    packet.generate_message_authenticator(client.client().secret())?;
+
+    client.transmit_to(&mut packet, port).await.map(|_| ())
+}
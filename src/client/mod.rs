@@ -12,7 +12,10 @@ use async_trait::async_trait;
 /// This trait is to be implemented by user, if they are planning to resolve AUTH, ACCT or CoA
 /// RADIUS requests for Async RADIUS Client
 pub trait AsyncClientTrait {
-    /// Responsible for sending packets off to RADIUS Server ignoring any response received
+    /// Responsible for sending packets off to RADIUS Server, without returning its response
+    ///
+    /// Packet counts as sent, once RADIUS Server has responded to it: response is awaited (packet
+    /// is retransmitted, if needed) and then ignored; error is returned, if there is no response
     async fn send_packet(&self, _packet: &mut RadiusPacket) -> Result<(), RadiusError> {
         todo!()
     }
@@ -25,7 +28,11 @@ pub trait AsyncClientTrait {
 /// This trait is to be implemented by user, if they are planning to resolve AUTH, ACCT or CoA
 /// RADIUS requests for Async RADIUS Client
 pub trait SyncClientTrait {
-    /// Responsible for sending packets off to RADIUS Server ignoring any response received
+    /// Responsible for sending packets off to RADIUS Server, without returning its response
+    ///
+    /// Packet counts as sent, once RADIUS Server has responded to it: response is waited for
+    /// (packet is retransmitted, if needed) and then ignored; error is returned, if there is no
+    /// response
     fn send_packet(&mut self, _packet: &mut RadiusPacket) -> Result<(), RadiusError> {
         todo!()
     }
@@ -35,4 +42,22 @@ pub trait SyncClientTrait {
     }
 }
 
+pub mod accounting;
+pub mod auth;
 pub mod client;
+pub mod dynamic_authorization;
+pub mod health;
+pub mod retransmission;
+pub mod spool;
+pub mod transaction;
+pub mod udp_client;
+#[cfg(feature = "tokio")]
+pub mod tokio_client;
+#[cfg(feature = "tokio")]
+pub mod multiplex_client;
+#[cfg(feature = "tokio")]
+pub mod failover_client;
+#[cfg(feature = "tokio")]
+pub mod pool;
+#[cfg(feature = "tokio")]
+mod home_server;
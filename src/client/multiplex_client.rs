@@ -0,0 +1,435 @@
+//! RADIUS Async UDP Client implementation, which multiplexes many in-flight requests over a small
+//! number of sockets, built on top of Tokio runtime
+
+
+use crate::client::AsyncClientTrait;
+use crate::client::client::Client;
+use crate::client::tokio_client::resolve_remote;
+use crate::client::transaction::{ ClientTransaction, TransactionOutput };
+use crate::protocol::error::RadiusError;
+use crate::protocol::radius_packet::RadiusPacket;
+use crate::tools::lock;
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::io::{ Error, ErrorKind };
+use std::net::SocketAddr;
+use std::sync::{ Arc, Mutex };
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{ timeout_at, Instant };
+
+
+const MAX_PACKET_SIZE:            usize = 4096;
+const MAX_OUTSTANDING_PER_SOCKET: usize = 256;
+const MAX_QUEUED_REPLIES:         usize = 8;
+
+
+#[derive(Debug)]
+struct PendingReply {
+    remote:  SocketAddr,
+    replies: mpsc::Sender<Vec<u8>>
+}
+
+#[derive(Debug, Default)]
+struct SourcePortState {
+    pending: HashMap<u8, PendingReply>,
+    next_id: u8,
+    closed:  bool
+}
+
+#[derive(Debug)]
+struct SourcePort {
+    socket:   Arc<UdpSocket>,
+    is_ipv6:  bool,
+    state:    Arc<Mutex<SourcePortState>>,
+    receiver: JoinHandle<()>
+}
+
+type SourcePorts = Arc<Mutex<Vec<Arc<SourcePort>>>>;
+
+/// Frees request ID on the source port once request is completed or its future is dropped
+struct IdGuard {
+    source_ports: SourcePorts,
+    port:         Arc<SourcePort>,
+    id:           u8
+}
+
+impl Drop for IdGuard {
+    fn drop(&mut self) {
+        let idle = {
+            let mut state = lock(&self.port.state);
+            state.pending.remove(&self.id);
+            state.pending.is_empty()
+        };
+        if idle {
+            release_idle_ports(&mut lock(&self.source_ports));
+        }
+    }
+}
+
+
+#[derive(Debug)]
+/// Represents Async RADIUS Client, which keeps up to 256 outstanding requests per source port
+///
+/// Request IDs are allocated by the client, so in-flight requests never collide: each request
+/// takes a free ID on an existing source port, and once all 256 IDs of every source port are in
+/// use, client opens an additional source port. Additional source port is closed again, once it
+/// has no outstanding requests and the other source ports are at most half full. Replies are
+/// matched back to requests by source port, ID and Response Authenticator (which is calculated
+/// over Request Authenticator)
+///
+/// Uses [Client's](crate::client::client::Client) server, ports, secret and retransmission policy;
+/// every request is driven by [ClientTransaction](crate::client::transaction::ClientTransaction)
+pub struct MultiplexClient {
+    base_client:  Arc<Client>,
+    source_ports: SourcePorts
+}
+
+impl MultiplexClient {
+    /// Initialises MultiplexClient from configured Client
+    ///
+    /// Source ports are opened lazily, when the first request is sent and whenever ID space of all
+    /// open source ports is exhausted
+    pub fn with_client(base_client: Client) -> MultiplexClient {
+        MultiplexClient {
+            base_client:  Arc::new(base_client),
+            source_ports: Arc::new(Mutex::new(Vec::new()))
+        }
+    }
+
+    /// Returns underlying Client
+    pub fn client(&self) -> &Client {
+        &self.base_client
+    }
+
+    /// Returns number of source ports, which are currently open
+    pub fn source_ports(&self) -> usize {
+        lock(&self.source_ports).len()
+    }
+
+    /// Returns number of requests, which are waiting for reply
+    pub fn outstanding_requests(&self) -> usize {
+        lock(&self.source_ports).iter().map(|port| lock(&port.state).pending.len()).sum()
+    }
+
+    /// Sends packet to RADIUS Server and returns verified reply as RadiusPacket
+    ///
+    /// Packet's ID is overridden with the ID allocated by the client. Future is cancellation safe:
+    /// if it is dropped, allocated ID is released straight away
+    pub async fn send_and_receive_reply(&self, packet: &mut RadiusPacket) -> Result<RadiusPacket, RadiusError> {
+        let reply = self.transmit(packet).await?;
+        self.base_client.initialise_packet_from_bytes(&reply)
+    }
+
+    async fn transmit(&self, packet: &mut RadiusPacket) -> Result<Vec<u8>, RadiusError> {
+        let remote_port = self.base_client.port(packet.code()).ok_or_else(|| RadiusError::MalformedPacketError { error: String::from("There is no port match for packet code") })?;
+        let remote      = resolve_remote(self.base_client.server(), remote_port).await?;
+
+        // Message-Authenticator and Request Authenticator of Accounting-Request depend on ID, so
+        // they are regenerated (and transaction is initialised) after the ID has been allocated
+        let (mut guard, mut replies) = self.register(packet, remote)?;
+        if packet.message_authenticator().is_ok() {
+            packet.generate_message_authenticator(self.base_client.secret())?;
+        }
+        let mut transaction          = ClientTransaction::new(&self.base_client, packet.clone())?;
+        let reply                    = self.run_transaction(&mut transaction, &mut guard, &mut replies, remote).await;
+
+        *packet = transaction.into_packet();
+        reply
+    }
+
+    async fn run_transaction(&self, transaction: &mut ClientTransaction, guard: &mut IdGuard, replies: &mut mpsc::Receiver<Vec<u8>>, remote: SocketAddr) -> Result<Vec<u8>, RadiusError> {
+        let mut last_error = None;
+        let mut output     = transaction.start(Instant::now().into_std());
+
+        loop {
+            let deadline = match output {
+                TransactionOutput::Transmit { datagram, deadline, .. } => {
+                    guard.port.socket.send_to(&datagram, remote).await?;
+                    deadline
+                },
+                TransactionOutput::Wait { deadline }             => deadline,
+                TransactionOutput::Discarded { error, deadline } => {
+                    last_error = Some(error);
+                    deadline
+                },
+                TransactionOutput::Reply(mut reply)              => return Ok(reply.to_bytes()),
+                TransactionOutput::TimedOut                      => return Err(last_error.unwrap_or_else(|| RadiusError::SocketConnectionError(Error::new(ErrorKind::TimedOut, "RADIUS Server did not reply in time"))))
+            };
+
+            output = match timeout_at(Instant::from_std(deadline), replies.recv()).await {
+                Ok(Some(reply)) => transaction.handle_datagram(&self.base_client, &reply, Instant::now().into_std()),
+                Ok(None)        => return Err( RadiusError::SocketConnectionError(Error::new(ErrorKind::BrokenPipe, "Source port has been closed")) ),
+                Err(_)          => {
+                    // Accounting-Request with updated Acct-Delay-Time needs new ID, which is
+                    // moved over from the old one, so the request keeps its reply channel
+                    let port = Arc::clone(&guard.port);
+                    let old  = guard.id;
+                    let new  = &mut guard.id;
+                    transaction.handle_timeout_with_id(Instant::now().into_std(), || {
+                        *new = reassign_id(&port, old)?;
+                        Some(*new)
+                    })
+                }
+            };
+        }
+    }
+
+    fn register(&self, packet: &mut RadiusPacket, remote: SocketAddr) -> Result<(IdGuard, mpsc::Receiver<Vec<u8>>), RadiusError> {
+        let mut source_ports = lock(&self.source_ports);
+
+        let (sender, receiver) = mpsc::channel(MAX_QUEUED_REPLIES);
+        let mut pending        = PendingReply { remote, replies: sender };
+
+        for port in source_ports.iter().filter(|port| port.is_ipv6 == remote.is_ipv6()) {
+            match allocate_id(port, pending) {
+                Ok(id)      => {
+                    packet.override_id(id);
+                    return Ok((IdGuard { source_ports: Arc::clone(&self.source_ports), port: Arc::clone(port), id }, receiver))
+                },
+                Err(unused) => pending = unused
+            }
+        }
+
+        let port = Arc::new(self.open_source_port(remote)?);
+        let id   = allocate_id(&port, pending).map_err(|_| RadiusError::SocketInvalidConnectionError { error: String::from("Could not allocate packet ID on new source port") })?;
+
+        source_ports.push(Arc::clone(&port));
+        packet.override_id(id);
+        Ok((IdGuard { source_ports: Arc::clone(&self.source_ports), port, id }, receiver))
+    }
+
+    fn open_source_port(&self, remote: SocketAddr) -> Result<SourcePort, RadiusError> {
+        let local_bind = if remote.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" };
+        let socket     = std::net::UdpSocket::bind(local_bind)?;
+        socket.set_nonblocking(true)?;
+
+        let socket   = Arc::new(UdpSocket::from_std(socket)?);
+        let state    = Arc::new(Mutex::new(SourcePortState::default()));
+        let receiver = tokio::spawn(receive_replies(Arc::clone(&socket), Arc::clone(&state)));
+
+        Ok(SourcePort { socket, is_ipv6: remote.is_ipv6(), state, receiver })
+    }
+}
+
+impl Drop for MultiplexClient {
+    fn drop(&mut self) {
+        for port in lock(&self.source_ports).iter() {
+            port.receiver.abort();
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncClientTrait for MultiplexClient {
+    async fn send_packet(&self, packet: &mut RadiusPacket) -> Result<(), RadiusError> {
+        self.transmit(packet).await.map(|_| ())
+    }
+
+    async fn send_and_receive_packet(&self, packet: &mut RadiusPacket) -> Result<Vec<u8>, RadiusError> {
+        self.transmit(packet).await
+    }
+}
+
+/// Allocates free ID on the source port and registers pending reply under it; pending reply is
+/// handed back, if there is no free ID
+fn allocate_id(port: &SourcePort, pending: PendingReply) -> Result<u8, PendingReply> {
+    let mut state = lock(&port.state);
+
+    match next_free_id(&mut state) {
+        Some(id) => {
+            state.pending.insert(id, pending);
+            Ok(id)
+        },
+        None     => Err(pending)
+    }
+}
+
+/// Moves pending reply from `old` ID to a newly allocated free ID on the same source port
+fn reassign_id(port: &SourcePort, old: u8) -> Option<u8> {
+    let mut state = lock(&port.state);
+
+    let id      = next_free_id(&mut state)?;
+    let pending = state.pending.remove(&old)?;
+    state.pending.insert(id, pending);
+    Some(id)
+}
+
+fn next_free_id(state: &mut SourcePortState) -> Option<u8> {
+    if state.closed || state.pending.len() >= MAX_OUTSTANDING_PER_SOCKET {
+        return None
+    }
+
+    // IDs are handed out round-robin, so recently completed ID is not reused straight away and
+    // late reply to it cannot be mistaken for reply to a new request
+    let start = state.next_id;
+    let id    = (0..=255u8).map(|offset| start.wrapping_add(offset)).find(|id| !state.pending.contains_key(id))?;
+
+    state.next_id = id.wrapping_add(1);
+    Some(id)
+}
+
+/// Closes additional source ports, which have no outstanding requests, while the other source
+/// ports of the same address family are at most half full, so client doesn't keep opening and
+/// closing source port around 256 outstanding requests; first source port of every address
+/// family is kept open
+fn release_idle_ports(source_ports: &mut Vec<Arc<SourcePort>>) {
+    let mut index = source_ports.len();
+
+    while index > 0 {
+        index -= 1;
+        let is_ipv6 = source_ports[index].is_ipv6;
+
+        if !source_ports[..index].iter().any(|port| port.is_ipv6 == is_ipv6) || !lock(&source_ports[index].state).pending.is_empty() {
+            continue;
+        }
+        let others: Vec<usize> = source_ports.iter().enumerate()
+            .filter(|(other, port)| *other != index && port.is_ipv6 == is_ipv6)
+            .map(|(_, port)| lock(&port.state).pending.len())
+            .collect();
+        if others.iter().sum::<usize>() * 2 > others.len() * MAX_OUTSTANDING_PER_SOCKET {
+            continue;
+        }
+
+        let port = source_ports.remove(index);
+        lock(&port.state).closed = true;
+        port.receiver.abort();
+    }
+}
+
+async fn receive_replies(socket: Arc<UdpSocket>, state: Arc<Mutex<SourcePortState>>) {
+    let mut buffer = vec![0u8; MAX_PACKET_SIZE];
+
+    loop {
+        let (amount, source) = match socket.recv_from(&mut buffer).await {
+            Ok(received) => received,
+            Err(_)       => {
+                // Outstanding requests are failed by dropping their senders; new requests would
+                // be allocated on other source ports
+                let mut state = lock(&state);
+                state.closed  = true;
+                state.pending.clear();
+                return
+            }
+        };
+        if amount < 20 {
+            continue;
+        }
+
+        // Reply is verified by request's transaction; should the request fall behind, excess
+        // datagrams are dropped the same way as they would be by a full socket buffer
+        let reply = &buffer[..amount];
+        let state = lock(&state);
+        if let Some(pending) = state.pending.get(&reply[1]).filter(|pending| pending.remote == source) {
+            let _ = pending.replies.try_send(reply.to_vec());
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::retransmission::RetransmissionPolicy;
+    use crate::protocol::radius_packet::{ RadiusMsgType, TypeCode };
+    use crate::server::client_table::ClientEntry;
+    use crate::testing::{ spawn_server, test_client };
+
+    use futures::future::join_all;
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_concurrent_requests_open_additional_source_port() {
+        let server_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_port   = server_socket.local_addr().unwrap().port();
+
+        let client_ports = Arc::new(Mutex::new(HashSet::new()));
+        let seen_ports   = Arc::clone(&client_ports);
+
+        spawn_server(server_socket, "secret", move |_, _, source| {
+            lock(&seen_ports).insert(source.port());
+            Some((TypeCode::AccessAccept, vec![]))
+        });
+
+        let client = MultiplexClient::with_client(test_client(server_port, "secret").set_retries(3).set_timeout(2));
+
+        let mut packets: Vec<RadiusPacket> = (0..300).map(|_| client.client().create_auth_packet()).collect();
+        let replies = join_all(packets.iter_mut().map(|packet| client.send_and_receive_reply(packet))).await;
+
+        assert_eq!(300, replies.len());
+        for (packet, reply) in packets.iter().zip(replies) {
+            assert_eq!(packet.id(), reply.unwrap().id());
+        }
+        assert_eq!(2, lock(&client_ports).len());
+        // Additional source port is closed, once its requests are completed
+        assert_eq!(1, client.source_ports());
+        assert_eq!(0, client.outstanding_requests());
+    }
+
+    #[tokio::test]
+    async fn test_pap_request_passes_server_verification() {
+        let server_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_port   = server_socket.local_addr().unwrap().port();
+
+        // Request is only answered, if it passes the same checks built-in servers do, including
+        // Message-Authenticator
+        let nas = ClientEntry::new("127.0.0.1", String::from("secret")).unwrap();
+        spawn_server(server_socket, "secret", move |server, packet, _| {
+            server.process_request(&nas, &packet.clone().to_bytes()).ok().map(|_| (TypeCode::AccessAccept, vec![]))
+        });
+
+        let client = MultiplexClient::with_client(test_client(server_port, "secret")
+            .set_retransmission_policy(RetransmissionPolicy::fixed(Duration::from_millis(500), 2)));
+
+        let mut packet = client.client().create_pap_packet("testing", "password").unwrap();
+        let reply      = client.send_and_receive_reply(&mut packet).await.unwrap();
+
+        assert_eq!(&TypeCode::AccessAccept, reply.code());
+        assert_eq!(packet.id(), reply.id());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_request_releases_id() {
+        let server_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_port   = server_socket.local_addr().unwrap().port();
+
+        let client = MultiplexClient::with_client(test_client(server_port, "secret"));
+
+        let mut packet = client.client().create_auth_packet();
+        let result     = tokio::time::timeout(Duration::from_millis(50), client.send_and_receive_reply(&mut packet)).await;
+
+        assert!(result.is_err());
+        assert_eq!(1, client.source_ports());
+        assert_eq!(0, client.outstanding_requests());
+    }
+
+    #[tokio::test]
+    async fn test_accounting_retransmit_gets_new_id() {
+        let server_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_port   = server_socket.local_addr().unwrap().port();
+
+        // First transmission is lost, so only retransmit is answered
+        let request_ids = Arc::new(Mutex::new(Vec::new()));
+        let seen_ids    = Arc::clone(&request_ids);
+        spawn_server(server_socket, "secret", move |_, packet, _| {
+            let mut seen_ids = lock(&seen_ids);
+            seen_ids.push(packet.id());
+            (seen_ids.len() > 1).then(|| (TypeCode::AccountingResponse, vec![]))
+        });
+
+        let client = MultiplexClient::with_client(test_client(0, "secret")
+            .set_port(RadiusMsgType::ACCT, server_port)
+            .set_retransmission_policy(RetransmissionPolicy::fixed(Duration::from_millis(1500), 3)));
+
+        let mut packet = client.client().create_acct_packet();
+        let reply      = client.send_and_receive_reply(&mut packet).await.unwrap();
+        let first_id   = lock(&request_ids)[0];
+
+        assert_ne!(first_id, packet.id());
+        assert_eq!(packet.id(), reply.id());
+        assert_eq!(0, client.outstanding_requests());
+    }
+}
@@ -0,0 +1,390 @@
+//! RADIUS Async UDP Client implementation, which spreads requests across several RADIUS Servers,
+//! built on top of Tokio runtime
+
+
+use crate::client::AsyncClientTrait;
+use crate::client::client::Client;
+use crate::client::health::{ is_server_failure, HealthCheckPolicy, ServerStatus };
+use crate::client::home_server::HomeServer;
+use crate::protocol::error::RadiusError;
+use crate::protocol::radius_packet::{ RadiusPacket, TypeCode };
+
+use async_trait::async_trait;
+use md5::{ Digest, Md5 };
+use rand::{ thread_rng, Rng };
+use std::convert::TryInto;
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::time::Instant;
+
+
+#[derive(Debug, Clone, PartialEq)]
+/// Represents the way ClientPool picks a member for every request
+pub enum PoolStrategy {
+    /// Members are picked in turn
+    RoundRobin,
+    /// Members are picked at random, in proportion to their weights
+    WeightedRandom,
+    /// Member with the least number of outstanding requests is picked
+    LeastOutstanding,
+    /// Member is picked by weighted rendezvous hashing of the value of attribute with given ID (ie
+    /// User-Name or Acct-Session-Id), so all requests of one session go to the same member for as
+    /// long as it is alive. Requests without the attribute are spread round-robin
+    ConsistentHash {
+        /// ID of the attribute, which value is used as hashing key
+        attribute_id: u8
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Represents statistics of single ClientPool member
+pub struct MemberStatistics {
+    status:      ServerStatus,
+    weight:      u32,
+    requests:    u64,
+    replies:     u64,
+    failures:    u64,
+    outstanding: usize
+}
+
+impl MemberStatistics {
+    /// Returns member's status
+    pub fn status(&self) -> ServerStatus {
+        self.status
+    }
+
+    /// Returns member's weight
+    pub fn weight(&self) -> u32 {
+        self.weight
+    }
+
+    /// Returns number of requests sent to the member
+    pub fn requests(&self) -> u64 {
+        self.requests
+    }
+
+    /// Returns number of requests, which member has replied to
+    pub fn replies(&self) -> u64 {
+        self.replies
+    }
+
+    /// Returns number of requests, which member has not replied to
+    pub fn failures(&self) -> u64 {
+        self.failures
+    }
+
+    /// Returns number of requests, which are waiting for member's reply
+    pub fn outstanding(&self) -> usize {
+        self.outstanding
+    }
+}
+
+
+#[derive(Debug)]
+struct Member {
+    home_server: Arc<HomeServer>,
+    weight:      u32
+}
+
+#[derive(Debug)]
+/// Represents Async RADIUS Client, which spreads requests across a pool of RADIUS Servers
+///
+/// Every member is configured with its own [Client](crate::client::client::Client) and weight, and
+/// keeps the same health state as [FailoverClient's](crate::client::failover_client::FailoverClient)
+/// home servers: member, which does not answer, is marked dead, is skipped by every strategy and is
+/// probed with Status-Server until it answers. Request, which has not been answered, fails over to
+/// another member picked by the same strategy
+///
+/// Probing is lazy: due probes are only sent when request is sent, so without traffic dead members
+/// stay dead. Callers with sporadic traffic could drive probes with [probe_due](ClientPool::probe_due)
+///
+/// Request is expected to be built with the secret of the first member. Member with another secret
+/// is sent a copy of request with re-encrypted User-Password and regenerated Message-Authenticator,
+/// so request itself always stays keyed with the secret of the first member
+pub struct ClientPool {
+    strategy:    PoolStrategy,
+    policy:      HealthCheckPolicy,
+    members:     Vec<Member>,
+    round_robin: AtomicUsize
+}
+
+impl ClientPool {
+    /// Initialises empty ClientPool with given strategy
+    pub fn with_strategy(strategy: PoolStrategy) -> ClientPool {
+        ClientPool::with_health_check_policy(strategy, HealthCheckPolicy::default())
+    }
+
+    /// Initialises empty ClientPool with given strategy and health check policy
+    pub fn with_health_check_policy(strategy: PoolStrategy, policy: HealthCheckPolicy) -> ClientPool {
+        ClientPool {
+            strategy,
+            policy,
+            members:     Vec::new(),
+            round_robin: AtomicUsize::new(0)
+        }
+    }
+
+    /// Adds configured Client as pool member with given weight
+    ///
+    /// Weight is only used by WeightedRandom and ConsistentHash strategies; member with weight 0
+    /// is picked by them only if no other member is available
+    pub fn add_client(mut self, client: Client, weight: u32) -> ClientPool {
+        self.members.push(Member {
+            home_server: Arc::new(HomeServer::new(client, self.policy.clone())),
+            weight
+        });
+        self
+    }
+
+    /// Returns pool strategy
+    pub fn strategy(&self) -> &PoolStrategy {
+        &self.strategy
+    }
+
+    /// Returns number of pool members
+    pub fn members(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Returns Client of member with given index
+    pub fn client(&self, index: usize) -> Option<&Client> {
+        self.members.get(index).map(|member| member.home_server.client())
+    }
+
+    /// Returns statistics of member with given index
+    pub fn statistics(&self, index: usize) -> Option<MemberStatistics> {
+        self.members.get(index).map(|member| MemberStatistics {
+            status:      member.home_server.status(),
+            weight:      member.weight,
+            requests:    member.home_server.requests(),
+            replies:     member.home_server.replies(),
+            failures:    member.home_server.failures(),
+            outstanding: member.home_server.outstanding()
+        })
+    }
+
+    /// Sends packet to pool member picked by pool strategy and returns verified reply as RadiusPacket
+    pub async fn send_and_receive_reply(&self, packet: &mut RadiusPacket) -> Result<RadiusPacket, RadiusError> {
+        let (index, reply) = self.transmit(packet).await?;
+        self.members[index].home_server.client().initialise_packet_from_bytes(&reply)
+    }
+
+    /// Probes dead members, which are due to be probed, with Status-Server in the background
+    ///
+    /// Has to be called from within Tokio runtime, ie periodically from `tokio::time::interval`
+    pub fn probe_due(&self, now: Instant) {
+        for member in self.members.iter() {
+            member.home_server.spawn_probe_if_due(now);
+        }
+    }
+
+    async fn transmit(&self, packet: &mut RadiusPacket) -> Result<(usize, Vec<u8>), RadiusError> {
+        self.probe_due(Instant::now());
+
+        let secret         = self.client(0).map(|client| client.secret().to_string()).ok_or_else(|| RadiusError::SocketInvalidConnectionError { error: String::from("There are no pool members configured") })?;
+        let mut last_error = None;
+        let mut tried      = vec![false; self.members.len()];
+
+        while let Some(index) = self.select(packet, &tried) {
+            tried[index] = true;
+
+            match self.members[index].home_server.send_keyed_with(packet, &secret).await {
+                Ok(reply)                               => return Ok((index, reply)),
+                Err(error) if is_server_failure(&error) => last_error = Some(error),
+                Err(error)                              => return Err(error)
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| RadiusError::SocketInvalidConnectionError { error: String::from("There are no pool members configured") }))
+    }
+
+    /// Picks member, which has not been tried yet; dead members are picked only if there is no
+    /// alive member left
+    fn select(&self, packet: &RadiusPacket, tried: &[bool]) -> Option<usize> {
+        let untried: Vec<usize> = (0..self.members.len()).filter(|&index| !tried[index]).collect();
+        let alive:   Vec<usize> = untried.iter().copied().filter(|&index| self.members[index].home_server.is_alive()).collect();
+        let eligible            = if alive.is_empty() { untried } else { alive };
+
+        if eligible.is_empty() {
+            return None
+        }
+
+        match &self.strategy {
+            PoolStrategy::RoundRobin                     => Some(self.select_round_robin(&eligible)),
+            PoolStrategy::WeightedRandom                 => Some(self.select_weighted_random(&eligible)),
+            PoolStrategy::LeastOutstanding               => eligible.into_iter().min_by_key(|&index| self.members[index].home_server.outstanding()),
+            PoolStrategy::ConsistentHash { attribute_id } => match packet.attribute_by_id(*attribute_id) {
+                Some(attr) => self.select_consistent_hash(&eligible, attr.value()),
+                None       => Some(self.select_round_robin(&eligible))
+            }
+        }
+    }
+
+    fn select_round_robin(&self, eligible: &[usize]) -> usize {
+        eligible[self.round_robin.fetch_add(1, Ordering::Relaxed) % eligible.len()]
+    }
+
+    fn select_weighted_random(&self, eligible: &[usize]) -> usize {
+        let total_weight: u64 = eligible.iter().map(|&index| self.members[index].weight as u64).sum();
+        if total_weight == 0 {
+            return self.select_round_robin(eligible)
+        }
+
+        let mut point = thread_rng().gen_range(0..total_weight);
+        for &index in eligible {
+            let weight = self.members[index].weight as u64;
+            if point < weight {
+                return index
+            }
+            point -= weight;
+        }
+        eligible[eligible.len() - 1]
+    }
+
+    fn select_consistent_hash(&self, eligible: &[usize], key: &[u8]) -> Option<usize> {
+        // Weighted rendezvous hashing: every member scores `weight / -ln(hash)` for the key, so
+        // member failure only moves the keys of that member
+        eligible.iter().copied()
+            .map(|index| (index, self.rendezvous_score(index, key)))
+            .max_by(|(_, left), (_, right)| left.partial_cmp(right).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(index, _)| index)
+    }
+
+    fn rendezvous_score(&self, index: usize, key: &[u8]) -> f64 {
+        let member     = &self.members[index];
+        let client     = member.home_server.client();
+        let mut hasher = Md5::new();
+
+        // MD5 (unlike std's hashers) is stable across Rust releases and platforms, so every
+        // process maps the same key to the same member
+        hasher.update((key.len() as u32).to_be_bytes());
+        hasher.update(key);
+        hasher.update(client.server().as_bytes());
+        hasher.update(client.port(&TypeCode::AccessRequest).unwrap_or(0).to_be_bytes());
+        hasher.update(client.port(&TypeCode::AccountingRequest).unwrap_or(0).to_be_bytes());
+
+        // Maps hash into (0, 1) interval
+        let digest = u64::from_be_bytes(hasher.finalize()[..8].try_into().expect("MD5 digest has 16 bytes"));
+        let hash   = ((digest >> 11) as f64 + 0.5) / (1u64 << 53) as f64;
+        member.weight as f64 / -hash.ln()
+    }
+}
+
+#[async_trait]
+impl AsyncClientTrait for ClientPool {
+    async fn send_packet(&self, packet: &mut RadiusPacket) -> Result<(), RadiusError> {
+        self.transmit(packet).await.map(|_| ())
+    }
+
+    async fn send_and_receive_packet(&self, packet: &mut RadiusPacket) -> Result<Vec<u8>, RadiusError> {
+        self.transmit(packet).await.map(|(_, reply)| reply)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::retransmission::RetransmissionPolicy;
+    use crate::testing;
+
+    use std::time::Duration;
+    use tokio::net::UdpSocket;
+
+    const USER_NAME_ID: u8 = 1;
+
+    fn test_client(port: u16) -> Client {
+        testing::test_client(port, "secret").set_retransmission_policy(RetransmissionPolicy::fixed(Duration::from_millis(100), 1))
+    }
+
+    async fn spawn_server() -> u16 {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port   = socket.local_addr().unwrap().port();
+
+        testing::spawn_server(socket, "secret", |_, _, _| Some((TypeCode::AccessAccept, vec![])));
+        port
+    }
+
+    fn user_packet(pool: &ClientPool, user_name: &str) -> RadiusPacket {
+        let mut packet = pool.client(0).unwrap().create_auth_packet();
+        packet.set_attributes(vec![pool.client(0).unwrap().create_attribute_by_id(USER_NAME_ID, user_name.as_bytes().to_vec()).unwrap()]);
+        packet
+    }
+
+    #[tokio::test]
+    async fn test_round_robin() {
+        let pool = ClientPool::with_strategy(PoolStrategy::RoundRobin)
+            .add_client(test_client(spawn_server().await), 1)
+            .add_client(test_client(spawn_server().await), 1);
+
+        for _ in 0..4 {
+            pool.send_and_receive_reply(&mut pool.client(0).unwrap().create_auth_packet()).await.unwrap();
+        }
+
+        for index in 0..2 {
+            let statistics = pool.statistics(index).unwrap();
+            assert_eq!(2, statistics.requests());
+            assert_eq!(2, statistics.replies());
+            assert_eq!(0, statistics.outstanding());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_consistent_hash_is_sticky_and_fails_over() {
+        // Second member never answers
+        let silent_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let pool          = ClientPool::with_strategy(PoolStrategy::ConsistentHash { attribute_id: USER_NAME_ID })
+            .add_client(test_client(spawn_server().await), 1)
+            .add_client(test_client(silent_socket.local_addr().unwrap().port()), 1);
+
+        for _ in 0..3 {
+            pool.send_and_receive_reply(&mut user_packet(&pool, "testing")).await.unwrap();
+        }
+
+        let (alive, silent) = (pool.statistics(0).unwrap(), pool.statistics(1).unwrap());
+        assert_eq!(3, alive.requests());
+        assert_eq!(3, alive.replies());
+        assert!(silent.requests() <= 1);
+        assert_eq!(silent.requests(), silent.failures());
+    }
+
+    #[test]
+    fn test_consistent_hash_mapping_is_stable() {
+        let pool = ClientPool::with_strategy(PoolStrategy::ConsistentHash { attribute_id: USER_NAME_ID })
+            .add_client(test_client(1812), 1)
+            .add_client(test_client(1813), 1)
+            .add_client(test_client(1814), 2);
+
+        // Mapping must not change between Rust releases, platforms or crate versions, otherwise
+        // sessions would move between servers after upgrade
+        let selected: Vec<usize> = ["alice", "bob", "carol", "dave", "eve", "frank", "grace", "heidi"].iter()
+            .map(|user_name| pool.select(&user_packet(&pool, user_name), &[false, false, false]).unwrap())
+            .collect();
+        assert_eq!(vec![0, 0, 2, 1, 2, 0, 2, 2], selected);
+    }
+
+    #[test]
+    fn test_select() {
+        let pool   = ClientPool::with_strategy(PoolStrategy::ConsistentHash { attribute_id: USER_NAME_ID })
+            .add_client(test_client(1812), 1)
+            .add_client(test_client(1813), 1)
+            .add_client(test_client(1814), 0);
+        let packet = user_packet(&pool, "testing");
+
+        let selected = pool.select(&packet, &[false, false, false]).unwrap();
+        assert_ne!(2, selected);
+        assert_eq!(Some(selected), pool.select(&packet, &[false, false, false]));
+
+        // Once selected member has been tried, the other one with non-zero weight is next
+        assert_eq!(Some(1 - selected), pool.select(&packet, &[selected == 0, selected == 1, false]));
+        assert_eq!(Some(2), pool.select(&packet, &[true, true, false]));
+        assert_eq!(None,    pool.select(&packet, &[true, true, true]));
+
+        let pool = ClientPool::with_strategy(PoolStrategy::WeightedRandom)
+            .add_client(test_client(1812), 0)
+            .add_client(test_client(1813), 5);
+        for _ in 0..10 {
+            assert_eq!(Some(1), pool.select(&packet, &[false, false]));
+        }
+    }
+}
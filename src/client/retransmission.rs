@@ -0,0 +1,177 @@
+//! RADIUS Client retransmission policy, as defined in RFC 5080 Section 2.2.1
+
+
+use rand::{ thread_rng, Rng };
+use std::time::Duration;
+
+
+const RAND_FACTOR: f64 = 0.1;
+
+
+#[derive(Debug, Clone, PartialEq)]
+/// Represents retransmission parameters of RADIUS Client
+///
+/// Retransmission timeout (RT) is calculated as defined in RFC 5080 Section 2.2.1:
+/// * first transmission: `RT = IRT + RAND*IRT`
+/// * every retransmit:   `RT = 2*RTprev + RAND*RTprev`
+/// * if `RT > MRT`:      `RT = MRT + RAND*MRT`
+///
+/// where RAND is uniformly distributed between -0.1 and 0.1. Exchange fails once request has been
+/// transmitted MRC times or MRD has elapsed since first transmission. Zero MRT, MRC or MRD means
+/// there is no limit on that parameter
+///
+/// Request is retransmitted with the same ID and authenticator, except for Accounting-Request:
+/// once its Acct-Delay-Time is updated, it is a new request (RFC 2866 Section 5.2), so it gets a
+/// new ID and Request Authenticator
+pub struct RetransmissionPolicy {
+    initial_rt: Duration,
+    max_rt:     Duration,
+    max_rc:     u32,
+    max_rd:     Duration
+}
+
+impl Default for RetransmissionPolicy {
+    /// Returns policy with values recommended by RFC 5080: IRT = 2s, MRT = 16s, MRC = 5, MRD = 30s
+    fn default() -> RetransmissionPolicy {
+        RetransmissionPolicy {
+            initial_rt: Duration::from_secs(2),
+            max_rt:     Duration::from_secs(16),
+            max_rc:     5,
+            max_rd:     Duration::from_secs(30)
+        }
+    }
+}
+
+impl RetransmissionPolicy {
+    /// Initialises policy, which retransmits request every `timeout` (no backoff), up to `attempts`
+    /// transmissions in total
+    pub fn fixed(timeout: Duration, attempts: u32) -> RetransmissionPolicy {
+        RetransmissionPolicy {
+            initial_rt: timeout,
+            max_rt:     timeout,
+            max_rc:     attempts,
+            max_rd:     Duration::from_secs(0)
+        }
+    }
+
+    // === Builder for RetransmissionPolicy ===
+    /// Sets initial retransmission time (IRT)
+    pub fn set_initial_rt(mut self, initial_rt: Duration) -> RetransmissionPolicy {
+        self.initial_rt = initial_rt;
+        self
+    }
+
+    /// Sets maximum retransmission time (MRT); zero means no upper bound on RT
+    pub fn set_max_rt(mut self, max_rt: Duration) -> RetransmissionPolicy {
+        self.max_rt = max_rt;
+        self
+    }
+
+    /// Sets maximum transmission count (MRC); zero means no limit
+    pub fn set_max_rc(mut self, max_rc: u32) -> RetransmissionPolicy {
+        self.max_rc = max_rc;
+        self
+    }
+
+    /// Sets maximum retransmission duration (MRD); zero means no limit
+    pub fn set_max_rd(mut self, max_rd: Duration) -> RetransmissionPolicy {
+        self.max_rd = max_rd;
+        self
+    }
+    // ===================
+
+    /// Returns initial retransmission time (IRT)
+    pub fn initial_rt(&self) -> Duration {
+        self.initial_rt
+    }
+
+    /// Returns maximum retransmission time (MRT)
+    pub fn max_rt(&self) -> Duration {
+        self.max_rt
+    }
+
+    /// Returns maximum transmission count (MRC)
+    pub fn max_rc(&self) -> u32 {
+        self.max_rc
+    }
+
+    /// Returns maximum retransmission duration (MRD)
+    pub fn max_rd(&self) -> Duration {
+        self.max_rd
+    }
+
+    /// Returns jittered RT for the first transmission of the request
+    pub fn initial_timeout(&self) -> Duration {
+        self.cap(self.initial_rt.mul_f64(1.0 + rand()))
+    }
+
+    /// Returns jittered RT for the retransmit, following transmission which used `previous_rt`
+    pub fn next_timeout(&self, previous_rt: Duration) -> Duration {
+        self.cap(previous_rt.mul_f64(2.0 + rand()))
+    }
+
+    /// Returns true, if request must not be retransmitted anymore after it has been transmitted
+    /// `transmissions` times over `elapsed` time
+    pub fn is_exhausted(&self, transmissions: u32, elapsed: Duration) -> bool {
+        (self.max_rc != 0 && transmissions >= self.max_rc) || (!self.max_rd.is_zero() && elapsed >= self.max_rd)
+    }
+
+    fn cap(&self, rt: Duration) -> Duration {
+        if !self.max_rt.is_zero() && rt > self.max_rt {
+            self.max_rt.mul_f64(1.0 + rand())
+        } else {
+            rt
+        }
+    }
+}
+
+/// Returns RAND, uniformly distributed between -0.1 and 0.1
+fn rand() -> f64 {
+    thread_rng().gen_range(-RAND_FACTOR..=RAND_FACTOR)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_within(expected: Duration, actual: Duration) {
+        assert!(actual >= expected.mul_f64(1.0 - RAND_FACTOR) && actual <= expected.mul_f64(1.0 + RAND_FACTOR), "{:?} is not within 10% of {:?}", actual, expected);
+    }
+
+    #[test]
+    fn test_exponential_backoff_is_capped() {
+        let policy = RetransmissionPolicy::default()
+            .set_initial_rt(Duration::from_millis(250))
+            .set_max_rt(Duration::from_secs(1));
+
+        let first = policy.initial_timeout();
+        assert_within(Duration::from_millis(250), first);
+
+        let second = policy.next_timeout(first);
+        assert_within(first * 2, second);
+
+        assert_within(Duration::from_secs(1), policy.next_timeout(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_is_exhausted() {
+        let policy = RetransmissionPolicy::default();
+
+        assert!(!policy.is_exhausted(4, Duration::from_secs(29)));
+        assert!(policy.is_exhausted(5,  Duration::from_secs(1)));
+        assert!(policy.is_exhausted(1,  Duration::from_secs(30)));
+
+        let unlimited = policy.set_max_rc(0).set_max_rd(Duration::from_secs(0));
+        assert!(!unlimited.is_exhausted(1000, Duration::from_secs(1000)));
+    }
+
+    #[test]
+    fn test_fixed() {
+        let policy = RetransmissionPolicy::fixed(Duration::from_millis(500), 3);
+
+        assert_within(Duration::from_millis(500), policy.next_timeout(Duration::from_millis(500)));
+        assert!(!policy.is_exhausted(2, Duration::from_secs(1000)));
+        assert!(policy.is_exhausted(3, Duration::from_secs(0)));
+    }
+}
@@ -0,0 +1,680 @@
+//! Disk-backed store-and-forward spool for Accounting-Requests
+//!
+//! Spool is an append-only file of encoded Accounting-Requests, each one stamped with the time it
+//! has been spooled at; position of the oldest undelivered request is kept in a separate
+//! `<spool>.offset` file, so delivered requests are not replayed after restart.
+//!
+//! Requests are replayed in the order they have been spooled, with the time they have spent in
+//! the spool added to Acct-Delay-Time and with new ID (RFC 2866 Section 5.2). Delivery is
+//! at-least-once: request
+//! could be replayed again, if process stops between receiving Accounting-Response and recording
+//! it in the spool
+//!
+//! Request, which could not be read back, is discarded and counted, so it does not hold back
+//! requests spooled after it
+
+
+use crate::client::client::Client;
+use crate::client::health::is_server_failure;
+use crate::client::udp_client::UdpClient;
+use crate::protocol::error::RadiusError;
+use crate::protocol::radius_packet::{ RadiusPacket, TypeCode };
+use crate::tools::integer_to_bytes;
+
+use rand::{ thread_rng, Rng };
+use std::convert::TryInto;
+use std::fs::{ self, File, OpenOptions };
+use std::io::{ Error, ErrorKind, Read, Seek, SeekFrom, Write };
+use std::path::{ Path, PathBuf };
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+
+const ACCT_DELAY_TIME_ID: u8    = 41;
+const RECORD_HEADER_SIZE: u64   = 12;
+const MIN_PACKET_SIZE:    usize = 20;
+const MAX_PACKET_SIZE:    usize = 4096;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Represents how often spooled requests are flushed to disk
+pub enum FsyncPolicy {
+    /// Spool is flushed after every spooled or delivered request
+    Always,
+    /// Spool is flushed after every N spooled or delivered requests
+    Every(u32),
+    /// Spool is never flushed explicitly and it is up to OS to write it to disk
+    Never
+}
+
+
+#[derive(Debug)]
+/// Represents durable spool of Accounting-Requests, which could not be delivered to RADIUS Server
+///
+/// Spool size is bounded: requests, which do not fit, are rejected. Space, taken by delivered
+/// requests, is reclaimed once the spool is fully drained or, if it is not, once delivered
+/// requests take more space than undelivered ones and the spool file reaches its size limit
+pub struct AccountingSpool {
+    path:         PathBuf,
+    offset_path:  PathBuf,
+    file:         File,
+    read_offset:  u64,
+    file_size:    u64,
+    pending:      usize,
+    corrupted:    usize,
+    max_size:     u64,
+    fsync_policy: FsyncPolicy,
+    unsynced:     u32
+}
+
+impl AccountingSpool {
+    /// Opens spool file (it is created, if it does not exist) with 16MiB size limit and
+    /// FsyncPolicy::Always
+    ///
+    /// Partially written request at the end of the file (ie after crash) is discarded
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<AccountingSpool, RadiusError> {
+        let path        = path.as_ref().to_path_buf();
+        let offset_path = offset_path(&path);
+        let mut file    = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path).map_err(spool_error)?;
+        let file_size   = file.metadata().map_err(spool_error)?.len();
+
+        let read_offset = match fs::read(&offset_path) {
+            Ok(bytes)                                       => {
+                let bytes: [u8; 8] = bytes.as_slice().try_into().map_err(|_| RadiusError::SpoolError { error: String::from("Offset file is corrupted") })?;
+                u64::from_be_bytes(bytes)
+            },
+            Err(error) if error.kind() == ErrorKind::NotFound => 0,
+            Err(error)                                      => return Err(spool_error(error))
+        };
+        // Spool file is truncated before offset is reset, so offset past the end of file means
+        // that spool has been drained; reset is persisted before anything is appended to the file
+        let read_offset = if read_offset > file_size {
+            write_offset(&offset_path, 0, true)?;
+            0
+        } else {
+            read_offset
+        };
+
+        let mut pending = 0;
+        let mut offset  = read_offset;
+        while let Some(record_size) = read_record_size(&mut file, offset, file_size)? {
+            pending += 1;
+            offset  += record_size;
+        }
+        if offset < file_size {
+            file.set_len(offset).map_err(spool_error)?;
+            file.sync_data().map_err(spool_error)?;
+        }
+
+        Ok(AccountingSpool {
+            path,
+            offset_path,
+            file,
+            read_offset,
+            file_size:    offset,
+            pending,
+            corrupted:    0,
+            max_size:     16 * 1024 * 1024,
+            fsync_policy: FsyncPolicy::Always,
+            unsynced:     0
+        })
+    }
+
+    // === Builder for AccountingSpool ===
+    /// Sets maximum size of undelivered requests in bytes
+    ///
+    /// Spool file could take up to twice as much, as space, taken by delivered requests, is only
+    /// reclaimed once they take more of it than undelivered ones
+    pub fn set_max_size(mut self, max_size: u64) -> AccountingSpool {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Sets how often spool is flushed to disk
+    pub fn set_fsync_policy(mut self, fsync_policy: FsyncPolicy) -> AccountingSpool {
+        self.fsync_policy = fsync_policy;
+        self
+    }
+    // ===================
+
+    /// Returns path of spool file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns maximum size of undelivered requests in bytes
+    pub fn max_size(&self) -> u64 {
+        self.max_size
+    }
+
+    /// Returns current size of spool file in bytes
+    pub fn size(&self) -> u64 {
+        self.file_size
+    }
+
+    /// Returns fsync policy
+    pub fn fsync_policy(&self) -> FsyncPolicy {
+        self.fsync_policy
+    }
+
+    /// Returns number of undelivered requests
+    pub fn len(&self) -> usize {
+        self.pending
+    }
+
+    /// Returns true, if there are no undelivered requests
+    pub fn is_empty(&self) -> bool {
+        self.pending == 0
+    }
+
+    /// Returns number of requests, which have been discarded, because they could not be read back
+    pub fn corrupted(&self) -> usize {
+        self.corrupted
+    }
+
+    /// Appends Accounting-Request to the end of spool
+    ///
+    /// Returns error, if request does not fit into spool
+    pub fn enqueue(&mut self, packet: &mut RadiusPacket) -> Result<(), RadiusError> {
+        if packet.code() != &TypeCode::AccountingRequest {
+            return Err( RadiusError::MalformedPacketError { error: String::from("Only Accounting-Request could be spooled") } )
+        }
+        let bytes = packet.to_bytes();
+        if bytes.len() > MAX_PACKET_SIZE {
+            return Err( RadiusError::MalformedPacketError { error: String::from("Accounting-Request is too big to be spooled") } )
+        }
+        let record_size = RECORD_HEADER_SIZE + bytes.len() as u64;
+        if self.file_size - self.read_offset + record_size > self.max_size {
+            return Err( RadiusError::SpoolError { error: String::from("Spool is full") } )
+        }
+        if self.file_size + record_size > self.max_size && self.read_offset > self.file_size - self.read_offset {
+            self.compact()?;
+        }
+
+        let mut record = Vec::with_capacity(RECORD_HEADER_SIZE as usize + bytes.len());
+        record.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+        record.extend_from_slice(&unix_timestamp().to_be_bytes());
+        record.extend_from_slice(&bytes);
+
+        self.file.seek(SeekFrom::Start(self.file_size)).map_err(spool_error)?;
+        if let Err(error) = self.file.write_all(&record) {
+            // Do not leave partially written request behind
+            let _ = self.file.set_len(self.file_size);
+            return Err(spool_error(error))
+        }
+        self.file_size += record.len() as u64;
+        self.pending   += 1;
+
+        if self.should_sync() {
+            self.file.sync_data().map_err(spool_error)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the oldest undelivered Accounting-Request without removing it from spool
+    ///
+    /// Time, request has spent in spool, is added to its Acct-Delay-Time (attribute is added, if
+    /// it is missing). Updated request is a new request, so it gets new ID, which differs from
+    /// the ID it has been spooled with (RFC 2866 Section 5.2); Request Authenticator is left to be
+    /// regenerated on transmission
+    ///
+    /// Request, which could not be read back, is discarded; if its size could not be read back
+    /// either, so could not be any request after it and the whole spool is discarded
+    pub fn peek(&mut self, client: &Client) -> Result<Option<RadiusPacket>, RadiusError> {
+        while !self.is_empty() {
+            let record = match self.read_head()? {
+                Some(record) => record,
+                None         => {
+                    self.corrupted += self.pending;
+                    self.pending    = 0;
+                    self.reset()?;
+                    break
+                }
+            };
+
+            match replayed_packet(client, &record) {
+                Ok(packet) => return Ok(Some(packet)),
+                Err(_)     => {
+                    self.corrupted += 1;
+                    self.advance(record.len() as u64)?;
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Removes the oldest undelivered Accounting-Request from spool, once it has been delivered
+    pub fn pop(&mut self) -> Result<(), RadiusError> {
+        let record_size = read_record_size(&mut self.file, self.read_offset, self.file_size)?
+            .ok_or_else(|| RadiusError::SpoolError { error: String::from("Spool is empty") })?;
+        self.advance(record_size)
+    }
+
+    /// Flushes spool to disk regardless of fsync policy
+    pub fn sync(&mut self) -> Result<(), RadiusError> {
+        self.unsynced = 0;
+        self.file.sync_data().map_err(spool_error)?;
+        write_offset(&self.offset_path, self.read_offset, true)
+    }
+
+    /// Sends spooled Accounting-Requests in order, until spool is empty or RADIUS Server stops
+    /// answering, and returns the number of delivered requests
+    ///
+    /// Request, which has not been answered, stays at the head of spool
+    pub fn drain(&mut self, client: &mut UdpClient) -> Result<usize, RadiusError> {
+        let mut delivered = 0;
+
+        while let Some(mut packet) = self.peek(client.client())? {
+            match client.send_and_receive_reply(&mut packet) {
+                Ok(_)                                  => {
+                    self.pop()?;
+                    delivered += 1;
+                },
+                Err(error) if is_server_failure(&error) => break,
+                Err(error)                             => return Err(error)
+            }
+        }
+        Ok(delivered)
+    }
+
+    /// Sends Accounting-Request to RADIUS Server and returns its reply; if RADIUS Server does not
+    /// answer, request is spooled and None is returned
+    ///
+    /// Spool is drained first and, if it could not be drained, request is spooled straight away,
+    /// so requests always reach RADIUS Server in order; should draining fail, request is spooled
+    /// before the error is returned
+    pub fn send_or_enqueue(&mut self, client: &mut UdpClient, packet: &mut RadiusPacket) -> Result<Option<RadiusPacket>, RadiusError> {
+        if let Err(error) = self.drain(client) {
+            self.enqueue(packet)?;
+            return Err(error)
+        }
+        if !self.is_empty() {
+            self.enqueue(packet)?;
+            return Ok(None)
+        }
+
+        match client.send_and_receive_reply(packet) {
+            Ok(reply)                              => Ok(Some(reply)),
+            Err(error) if is_server_failure(&error) => {
+                self.enqueue(packet)?;
+                Ok(None)
+            },
+            Err(error)                             => Err(error)
+        }
+    }
+
+    /// Returns the oldest undelivered record or None, if it is cut short
+    fn read_head(&mut self) -> Result<Option<Vec<u8>>, RadiusError> {
+        let record_size = match read_record_size(&mut self.file, self.read_offset, self.file_size)? {
+            Some(record_size) => record_size,
+            None              => return Ok(None)
+        };
+
+        let mut record = vec![0u8; record_size as usize];
+        self.file.seek(SeekFrom::Start(self.read_offset)).map_err(spool_error)?;
+        match self.file.read_exact(&mut record) {
+            Ok(())                                                 => Ok(Some(record)),
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => Ok(None),
+            Err(error)                                             => Err(spool_error(error))
+        }
+    }
+
+    fn advance(&mut self, record_size: u64) -> Result<(), RadiusError> {
+        self.read_offset += record_size;
+        self.pending     -= 1;
+
+        if self.is_empty() {
+            return self.reset()
+        }
+
+        let sync = self.should_sync();
+        if sync {
+            self.file.sync_data().map_err(spool_error)?;
+        }
+        write_offset(&self.offset_path, self.read_offset, sync)
+    }
+
+    /// Empties spool file
+    fn reset(&mut self) -> Result<(), RadiusError> {
+        // Truncation and offset reset are always synced, truncation first: should process stop
+        // in between, offset is past the end of empty file and is reset on open, while new
+        // requests are only appended once offset reset is on disk
+        self.file.set_len(0).map_err(spool_error)?;
+        self.file.sync_data().map_err(spool_error)?;
+        self.read_offset = 0;
+        self.file_size   = 0;
+        self.unsynced    = 0;
+        write_offset(&self.offset_path, self.read_offset, true)
+    }
+
+    /// Moves undelivered requests to the start of spool file
+    ///
+    /// Is only called, when delivered requests take more space than undelivered ones: should
+    /// process stop before offset is reset, offset is past the end of compacted file and is reset
+    /// on open, same as with emptied spool
+    fn compact(&mut self) -> Result<(), RadiusError> {
+        let mut tmp_path = self.path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+
+        let mut pending = vec![0u8; (self.file_size - self.read_offset) as usize];
+        self.file.seek(SeekFrom::Start(self.read_offset)).map_err(spool_error)?;
+        self.file.read_exact(&mut pending).map_err(spool_error)?;
+
+        let mut tmp_file = File::create(&tmp_path).map_err(spool_error)?;
+        tmp_file.write_all(&pending).map_err(spool_error)?;
+        tmp_file.sync_data().map_err(spool_error)?;
+        fs::rename(&tmp_path, &self.path).map_err(spool_error)?;
+
+        self.file        = OpenOptions::new().read(true).write(true).open(&self.path).map_err(spool_error)?;
+        self.read_offset = 0;
+        self.file_size   = pending.len() as u64;
+        self.unsynced    = 0;
+        write_offset(&self.offset_path, self.read_offset, true)
+    }
+
+    fn should_sync(&mut self) -> bool {
+        match self.fsync_policy {
+            FsyncPolicy::Always       => true,
+            FsyncPolicy::Never        => false,
+            FsyncPolicy::Every(every) => {
+                self.unsynced += 1;
+                if self.unsynced >= every {
+                    self.unsynced = 0;
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
+
+fn offset_path(path: &Path) -> PathBuf {
+    let mut offset_path = path.as_os_str().to_owned();
+    offset_path.push(".offset");
+    PathBuf::from(offset_path)
+}
+
+/// Offset is written to temporary file first and then renamed, so it is never partially written
+fn write_offset(offset_path: &Path, offset: u64, sync: bool) -> Result<(), RadiusError> {
+    let mut tmp_path = offset_path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+
+    let mut tmp_file = File::create(&tmp_path).map_err(spool_error)?;
+    tmp_file.write_all(&offset.to_be_bytes()).map_err(spool_error)?;
+    if sync {
+        tmp_file.sync_data().map_err(spool_error)?;
+    }
+    fs::rename(&tmp_path, offset_path).map_err(spool_error)
+}
+
+/// Returns size of complete record at given offset or None, if there is no complete record
+fn read_record_size(file: &mut File, offset: u64, file_size: u64) -> Result<Option<u64>, RadiusError> {
+    if offset + RECORD_HEADER_SIZE > file_size {
+        return Ok(None)
+    }
+
+    let mut length = [0u8; 4];
+    file.seek(SeekFrom::Start(offset)).map_err(spool_error)?;
+    match file.read_exact(&mut length) {
+        Ok(())                                                 => {},
+        Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error)                                             => return Err(spool_error(error))
+    }
+
+    let packet_size = u32::from_be_bytes(length) as usize;
+    let record_size = RECORD_HEADER_SIZE + packet_size as u64;
+    if !(MIN_PACKET_SIZE..=MAX_PACKET_SIZE).contains(&packet_size) || offset + record_size > file_size {
+        Ok(None)
+    } else {
+        Ok(Some(record_size))
+    }
+}
+
+/// Decodes spooled record into the request, which is to be replayed
+///
+/// Time, request has spent in spool, is added to its Acct-Delay-Time (attribute is added, if
+/// it is missing) and request gets new ID
+fn replayed_packet(client: &Client, record: &[u8]) -> Result<RadiusPacket, RadiusError> {
+    let spooled_at = u64::from_be_bytes(record[4..RECORD_HEADER_SIZE as usize].try_into().expect("header has 8 timestamp bytes"));
+    let mut packet = client.initialise_packet_from_bytes(&record[RECORD_HEADER_SIZE as usize..])?;
+    let spooled    = unix_timestamp().saturating_sub(spooled_at).min(u32::MAX as u64) as u32;
+
+    match packet.attribute_by_id(ACCT_DELAY_TIME_ID) {
+        Some(attr) => {
+            let acct_delay_time = client.radius_attr_original_integer_value(attr)?;
+            packet.override_attribute_by_id(ACCT_DELAY_TIME_ID, integer_to_bytes(acct_delay_time.saturating_add(spooled)))?;
+        },
+        None       => {
+            let mut attributes = packet.attributes().to_vec();
+            attributes.push(client.create_attribute_by_id(ACCT_DELAY_TIME_ID, integer_to_bytes(spooled))?);
+            packet.set_attributes(attributes);
+        }
+    }
+    packet.override_id(packet.id().wrapping_add(thread_rng().gen_range(1u8..=255u8)));
+    Ok(packet)
+}
+
+fn spool_error(error: Error) -> RadiusError {
+    RadiusError::SpoolError { error: error.to_string() }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::radius_packet::RadiusMsgType;
+    use crate::testing::{ self, serve_requests };
+
+    use std::net::UdpSocket;
+
+    const ACCT_STATUS_TYPE_ID: u8 = 40;
+
+    fn test_client(port: u16) -> Client {
+        testing::test_client(0, "secret").set_retries(1).set_timeout(1).set_port(RadiusMsgType::ACCT, port)
+    }
+
+    /// Directory, which holds spool files of a single test and is removed with them afterwards
+    struct SpoolDir(PathBuf);
+
+    impl SpoolDir {
+        fn new(name: &str) -> SpoolDir {
+            let dir = std::env::temp_dir().join(format!("radius-spool-{}-{}", name, std::process::id()));
+            let _   = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            SpoolDir(dir)
+        }
+
+        fn spool_path(&self) -> PathBuf {
+            self.0.join("spool")
+        }
+    }
+
+    impl Drop for SpoolDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_spool_is_replayed_in_order() {
+        let client      = test_client(0);
+        let dir         = SpoolDir::new("order");
+        let path        = dir.spool_path();
+        let mut session = client.create_acct_session(vec![]);
+        let mut start   = client.create_acct_start_packet(&mut session).unwrap();
+        let mut stop    = client.create_acct_stop_packet(&session, None).unwrap();
+
+        let mut spool = AccountingSpool::open(&path).unwrap().set_fsync_policy(FsyncPolicy::Every(2));
+        spool.enqueue(&mut start).unwrap();
+        spool.enqueue(&mut stop).unwrap();
+        drop(spool);
+
+        // Partially written request is discarded on open
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[0, 0, 0, 40, 0]).unwrap();
+
+        let mut spool = AccountingSpool::open(&path).unwrap();
+        assert_eq!(2, spool.len());
+
+        // Replayed request is a new request, so it has new ID
+        let packet = spool.peek(&client).unwrap().unwrap();
+        assert_ne!(start.id(), packet.id());
+        assert_eq!(start.attribute_by_id(ACCT_STATUS_TYPE_ID).unwrap().value(), packet.attribute_by_id(ACCT_STATUS_TYPE_ID).unwrap().value());
+        assert_eq!(session.session_id().as_bytes(), packet.attribute_by_id(44).unwrap().value());
+        assert!(packet.attribute_by_id(ACCT_DELAY_TIME_ID).is_some());
+        spool.pop().unwrap();
+        drop(spool);
+
+        let mut spool = AccountingSpool::open(&path).unwrap();
+        assert_eq!(1, spool.len());
+        let packet = spool.peek(&client).unwrap().unwrap();
+        assert_ne!(stop.id(), packet.id());
+        assert_eq!(stop.attribute_by_id(ACCT_STATUS_TYPE_ID).unwrap().value(), packet.attribute_by_id(ACCT_STATUS_TYPE_ID).unwrap().value());
+        spool.pop().unwrap();
+
+        assert!(spool.is_empty());
+        assert_eq!(0, spool.size());
+        assert!(spool.peek(&client).unwrap().is_none());
+        match spool.pop() {
+            Err(error) => assert_eq!(String::from("Accounting spool error: Spool is empty"), error.to_string()),
+            _          => assert!(false)
+        }
+    }
+
+    #[test]
+    fn test_stale_offset_of_drained_spool_is_reset() {
+        let client      = test_client(0);
+        let dir         = SpoolDir::new("stale-offset");
+        let path        = dir.spool_path();
+        let mut session = client.create_acct_session(vec![]);
+        let mut start   = client.create_acct_start_packet(&mut session).unwrap();
+
+        let mut spool = AccountingSpool::open(&path).unwrap();
+        spool.enqueue(&mut start).unwrap();
+        spool.enqueue(&mut start).unwrap();
+        spool.pop().unwrap();
+        drop(spool);
+
+        // Process has stopped after spool file has been truncated, but before offset was reset
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(0).unwrap();
+
+        let mut spool = AccountingSpool::open(&path).unwrap();
+        assert!(spool.is_empty());
+        spool.enqueue(&mut start).unwrap();
+        drop(spool);
+
+        let spool = AccountingSpool::open(&path).unwrap();
+        assert_eq!(1, spool.len());
+    }
+
+    #[test]
+    fn test_spool_is_bounded() {
+        let client      = test_client(0);
+        let dir         = SpoolDir::new("bounded");
+        let path        = dir.spool_path();
+        let mut session = client.create_acct_session(vec![]);
+        let mut packet  = client.create_acct_start_packet(&mut session).unwrap();
+        let record_size = RECORD_HEADER_SIZE + packet.to_bytes().len() as u64;
+
+        let mut spool = AccountingSpool::open(&path).unwrap().set_max_size(record_size * 3);
+        spool.enqueue(&mut packet).unwrap();
+        spool.enqueue(&mut packet).unwrap();
+        spool.enqueue(&mut packet).unwrap();
+
+        match spool.enqueue(&mut packet) {
+            Err(error) => assert_eq!(String::from("Accounting spool error: Spool is full"), error.to_string()),
+            _          => assert!(false)
+        }
+        assert_eq!(3, spool.len());
+
+        let mut auth_packet = client.create_auth_packet();
+        assert!(spool.enqueue(&mut auth_packet).is_err());
+
+        // Delivered requests do not count against the limit: once they take more space than
+        // undelivered ones, they are reclaimed
+        spool.pop().unwrap();
+        spool.pop().unwrap();
+        spool.enqueue(&mut packet).unwrap();
+        assert_eq!(2, spool.len());
+        assert_eq!(record_size * 2, spool.size());
+        drop(spool);
+
+        let spool = AccountingSpool::open(&path).unwrap();
+        assert_eq!(2, spool.len());
+    }
+
+    #[test]
+    fn test_corrupted_request_is_skipped() {
+        let client      = test_client(0);
+        let dir         = SpoolDir::new("corrupted");
+        let path        = dir.spool_path();
+        let mut session = client.create_acct_session(vec![]);
+        let mut start   = client.create_acct_start_packet(&mut session).unwrap();
+        let mut stop    = client.create_acct_stop_packet(&session, None).unwrap();
+
+        let mut spool = AccountingSpool::open(&path).unwrap();
+        spool.enqueue(&mut start).unwrap();
+        spool.enqueue(&mut stop).unwrap();
+        spool.enqueue(&mut stop).unwrap();
+        drop(spool);
+
+        // Code of the first spooled request is overwritten with invalid one
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(RECORD_HEADER_SIZE)).unwrap();
+        file.write_all(&[0]).unwrap();
+
+        let mut spool = AccountingSpool::open(&path).unwrap();
+        assert_eq!(3, spool.len());
+
+        let packet = spool.peek(&client).unwrap().unwrap();
+        assert_eq!(stop.attribute_by_id(ACCT_STATUS_TYPE_ID).unwrap().value(), packet.attribute_by_id(ACCT_STATUS_TYPE_ID).unwrap().value());
+        assert_eq!(2, spool.len());
+        assert_eq!(1, spool.corrupted());
+
+        // Spool file is cut short under the last request, so it could not be read back at all
+        let size = spool.size();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(size - 1).unwrap();
+        spool.pop().unwrap();
+
+        assert!(spool.peek(&client).unwrap().is_none());
+        assert!(spool.is_empty());
+        assert_eq!(2, spool.corrupted());
+        assert_eq!(0, spool.size());
+    }
+
+    #[test]
+    fn test_send_or_enqueue_drains_spool() {
+        let server_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_port   = server_socket.local_addr().unwrap().port();
+        let dir           = SpoolDir::new("drain");
+        let path          = dir.spool_path();
+
+        let mut client  = UdpClient::with_client(test_client(server_port)).unwrap();
+        let mut session = client.client().create_acct_session(vec![]);
+        let mut start   = client.client().create_acct_start_packet(&mut session).unwrap();
+        let mut stop    = client.client().create_acct_stop_packet(&session, None).unwrap();
+
+        // Server is down: Start is spooled
+        let mut spool = AccountingSpool::open(&path).unwrap();
+        assert!(spool.send_or_enqueue(&mut client, &mut start).unwrap().is_none());
+        assert_eq!(1, spool.len());
+
+        // Server comes back: requests, which have not been answered while it was down, are lost
+        server_socket.set_nonblocking(true).unwrap();
+        while server_socket.recv_from(&mut [0u8; MAX_PACKET_SIZE]).is_ok() {}
+        server_socket.set_nonblocking(false).unwrap();
+
+        let server_thread = serve_requests(server_socket, "secret", 2, |_, _, _| Some((TypeCode::AccountingResponse, vec![])));
+
+        let reply = spool.send_or_enqueue(&mut client, &mut stop).unwrap().unwrap();
+        assert_eq!(&TypeCode::AccountingResponse, reply.code());
+        assert!(spool.is_empty());
+
+        let statuses: Vec<Vec<u8>> = server_thread.join().unwrap().iter().map(|request| request.attribute_by_id(ACCT_STATUS_TYPE_ID).unwrap().value().to_vec()).collect();
+        assert_eq!(vec![integer_to_bytes(1), integer_to_bytes(2)], statuses);
+    }
+}
@@ -0,0 +1,246 @@
+//! RADIUS Async UDP Client implementation, built on top of Tokio runtime
+
+
+use crate::client::AsyncClientTrait;
+use crate::client::auth::{ AuthOutcome, Challenge };
+use crate::client::client::Client;
+use crate::client::dynamic_authorization::DynAuthOutcome;
+use crate::client::transaction::{ ClientTransaction, TransactionOutput };
+use crate::protocol::error::RadiusError;
+use crate::protocol::radius_packet::{ RadiusAttribute, RadiusPacket };
+
+use async_trait::async_trait;
+use std::io::{ Error, ErrorKind };
+use std::net::SocketAddr;
+use tokio::net::{ lookup_host, UdpSocket };
+use tokio::time::{ timeout_at, Instant };
+
+
+const MAX_PACKET_SIZE: usize = 4096;
+
+
+#[derive(Debug)]
+/// Represents ready-to-use Async RADIUS Client, which sends packets over UDP using Tokio
+///
+/// Wraps [Client](crate::client::client::Client) and uses its server, ports, secret and
+/// retransmission policy; every request is driven by [ClientTransaction](crate::client::transaction::ClientTransaction)
+///
+/// Every request is sent from its own socket, so request futures are cancellation safe: dropping
+/// one (ie when it loses in `tokio::select!`) leaves no state behind and cannot affect any other
+/// in-flight request
+pub struct TokioClient {
+    base_client: Client
+}
+
+impl TokioClient {
+    /// Initialises TokioClient from configured Client
+    pub fn with_client(base_client: Client) -> TokioClient {
+        TokioClient { base_client }
+    }
+
+    /// Returns underlying Client
+    pub fn client(&self) -> &Client {
+        &self.base_client
+    }
+
+    /// Sends packet to RADIUS Server and returns verified reply as RadiusPacket
+    ///
+    /// Reply is accepted only if it has the same ID as request, its authenticator is valid and, if
+    /// present, its Message-Authenticator is valid. Any other datagram is discarded and client
+    /// keeps waiting until timeout
+    pub async fn send_and_receive_reply(&self, packet: &mut RadiusPacket) -> Result<RadiusPacket, RadiusError> {
+        let reply = self.transmit(packet).await?;
+        self.base_client.initialise_packet_from_bytes(&reply)
+    }
+
+    /// Authenticates user with PAP and returns the outcome of authentication
+    ///
+    /// Access-Challenge is to be answered with [continue_challenge](Self::continue_challenge)
+    pub async fn authenticate_pap(&self, user_name: &str, password: &str) -> Result<AuthOutcome, RadiusError> {
+        let mut packet = self.base_client.create_pap_packet(user_name, password)?;
+        self.authenticate(&mut packet).await
+    }
+
+    /// Authenticates user with CHAP and returns the outcome of authentication
+    ///
+    /// Access-Challenge is to be answered with [continue_challenge](Self::continue_challenge)
+    pub async fn authenticate_chap(&self, user_name: &str, password: &str) -> Result<AuthOutcome, RadiusError> {
+        let mut packet = self.base_client.create_chap_packet(user_name, password)?;
+        self.authenticate(&mut packet).await
+    }
+
+    /// Answers Access-Challenge (ie with OTP) and returns the outcome of authentication, which
+    /// could be yet another challenge
+    pub async fn continue_challenge(&self, challenge: &Challenge, response: &str) -> Result<AuthOutcome, RadiusError> {
+        let mut packet = self.base_client.create_challenge_response_packet(challenge, response)?;
+        self.authenticate(&mut packet).await
+    }
+
+    /// Asks NAS to terminate the session(s) identified by given session identification attributes
+    /// and returns NAS's answer
+    pub async fn disconnect(&self, session_identifiers: Vec<RadiusAttribute>) -> Result<DynAuthOutcome, RadiusError> {
+        let mut packet = self.base_client.create_disconnect_packet(session_identifiers)?;
+        self.dynamic_authorization(&mut packet).await
+    }
+
+    /// Sends Accounting-On, which tells RADIUS Server that NAS has started up, and returns
+    /// Accounting-Response; see [UdpClient::accounting_on](crate::client::udp_client::UdpClient::accounting_on)
+    pub async fn accounting_on(&self, attributes: Vec<RadiusAttribute>) -> Result<RadiusPacket, RadiusError> {
+        let mut packet = self.base_client.create_accounting_on_packet(attributes)?;
+        self.send_and_receive_reply(&mut packet).await
+    }
+
+    /// Sends Accounting-Off, which tells RADIUS Server that NAS is shutting down, and returns
+    /// Accounting-Response; see [UdpClient::accounting_off](crate::client::udp_client::UdpClient::accounting_off)
+    pub async fn accounting_off(&self, attributes: Vec<RadiusAttribute>) -> Result<RadiusPacket, RadiusError> {
+        let mut packet = self.base_client.create_accounting_off_packet(attributes)?;
+        self.send_and_receive_reply(&mut packet).await
+    }
+
+    /// Asks NAS to change authorization of the session(s) identified by given session
+    /// identification attributes to given attributes and returns NAS's answer
+    pub async fn change_of_authorization(&self, session_identifiers: Vec<RadiusAttribute>, attributes: Vec<RadiusAttribute>) -> Result<DynAuthOutcome, RadiusError> {
+        let mut packet = self.base_client.create_change_of_authorization_packet(session_identifiers, attributes)?;
+        self.dynamic_authorization(&mut packet).await
+    }
+
+    async fn authenticate(&self, packet: &mut RadiusPacket) -> Result<AuthOutcome, RadiusError> {
+        let reply = self.send_and_receive_reply(packet).await?;
+        AuthOutcome::from_reply(packet, &reply)
+    }
+
+    async fn dynamic_authorization(&self, packet: &mut RadiusPacket) -> Result<DynAuthOutcome, RadiusError> {
+        let reply = self.send_and_receive_reply(packet).await?;
+        DynAuthOutcome::from_reply(packet, &reply)
+    }
+
+    async fn transmit(&self, packet: &mut RadiusPacket) -> Result<Vec<u8>, RadiusError> {
+        let remote_port = self.base_client.port(packet.code()).ok_or_else(|| RadiusError::MalformedPacketError { error: String::from("There is no port match for packet code") })?;
+        self.transmit_to(packet, remote_port).await
+    }
+
+    /// Sends packet to given port of RADIUS Server rather than to the port of packet's type (ie
+    /// Status-Server to accounting port) and returns verified reply as raw bytes
+    pub(crate) async fn transmit_to(&self, packet: &mut RadiusPacket, remote_port: u16) -> Result<Vec<u8>, RadiusError> {
+        let remote      = resolve_remote(self.base_client.server(), remote_port).await?;
+        let local_bind  = if remote.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" };
+
+        let socket = UdpSocket::bind(local_bind).await?;
+        socket.connect(remote).await?;
+
+        let mut transaction = ClientTransaction::new(&self.base_client, packet.clone())?;
+        let reply           = self.run_transaction(&mut transaction, &socket).await;
+
+        *packet = transaction.into_packet();
+        reply
+    }
+
+    async fn run_transaction(&self, transaction: &mut ClientTransaction, socket: &UdpSocket) -> Result<Vec<u8>, RadiusError> {
+        let mut last_error = None;
+        let mut response   = vec![0u8; MAX_PACKET_SIZE];
+        let mut output     = transaction.start(Instant::now().into_std());
+
+        loop {
+            let deadline = match output {
+                TransactionOutput::Transmit { datagram, deadline, .. } => {
+                    socket.send(&datagram).await?;
+                    deadline
+                },
+                TransactionOutput::Wait { deadline }             => deadline,
+                TransactionOutput::Discarded { error, deadline } => {
+                    last_error = Some(error);
+                    deadline
+                },
+                TransactionOutput::Reply(mut reply)              => return Ok(reply.to_bytes()),
+                TransactionOutput::TimedOut                      => return Err(last_error.unwrap_or_else(|| RadiusError::SocketConnectionError(Error::new(ErrorKind::TimedOut, "RADIUS Server did not reply in time"))))
+            };
+
+            output = match timeout_at(Instant::from_std(deadline), socket.recv(&mut response)).await {
+                Ok(received) => transaction.handle_datagram(&self.base_client, &response[..received?], Instant::now().into_std()),
+                Err(_)       => transaction.handle_timeout(Instant::now().into_std())
+            };
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncClientTrait for TokioClient {
+    async fn send_packet(&self, packet: &mut RadiusPacket) -> Result<(), RadiusError> {
+        self.transmit(packet).await.map(|_| ())
+    }
+
+    async fn send_and_receive_packet(&self, packet: &mut RadiusPacket) -> Result<Vec<u8>, RadiusError> {
+        self.transmit(packet).await
+    }
+}
+
+pub(crate) async fn resolve_remote(server: &str, port: u16) -> Result<SocketAddr, RadiusError> {
+    lookup_host((server, port)).await?
+        .next()
+        .ok_or_else(|| RadiusError::SocketInvalidConnectionError { error: format!("Could not resolve RADIUS Server address: {}", server) })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::radius_packet::TypeCode;
+    use crate::testing::{ self, spawn_server };
+
+    use md5::{ Digest, Md5 };
+    use std::time::Duration;
+
+    fn test_client(port: u16) -> TokioClient {
+        TokioClient::with_client(testing::test_client(port, "secret").set_retries(2).set_timeout(1))
+    }
+
+    #[tokio::test]
+    async fn test_send_and_receive_reply() {
+        let server_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_port   = server_socket.local_addr().unwrap().port();
+
+        spawn_server(server_socket, "secret", |_, _, _| Some((TypeCode::AccessReject, vec![])));
+
+        let client     = test_client(server_port);
+        let mut packet = client.client().create_auth_packet();
+
+        let reply = client.send_and_receive_reply(&mut packet).await.unwrap();
+
+        assert_eq!(&TypeCode::AccessReject, reply.code());
+        assert_eq!(packet.id(), reply.id());
+    }
+
+    #[tokio::test]
+    async fn test_send_and_receive_reply_cancelled() {
+        let server_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_port   = server_socket.local_addr().unwrap().port();
+
+        let client     = test_client(server_port);
+        let mut packet = client.client().create_auth_packet();
+
+        let result = tokio::time::timeout(Duration::from_millis(100), client.send_and_receive_reply(&mut packet)).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_chap() {
+        let server_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_port   = server_socket.local_addr().unwrap().port();
+
+        spawn_server(server_socket, "secret", |_, packet, _| {
+            let chap_password = packet.attribute_by_name("CHAP-Password").unwrap().value();
+
+            let mut md5_hasher = Md5::new();
+            md5_hasher.update(&chap_password[..1]);
+            md5_hasher.update(b"password");
+            md5_hasher.update(packet.authenticator());
+
+            let reply_code = if md5_hasher.finalize().as_slice() == &chap_password[1..] { TypeCode::AccessAccept } else { TypeCode::AccessReject };
+            Some((reply_code, vec![]))
+        });
+
+        let client = test_client(server_port);
+
+        assert!(client.authenticate_chap("testing", "password").await.unwrap().is_accept());
+    }
+}
@@ -0,0 +1,456 @@
+//! Sans-IO RADIUS Client transaction
+//!
+//! `ClientTransaction` doesn't own any socket or timer - it only tells caller which datagram to
+//! send and when it wants to be woken up, which makes it usable with any event loop (mio, glommio,
+//! DPDK-based stacks and etc)
+
+
+use crate::client::client::Client;
+use crate::client::retransmission::RetransmissionPolicy;
+use crate::protocol::error::RadiusError;
+use crate::protocol::radius_packet::{ RadiusPacket, TypeCode };
+use crate::tools::integer_to_bytes;
+#[cfg(feature = "metrics")]
+use crate::metrics::{ self, Metric };
+
+use std::time::{ Duration, Instant };
+
+
+const ACCT_DELAY_TIME_ID: u8 = 41;
+
+
+#[derive(Debug)]
+/// Represents the action caller has to take after feeding an event into ClientTransaction
+pub enum TransactionOutput {
+    /// Datagram has to be sent to RADIUS Server and transaction has to be woken up via
+    /// [handle_timeout](ClientTransaction::handle_timeout) at `deadline`, unless reply arrives first
+    Transmit {
+        /// Encoded request; it is the same (same ID and authenticator) for every retransmit, except
+        /// for Accounting-Request, which gets new ID whenever its Acct-Delay-Time (and so its
+        /// authenticator) is updated
+        datagram:   Vec<u8>,
+        /// Time at which caller should call `handle_timeout`
+        deadline:   Instant,
+        /// True, if this is a retransmit of the request rather than its first transmission
+        retransmit: bool
+    },
+    /// Nothing to be done yet; keep waiting for reply until `deadline`
+    Wait {
+        /// Time at which caller should call `handle_timeout`
+        deadline: Instant
+    },
+    /// Received datagram was not a valid reply to this request and has been discarded; keep
+    /// waiting for reply until `deadline`
+    Discarded {
+        /// Reason datagram has been discarded
+        error:    RadiusError,
+        /// Time at which caller should call `handle_timeout`
+        deadline: Instant
+    },
+    /// Verified reply has been received and transaction is completed
+    Reply(RadiusPacket),
+    /// No valid reply has been received within retransmission limits and transaction is completed
+    TimedOut
+}
+
+#[derive(Debug, PartialEq)]
+enum TransactionState {
+    Idle,
+    Waiting { deadline: Instant },
+    Completed
+}
+
+#[derive(Debug)]
+/// Represents single request/reply exchange between RADIUS Client and RADIUS Server, without any IO
+///
+/// Retransmits follow [Client's](crate::client::client::Client) [RetransmissionPolicy](crate::client::retransmission::RetransmissionPolicy)
+///
+/// Accounting-Request carries Acct-Delay-Time attribute, which is updated on every retransmit with
+/// the number of seconds since the first transmission, and its Request Authenticator is recomputed
+/// (RFC 2866 Section 3). Since updated request is a new request, it gets a new ID as well (RFC 2866
+/// Section 5.2); should Acct-Delay-Time stay the same (or no ID be available), request is
+/// retransmitted unchanged. CoA-Request and Disconnect-Request get Request Authenticator computed
+/// the same way (RFC 5176 Section 2.3). Other requests are retransmitted with the same ID and authenticator
+pub struct ClientTransaction {
+    packet:          RadiusPacket,
+    datagram:        Vec<u8>,
+    secret:          String,
+    policy:          RetransmissionPolicy,
+    acct_delay_time: Option<u32>,
+    sent_delay_time: u32,
+    state:           TransactionState,
+    started:         Option<Instant>,
+    transmissions:   u32,
+    current_rt:      Duration,
+    #[cfg(feature = "metrics")]
+    server:          String
+}
+
+impl ClientTransaction {
+    /// Initialises transaction for given request packet with Client's secret and retransmission policy
+    ///
+    /// For Accounting-Request, Acct-Delay-Time attribute is added (unless it is already present or
+    /// is not defined in dictionary) and Request Authenticator is generated; for CoA-Request and
+    /// Disconnect-Request only Request Authenticator is generated
+    pub fn new(client: &Client, mut packet: RadiusPacket) -> Result<ClientTransaction, RadiusError> {
+        let mut acct_delay_time = None;
+
+        if packet.code() == &TypeCode::AccountingRequest {
+            match packet.attribute_by_id(ACCT_DELAY_TIME_ID) {
+                Some(attr) => acct_delay_time = Some(client.radius_attr_original_integer_value(attr)?),
+                None       => if let Ok(attr) = client.create_attribute_by_id(ACCT_DELAY_TIME_ID, integer_to_bytes(0)) {
+                    let mut attributes = packet.attributes().to_vec();
+                    attributes.push(attr);
+                    packet.set_attributes(attributes);
+                    acct_delay_time = Some(0);
+                }
+            }
+        }
+        if matches!(packet.code(), TypeCode::AccountingRequest | TypeCode::CoARequest | TypeCode::DisconnectRequest) {
+            packet.generate_request_authenticator(client.secret())?;
+        }
+        let datagram = packet.to_bytes();
+
+        Ok(ClientTransaction {
+            packet,
+            datagram,
+            secret:        client.secret().to_string(),
+            policy:        client.retransmission_policy(),
+            acct_delay_time,
+            sent_delay_time: acct_delay_time.unwrap_or_default(),
+            state:         TransactionState::Idle,
+            started:       None,
+            transmissions: 0,
+            current_rt:    Duration::from_secs(0),
+            #[cfg(feature = "metrics")]
+            server:        client.server().to_string()
+        })
+    }
+
+    /// Returns request packet, as it was last transmitted
+    pub fn packet(&self) -> &RadiusPacket {
+        &self.packet
+    }
+
+    /// Consumes transaction, returning request packet, as it was last transmitted
+    pub fn into_packet(self) -> RadiusPacket {
+        self.packet
+    }
+
+    /// Returns number of times request has been transmitted so far
+    pub fn transmissions(&self) -> u32 {
+        self.transmissions
+    }
+
+    /// Returns time at which transaction wants [handle_timeout](ClientTransaction::handle_timeout)
+    /// to be called, if transaction is waiting for reply
+    pub fn deadline(&self) -> Option<Instant> {
+        match self.state {
+            TransactionState::Waiting { deadline } => Some(deadline),
+            _                                      => None
+        }
+    }
+
+    /// Returns true, if transaction has received reply or timed out
+    pub fn is_completed(&self) -> bool {
+        self.state == TransactionState::Completed
+    }
+
+    /// Starts transaction: returns first transmission of the request
+    ///
+    /// Calling it on already started transaction behaves as [handle_timeout](ClientTransaction::handle_timeout)
+    pub fn start(&mut self, now: Instant) -> TransactionOutput {
+        match self.state {
+            TransactionState::Idle => {
+                self.started    = Some(now);
+                self.current_rt = self.policy.initial_timeout();
+                #[cfg(feature = "metrics")]
+                self.record(&metrics::CLIENT_REQUESTS);
+                self.transmit(now, false)
+            },
+            _                      => self.handle_timeout(now)
+        }
+    }
+
+    /// Processes timer expiry: returns retransmit of the request or reports that transaction timed out
+    ///
+    /// Accounting-Request with updated Acct-Delay-Time gets the next ID (modulo 256), which is fine
+    /// for a socket dedicated to the request; callers, which share the ID space between many
+    /// requests, should use [handle_timeout_with_id](ClientTransaction::handle_timeout_with_id)
+    pub fn handle_timeout(&mut self, now: Instant) -> TransactionOutput {
+        let next_id = self.packet.id().wrapping_add(1);
+        self.handle_timeout_with_id(now, || Some(next_id))
+    }
+
+    /// Processes timer expiry the same way as [handle_timeout](ClientTransaction::handle_timeout),
+    /// but new ID of Accounting-Request with updated Acct-Delay-Time is taken from `allocate_id`
+    ///
+    /// `allocate_id` is only called when the ID is actually going to change; if it returns None,
+    /// previous datagram is retransmitted as is
+    pub fn handle_timeout_with_id<F: FnOnce() -> Option<u8>>(&mut self, now: Instant, allocate_id: F) -> TransactionOutput {
+        match self.state {
+            TransactionState::Idle                                   => self.start(now),
+            TransactionState::Completed                              => TransactionOutput::TimedOut,
+            TransactionState::Waiting { deadline } if now < deadline => TransactionOutput::Wait { deadline },
+            TransactionState::Waiting { .. }                         => {
+                let elapsed = self.started.map(|started| now.saturating_duration_since(started)).unwrap_or_default();
+
+                if self.policy.is_exhausted(self.transmissions, elapsed) {
+                    self.state = TransactionState::Completed;
+                    #[cfg(feature = "metrics")]
+                    self.record(&metrics::CLIENT_TIMEOUTS);
+                    return TransactionOutput::TimedOut
+                }
+                if let Some(acct_delay_time) = self.acct_delay_time {
+                    // Acct-Delay-Time update is a SHOULD (RFC 2866 Section 5.2), so should it fail,
+                    // previous datagram is retransmitted as is
+                    let acct_delay_time = acct_delay_time.saturating_add(elapsed.as_secs() as u32);
+                    if acct_delay_time != self.sent_delay_time {
+                        let _ = self.update_acct_delay_time(acct_delay_time, allocate_id);
+                    }
+                }
+
+                self.current_rt = self.policy.next_timeout(self.current_rt);
+                self.transmit(now, true)
+            }
+        }
+    }
+
+    /// Processes datagram received from RADIUS Server
+    ///
+    /// Datagram is verified against the request with [Client's](crate::client::client::Client) ID,
+    /// authenticator and Message-Authenticator checks
+    pub fn handle_datagram(&mut self, client: &Client, datagram: &[u8], now: Instant) -> TransactionOutput {
+        let deadline = match self.state {
+            TransactionState::Waiting { deadline } => deadline,
+            TransactionState::Idle                 => return TransactionOutput::Discarded { error: RadiusError::ValidationError { error: String::from("Transaction has not been started") }, deadline: now },
+            TransactionState::Completed            => return TransactionOutput::Discarded { error: RadiusError::ValidationError { error: String::from("Transaction is already completed") },  deadline: now }
+        };
+
+        match client.process_reply(&self.packet, datagram) {
+            Ok(reply)  => {
+                self.state = TransactionState::Completed;
+                #[cfg(feature = "metrics")]
+                self.record_round_trip(now);
+                TransactionOutput::Reply(reply)
+            },
+            Err(error) => TransactionOutput::Discarded { error, deadline }
+        }
+    }
+
+    fn transmit(&mut self, now: Instant, retransmit: bool) -> TransactionOutput {
+        let mut deadline = now + self.current_rt;
+        if let Some(max_deadline) = self.max_deadline() {
+            deadline = std::cmp::min(deadline, max_deadline);
+        }
+
+        self.transmissions += 1;
+        self.state          = TransactionState::Waiting { deadline };
+        #[cfg(feature = "metrics")]
+        if retransmit {
+            self.record(&metrics::CLIENT_RETRANSMITS);
+        }
+
+        TransactionOutput::Transmit { datagram: self.datagram.clone(), deadline, retransmit }
+    }
+
+    fn update_acct_delay_time<F: FnOnce() -> Option<u8>>(&mut self, acct_delay_time: u32, allocate_id: F) -> Result<(), RadiusError> {
+        let mut packet = self.packet.clone();
+        packet.override_attribute_by_id(ACCT_DELAY_TIME_ID, integer_to_bytes(acct_delay_time))?;
+
+        let id = allocate_id().ok_or_else(|| RadiusError::ValidationError { error: String::from("There is no free ID for updated Accounting-Request") })?;
+        packet.override_id(id);
+        // Message-Authenticator (if present) is regenerated along with Request Authenticator, as
+        // both of them are calculated over ID
+        packet.generate_request_authenticator(&self.secret)?;
+
+        self.datagram        = packet.to_bytes();
+        self.packet          = packet;
+        self.sent_delay_time = acct_delay_time;
+        Ok(())
+    }
+
+    #[cfg(feature = "metrics")]
+    fn record(&self, metric: &'static Metric) {
+        metrics::registry().increment_counter(metric, &[&self.server, metrics::code_label(self.packet.code())]);
+    }
+
+    // Reply to retransmitted request could be a reply to any of its transmissions, so only
+    // requests, which have been transmitted once, are measured (Karn's algorithm)
+    #[cfg(feature = "metrics")]
+    fn record_round_trip(&self, now: Instant) {
+        if let (1, Some(started)) = (self.transmissions, self.started) {
+            let labels = [self.server.as_str(), metrics::code_label(self.packet.code())];
+            metrics::registry().observe(&metrics::CLIENT_ROUND_TRIP, &labels, now.saturating_duration_since(started).as_secs_f64());
+        }
+    }
+
+    fn max_deadline(&self) -> Option<Instant> {
+        match self.started {
+            Some(started) if !self.policy.max_rd().is_zero() => Some(started + self.policy.max_rd()),
+            _                                                => None
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::radius_packet::TypeCode;
+    use crate::server::client_table::ClientEntry;
+    use crate::testing::{ self, test_server };
+
+    fn test_client() -> Client {
+        testing::test_client(1812, "secret").set_retransmission_policy(RetransmissionPolicy::default())
+    }
+
+    #[test]
+    fn test_retransmits_keep_same_datagram_and_give_up() {
+        let client          = test_client();
+        let mut transaction = ClientTransaction::new(&client, client.create_auth_packet()).unwrap();
+        let started         = Instant::now();
+
+        let first_datagram = match transaction.start(started) {
+            TransactionOutput::Transmit { datagram, deadline, retransmit } => {
+                assert!(!retransmit);
+                assert!(deadline >= started + Duration::from_millis(1800) && deadline <= started + Duration::from_millis(2200));
+                datagram
+            },
+            _ => panic!("expected first transmission")
+        };
+
+        let timed_out = loop {
+            let now = transaction.deadline().unwrap();
+            match transaction.handle_timeout(now) {
+                TransactionOutput::Transmit { datagram, retransmit, .. } => {
+                    assert!(retransmit);
+                    assert_eq!(first_datagram, datagram);
+                },
+                TransactionOutput::TimedOut => break now,
+                other                       => panic!("unexpected output: {:?}", other)
+            }
+        };
+
+        assert!(transaction.transmissions() <= 5);
+        assert!(timed_out <= started + Duration::from_secs(30));
+        assert!(transaction.is_completed());
+    }
+
+    #[test]
+    fn test_early_timeout_waits() {
+        let client          = test_client();
+        let mut transaction = ClientTransaction::new(&client, client.create_auth_packet()).unwrap();
+        let started         = Instant::now();
+
+        transaction.start(started);
+        match transaction.handle_timeout(started + Duration::from_millis(100)) {
+            TransactionOutput::Wait { deadline } => assert_eq!(transaction.deadline(), Some(deadline)),
+            other                                => panic!("unexpected output: {:?}", other)
+        }
+        assert_eq!(1, transaction.transmissions());
+    }
+
+    #[test]
+    fn test_reply_and_discarded_datagram() {
+        let client          = test_client();
+        let mut transaction = ClientTransaction::new(&client, client.create_auth_packet()).unwrap();
+        let started         = Instant::now();
+
+        let mut request = match transaction.start(started) {
+            TransactionOutput::Transmit { datagram, .. } => datagram,
+            _                                            => panic!("expected first transmission")
+        };
+
+        let server     = test_server("other-secret");
+        let bad_reply  = server.create_reply_packet(TypeCode::AccessAccept, vec![], &mut request).to_bytes();
+
+        match transaction.handle_datagram(&client, &bad_reply, started) {
+            TransactionOutput::Discarded { error, .. } => assert_eq!(String::from("Verification failed for incoming Radius packet: Packet authenticator mismatch"), error.to_string()),
+            other                                      => panic!("unexpected output: {:?}", other)
+        }
+
+        let server     = test_server("secret");
+        let reply      = server.create_reply_packet(TypeCode::AccessAccept, vec![], &mut request).to_bytes();
+
+        match transaction.handle_datagram(&client, &reply, started) {
+            TransactionOutput::Reply(reply) => assert_eq!(&TypeCode::AccessAccept, reply.code()),
+            other                           => panic!("unexpected output: {:?}", other)
+        }
+        assert!(transaction.is_completed());
+        assert_eq!(None, transaction.deadline());
+    }
+
+    #[test]
+    fn test_accounting_retransmit_updates_acct_delay_time() {
+        let client     = test_client();
+        let mut packet = client.create_acct_packet();
+        packet.set_attributes(vec![client.create_attribute_by_name("User-Name", String::from("testing").into_bytes()).unwrap()]);
+
+        let mut transaction = ClientTransaction::new(&client, packet).unwrap();
+        let started         = Instant::now();
+
+        let first_datagram = match transaction.start(started) {
+            TransactionOutput::Transmit { datagram, .. } => datagram,
+            _                                            => panic!("expected first transmission")
+        };
+        let first_packet = client.initialise_packet_from_bytes(&first_datagram).unwrap();
+        assert_eq!(integer_to_bytes(0), first_packet.attribute_by_id(ACCT_DELAY_TIME_ID).unwrap().value());
+
+        let retransmitted = match transaction.handle_timeout(started + Duration::from_secs(3)) {
+            TransactionOutput::Transmit { datagram, .. } => client.initialise_packet_from_bytes(&datagram).unwrap(),
+            other                                        => panic!("unexpected output: {:?}", other)
+        };
+
+        assert_ne!(first_packet.id(), retransmitted.id());
+        assert_eq!(transaction.packet().id(), retransmitted.id());
+        assert_ne!(first_packet.authenticator(), retransmitted.authenticator());
+        assert_eq!(integer_to_bytes(3), retransmitted.attribute_by_id(ACCT_DELAY_TIME_ID).unwrap().value());
+
+        let mut expected = retransmitted.clone();
+        expected.generate_request_authenticator(client.secret()).unwrap();
+        assert_eq!(expected.authenticator(), retransmitted.authenticator());
+    }
+
+    #[test]
+    fn test_accounting_retransmit_passes_server_verification() {
+        let client     = test_client();
+        let mut packet = client.create_acct_packet();
+        packet.set_attributes(vec![
+            client.create_attribute_by_name("User-Name", String::from("testing").into_bytes()).unwrap(),
+            client.create_attribute_by_name("Message-Authenticator", [0; 16].to_vec()).unwrap()
+        ]);
+
+        let mut transaction = ClientTransaction::new(&client, packet).unwrap();
+        let started         = Instant::now();
+        transaction.start(started);
+
+        let retransmitted = match transaction.handle_timeout(started + Duration::from_secs(3)) {
+            TransactionOutput::Transmit { datagram, .. } => datagram,
+            other                                        => panic!("unexpected output: {:?}", other)
+        };
+
+        let nas = ClientEntry::new("127.0.0.1", String::from("secret")).unwrap();
+        assert!(test_server("secret").process_request(&nas, &retransmitted).is_ok());
+    }
+
+    #[test]
+    fn test_accounting_retransmit_without_free_id_is_unchanged() {
+        let client     = test_client();
+        let mut packet = client.create_acct_packet();
+        packet.set_attributes(vec![client.create_attribute_by_name("User-Name", String::from("testing").into_bytes()).unwrap()]);
+
+        let mut transaction = ClientTransaction::new(&client, packet).unwrap();
+        let started         = Instant::now();
+
+        let first_datagram = match transaction.start(started) {
+            TransactionOutput::Transmit { datagram, .. } => datagram,
+            _                                            => panic!("expected first transmission")
+        };
+
+        match transaction.handle_timeout_with_id(started + Duration::from_secs(3), || None) {
+            TransactionOutput::Transmit { datagram, .. } => assert_eq!(first_datagram, datagram),
+            other                                        => panic!("unexpected output: {:?}", other)
+        }
+    }
+}
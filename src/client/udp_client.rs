@@ -0,0 +1,375 @@
+//! RADIUS Blocking UDP Client implementation
+
+
+use crate::client::SyncClientTrait;
+use crate::client::auth::{ AuthOutcome, Challenge };
+use crate::client::client::Client;
+use crate::client::dynamic_authorization::DynAuthOutcome;
+use crate::client::transaction::{ ClientTransaction, TransactionOutput };
+use crate::protocol::error::RadiusError;
+use crate::protocol::radius_packet::{ RadiusAttribute, RadiusPacket };
+
+use std::io::{ Error, ErrorKind };
+use std::net::{ SocketAddr, ToSocketAddrs, UdpSocket };
+use std::time::Instant;
+
+
+const MAX_PACKET_SIZE: usize = 4096;
+
+
+#[derive(Debug)]
+/// Represents ready-to-use blocking RADIUS Client, which sends packets over UDP
+///
+/// Wraps [Client](crate::client::client::Client) and uses its server, ports, secret and
+/// retransmission policy; every request is driven by [ClientTransaction](crate::client::transaction::ClientTransaction)
+pub struct UdpClient {
+    base_client: Client,
+    socket:      UdpSocket
+}
+
+impl UdpClient {
+    /// Initialises UdpClient from configured Client and binds local socket to any free port
+    ///
+    /// Local socket is bound to IPv4 or IPv6 wildcard address, depending on the address family of
+    /// Client's server
+    pub fn with_client(base_client: Client) -> Result<UdpClient, RadiusError> {
+        let remote     = resolve_remote(base_client.server(), 0)?;
+        let local_bind = if remote.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" };
+
+        UdpClient::with_local_addr(base_client, local_bind.parse()?)
+    }
+
+    /// Initialises UdpClient from configured Client and binds local socket to given address
+    pub fn with_local_addr(base_client: Client, local_addr: SocketAddr) -> Result<UdpClient, RadiusError> {
+        let socket = UdpSocket::bind(local_addr)?;
+        Ok(UdpClient { base_client, socket })
+    }
+
+    /// Returns underlying Client
+    pub fn client(&self) -> &Client {
+        &self.base_client
+    }
+
+    /// Returns address to which local socket is bound
+    pub fn local_addr(&self) -> Result<SocketAddr, RadiusError> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Sends packet to RADIUS Server and returns verified reply as RadiusPacket
+    ///
+    /// Reply is accepted only if it comes from the server's address, has the same ID as request,
+    /// its authenticator is valid and, if present, its Message-Authenticator is valid. Any other
+    /// datagram is discarded and client keeps waiting until timeout
+    pub fn send_and_receive_reply(&mut self, packet: &mut RadiusPacket) -> Result<RadiusPacket, RadiusError> {
+        let reply = self.transmit(packet)?;
+        self.base_client.initialise_packet_from_bytes(&reply)
+    }
+
+    /// Authenticates user with PAP and returns the outcome of authentication
+    ///
+    /// Access-Challenge is to be answered with [continue_challenge](Self::continue_challenge)
+    pub fn authenticate_pap(&mut self, user_name: &str, password: &str) -> Result<AuthOutcome, RadiusError> {
+        let mut packet = self.base_client.create_pap_packet(user_name, password)?;
+        self.authenticate(&mut packet)
+    }
+
+    /// Authenticates user with CHAP and returns the outcome of authentication
+    ///
+    /// Access-Challenge is to be answered with [continue_challenge](Self::continue_challenge)
+    pub fn authenticate_chap(&mut self, user_name: &str, password: &str) -> Result<AuthOutcome, RadiusError> {
+        let mut packet = self.base_client.create_chap_packet(user_name, password)?;
+        self.authenticate(&mut packet)
+    }
+
+    /// Answers Access-Challenge (ie with OTP) and returns the outcome of authentication, which
+    /// could be yet another challenge
+    pub fn continue_challenge(&mut self, challenge: &Challenge, response: &str) -> Result<AuthOutcome, RadiusError> {
+        let mut packet = self.base_client.create_challenge_response_packet(challenge, response)?;
+        self.authenticate(&mut packet)
+    }
+
+    /// Asks NAS to terminate the session(s) identified by given session identification attributes
+    /// and returns NAS's answer
+    pub fn disconnect(&mut self, session_identifiers: Vec<RadiusAttribute>) -> Result<DynAuthOutcome, RadiusError> {
+        let mut packet = self.base_client.create_disconnect_packet(session_identifiers)?;
+        self.dynamic_authorization(&mut packet)
+    }
+
+    /// Asks NAS to change authorization of the session(s) identified by given session
+    /// identification attributes to given attributes and returns NAS's answer
+    pub fn change_of_authorization(&mut self, session_identifiers: Vec<RadiusAttribute>, attributes: Vec<RadiusAttribute>) -> Result<DynAuthOutcome, RadiusError> {
+        let mut packet = self.base_client.create_change_of_authorization_packet(session_identifiers, attributes)?;
+        self.dynamic_authorization(&mut packet)
+    }
+
+    /// Sends Accounting-On, which tells RADIUS Server that NAS has started up, so sessions NAS has
+    /// left open are to be closed (RFC 2866 Section 5.1), and returns Accounting-Response
+    ///
+    /// Is to be called once NAS is up, before Start of any session is sent. Attributes identify
+    /// NAS (ie NAS-IP-Address or NAS-Identifier)
+    pub fn accounting_on(&mut self, attributes: Vec<RadiusAttribute>) -> Result<RadiusPacket, RadiusError> {
+        let mut packet = self.base_client.create_accounting_on_packet(attributes)?;
+        self.send_and_receive_reply(&mut packet)
+    }
+
+    /// Sends Accounting-Off, which tells RADIUS Server that NAS is shutting down, and returns
+    /// Accounting-Response
+    ///
+    /// Is to be called on shutdown, once Stop of every session has been sent
+    pub fn accounting_off(&mut self, attributes: Vec<RadiusAttribute>) -> Result<RadiusPacket, RadiusError> {
+        let mut packet = self.base_client.create_accounting_off_packet(attributes)?;
+        self.send_and_receive_reply(&mut packet)
+    }
+
+    fn authenticate(&mut self, packet: &mut RadiusPacket) -> Result<AuthOutcome, RadiusError> {
+        let reply = self.send_and_receive_reply(packet)?;
+        AuthOutcome::from_reply(packet, &reply)
+    }
+
+    fn dynamic_authorization(&mut self, packet: &mut RadiusPacket) -> Result<DynAuthOutcome, RadiusError> {
+        let reply = self.send_and_receive_reply(packet)?;
+        DynAuthOutcome::from_reply(packet, &reply)
+    }
+
+    fn transmit(&mut self, packet: &mut RadiusPacket) -> Result<Vec<u8>, RadiusError> {
+        let remote_port = self.base_client.port(packet.code()).ok_or_else(|| RadiusError::MalformedPacketError { error: String::from("There is no port match for packet code") })?;
+        let remote      = resolve_remote(self.base_client.server(), remote_port)?;
+
+        let mut transaction = ClientTransaction::new(&self.base_client, packet.clone())?;
+        let reply           = self.run_transaction(&mut transaction, remote);
+
+        *packet = transaction.into_packet();
+        reply
+    }
+
+    fn run_transaction(&mut self, transaction: &mut ClientTransaction, remote: SocketAddr) -> Result<Vec<u8>, RadiusError> {
+        let mut last_error = None;
+        let mut response   = [0u8; MAX_PACKET_SIZE];
+        let mut output     = transaction.start(Instant::now());
+
+        loop {
+            let deadline = match output {
+                TransactionOutput::Transmit { datagram, deadline, .. } => {
+                    self.socket.send_to(&datagram, remote)?;
+                    deadline
+                },
+                TransactionOutput::Wait { deadline }             => deadline,
+                TransactionOutput::Discarded { error, deadline } => {
+                    last_error = Some(error);
+                    deadline
+                },
+                TransactionOutput::Reply(mut reply)              => return Ok(reply.to_bytes()),
+                TransactionOutput::TimedOut                      => return Err(last_error.unwrap_or_else(|| RadiusError::SocketConnectionError(Error::new(ErrorKind::TimedOut, "RADIUS Server did not reply in time"))))
+            };
+
+            let remaining = match deadline.checked_duration_since(Instant::now()).filter(|remaining| !remaining.is_zero()) {
+                Some(remaining) => remaining,
+                None            => {
+                    output = transaction.handle_timeout(Instant::now());
+                    continue;
+                }
+            };
+            self.socket.set_read_timeout(Some(remaining))?;
+
+            output = match self.socket.recv_from(&mut response) {
+                Ok((amount, source)) if source == remote => transaction.handle_datagram(&self.base_client, &response[..amount], Instant::now()),
+                Ok(_)                                    => TransactionOutput::Wait { deadline },
+                Err(error) if error.kind() == ErrorKind::WouldBlock || error.kind() == ErrorKind::TimedOut => transaction.handle_timeout(Instant::now()),
+                Err(error)                               => return Err(RadiusError::SocketConnectionError(error))
+            };
+        }
+    }
+}
+
+impl SyncClientTrait for UdpClient {
+    fn send_packet(&mut self, packet: &mut RadiusPacket) -> Result<(), RadiusError> {
+        self.transmit(packet).map(|_| ())
+    }
+
+    fn send_and_receive_packet(&mut self, packet: &mut RadiusPacket) -> Result<Vec<u8>, RadiusError> {
+        self.transmit(packet)
+    }
+}
+
+fn resolve_remote(server: &str, port: u16) -> Result<SocketAddr, RadiusError> {
+    (server, port).to_socket_addrs()?
+        .next()
+        .ok_or_else(|| RadiusError::SocketInvalidConnectionError { error: format!("Could not resolve RADIUS Server address: {}", server) })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::retransmission::RetransmissionPolicy;
+    use crate::protocol::radius_packet::{ RadiusMsgType, TypeCode };
+    use crate::client::dynamic_authorization::ErrorCause;
+    use crate::testing::{ serve_requests, test_client };
+    use crate::tools::{ decrypt_data, integer_to_bytes };
+
+    use std::sync::atomic::{ AtomicBool, Ordering };
+    use std::time::Duration;
+
+    fn udp_client(port: u16, secret: &str) -> UdpClient {
+        UdpClient::with_client(test_client(port, secret).set_retries(1).set_timeout(1)).unwrap()
+    }
+
+    #[test]
+    fn test_send_and_receive_reply() {
+        let server_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_port   = server_socket.local_addr().unwrap().port();
+        let server_thread = serve_requests(server_socket, "secret", 1, |server, _, _| {
+            Some((TypeCode::AccessAccept, vec![server.create_attribute_by_name("Reply-Message", String::from("hello").into_bytes()).unwrap()]))
+        });
+
+        let mut client = udp_client(server_port, "secret");
+        let mut packet = client.client().create_auth_packet();
+        packet.set_attributes(vec![client.client().create_attribute_by_name("User-Name", String::from("testing").into_bytes()).unwrap()]);
+
+        let reply = client.send_and_receive_reply(&mut packet).unwrap();
+        server_thread.join().unwrap();
+
+        assert_eq!(&TypeCode::AccessAccept, reply.code());
+        assert_eq!(packet.id(), reply.id());
+        assert_eq!(b"hello", reply.attribute_by_name("Reply-Message").unwrap().value());
+    }
+
+    #[test]
+    fn test_send_and_receive_reply_wrong_secret() {
+        let server_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_port   = server_socket.local_addr().unwrap().port();
+        let server_thread = serve_requests(server_socket, "other-secret", 1, |_, _, _| Some((TypeCode::AccessAccept, vec![])));
+
+        let mut client = udp_client(server_port, "secret");
+        let mut packet = client.client().create_auth_packet();
+
+        match client.send_and_receive_reply(&mut packet) {
+            Err(error) => assert_eq!(String::from("Verification failed for incoming Radius packet: Packet authenticator mismatch"), error.to_string()),
+            _          => assert!(false)
+        }
+        server_thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_send_packet_waits_for_reply() {
+        let server_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_port   = server_socket.local_addr().unwrap().port();
+        let server_thread = serve_requests(server_socket.try_clone().unwrap(), "secret", 1, |_, _, _| Some((TypeCode::AccessAccept, vec![])));
+
+        let mut client = udp_client(server_port, "secret");
+        let mut packet = client.client().create_auth_packet();
+        client.send_packet(&mut packet).unwrap();
+        server_thread.join().unwrap();
+
+        // Server doesn't reply anymore
+        let mut packet = client.client().create_auth_packet();
+        match client.send_packet(&mut packet) {
+            Err(RadiusError::SocketConnectionError(error)) => assert_eq!(ErrorKind::TimedOut, error.kind()),
+            _                                              => assert!(false)
+        }
+    }
+
+    #[test]
+    fn test_retransmit_keeps_id_and_authenticator() {
+        let server_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_port   = server_socket.local_addr().unwrap().port();
+
+        // First transmission is "lost", reply is sent to the retransmit
+        let answered      = AtomicBool::new(false);
+        let server_thread = serve_requests(server_socket, "secret", 2, move |_, _, _| {
+            answered.swap(true, Ordering::SeqCst).then(|| (TypeCode::AccessAccept, vec![]))
+        });
+
+        let client = test_client(server_port, "secret")
+            .set_retransmission_policy(RetransmissionPolicy::default().set_initial_rt(Duration::from_millis(100)));
+
+        let mut client = UdpClient::with_client(client).unwrap();
+        let mut packet = client.client().create_auth_packet();
+
+        let reply        = client.send_and_receive_reply(&mut packet).unwrap();
+        let mut requests = server_thread.join().unwrap();
+
+        assert_eq!(&TypeCode::AccessAccept, reply.code());
+        let first = requests[0].to_bytes();
+        assert_eq!(first, requests[1].to_bytes());
+    }
+
+    #[test]
+    fn test_authenticate_pap_with_challenge() {
+        let server_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_port   = server_socket.local_addr().unwrap().port();
+
+        let server_thread = serve_requests(server_socket, "secret", 2, |server, packet, _| {
+            let password = decrypt_data(packet.attribute_by_name("Password").unwrap().value(), packet.authenticator(), b"secret");
+            assert!(packet.message_authenticator().is_ok());
+
+            match packet.attribute_by_name("State") {
+                None        => {
+                    assert_eq!(b"password", &password[..]);
+                    Some((TypeCode::AccessChallenge, vec![
+                        server.create_attribute_by_name("State",         b"state-1".to_vec()).unwrap(),
+                        server.create_attribute_by_name("Reply-Message", b"Enter OTP".to_vec()).unwrap()
+                    ]))
+                },
+                Some(state) => {
+                    assert_eq!(b"state-1", state.value());
+                    assert_eq!(b"123456",  &password[..]);
+                    Some((TypeCode::AccessAccept, vec![]))
+                }
+            }
+        });
+
+        let mut client = udp_client(server_port, "secret");
+
+        let challenge = match client.authenticate_pap("testing", "password").unwrap() {
+            AuthOutcome::Challenge(challenge) => challenge,
+            other                             => panic!("unexpected outcome: {:?}", other)
+        };
+        assert_eq!(Some("Enter OTP"), challenge.prompt());
+
+        assert!(client.continue_challenge(&challenge, "123456").unwrap().is_accept());
+        server_thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_accounting_on_and_off() {
+        let server_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_port   = server_socket.local_addr().unwrap().port();
+        let server_thread = serve_requests(server_socket, "secret", 2, |_, _, _| Some((TypeCode::AccountingResponse, vec![])));
+
+        let mut client = UdpClient::with_client(test_client(0, "secret").set_port(RadiusMsgType::ACCT, server_port)).unwrap();
+        let nas_id     = vec![client.client().create_attribute_by_name("NAS-Identifier", b"nas1".to_vec()).unwrap()];
+
+        assert_eq!(&TypeCode::AccountingResponse, client.accounting_on(nas_id.clone()).unwrap().code());
+        assert_eq!(&TypeCode::AccountingResponse, client.accounting_off(nas_id).unwrap().code());
+
+        let statuses: Vec<Vec<u8>> = server_thread.join().unwrap().iter()
+            .map(|request| request.attribute_by_name("Acct-Status-Type").unwrap().value().to_vec())
+            .collect();
+        assert_eq!(vec![integer_to_bytes(7).to_vec(), integer_to_bytes(8).to_vec()], statuses);
+    }
+
+    #[test]
+    fn test_disconnect() {
+        let server_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_port   = server_socket.local_addr().unwrap().port();
+
+        let server_thread = serve_requests(server_socket, "secret", 1, |server, packet, _| {
+            let mut expected = packet.clone();
+            expected.generate_request_authenticator("secret").unwrap();
+            assert_eq!(expected.authenticator(), packet.authenticator());
+            assert!(packet.attribute_by_name("Event-Timestamp").is_some());
+
+            Some((TypeCode::DisconnectNAK, vec![server.create_attribute_by_name("Error-Cause", integer_to_bytes(503)).unwrap()]))
+        });
+
+        let mut client          = UdpClient::with_client(test_client(0, "secret").set_port(RadiusMsgType::COA, server_port)).unwrap();
+        let session_identifiers = vec![client.client().create_attribute_by_name("Acct-Session-Id", b"session".to_vec()).unwrap()];
+
+        let outcome = client.disconnect(session_identifiers).unwrap();
+        server_thread.join().unwrap();
+
+        assert!(!outcome.is_ack());
+        assert_eq!(Some(ErrorCause::SessionContextNotFound), outcome.error_cause());
+    }
+}
@@ -16,20 +16,40 @@
 
 
 pub mod client;
-pub use client::{ client::Client, SyncClientTrait };
+pub use client::{ accounting::AccountingSession, auth::AuthOutcome, client::Client, dynamic_authorization::DynAuthOutcome, retransmission::RetransmissionPolicy, spool::AccountingSpool, transaction::ClientTransaction, udp_client::UdpClient, SyncClientTrait };
 #[cfg(all(feature = "async-radius"))]
 pub use client::AsyncClientTrait;
+#[cfg(feature = "tokio")]
+pub use client::{ failover_client::FailoverClient, multiplex_client::MultiplexClient, pool::ClientPool, tokio_client::TokioClient };
 
 pub mod server;
-pub use server::{ server::Server, SyncServerTrait };
+pub use server::{ client_resolver::{ ClientResolver, DirectoryResolver }, client_table::{ ClientEntry, ClientTable }, handler::{ HandlerOutcome, RadiusHandler, RequestContext }, overload::{ DropPolicy, OverloadStats, RequestQueue }, rate_limit::{ RateLimit, RateLimitAction, RateLimiter }, reply_cache::ReplyCache, server::Server, statistics::{ AcctStats, AuthStats, ClientStatistics, ServerStatistics }, udp_server::UdpServer, SyncServerTrait };
 #[cfg(all(feature = "async-radius"))]
-pub use server::AsyncServerTrait;
+pub use server::{ async_server::AsyncServer, client_resolver::AsyncClientResolver, AsyncServerTrait };
+#[cfg(feature = "tokio")]
+pub use server::tokio_server::TokioServer;
 
 pub mod protocol;
 pub mod tools;
 
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
+#[cfg(test)]
+mod testing;
+
 // Optional features
 pub mod features {
     #![cfg_attr(feature = "async-radius",      doc = "## Async RADIUS Server/Client Enabled")]
     #![cfg_attr(not(feature = "async-radius"), doc = "## Async RADIUS Server/Client Disabled")]
+    #![cfg_attr(feature = "eap-pwd",           doc = "## EAP-pwd Method Enabled")]
+    #![cfg_attr(not(feature = "eap-pwd"),      doc = "## EAP-pwd Method Disabled")]
+    #![cfg_attr(feature = "eap-sim-aka",       doc = "## EAP-SIM/EAP-AKA Methods Enabled")]
+    #![cfg_attr(not(feature = "eap-sim-aka"),  doc = "## EAP-SIM/EAP-AKA Methods Disabled")]
+    #![cfg_attr(feature = "tokio",             doc = "## Tokio RADIUS Server/Client Enabled")]
+    #![cfg_attr(not(feature = "tokio"),        doc = "## Tokio RADIUS Server/Client Disabled")]
+    #![cfg_attr(feature = "tokio-codec",       doc = "## Tokio RADIUS Codec Enabled")]
+    #![cfg_attr(not(feature = "tokio-codec"),  doc = "## Tokio RADIUS Codec Disabled")]
+    #![cfg_attr(feature = "metrics",           doc = "## Prometheus Metrics Enabled")]
+    #![cfg_attr(not(feature = "metrics"),      doc = "## Prometheus Metrics Disabled")]
 }
@@ -0,0 +1,434 @@
+//! Prometheus metrics of RADIUS Client and RADIUS Server
+//!
+//! Metrics are collected into the process-wide [registry], once `metrics` feature is enabled, and
+//! are exported in Prometheus text format either by [MetricsEndpoint] or by user's own HTTP
+//! server via [MetricsRegistry::render]
+//!
+//! RADIUS Server exports:
+//! * `radius_server_requests_total{socket, code, client, outcome}` - requests by their outcome:
+//!   reply code (ie `access_accept`), `duplicate`, `malformed`, `bad_authenticator`,
+//!   `unknown_type`, `dropped` or `invalid` (request from unknown address, client is empty);
+//!   Status-Server requests are not counted
+//! * `radius_server_handler_duration_seconds{socket, code}` - time handler takes to resolve request
+//!
+//! RADIUS Client exports:
+//! * `radius_client_requests_total{server, code}` - requests, which have been sent
+//! * `radius_client_round_trip_seconds{server, code}` - time between request and its reply; only
+//!   requests, which have been answered without retransmits, are measured (Karn's algorithm)
+//! * `radius_client_retransmits_total{server, code}` - retransmits of requests
+//! * `radius_client_timeouts_total{server, code}` - requests, which have not been answered
+//! * `radius_client_server_up{server}` - 1, if RADIUS Server is alive, 0 if it is dead (only
+//!   for FailoverClient and ClientPool, which require `tokio` feature)
+
+
+use crate::protocol::error::RadiusError;
+use crate::protocol::radius_packet::{ RadiusMsgType, TypeCode };
+use crate::tools::lock;
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{ ErrorKind, Read, Write };
+use std::net::{ SocketAddr, TcpListener, TcpStream };
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicBool, AtomicUsize, Ordering };
+use std::thread;
+use std::time::Duration;
+
+
+const HISTOGRAM_BUCKETS: [f64; 13]     = [0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+const MAX_HTTP_REQUEST_SIZE: usize     = 8192;
+const HTTP_TIMEOUT: Duration           = Duration::from_secs(5);
+const MAX_CONCURRENT_SCRAPES: usize    = 8;
+const ACCEPT_ERROR_PAUSE: Duration     = Duration::from_millis(100);
+const STOP_POLL_TIMEOUT: Duration      = Duration::from_millis(50);
+
+static REGISTRY: MetricsRegistry = MetricsRegistry { families: Mutex::new(Vec::new()) };
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MetricKind {
+    Counter,
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    Gauge,
+    Histogram
+}
+
+#[derive(Debug)]
+/// Describes metric family: its name, help, type and label names
+pub(crate) struct Metric {
+    name:   &'static str,
+    help:   &'static str,
+    kind:   MetricKind,
+    labels: &'static [&'static str]
+}
+
+pub(crate) const SERVER_REQUESTS: Metric = Metric {
+    name:   "radius_server_requests_total",
+    help:   "RADIUS requests received by RADIUS Server, by outcome",
+    kind:   MetricKind::Counter,
+    labels: &["socket", "code", "client", "outcome"]
+};
+
+pub(crate) const SERVER_HANDLER_DURATION: Metric = Metric {
+    name:   "radius_server_handler_duration_seconds",
+    help:   "Time RADIUS Server's handler takes to resolve request",
+    kind:   MetricKind::Histogram,
+    labels: &["socket", "code"]
+};
+
+pub(crate) const CLIENT_REQUESTS: Metric = Metric {
+    name:   "radius_client_requests_total",
+    help:   "RADIUS requests sent by RADIUS Client",
+    kind:   MetricKind::Counter,
+    labels: &["server", "code"]
+};
+
+pub(crate) const CLIENT_ROUND_TRIP: Metric = Metric {
+    name:   "radius_client_round_trip_seconds",
+    help:   "Round-trip time of RADIUS requests, which have been answered without retransmits",
+    kind:   MetricKind::Histogram,
+    labels: &["server", "code"]
+};
+
+pub(crate) const CLIENT_RETRANSMITS: Metric = Metric {
+    name:   "radius_client_retransmits_total",
+    help:   "Retransmits of RADIUS requests",
+    kind:   MetricKind::Counter,
+    labels: &["server", "code"]
+};
+
+pub(crate) const CLIENT_TIMEOUTS: Metric = Metric {
+    name:   "radius_client_timeouts_total",
+    help:   "RADIUS requests, which have not been answered",
+    kind:   MetricKind::Counter,
+    labels: &["server", "code"]
+};
+
+#[cfg(feature = "tokio")]
+pub(crate) const CLIENT_SERVER_UP: Metric = Metric {
+    name:   "radius_client_server_up",
+    help:   "Whether RADIUS Server is alive (1) or dead (0)",
+    kind:   MetricKind::Gauge,
+    labels: &["server"]
+};
+
+#[derive(Debug, Clone)]
+enum Series {
+    Counter(u64),
+    Gauge(f64),
+    Histogram { buckets: [u64; 13], sum: f64, count: u64 }
+}
+
+#[derive(Debug)]
+struct Family {
+    metric: &'static Metric,
+    series: HashMap<Vec<String>, Series>
+}
+
+#[derive(Debug)]
+/// Holds all metrics of RADIUS Client and RADIUS Server
+pub struct MetricsRegistry {
+    families: Mutex<Vec<Family>>
+}
+
+/// Returns process-wide MetricsRegistry
+pub fn registry() -> &'static MetricsRegistry {
+    &REGISTRY
+}
+
+impl MetricsRegistry {
+    /// Renders all metrics in Prometheus text exposition format (version 0.0.4)
+    pub fn render(&self) -> String {
+        let families = lock(&self.families);
+        let mut text = String::new();
+
+        for family in families.iter() {
+            let metric = family.metric;
+            let kind   = match metric.kind {
+                MetricKind::Counter   => "counter",
+                MetricKind::Gauge     => "gauge",
+                MetricKind::Histogram => "histogram"
+            };
+            let _ = writeln!(text, "# HELP {} {}", metric.name, metric.help);
+            let _ = writeln!(text, "# TYPE {} {}", metric.name, kind);
+
+            let mut series: Vec<(&Vec<String>, &Series)> = family.series.iter().collect();
+            series.sort_by(|left, right| left.0.cmp(right.0));
+
+            for (values, series) in series {
+                let labels = format_labels(metric.labels, values);
+                match series {
+                    Series::Counter(value)                    => { let _ = writeln!(text, "{}{{{}}} {}", metric.name, labels, value); },
+                    Series::Gauge(value)                      => { let _ = writeln!(text, "{}{{{}}} {}", metric.name, labels, value); },
+                    Series::Histogram { buckets, sum, count } => {
+                        let separator = if labels.is_empty() { "" } else { "," };
+                        for (bound, bucket) in HISTOGRAM_BUCKETS.iter().zip(buckets.iter()) {
+                            let _ = writeln!(text, "{}_bucket{{{}{}le=\"{}\"}} {}", metric.name, labels, separator, bound, bucket);
+                        }
+                        let _ = writeln!(text, "{}_bucket{{{}{}le=\"+Inf\"}} {}", metric.name, labels, separator, count);
+                        let _ = writeln!(text, "{}_sum{{{}}} {}", metric.name, labels, sum);
+                        let _ = writeln!(text, "{}_count{{{}}} {}", metric.name, labels, count);
+                    }
+                }
+            }
+        }
+        text
+    }
+
+    /// Increments counter with given label values by 1
+    pub(crate) fn increment_counter(&self, metric: &'static Metric, labels: &[&str]) {
+        self.update(metric, labels, |series| if let Series::Counter(value) = series {
+            *value += 1;
+        });
+    }
+
+    /// Sets gauge with given label values
+    #[cfg(feature = "tokio")]
+    pub(crate) fn set_gauge(&self, metric: &'static Metric, labels: &[&str], new_value: f64) {
+        self.update(metric, labels, |series| if let Series::Gauge(value) = series {
+            *value = new_value;
+        });
+    }
+
+    /// Adds observation (ie duration in seconds) to histogram with given label values
+    pub(crate) fn observe(&self, metric: &'static Metric, labels: &[&str], value: f64) {
+        self.update(metric, labels, |series| if let Series::Histogram { buckets, sum, count } = series {
+            for (bound, bucket) in HISTOGRAM_BUCKETS.iter().zip(buckets.iter_mut()) {
+                if value <= *bound {
+                    *bucket += 1;
+                }
+            }
+            *sum   += value;
+            *count += 1;
+        });
+    }
+
+    fn update(&self, metric: &'static Metric, labels: &[&str], update: impl FnOnce(&mut Series)) {
+        let mut families = lock(&self.families);
+
+        let index = match families.iter().position(|family| family.metric.name == metric.name) {
+            Some(index) => index,
+            None        => {
+                families.push(Family { metric, series: HashMap::new() });
+                families.len() - 1
+            }
+        };
+        let series = families[index].series.entry(labels.iter().map(|label| label.to_string()).collect()).or_insert_with(|| match metric.kind {
+            MetricKind::Counter   => Series::Counter(0),
+            MetricKind::Gauge     => Series::Gauge(0.0),
+            MetricKind::Histogram => Series::Histogram { buckets: [0; 13], sum: 0.0, count: 0 }
+        });
+        update(series);
+    }
+}
+
+#[derive(Debug)]
+/// Represents small HTTP server, which exports [registry] on `/metrics` path
+pub struct MetricsEndpoint {
+    listener: TcpListener,
+    stopped:  AtomicBool
+}
+
+impl MetricsEndpoint {
+    /// Binds MetricsEndpoint to given address (ie "0.0.0.0:9812")
+    pub fn bind(address: &str) -> Result<MetricsEndpoint, RadiusError> {
+        Ok(MetricsEndpoint { listener: TcpListener::bind(address)?, stopped: AtomicBool::new(false) })
+    }
+
+    /// Returns address MetricsEndpoint is bound to
+    pub fn local_addr(&self) -> Result<SocketAddr, RadiusError> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Serves scrapes until MetricsEndpoint is [stopped](MetricsEndpoint::stop), every scrape on
+    /// its own short-lived thread, so slow client doesn't stall other scrapes
+    ///
+    /// At most 8 scrapes are served at once, further connections are closed straight away.
+    /// Neither scrape, which fails (ie client disconnects), nor failure to accept connection (ie
+    /// when process runs out of file descriptors, accept is retried after a short pause) stops
+    /// MetricsEndpoint
+    ///
+    /// MetricsEndpoint, which has been stopped before it is served, returns straight away; once
+    /// serve returns, MetricsEndpoint could be served again
+    pub fn serve(&self) -> Result<(), RadiusError> {
+        self.listener.set_nonblocking(true)?;
+        let scrapes = Arc::new(AtomicUsize::new(0));
+
+        while !self.stopped.load(Ordering::Relaxed) {
+            let stream = match self.listener.accept() {
+                Ok((stream, _))                                     => stream,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(STOP_POLL_TIMEOUT);
+                    continue;
+                },
+                Err(_)                                              => {
+                    thread::sleep(ACCEPT_ERROR_PAUSE);
+                    continue;
+                }
+            };
+            if scrapes.fetch_add(1, Ordering::SeqCst) >= MAX_CONCURRENT_SCRAPES {
+                scrapes.fetch_sub(1, Ordering::SeqCst);
+                continue;
+            }
+
+            // Scrape is counted until its guard is dropped: when scrape is done, when it panics or,
+            // should thread fail to spawn, together with the closure
+            let scrape = InFlightScrape(Arc::clone(&scrapes));
+            let _      = thread::Builder::new().name(String::from("radius-metrics")).spawn(move || {
+                let _scrape = scrape;
+                let _       = respond(stream);
+            });
+        }
+        // Stop is only cleared once serving is done, so stop issued before serve is not lost
+        self.stopped.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Stops serving scrapes; [serve](MetricsEndpoint::serve) returns within 50 milliseconds,
+    /// while scrapes, which are being served, are finished on their own threads
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Decrements number of scrapes, which are being served, once it is dropped
+struct InFlightScrape(Arc<AtomicUsize>);
+
+impl Drop for InFlightScrape {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn respond(mut stream: TcpStream) -> std::io::Result<()> {
+    // Accepted stream could inherit non-blocking mode of the listener on some platforms
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+
+    let mut request = Vec::new();
+    let mut buffer  = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < MAX_HTTP_REQUEST_SIZE {
+        match stream.read(&mut buffer)? {
+            0      => break,
+            amount => request.extend_from_slice(&buffer[..amount])
+        }
+    }
+
+    let request_line = String::from_utf8_lossy(&request);
+    let mut parts    = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK",        registry().render()),
+        _                               => ("404 Not Found", String::from("Not Found\n"))
+    };
+
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body)?;
+    stream.flush()
+}
+
+fn format_labels(names: &[&str], values: &[String]) -> String {
+    names.iter().zip(values.iter())
+        .map(|(name, value)| format!("{}=\"{}\"", name, value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// Returns label of RADIUS Message Type of the socket
+pub(crate) fn socket_label(msg_type: RadiusMsgType) -> &'static str {
+    match msg_type {
+        RadiusMsgType::AUTH => "auth",
+        RadiusMsgType::ACCT => "acct",
+        RadiusMsgType::COA  => "coa"
+    }
+}
+
+/// Returns label of RADIUS packet code
+pub(crate) fn code_label(code: &TypeCode) -> &'static str {
+    match code {
+        TypeCode::AccessRequest      => "access_request",
+        TypeCode::AccessAccept       => "access_accept",
+        TypeCode::AccessReject       => "access_reject",
+        TypeCode::AccountingRequest  => "accounting_request",
+        TypeCode::AccountingResponse => "accounting_response",
+        TypeCode::AccessChallenge    => "access_challenge",
+        TypeCode::StatusServer       => "status_server",
+        TypeCode::StatusClient       => "status_client",
+        TypeCode::DisconnectRequest  => "disconnect_request",
+        TypeCode::DisconnectACK      => "disconnect_ack",
+        TypeCode::DisconnectNAK      => "disconnect_nak",
+        TypeCode::CoARequest         => "coa_request",
+        TypeCode::CoAACK             => "coa_ack",
+        TypeCode::CoANAK             => "coa_nak"
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_COUNTER: Metric = Metric {
+        name:   "radius_test_total",
+        help:   "Test counter",
+        kind:   MetricKind::Counter,
+        labels: &["code"]
+    };
+
+    const TEST_HISTOGRAM: Metric = Metric {
+        name:   "radius_test_seconds",
+        help:   "Test histogram",
+        kind:   MetricKind::Histogram,
+        labels: &["code"]
+    };
+
+    #[test]
+    fn test_render_and_serve() {
+        registry().increment_counter(&TEST_COUNTER, &["access_\"request\""]);
+        registry().increment_counter(&TEST_COUNTER, &["access_\"request\""]);
+        registry().observe(&TEST_HISTOGRAM, &["access_request"], 0.003);
+
+        let text = registry().render();
+        assert!(text.contains("# TYPE radius_test_total counter\n"));
+        assert!(text.contains("radius_test_total{code=\"access_\\\"request\\\"\"} 2\n"));
+        assert!(text.contains("radius_test_seconds_bucket{code=\"access_request\",le=\"0.0025\"} 0\n"));
+        assert!(text.contains("radius_test_seconds_bucket{code=\"access_request\",le=\"0.005\"} 1\n"));
+        assert!(text.contains("radius_test_seconds_bucket{code=\"access_request\",le=\"+Inf\"} 1\n"));
+        assert!(text.contains("radius_test_seconds_count{code=\"access_request\"} 1\n"));
+
+        let endpoint = MetricsEndpoint::bind("127.0.0.1:0").unwrap();
+        let address  = endpoint.local_addr().unwrap();
+
+        let scrape = |path: &str| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        thread::scope(|scope| {
+            let serving = scope.spawn(|| endpoint.serve());
+
+            // Client, which never sends its request, doesn't hold up other scrapes
+            let _idle    = TcpStream::connect(address).unwrap();
+            let started  = std::time::Instant::now();
+            let response = scrape("/metrics");
+            assert!(started.elapsed() < HTTP_TIMEOUT);
+            assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+            assert!(response.contains("radius_test_total{code=\"access_\\\"request\\\"\"} 2\n"));
+            assert!(scrape("/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+            endpoint.stop();
+            assert!(serving.join().unwrap().is_ok());
+        });
+    }
+
+    #[test]
+    fn test_stop_before_serve() {
+        let endpoint = MetricsEndpoint::bind("127.0.0.1:0").unwrap();
+
+        endpoint.stop();
+        assert!(endpoint.serve().is_ok());
+        assert!(!endpoint.stopped.load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,222 @@
+//! RADIUS datagram codec for tokio-util
+//!
+//! Allows to compose RADIUS into Tokio pipelines, ie with `tokio_util::udp::UdpFramed`, which
+//! then yields `(RadiusPacket, SocketAddr)` items and accepts `(RadiusPacket, SocketAddr)` to send
+
+
+use super::dictionary::Dictionary;
+use super::error::RadiusError;
+use super::radius_packet::{ RadiusPacket, TypeCode };
+
+use bytes::BytesMut;
+use md5::{ Digest, Md5 };
+use std::sync::Arc;
+use tokio_util::codec::{ Decoder, Encoder };
+
+
+#[derive(Debug, Clone)]
+/// Encodes/decodes single RADIUS packet per UDP datagram
+///
+/// When request packet carries Message-Authenticator attribute, codec verifies it on decode and
+/// (re)generates it on encode with the codec's secret. Request Authenticator of Accounting-Request,
+/// CoA-Request and Disconnect-Request is generated on encode and verified on decode (RFC 2866
+/// Section 3 & RFC 5176 Section 2.3). Reply packets' authenticators depend on request
+/// authenticator, so they are left untouched and are to be checked by the caller
+pub struct RadiusCodec {
+    dictionary: Arc<Dictionary>,
+    secret:     String
+}
+
+impl RadiusCodec {
+    /// Initialises RadiusCodec with dictionary, used to decode attributes, and secret
+    pub fn new(dictionary: Arc<Dictionary>, secret: String) -> RadiusCodec {
+        RadiusCodec { dictionary, secret }
+    }
+
+    /// Returns codec's dictionary
+    pub fn dictionary(&self) -> &Dictionary {
+        &self.dictionary
+    }
+
+    /// Returns codec's secret
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+
+    fn verify_request_authenticator(&self, packet: &RadiusPacket, datagram: &[u8]) -> Result<(), RadiusError> {
+        let mut md5_hasher = Md5::new();
+
+        md5_hasher.update(&datagram[0..4]);        // Append request type code, request ID and request length
+        md5_hasher.update([0; 16]);                // Append zeroed authenticator
+        md5_hasher.update(&datagram[20..]);        // Append request attributes
+        md5_hasher.update(self.secret.as_bytes()); // Append codec's secret
+
+        if md5_hasher.finalize().as_slice() == packet.authenticator() {
+            Ok(())
+        } else {
+            Err( RadiusError::ValidationError { error: String::from("Packet authenticator mismatch") } )
+        }
+    }
+
+    fn verify_message_authenticator(&self, packet: &RadiusPacket, datagram: &[u8]) -> Result<(), RadiusError> {
+        let received_message_authenticator = packet.message_authenticator()?;
+
+        let mut expected_packet = RadiusPacket::initialise_packet_from_bytes(&self.dictionary, datagram)?;
+        if has_request_authenticator(packet.code()) {
+            // Message-Authenticator is calculated with zeroed Request Authenticator (RFC 3579 Section 3.2)
+            expected_packet.override_authenticator([0; 16].to_vec());
+        }
+        expected_packet.generate_message_authenticator(&self.secret)?;
+
+        if expected_packet.message_authenticator()? == received_message_authenticator {
+            Ok(())
+        } else {
+            Err( RadiusError::ValidationError { error: String::from("Packet Message-Authenticator mismatch") } )
+        }
+    }
+}
+
+impl Decoder for RadiusCodec {
+    type Item  = RadiusPacket;
+    type Error = RadiusError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<RadiusPacket>, RadiusError> {
+        if src.is_empty() {
+            return Ok(None)
+        }
+        // Whole datagram is consumed upfront, so malformed datagram is not decoded again after
+        // error is reported
+        let datagram = src.split();
+        let packet   = RadiusPacket::initialise_packet_from_bytes(&self.dictionary, &datagram)?;
+        let length   = u16::from_be_bytes([datagram[2], datagram[3]]) as usize;
+
+        if has_request_authenticator(packet.code()) {
+            self.verify_request_authenticator(&packet, &datagram[..length])?;
+        }
+        if is_request(packet.code()) && packet.message_authenticator().is_ok() {
+            self.verify_message_authenticator(&packet, &datagram)?;
+        }
+        Ok(Some(packet))
+    }
+}
+
+impl Encoder<RadiusPacket> for RadiusCodec {
+    type Error = RadiusError;
+
+    fn encode(&mut self, mut packet: RadiusPacket, dst: &mut BytesMut) -> Result<(), RadiusError> {
+        if has_request_authenticator(packet.code()) {
+            // Message-Authenticator (if any) is regenerated along with Request Authenticator
+            packet.generate_request_authenticator(&self.secret)?;
+        } else if is_request(packet.code()) && packet.message_authenticator().is_ok() {
+            packet.generate_message_authenticator(&self.secret)?;
+        }
+        dst.extend_from_slice(&packet.to_bytes());
+        Ok(())
+    }
+}
+
+fn is_request(code: &TypeCode) -> bool {
+    matches!(code, TypeCode::AccessRequest | TypeCode::AccountingRequest | TypeCode::StatusServer | TypeCode::StatusClient | TypeCode::DisconnectRequest | TypeCode::CoARequest)
+}
+
+fn has_request_authenticator(code: &TypeCode) -> bool {
+    matches!(code, TypeCode::AccountingRequest | TypeCode::DisconnectRequest | TypeCode::CoARequest)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::radius_packet::RadiusAttribute;
+
+    use futures::{ SinkExt, StreamExt };
+    use tokio::net::UdpSocket;
+    use tokio_util::udp::UdpFramed;
+
+    fn test_codec() -> RadiusCodec {
+        let dictionary = Dictionary::from_file("./dict_examples/integration_dict").unwrap();
+        RadiusCodec::new(Arc::new(dictionary), String::from("secret"))
+    }
+
+    fn test_packet(codec: &RadiusCodec) -> RadiusPacket {
+        let mut packet = RadiusPacket::initialise_packet(TypeCode::AccessRequest);
+        packet.set_attributes(vec![
+            RadiusAttribute::create_by_name(codec.dictionary(), "User-Name",             String::from("testing").into_bytes()).unwrap(),
+            RadiusAttribute::create_by_name(codec.dictionary(), "Message-Authenticator", [0; 16].to_vec()).unwrap()
+        ]);
+        packet
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let mut codec  = test_codec();
+        let packet     = test_packet(&codec);
+        let packet_id  = packet.id();
+        let mut buffer = BytesMut::new();
+
+        codec.encode(packet, &mut buffer).unwrap();
+        let decoded = codec.decode(&mut buffer).unwrap().unwrap();
+
+        assert_eq!(packet_id, decoded.id());
+        assert_ne!(&[0u8; 16], decoded.message_authenticator().unwrap());
+        assert!(buffer.is_empty());
+        assert!(codec.decode(&mut buffer).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_decode_message_authenticator_mismatch() {
+        let mut codec  = test_codec();
+        let mut packet = test_packet(&codec);
+        let mut buffer = BytesMut::from(&packet.to_bytes()[..]);
+
+        match codec.decode(&mut buffer) {
+            Err(error) => assert_eq!(String::from("Verification failed for incoming Radius packet: Packet Message-Authenticator mismatch"), error.to_string()),
+            _          => assert!(false)
+        }
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_encode_decode_request_authenticator() {
+        let mut codec  = test_codec();
+        let mut packet = RadiusPacket::initialise_packet(TypeCode::CoARequest);
+        packet.set_attributes(vec![
+            RadiusAttribute::create_by_name(codec.dictionary(), "User-Name",             String::from("testing").into_bytes()).unwrap(),
+            RadiusAttribute::create_by_name(codec.dictionary(), "Message-Authenticator", [0; 16].to_vec()).unwrap()
+        ]);
+        let mut buffer = BytesMut::new();
+
+        codec.encode(packet.clone(), &mut buffer).unwrap();
+        let mut expected = packet.clone();
+        expected.generate_request_authenticator(codec.secret()).unwrap();
+        assert_eq!(&expected.to_bytes()[..], &buffer[..]);
+
+        let decoded = codec.decode(&mut buffer).unwrap().unwrap();
+        assert_eq!(expected.authenticator(), decoded.authenticator());
+
+        // Random authenticator of initialised packet is not a valid Request Authenticator
+        let mut buffer = BytesMut::from(&packet.to_bytes()[..]);
+        match codec.decode(&mut buffer) {
+            Err(error) => assert_eq!(String::from("Verification failed for incoming Radius packet: Packet authenticator mismatch"), error.to_string()),
+            _          => assert!(false)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_udp_framed() {
+        let codec      = test_codec();
+        let packet     = test_packet(&codec);
+        let packet_id  = packet.id();
+        let mut server = UdpFramed::new(UdpSocket::bind("127.0.0.1:0").await.unwrap(), codec.clone());
+        let mut client = UdpFramed::new(UdpSocket::bind("127.0.0.1:0").await.unwrap(), codec);
+
+        let server_addr = server.get_ref().local_addr().unwrap();
+        let client_addr = client.get_ref().local_addr().unwrap();
+
+        client.send((packet, server_addr)).await.unwrap();
+        let (received, source) = server.next().await.unwrap().unwrap();
+
+        assert_eq!(packet_id,   received.id());
+        assert_eq!(client_addr, source);
+    }
+}
@@ -6,7 +6,7 @@ use std::io::{self, BufRead};
 
 use super::error::RadiusError;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 /// Represents a list of supported data types
 /// as defined in RFC 2865 & RFC 8044
 pub enum SupportedAttributeTypes {
@@ -49,7 +49,7 @@ pub enum SupportedAttributeTypes {
 }
 
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 /// Represents an ATTRIBUTE from RADIUS dictionary file
 pub struct DictionaryAttribute {
     /*
@@ -80,7 +80,7 @@ impl DictionaryAttribute {
 }
 
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 /// Represents a VALUE from RADIUS dictionary file
 pub struct DictionaryValue {
     attribute_name: String,
@@ -107,7 +107,7 @@ impl DictionaryValue {
 }
 
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 /// Represents a VENDOR from RADIUS dictionary file
 pub struct DictionaryVendor {
     name: String,
@@ -117,7 +117,7 @@ pub struct DictionaryVendor {
 
 const COMMENT_PREFIX: &str = "#";
 
-#[derive(Debug, Default, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
 /// Represents RADIUS dictionary
 pub struct Dictionary {
     attributes: Vec<DictionaryAttribute>,
@@ -2,6 +2,8 @@
 //! states
 
 
+use crate::server::overload::{ RequestQueue, ShedReason };
+
 use thiserror::Error;
 
 // TODO - https://rust-lang.github.io/api-guidelines/naming.html#c-word-order
@@ -59,10 +61,65 @@ pub enum RadiusError {
         /// Error definition received from crate
         error: std::io::Error
     },
+    /// Error happens, when reply to RADIUS request fails one of RADIUS Client checks
+    #[error("Verification failed for incoming Radius packet: {error}")]
+    InvalidReplyError            {
+        /// Check, which reply has failed
+        kind:  InvalidReplyKind,
+        /// Error definition received from crate
+        error: String
+    },
     /// Error happens, when wrong RADIUS Code is supplied
     #[error("Supplied RADIUS Code is not supported by this library: {error}")]
     UnsupportedTypeCodeError     {
         /// Error definition received from crate
         error: String
     },
+    /// Error happens, when EAP conversation cannot be carried on: EAP-Message is missing or
+    /// malformed, or EAP method has failed on Server's side
+    #[error("EAP error: {error}")]
+    EapError                     {
+        /// Error definition received from crate
+        error: String
+    },
+    /// Error happens, when Accounting spool cannot be read or written, is corrupted, is full or
+    /// is empty
+    #[error("Accounting spool error: {error}")]
+    SpoolError                   {
+        /// Error definition received from crate
+        error: String
+    },
+    /// Error happens, when RADIUS request handler panics, while it resolves request
+    #[error("RADIUS request handler has panicked: {error}")]
+    HandlerPanicError            {
+        /// Panic message of the handler
+        error: String
+    },
+    /// Error happens, when RADIUS Server sheds request because of overload
+    #[error("{queue:?} request has been shed: {reason}")]
+    RequestShedError             {
+        /// Queue, request has been shed from
+        queue:  RequestQueue,
+        /// Why request has been shed
+        reason: ShedReason
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Represents the check, which reply to RADIUS request has failed
+pub enum InvalidReplyKind {
+    /// Reply is truncated or its attributes could not be parsed
+    Malformed,
+    /// Reply ID doesn't match request ID
+    IdentifierMismatch,
+    /// Reply code is not a valid answer to request code (ie Accounting-Response to Access-Request)
+    UnexpectedCode,
+    /// Response Authenticator is invalid
+    AuthenticatorMismatch,
+    /// Message-Authenticator is required, but is missing
+    MissingMessageAuthenticator,
+    /// Message-Authenticator is invalid
+    MessageAuthenticatorMismatch,
+    /// Attribute value doesn't match its dictionary type
+    InvalidAttribute
 }
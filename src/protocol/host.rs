@@ -64,8 +64,10 @@ impl Host{
     pub fn port(&self, code: &TypeCode) -> Option<u16> {
         match code {
             TypeCode::AccessRequest     => Some(self.auth_port),
+            TypeCode::StatusServer      => Some(self.auth_port),
             TypeCode::AccountingRequest => Some(self.acct_port),
             TypeCode::CoARequest        => Some(self.coa_port),
+            TypeCode::DisconnectRequest => Some(self.coa_port),
             _                           => None
         }
     }
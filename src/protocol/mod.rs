@@ -7,9 +7,14 @@
 //! Client to RADIUS Server and/or RADIUS Server to RADIUS Client
 //!
 //! `error` module - represents custom errors defined for `radius-rust` crate
+//!
+//! `codec` module - represents tokio-util codec, that encodes/decodes RADIUS packets to/from UDP
+//! datagrams (only available with `tokio-codec` feature)
 
 
 pub mod dictionary;
 pub mod radius_packet;
 pub(crate) mod host;
 pub mod error;
+#[cfg(feature = "tokio-codec")]
+pub mod codec;
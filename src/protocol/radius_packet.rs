@@ -14,7 +14,7 @@ use crate::tools::{
 };
 
 use hmac::{ Hmac, Mac };
-use md5::Md5;
+use md5::{ Digest, Md5 };
 
 use rand::{ thread_rng, Rng };
 use rand::distributions::{ Distribution, Uniform };
@@ -27,7 +27,7 @@ use std::fmt;
 type HmacMd5 = Hmac<Md5>;
 
 
-#[derive(PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 /// Allowed types of RADIUS messages/packets
 ///
 /// Mainly used in RADIUS Server implementation to distinguish between sockets and functions, that should
@@ -52,7 +52,7 @@ impl fmt::Display for RadiusMsgType {
 }
 
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 /// Contains all supported Codes of RADIUS message/packet
 /// as defined in RFC 2865 & RFC 3576
 pub enum TypeCode {
@@ -130,7 +130,7 @@ impl TypeCode {
 }
 
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 /// Represents an attribute, which would be sent to RADIUS Server/client as a part of RadiusPacket
 pub struct RadiusAttribute {
     id:    u8,
@@ -354,7 +354,7 @@ impl RadiusAttribute {
 }
 
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 /// Represents RADIUS packet
 pub struct RadiusPacket {
     id:            u8,
@@ -393,11 +393,14 @@ impl RadiusPacket {
         let mut last_index = 20;
 
         while last_index != packet_len {
+            if last_index + 2 > packet_len {
+                return Err( RadiusError::MalformedPacketError {error:String::from("attribute header exceeds packet length")} )
+            }
             let attr_id     = bytes[last_index];
             let attr_length = bytes[last_index + 1] as usize;
 
-            if attr_length == 0 {
-                return Err( RadiusError::MalformedPacketError {error:format!("attribute with ID: {} has invalid length 0", attr_id)} )
+            if attr_length < 2 || last_index + attr_length > packet_len {
+                return Err( RadiusError::MalformedPacketError {error:format!("attribute with ID: {} has invalid length {}", attr_id, attr_length)} )
             }
 
             let attr_value  = &bytes[(last_index + 2)..=(last_index + attr_length - 1)];
@@ -469,6 +472,43 @@ impl RadiusPacket {
         Ok(())
     }
 
+    /// Overrides value of RadiusAttribute with given id
+    ///
+    /// Note: would fail if RadiusPacket has no such attribute defined
+    pub fn override_attribute_by_id(&mut self, id: u8, new_value: Vec<u8>) -> Result<(), RadiusError> {
+        match self.attributes.iter_mut().find(|attr| attr.id() == id) {
+            Some(attr) => {
+                attr.override_value(new_value);
+                Ok(())
+            },
+            _          => Err( RadiusError::MalformedPacketError {error:format!("attribute with ID: {} not found in packet", id)} )
+        }
+    }
+
+    /// Generates Request Authenticator for Accounting-Request, CoA-Request and Disconnect-Request
+    /// packets, as defined in RFC 2866 Section 3 and RFC 5176 Section 2.3
+    ///
+    /// Note: if RadiusPacket has Message-Authenticator attribute, it is regenerated as well (with
+    /// Request Authenticator set to zeros, as required by RFC 3579 Section 3.2)
+    pub fn generate_request_authenticator(&mut self, secret: &str) -> Result<(), RadiusError> {
+        // Step 1. Set Request Authenticator to an array of 16 zeros
+        self.override_authenticator([0; 16].to_vec());
+
+        // Step 2. Calculate Message-Authenticator, if it is present
+        if self.message_authenticator().is_ok() {
+            self.generate_message_authenticator(secret)?;
+        }
+
+        // Step 3. Set Request Authenticator to MD5 of the entire RadiusPacket followed by secret
+        let mut md5_hasher = Md5::new();
+        md5_hasher.update(self.to_bytes());
+        md5_hasher.update(secret.as_bytes());
+
+        self.override_authenticator(md5_hasher.finalize().to_vec());
+
+        Ok(())
+    }
+
     /// Returns Message-Authenticator value, if exists in RadiusPacket
     pub fn message_authenticator(&self) -> Result<&[u8], RadiusError> {
         match self.attributes.iter().find(|attr| attr.name() == "Message-Authenticator") {
@@ -828,4 +868,24 @@ mod tests {
 
         assert_eq!(expected_message_authenticator, packet.message_authenticator().unwrap());
     }
+
+    #[test]
+    fn test_generate_request_authenticator() {
+        let dict       = Dictionary::from_file("./dict_examples/integration_dict").unwrap();
+        let secret     = "secret";
+        let mut packet = RadiusPacket::initialise_packet(TypeCode::AccountingRequest);
+
+        packet.set_attributes(vec![ RadiusAttribute::create_by_name(&dict, "User-Name", String::from("testing").into_bytes()).unwrap() ]);
+        packet.override_id(220);
+        packet.generate_request_authenticator(secret).unwrap();
+
+        let mut packet_bytes = packet.to_bytes();
+        packet_bytes[4..20].copy_from_slice(&[0; 16]);
+
+        let mut md5_hasher = Md5::new();
+        md5_hasher.update(&packet_bytes);
+        md5_hasher.update(secret.as_bytes());
+
+        assert_eq!(md5_hasher.finalize().as_slice(), packet.authenticator());
+    }
 }
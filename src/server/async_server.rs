@@ -0,0 +1,351 @@
+//! Runtime-agnostic core of Async RADIUS Server
+//!
+//! [AsyncServer] doesn't own any socket and doesn't spawn any task, so it works with any async
+//! runtime (ie Tokio or async-std): runtime-specific code receives datagrams, waits for
+//! [acquire](AsyncServer::acquire) and spawns [handle_datagram](AsyncServer::handle_datagram), which
+//! returns the reply to be sent back. For Tokio see [TokioServer](crate::server::tokio_server::TokioServer)
+
+
+use crate::protocol::error::RadiusError;
+use crate::protocol::radius_packet::{ RadiusMsgType, RadiusPacket, TypeCode };
+use crate::server::AsyncServerTrait;
+use crate::server::handler::{ HandlerOutcome, RequestContext };
+use crate::server::server::{ Admission, Server };
+use crate::tools::lock;
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{ Arc, Mutex };
+use std::task::{ Context, Poll, Waker };
+use std::time::Instant;
+
+
+const DEFAULT_MAX_CONCURRENCY: usize = 1024;
+
+
+#[derive(Debug)]
+/// Represents Async RADIUS Server, which resolves requests with user's handler and limits the
+/// number of requests, which are resolved at once
+///
+/// AsyncServer is cheap to clone; clones share Server, handler and concurrency limit
+pub struct AsyncServer<H> {
+    base_server: Arc<Server>,
+    handler:     Arc<H>,
+    limit:       Arc<ConcurrencyLimit>
+}
+
+impl<H> Clone for AsyncServer<H> {
+    fn clone(&self) -> AsyncServer<H> {
+        AsyncServer {
+            base_server: Arc::clone(&self.base_server),
+            handler:     Arc::clone(&self.handler),
+            limit:       Arc::clone(&self.limit)
+        }
+    }
+}
+
+impl<H: AsyncServerTrait + Send + Sync> AsyncServer<H> {
+    /// Initialises AsyncServer from configured Server and handler, which resolves at most 1024
+    /// requests at once
+    pub fn new(base_server: Arc<Server>, handler: H) -> AsyncServer<H> {
+        AsyncServer {
+            base_server,
+            handler: Arc::new(handler),
+            limit:   Arc::new(ConcurrencyLimit::new(DEFAULT_MAX_CONCURRENCY))
+        }
+    }
+
+    // === Builder for AsyncServer ===
+    /// Sets maximum number of requests, which are resolved at once (at least 1)
+    pub fn set_max_concurrency(mut self, max_concurrency: usize) -> AsyncServer<H> {
+        self.limit = Arc::new(ConcurrencyLimit::new(std::cmp::max(max_concurrency, 1)));
+        self
+    }
+    // ===================
+
+    /// Returns underlying Server
+    pub fn server(&self) -> &Server {
+        &self.base_server
+    }
+
+    /// Returns handler
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// Returns maximum number of requests, which are resolved at once
+    pub fn max_concurrency(&self) -> usize {
+        self.limit.max
+    }
+
+    /// Returns number of requests, which are being resolved
+    pub fn in_flight(&self) -> usize {
+        lock(&self.limit.state).in_flight
+    }
+
+    /// Waits until one more request could be resolved
+    ///
+    /// Request is counted as being resolved until returned permit is dropped; while it waits,
+    /// caller is expected to stop receiving datagrams, so the backlog stays in socket buffer
+    pub fn acquire(&self) -> Acquire {
+        Acquire { limit: Arc::clone(&self.limit) }
+    }
+
+    /// Verifies datagram, received from given source on the socket of given RADIUS Message
+    /// Type, resolves it with handler and returns reply to be sent back, if there is one
+    ///
+    /// Datagrams from hosts, which are not allowed, are silently discarded, unless client resolver
+    /// has failed to look them up, which is reported to handler; see
+    /// [set_async_client_resolver](Server::set_async_client_resolver). Requests, which fail
+    /// [verification](Server::process_request) or are not expected on the socket they arrived
+    /// to, are discarded and reported to handler
+    pub async fn handle_datagram(&self, permit: RequestPermit, source: SocketAddr, msg_type: RadiusMsgType, datagram: &[u8]) -> Option<Vec<u8>> {
+        let _permit = permit;
+        let client  = match self.base_server.resolve_client_async(&source).await {
+            Ok(Some(client)) => client.into_owned(),
+            Ok(None)         => {
+                self.base_server.record_invalid_request(msg_type, datagram);
+                return None
+            },
+            Err(error)       => {
+                self.base_server.record_invalid_request(msg_type, datagram);
+                if self.base_server.report_invalid_request() {
+                    self.handler.handle_resolver_error(source, error);
+                }
+                return None
+            }
+        };
+        let context = RequestContext::new(source, msg_type, client, Instant::now());
+
+        let packet = match self.base_server.admit_request(&context, datagram) {
+            Ok(Admission::Request(packet)) => packet,
+            Ok(Admission::Reply(reply))    => return reply,
+            Err(error)                     => {
+                if self.base_server.report_invalid_request() {
+                    self.handler.handle_error(&context, error);
+                }
+                return None
+            }
+        };
+
+        match self.resolve(&context, &packet).await {
+            Ok(reply)  => reply,
+            Err(error) => {
+                self.handler.handle_error(&context, error);
+                None
+            }
+        }
+    }
+
+    async fn resolve(&self, context: &RequestContext, packet: &RadiusPacket) -> Result<Option<Vec<u8>>, RadiusError> {
+        if packet.code() == &TypeCode::StatusServer {
+            return self.base_server.status_server_reply(context, packet)
+        }
+        if let Some(reply) = self.base_server.duplicate_request(context, packet) {
+            return Ok(reply)
+        }
+
+        #[cfg(feature = "metrics")]
+        let started = Instant::now();
+        let outcome = self.handler.handle_request(context, packet).await;
+        #[cfg(feature = "metrics")]
+        crate::server::handler::record_handler_duration(context, packet, started);
+
+        let reply = match outcome {
+            Ok(HandlerOutcome::Reply(mut reply)) => self.base_server.finalise_reply_packet(context.client(), packet, &mut reply).map(Some),
+            Ok(HandlerOutcome::Drop)             => Ok(None),
+            Err(error)                           => Err(error)
+        };
+        self.base_server.finish_request(context, packet, &reply);
+        reply
+    }
+}
+
+
+#[derive(Debug)]
+struct ConcurrencyLimit {
+    max:   usize,
+    state: Mutex<ConcurrencyState>
+}
+
+#[derive(Debug)]
+struct ConcurrencyState {
+    in_flight: usize,
+    waiters:   Vec<Waker>
+}
+
+impl ConcurrencyLimit {
+    fn new(max: usize) -> ConcurrencyLimit {
+        ConcurrencyLimit {
+            max,
+            state: Mutex::new(ConcurrencyState { in_flight: 0, waiters: Vec::new() })
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Future returned by [AsyncServer::acquire]
+pub struct Acquire {
+    limit: Arc<ConcurrencyLimit>
+}
+
+impl Future for Acquire {
+    type Output = RequestPermit;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<RequestPermit> {
+        let mut state = lock(&self.limit.state);
+
+        if state.in_flight < self.limit.max {
+            state.in_flight += 1;
+            Poll::Ready(RequestPermit { limit: Arc::clone(&self.limit) })
+        } else {
+            if !state.waiters.iter().any(|waiter| waiter.will_wake(cx.waker())) {
+                state.waiters.push(cx.waker().clone());
+            }
+            Poll::Pending
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Keeps request counted as being resolved by [AsyncServer] until it is dropped
+pub struct RequestPermit {
+    limit: Arc<ConcurrencyLimit>
+}
+
+impl Drop for RequestPermit {
+    fn drop(&mut self) {
+        let waiters = {
+            let mut state = lock(&self.limit.state);
+            state.in_flight -= 1;
+            std::mem::take(&mut state.waiters)
+        };
+        // All waiters are woken, so permit is not lost, if one of them has been dropped meanwhile
+        for waiter in waiters {
+            waiter.wake();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::radius_packet::TypeCode;
+    use crate::server::client_resolver::{ AsyncClientResolver, ClientResolver };
+    use crate::server::client_table::ClientEntry;
+    use crate::testing::{ test_client, test_server };
+
+    use async_trait::async_trait;
+    use futures::FutureExt;
+    use futures::executor::block_on;
+
+    struct TestHandler;
+
+    #[async_trait]
+    impl AsyncServerTrait for TestHandler {
+        async fn handle_request(&self, _context: &RequestContext, request: &RadiusPacket) -> Result<HandlerOutcome, RadiusError> {
+            match request.code() {
+                TypeCode::AccessRequest => Ok(HandlerOutcome::Reply(RadiusPacket::initialise_packet(TypeCode::AccessAccept))),
+                _                       => Ok(HandlerOutcome::Drop)
+            }
+        }
+    }
+
+    struct FailingResolver;
+
+    impl ClientResolver for FailingResolver {
+        fn resolve(&self, _address: &std::net::IpAddr) -> Result<Option<ClientEntry>, RadiusError> {
+            Err( RadiusError::ValidationError { error: String::from("CMDB is unavailable") } )
+        }
+    }
+
+    struct CountingResolver {
+        calls: Mutex<usize>
+    }
+
+    #[async_trait]
+    impl AsyncClientResolver for CountingResolver {
+        async fn resolve(&self, address: &std::net::IpAddr) -> Result<Option<ClientEntry>, RadiusError> {
+            *lock(&self.calls) += 1;
+            futures::future::ready(()).await;
+            Ok(Some(ClientEntry::new(&address.to_string(), String::from("nas-secret"))?))
+        }
+    }
+
+    struct ResolverErrorHandler {
+        errors: Mutex<Vec<(SocketAddr, String)>>
+    }
+
+    #[async_trait]
+    impl AsyncServerTrait for ResolverErrorHandler {
+        async fn handle_request(&self, _context: &RequestContext, _request: &RadiusPacket) -> Result<HandlerOutcome, RadiusError> {
+            Ok(HandlerOutcome::Drop)
+        }
+
+        fn handle_resolver_error(&self, source: SocketAddr, error: RadiusError) {
+            lock(&self.errors).push((source, error.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_handle_datagram_with_concurrency_limit() {
+        let server = test_server("secret").set_allowed_hosts(vec![String::from("127.0.0.1")]);
+        let server = AsyncServer::new(Arc::new(server), TestHandler).set_max_concurrency(2);
+
+        let client      = test_client(1812, "secret");
+        let mut request = client.create_pap_packet("testing", "password").unwrap();
+        let datagram    = request.to_bytes();
+
+        let first_permit  = block_on(server.acquire());
+        let second_permit = block_on(server.acquire());
+        assert_eq!(2, server.in_flight());
+        assert!(server.acquire().now_or_never().is_none());
+
+        let reply = block_on(server.handle_datagram(first_permit, "127.0.0.1:50000".parse().unwrap(), RadiusMsgType::AUTH, &datagram)).unwrap();
+        assert_eq!(&TypeCode::AccessAccept, client.process_reply(&request, &reply).unwrap().code());
+        assert_eq!(1, server.in_flight());
+
+        // Retransmission gets cached reply
+        let permit = block_on(server.acquire());
+        assert_eq!(Some(reply), block_on(server.handle_datagram(permit, "127.0.0.1:50000".parse().unwrap(), RadiusMsgType::AUTH, &datagram)));
+
+        // Host, which is not allowed, gets no reply
+        assert!(block_on(server.handle_datagram(second_permit, "127.0.0.2:50000".parse().unwrap(), RadiusMsgType::AUTH, &datagram)).is_none());
+        assert_eq!(0, server.in_flight());
+    }
+
+    #[test]
+    fn test_handle_datagram_reports_resolver_error() {
+        let server     = test_server("secret").set_client_resolver(FailingResolver);
+        let handler    = ResolverErrorHandler { errors: Mutex::new(Vec::new()) };
+        let server     = AsyncServer::new(Arc::new(server), handler);
+
+        let client     = test_client(1812, "secret");
+        let datagram   = client.create_pap_packet("testing", "password").unwrap().to_bytes();
+        let source     = "10.0.0.7:50000".parse().unwrap();
+
+        let permit = block_on(server.acquire());
+        assert!(block_on(server.handle_datagram(permit, source, RadiusMsgType::AUTH, &datagram)).is_none());
+        assert_eq!(vec![(source, String::from("Verification failed for incoming Radius packet: CMDB is unavailable"))], *lock(&server.handler.errors));
+    }
+
+    #[test]
+    fn test_handle_datagram_with_async_resolver() {
+        let resolver = CountingResolver { calls: Mutex::new(0) };
+        let server   = test_server("secret").set_client_resolver(FailingResolver).set_async_client_resolver(resolver);
+        let server   = AsyncServer::new(Arc::new(server), TestHandler);
+
+        let client = test_client(1812, "nas-secret");
+        let source = "10.0.0.7:50000".parse().unwrap();
+        for _ in 0..2 {
+            let mut request = client.create_pap_packet("testing", "password").unwrap();
+            let permit      = block_on(server.acquire());
+            let reply       = block_on(server.handle_datagram(permit, source, RadiusMsgType::AUTH, &request.to_bytes())).unwrap();
+            assert_eq!(&TypeCode::AccessAccept, client.process_reply(&request, &reply).unwrap().code());
+        }
+        // Resolved client is cached and is visible to blocking lookups as well
+        assert_eq!("nas-secret", server.server().client(&source).unwrap().secret());
+    }
+}
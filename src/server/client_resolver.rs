@@ -0,0 +1,326 @@
+//! Dynamic resolution of RADIUS Clients (NASes), which are not listed in Server's client table
+
+
+use crate::protocol::error::RadiusError;
+use crate::server::client_table::{ ClientEntry, ClientTable };
+use crate::tools::lock;
+
+#[cfg(feature = "async-radius")]
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{ Path, PathBuf };
+use std::sync::Mutex;
+use std::time::{ Duration, Instant };
+
+
+const DEFAULT_TTL:          Duration = Duration::from_secs(300);
+const DEFAULT_NEGATIVE_TTL: Duration = Duration::from_secs(30);
+const MAX_CACHED_CLIENTS:   usize    = 65536;
+
+
+/// This trait is to be implemented by user to look up RADIUS Clients in external source (ie
+/// CMDB), whenever Server receives request from address, which is not listed in its client
+/// table or allowed hosts
+///
+/// Results (including unknown addresses) are cached by Server, see
+/// [set_client_cache_ttl](crate::server::server::Server::set_client_cache_ttl); resolver is
+/// called from the thread or task, which received request, so it should not block for long.
+/// Resolver of Async RADIUS Server, which has to wait for external source, should rather be
+/// `AsyncClientResolver`
+pub trait ClientResolver: Send + Sync {
+    /// Returns client for given address or None, if address is unknown
+    ///
+    /// If resolver returns an error, request is discarded, result is not cached and error is
+    /// passed to handler's `handle_resolver_error` (at most as often as
+    /// [error reports](crate::server::rate_limit::RateLimiter::set_error_report_limit) are allowed)
+    fn resolve(&self, address: &IpAddr) -> Result<Option<ClientEntry>, RadiusError>;
+}
+
+#[cfg(feature = "async-radius")]
+#[async_trait]
+/// Same as [ClientResolver], but for Async RADIUS Server: resolver awaits external source (ie
+/// async database or DNS lookup), instead of blocking runtime worker, which serves other requests
+///
+/// See [set_async_client_resolver](crate::server::server::Server::set_async_client_resolver)
+pub trait AsyncClientResolver: Send + Sync {
+    /// Returns client for given address or None, if address is unknown
+    ///
+    /// Errors are treated the same way as errors of [ClientResolver::resolve]
+    async fn resolve(&self, address: &IpAddr) -> Result<Option<ClientEntry>, RadiusError>;
+}
+
+#[derive(Debug, Clone)]
+/// Resolves RADIUS Clients from a directory, which holds one file per client
+///
+/// Every file is made of `key = value` lines; `ipaddr` (IPv4/IPv6 address or CIDR) and `secret`
+/// are required, `shortname`, `nas_type` and `require_message_authenticator` (yes/no) are
+/// optional. Empty lines, lines starting with `#` and hidden files are ignored
+///
+/// ```text
+/// ipaddr    = 10.0.0.0/24
+/// secret    = testing123
+/// shortname = nas1
+/// ```
+///
+/// Directory is read whenever Server's cache misses, so clients could be added without
+/// restarting Server; files, which could not be read (ie are not UTF-8) or parsed, are skipped
+pub struct DirectoryResolver {
+    path: PathBuf
+}
+
+impl DirectoryResolver {
+    /// Initialises DirectoryResolver, which reads clients from given directory
+    pub fn new<P: AsRef<Path>>(path: P) -> DirectoryResolver {
+        DirectoryResolver { path: path.as_ref().to_path_buf() }
+    }
+
+    /// Returns directory path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads all clients from directory
+    pub fn load(&self) -> Result<ClientTable, RadiusError> {
+        let mut table = ClientTable::new();
+
+        for entry in fs::read_dir(&self.path)? {
+            let path   = entry?.path();
+            let hidden = match path.file_name() {
+                Some(name) => name.to_string_lossy().starts_with('.'),
+                None       => true
+            };
+            if hidden || !path.is_file() {
+                continue;
+            }
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(_)      => continue
+            };
+            if let Ok(client) = DirectoryResolver::parse(&content) {
+                table.insert(client);
+            }
+        }
+        Ok(table)
+    }
+
+    fn parse(content: &str) -> Result<ClientEntry, RadiusError> {
+        let mut options = HashMap::new();
+
+        for line in content.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            match line.split_once('=') {
+                Some((key, value)) => options.insert(key.trim(), value.trim()),
+                None               => return Err( RadiusError::ValidationError { error: format!("Invalid client option: {}", line) } )
+            };
+        }
+
+        let required = |key: &str| options.get(key).copied().ok_or_else(|| RadiusError::ValidationError { error: format!("Client option {} is missing", key) });
+        let mut client = ClientEntry::new(required("ipaddr")?, required("secret")?.to_string())?;
+
+        if let Some(shortname) = options.get("shortname") {
+            client = client.set_shortname(shortname.to_string());
+        }
+        if let Some(nas_type) = options.get("nas_type") {
+            client = client.set_nas_type(nas_type.to_string());
+        }
+        if let Some(require_message_authenticator) = options.get("require_message_authenticator") {
+            client = client.set_require_message_authenticator(matches!(*require_message_authenticator, "yes" | "true"));
+        }
+        Ok(client)
+    }
+}
+
+impl ClientResolver for DirectoryResolver {
+    fn resolve(&self, address: &IpAddr) -> Result<Option<ClientEntry>, RadiusError> {
+        Ok(self.load()?.lookup(address).cloned())
+    }
+}
+
+
+/// Caches results of ClientResolver, so resolver is only called once per TTL for every address
+pub(crate) struct ClientCache {
+    resolver:       Option<Box<dyn ClientResolver>>,
+    #[cfg(feature = "async-radius")]
+    async_resolver: Option<Box<dyn AsyncClientResolver>>,
+    ttl:            Duration,
+    negative_ttl:   Duration,
+    entries:        Mutex<HashMap<IpAddr, (Option<ClientEntry>, Instant)>>
+}
+
+impl ClientCache {
+    pub(crate) fn new() -> ClientCache {
+        ClientCache {
+            resolver:       None,
+            #[cfg(feature = "async-radius")]
+            async_resolver: None,
+            ttl:            DEFAULT_TTL,
+            negative_ttl:   DEFAULT_NEGATIVE_TTL,
+            entries:        Mutex::new(HashMap::new())
+        }
+    }
+
+    pub(crate) fn set_resolver(&mut self, resolver: Box<dyn ClientResolver>) {
+        self.resolver = Some(resolver);
+        lock(&self.entries).clear();
+    }
+
+    #[cfg(feature = "async-radius")]
+    pub(crate) fn set_async_resolver(&mut self, resolver: Box<dyn AsyncClientResolver>) {
+        self.async_resolver = Some(resolver);
+        lock(&self.entries).clear();
+    }
+
+    pub(crate) fn set_ttl(&mut self, ttl: Duration, negative_ttl: Duration) {
+        self.ttl          = ttl;
+        self.negative_ttl = negative_ttl;
+    }
+
+    /// Returns cached client or resolves it, if allowed; resolver's error is returned as is and
+    /// is not cached
+    pub(crate) fn resolve(&self, address: &IpAddr, allowed: impl FnOnce() -> bool) -> Result<Option<ClientEntry>, RadiusError> {
+        let now = Instant::now();
+
+        if let Some(client) = self.cached(address, now) {
+            return Ok(client)
+        }
+        let resolver = match self.resolver.as_ref() {
+            Some(resolver) => resolver,
+            None           => return Ok(None)
+        };
+        if !allowed() {
+            return Ok(None)
+        }
+        // Cache is not locked while resolver runs, so the same address could be resolved by
+        // several threads at once; the last result wins
+        let client = resolver.resolve(address)?;
+        self.store(*address, client.clone(), now);
+        Ok(client)
+    }
+
+    /// Same as [resolve](ClientCache::resolve), but awaits async resolver, if there is one;
+    /// otherwise falls back to blocking resolver
+    #[cfg(feature = "async-radius")]
+    pub(crate) async fn resolve_async(&self, address: &IpAddr, allowed: impl FnOnce() -> bool) -> Result<Option<ClientEntry>, RadiusError> {
+        let resolver = match self.async_resolver.as_ref() {
+            Some(resolver) => resolver,
+            None           => return self.resolve(address, allowed)
+        };
+        let now = Instant::now();
+
+        if let Some(client) = self.cached(address, now) {
+            return Ok(client)
+        }
+        if !allowed() {
+            return Ok(None)
+        }
+        let client = resolver.resolve(address).await?;
+        self.store(*address, client.clone(), now);
+        Ok(client)
+    }
+
+    /// Returns cached result for given address, unless it has expired
+    fn cached(&self, address: &IpAddr, now: Instant) -> Option<Option<ClientEntry>> {
+        lock(&self.entries).get(address)
+            .filter(|(_, expires)| *expires > now)
+            .map(|(client, _)| client.clone())
+    }
+
+    fn store(&self, address: IpAddr, client: Option<ClientEntry>, now: Instant) {
+        let expires = now + if client.is_some() { self.ttl } else { self.negative_ttl };
+
+        let mut entries = lock(&self.entries);
+        if entries.len() >= MAX_CACHED_CLIENTS {
+            entries.retain(|_, (_, expires)| *expires > now);
+            if entries.len() >= MAX_CACHED_CLIENTS {
+                entries.clear();
+            }
+        }
+        entries.insert(address, (client, expires));
+    }
+}
+
+impl fmt::Debug for ClientCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("ClientCache");
+        debug.field("resolver",       &self.resolver.as_ref().map(|_| "ClientResolver"));
+        #[cfg(feature = "async-radius")]
+        debug.field("async_resolver", &self.async_resolver.as_ref().map(|_| "AsyncClientResolver"));
+        debug.field("ttl",            &self.ttl)
+            .field("negative_ttl",   &self.negative_ttl)
+            .finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::sync::atomic::{ AtomicUsize, Ordering };
+
+    struct CountingResolver {
+        resolver: DirectoryResolver,
+        calls:    Arc<AtomicUsize>
+    }
+
+    impl ClientResolver for CountingResolver {
+        fn resolve(&self, address: &IpAddr) -> Result<Option<ClientEntry>, RadiusError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.resolver.resolve(address)
+        }
+    }
+
+    struct FailingResolver;
+
+    impl ClientResolver for FailingResolver {
+        fn resolve(&self, _address: &IpAddr) -> Result<Option<ClientEntry>, RadiusError> {
+            Err( RadiusError::ValidationError { error: String::from("CMDB is unavailable") } )
+        }
+    }
+
+    #[test]
+    fn test_directory_resolver_with_cache() {
+        let path = std::env::temp_dir().join(format!("radius-clients-{}", std::process::id()));
+        let _    = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("nas1"),    "# Core NAS\nipaddr = 10.0.0.0/24\nsecret = nas-secret\nshortname = nas1\nrequire_message_authenticator = yes\n").unwrap();
+        fs::write(path.join("invalid"), "ipaddr = 10.0.1.0/24\n").unwrap();
+        fs::write(path.join("binary"),  [0xff, 0xfe, 0x00, 0x01]).unwrap();
+
+        let calls     = Arc::new(AtomicUsize::new(0));
+        let mut cache = ClientCache::new();
+        cache.set_resolver(Box::new(CountingResolver { resolver: DirectoryResolver::new(&path), calls: Arc::clone(&calls) }));
+        cache.set_ttl(Duration::from_secs(60), Duration::from_millis(0));
+
+        let nas = cache.resolve(&"10.0.0.7".parse().unwrap(), || true).unwrap().unwrap();
+        assert_eq!("nas1",       nas.shortname());
+        assert_eq!("nas-secret", nas.secret());
+        assert!(nas.require_message_authenticator());
+        assert!(cache.resolve(&"10.0.0.7".parse().unwrap(), || true).unwrap().is_some());
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+
+        // Negative result expires straight away, so newly added client is picked up
+        assert!(cache.resolve(&"10.0.1.7".parse().unwrap(), || true).unwrap().is_none());
+        fs::write(path.join("nas2"), "ipaddr = 10.0.1.0/24\nsecret = other-secret\n").unwrap();
+        assert_eq!("other-secret", cache.resolve(&"10.0.1.7".parse().unwrap(), || true).unwrap().unwrap().secret());
+        assert_eq!(3, calls.load(Ordering::SeqCst));
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_resolver_error_is_returned() {
+        let mut cache = ClientCache::new();
+        cache.set_resolver(Box::new(FailingResolver));
+
+        match cache.resolve(&"10.0.0.7".parse().unwrap(), || true) {
+            Err(error) => assert_eq!("Verification failed for incoming Radius packet: CMDB is unavailable", error.to_string()),
+            _          => assert!(false)
+        }
+        // Error is not cached, while address, which is not allowed to be resolved, is just unknown
+        assert!(cache.resolve(&"10.0.0.7".parse().unwrap(), || false).unwrap().is_none());
+    }
+}
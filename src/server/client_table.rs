@@ -0,0 +1,223 @@
+//! Table of RADIUS Clients (NASes), which are allowed to send requests to RADIUS Server
+
+
+use crate::protocol::error::RadiusError;
+use crate::server::rate_limit::RateLimit;
+
+use std::net::IpAddr;
+
+
+#[derive(Debug, Clone, PartialEq)]
+/// Represents RADIUS Client (NAS) or network of RADIUS Clients, which share the same secret
+pub struct ClientEntry {
+    network:                       IpAddr,
+    prefix_len:                    u8,
+    shortname:                     String,
+    secret:                        String,
+    nas_type:                      Option<String>,
+    require_message_authenticator: bool,
+    rate_limit:                    Option<RateLimit>
+}
+
+impl ClientEntry {
+    // === Builder for ClientEntry ===
+    /// Initialises ClientEntry from IPv4/IPv6 address or CIDR (ie "10.0.0.0/8" or "fc66::/16")
+    /// and secret
+    ///
+    /// Shortname defaults to given CIDR
+    pub fn new(cidr: &str, secret: String) -> Result<ClientEntry, RadiusError> {
+        let (address, prefix_len) = match cidr.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None                        => (cidr, None)
+        };
+        let address: IpAddr = address.parse().map_err(|error: std::net::AddrParseError| RadiusError::MalformedIpAddrError { error: error.to_string() })?;
+        let max_prefix_len  = if address.is_ipv4() { 32 } else { 128 };
+
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len.parse::<u8>().map_err(|error| RadiusError::MalformedIpAddrError { error: error.to_string() })?,
+            None             => max_prefix_len
+        };
+        if prefix_len > max_prefix_len {
+            return Err( RadiusError::MalformedIpAddrError { error: format!("Subnet must be no greater than {}, you provided {}", max_prefix_len, prefix_len) } )
+        }
+
+        Ok(ClientEntry {
+            network:                       mask(&address, prefix_len),
+            prefix_len,
+            shortname:                     cidr.to_string(),
+            secret,
+            nas_type:                      None,
+            require_message_authenticator: false,
+            rate_limit:                    None
+        })
+    }
+
+    /// Sets shortname, which identifies client in logs and in [RequestContext](crate::server::handler::RequestContext)
+    pub fn set_shortname(mut self, shortname: String) -> ClientEntry {
+        self.shortname = shortname;
+        self
+    }
+
+    /// Sets NAS type (ie "cisco"), which is not used by Server itself
+    pub fn set_nas_type(mut self, nas_type: String) -> ClientEntry {
+        self.nas_type = Some(nas_type);
+        self
+    }
+
+    /// Sets whether Access-Request from this client is discarded, if it doesn't carry
+    /// Message-Authenticator (false by default)
+    pub fn set_require_message_authenticator(mut self, require_message_authenticator: bool) -> ClientEntry {
+        self.require_message_authenticator = require_message_authenticator;
+        self
+    }
+
+    /// Sets limit of requests from this client, which overrides
+    /// [RateLimiter's](crate::server::rate_limit::RateLimiter) client limit
+    pub fn set_rate_limit(mut self, rate_limit: Option<RateLimit>) -> ClientEntry {
+        self.rate_limit = rate_limit;
+        self
+    }
+    // ===================
+
+    /// Returns network address
+    pub fn network(&self) -> IpAddr {
+        self.network
+    }
+
+    /// Returns network prefix length
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Returns shortname
+    pub fn shortname(&self) -> &str {
+        &self.shortname
+    }
+
+    /// Returns secret shared with client
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+
+    /// Returns NAS type
+    pub fn nas_type(&self) -> Option<&str> {
+        self.nas_type.as_deref()
+    }
+
+    /// Returns whether Access-Request from this client must carry Message-Authenticator
+    pub fn require_message_authenticator(&self) -> bool {
+        self.require_message_authenticator
+    }
+
+    /// Returns limit of requests from this client
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.rate_limit
+    }
+
+    /// Checks if given address belongs to client's network
+    ///
+    /// IPv4-mapped IPv6 address (as received on dual-stack socket) is matched as IPv4 address
+    pub fn contains(&self, address: &IpAddr) -> bool {
+        let address = canonical(address);
+        address.is_ipv4() == self.network.is_ipv4() && mask(&address, self.prefix_len) == self.network
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Represents table of RADIUS Clients, which is searched with longest-prefix match
+pub struct ClientTable {
+    entries: Vec<ClientEntry>
+}
+
+impl ClientTable {
+    /// Initialises empty ClientTable
+    pub fn new() -> ClientTable {
+        ClientTable { entries: Vec::new() }
+    }
+
+    // === Builder for ClientTable ===
+    /// Adds client to the table
+    ///
+    /// If table already has client with the same network, it is replaced
+    pub fn add_client(mut self, client: ClientEntry) -> ClientTable {
+        self.insert(client);
+        self
+    }
+    // ===================
+
+    /// Adds client to the table, replacing client with the same network, if there is one
+    pub fn insert(&mut self, client: ClientEntry) {
+        match self.entries.iter_mut().find(|entry| entry.network == client.network && entry.prefix_len == client.prefix_len) {
+            Some(entry) => *entry = client,
+            None        => self.entries.push(client)
+        }
+    }
+
+    /// Returns all clients
+    pub fn clients(&self) -> &[ClientEntry] {
+        &self.entries
+    }
+
+    /// Returns number of clients
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true, if table has no clients
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the most specific client, which network contains given address
+    pub fn lookup(&self, address: &IpAddr) -> Option<&ClientEntry> {
+        self.entries.iter()
+            .filter(|entry| entry.contains(address))
+            .max_by_key(|entry| entry.prefix_len)
+    }
+}
+
+fn canonical(address: &IpAddr) -> IpAddr {
+    match address {
+        IpAddr::V6(ipv6) => ipv6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(*address),
+        IpAddr::V4(_)    => *address
+    }
+}
+
+fn mask(address: &IpAddr, prefix_len: u8) -> IpAddr {
+    match address {
+        IpAddr::V4(ipv4) => {
+            let mask = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+            IpAddr::V4((u32::from(*ipv4) & mask).into())
+        },
+        IpAddr::V6(ipv6) => {
+            let mask = u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0);
+            IpAddr::V6((u128::from(*ipv6) & mask).into())
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_longest_prefix() {
+        let table = ClientTable::new()
+            .add_client(ClientEntry::new("10.0.0.0/8",  String::from("network")).unwrap())
+            .add_client(ClientEntry::new("10.1.2.3",    String::from("host")).unwrap().set_shortname(String::from("nas1")))
+            .add_client(ClientEntry::new("10.1.0.0/16", String::from("subnet")).unwrap())
+            .add_client(ClientEntry::new("fc66::/16",   String::from("ipv6")).unwrap());
+
+        assert_eq!("nas1",        table.lookup(&"10.1.2.3".parse().unwrap()).unwrap().shortname());
+        assert_eq!("subnet",      table.lookup(&"10.1.2.4".parse().unwrap()).unwrap().secret());
+        assert_eq!("network",     table.lookup(&"10.2.0.1".parse().unwrap()).unwrap().secret());
+        assert_eq!("network",     table.lookup(&"::ffff:10.2.0.1".parse().unwrap()).unwrap().secret());
+        assert_eq!("fc66::/16",   table.lookup(&"fc66::1".parse().unwrap()).unwrap().shortname());
+        assert!(table.lookup(&"11.0.0.1".parse().unwrap()).is_none());
+        assert!(table.lookup(&"fc67::1".parse().unwrap()).is_none());
+
+        assert!(ClientEntry::new("10.0.0.0/33", String::from("secret")).is_err());
+        assert!(ClientEntry::new("localhost",   String::from("secret")).is_err());
+    }
+}
@@ -0,0 +1,869 @@
+//! Server side of EAP (RFC 3748) carried over RADIUS (RFC 3579): registry of EAP methods, which
+//! Server offers, authenticator, which runs EAP sessions across Access-Challenges, and export of
+//! session keys to RADIUS Client as MS-MPPE keys (RFC 2548)
+
+
+use crate::protocol::dictionary::Dictionary;
+use crate::protocol::error::RadiusError;
+use crate::protocol::host::Host;
+use crate::protocol::radius_packet::{ RadiusAttribute, RadiusPacket, TypeCode };
+use crate::tools::lock;
+
+use md5::{ Digest, Md5 };
+use rand::{ thread_rng, Rng };
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{ Arc, Mutex };
+use std::time::{ Duration, Instant };
+
+
+#[cfg(feature = "eap-pwd")]
+pub mod pwd;
+#[cfg(feature = "eap-sim-aka")]
+pub mod sim_aka;
+
+
+/// EAP Type of Identity
+pub const EAP_TYPE_IDENTITY:  u8 = 1;
+/// EAP Type of Nak (Response only)
+pub const EAP_TYPE_NAK:       u8 = 3;
+/// EAP Type of EAP-SIM (RFC 4186)
+pub const EAP_TYPE_SIM:       u8 = 18;
+/// EAP Type of EAP-AKA (RFC 4187)
+pub const EAP_TYPE_AKA:       u8 = 23;
+/// EAP Type of EAP-AKA' (RFC 5448)
+pub const EAP_TYPE_AKA_PRIME: u8 = 50;
+/// EAP Type of EAP-pwd (RFC 5931)
+pub const EAP_TYPE_PWD:       u8 = 52;
+
+const STATE_ID:                 u8 = 24;
+const VENDOR_SPECIFIC_ID:       u8 = 26;
+const EAP_MESSAGE_ID:           u8 = 79;
+const MESSAGE_AUTHENTICATOR_ID: u8 = 80;
+
+const MICROSOFT_VENDOR: u32 = 311;
+const MS_MPPE_SEND_KEY: u8  = 16;
+const MS_MPPE_RECV_KEY: u8  = 17;
+
+const MAX_ATTRIBUTE_LENGTH:    usize    = 253;
+const DEFAULT_SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_MAX_SESSIONS:    usize    = 65536;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Contains Codes of EAP packet as defined in RFC 3748
+pub enum EapCode {
+    /// Request  = 1
+    Request,
+    /// Response = 2
+    Response,
+    /// Success  = 3
+    Success,
+    /// Failure  = 4
+    Failure
+}
+
+impl EapCode {
+    /// Convert integer(u8) value into corresponding EapCode enum
+    pub fn from_u8(code: u8) -> Result<EapCode, RadiusError> {
+        match code {
+            1 => Ok(EapCode::Request),
+            2 => Ok(EapCode::Response),
+            3 => Ok(EapCode::Success),
+            4 => Ok(EapCode::Failure),
+            _ => Err( RadiusError::EapError { error: format!("unknown EAP code {}", code) } )
+        }
+    }
+
+    /// Convert EapCode enum value into corresponding integer(u8)
+    pub fn to_u8(&self) -> u8 {
+        match self {
+            EapCode::Request  => 1,
+            EapCode::Response => 2,
+            EapCode::Success  => 3,
+            EapCode::Failure  => 4
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Represents EAP packet, which is carried in one or more EAP-Message attributes
+pub struct EapPacket {
+    code:       EapCode,
+    identifier: u8,
+    data:       Vec<u8>
+}
+
+impl EapPacket {
+    /// Initialises EAP Request or Response of given EAP Type
+    pub fn new(code: EapCode, identifier: u8, eap_type: u8, type_data: &[u8]) -> EapPacket {
+        let mut data = vec![eap_type];
+        data.extend_from_slice(type_data);
+        EapPacket { code, identifier, data }
+    }
+
+    /// Initialises EAP Success or Failure, which carry no data
+    pub fn completion(code: EapCode, identifier: u8) -> EapPacket {
+        EapPacket { code, identifier, data: Vec::new() }
+    }
+
+    /// Initialises EAP packet from bytes
+    ///
+    /// Bytes beyond EAP packet length are ignored (RFC 3748 Section 4.1); Request and Response
+    /// have to carry EAP Type
+    pub fn from_bytes(bytes: &[u8]) -> Result<EapPacket, RadiusError> {
+        if bytes.len() < 4 {
+            return Err( RadiusError::EapError { error: String::from("EAP packet is shorter than its header") } )
+        }
+        let code   = EapCode::from_u8(bytes[0])?;
+        let length = u16::from_be_bytes([bytes[2], bytes[3]]) as usize;
+
+        if length < 4 || length > bytes.len() {
+            return Err( RadiusError::EapError { error: format!("EAP packet has invalid length {}", length) } )
+        }
+        if length == 4 && matches!(code, EapCode::Request | EapCode::Response) {
+            return Err( RadiusError::EapError { error: format!("EAP {:?} carries no EAP Type", code) } )
+        }
+        Ok(EapPacket { code, identifier: bytes[1], data: bytes[4..length].to_vec() })
+    }
+
+    /// Returns EAP packet as bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.code.to_u8(), self.identifier];
+        bytes.extend_from_slice(&((4 + self.data.len()) as u16).to_be_bytes());
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    /// Returns EAP Code
+    pub fn code(&self) -> EapCode {
+        self.code
+    }
+
+    /// Returns EAP Identifier
+    pub fn identifier(&self) -> u8 {
+        self.identifier
+    }
+
+    /// Returns EAP Type of Request or Response
+    pub fn eap_type(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    /// Returns data, which follows EAP Type
+    pub fn type_data(&self) -> &[u8] {
+        self.data.get(1..).unwrap_or(&[])
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+/// Represents keys, which EAP method has derived: Master Session Key, which is exported to
+/// RADIUS Client, and Extended Master Session Key
+pub struct EapKeys {
+    msk:  Vec<u8>,
+    emsk: Vec<u8>
+}
+
+impl EapKeys {
+    /// Initialises EapKeys; MSK has to be at least 64 octets long (RFC 3748 Section 7.10)
+    pub fn new(msk: Vec<u8>, emsk: Vec<u8>) -> EapKeys {
+        EapKeys { msk, emsk }
+    }
+
+    /// Returns Master Session Key
+    pub fn msk(&self) -> &[u8] {
+        &self.msk
+    }
+
+    /// Returns Extended Master Session Key
+    pub fn emsk(&self) -> &[u8] {
+        &self.emsk
+    }
+}
+
+impl fmt::Debug for EapKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EapKeys")
+            .field("msk",  &format!("<{} octets>", self.msk.len()))
+            .field("emsk", &format!("<{} octets>", self.emsk.len()))
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Represents what EAP method does next
+pub enum EapStep {
+    /// EAP-Request with given type data is sent to peer (in Access-Challenge)
+    Request(Vec<u8>),
+    /// Peer is authenticated: EAP-Success is sent to peer and MSK is exported to RADIUS Client
+    /// (in Access-Accept)
+    Success(EapKeys),
+    /// Peer is not authenticated: EAP-Failure is sent to peer (in Access-Reject)
+    Failure
+}
+
+/// This trait is to be implemented by EAP method to carry on a single authentication
+///
+/// Session is only called with responses of its own EAP Type, one at a time. Malformed or
+/// unexpected responses should end session with [EapStep::Failure]; errors are meant for
+/// failures on Server's side (ie password store is unavailable), they end session as well, but
+/// request is discarded without reply
+pub trait EapSession: Send {
+    /// Returns first step of the method: usually type data of first EAP-Request, which is to
+    /// carry given Identifier
+    fn start(&mut self, identifier: u8) -> Result<EapStep, RadiusError>;
+
+    /// Processes peer's EAP-Response and returns next step; EAP-Request, which follows, is to
+    /// carry given Identifier
+    ///
+    /// Identifier lets method protect the whole EAP packet (ie with MAC, which covers EAP header)
+    fn process(&mut self, response: &EapPacket, identifier: u8) -> Result<EapStep, RadiusError>;
+}
+
+/// This trait is to be implemented by EAP method, which could be registered with
+/// [EapMethodRegistry]
+pub trait EapMethod: Send + Sync {
+    /// Returns EAP Type of the method
+    fn eap_type(&self) -> u8;
+
+    /// Returns true, if method is to be offered first to peer of given identity, regardless of
+    /// registration order (ie identity carries method hint)
+    ///
+    /// Returns false by default
+    fn is_preferred_for(&self, _identity: &str) -> bool {
+        false
+    }
+
+    /// Starts authentication of peer, which has sent given EAP-Response/Identity
+    fn start_session(&self, identity: &str) -> Result<Box<dyn EapSession>, RadiusError>;
+}
+
+#[derive(Default)]
+/// Represents EAP methods, which are offered to peers, in order of preference
+///
+/// Peer is offered the first method, which prefers peer's identity, or else the first registered
+/// one; peer, which declines it with Nak, is offered the first registered method from the ones
+/// it desires
+pub struct EapMethodRegistry {
+    methods: Vec<Box<dyn EapMethod>>
+}
+
+impl EapMethodRegistry {
+    /// Initialises empty EapMethodRegistry
+    pub fn new() -> EapMethodRegistry {
+        EapMethodRegistry::default()
+    }
+
+    // === Builder for EapMethodRegistry ===
+    /// Registers EAP method; method of EAP Type, which is already registered, replaces the old one
+    pub fn register<M: EapMethod + 'static>(mut self, method: M) -> EapMethodRegistry {
+        self.methods.retain(|registered| registered.eap_type() != method.eap_type());
+        self.methods.push(Box::new(method));
+        self
+    }
+    // ===================
+
+    /// Returns EAP Types of registered methods, in order of preference
+    pub fn eap_types(&self) -> Vec<u8> {
+        self.methods.iter().map(|method| method.eap_type()).collect()
+    }
+
+    /// Returns method of given EAP Type
+    pub fn method(&self, eap_type: u8) -> Option<&dyn EapMethod> {
+        self.methods.iter().find(|method| method.eap_type() == eap_type).map(|method| method.as_ref())
+    }
+
+    /// Returns method, which is offered first to peer of given identity
+    fn select(&self, identity: &str) -> Option<&dyn EapMethod> {
+        self.methods.iter()
+            .find(|method| method.is_preferred_for(identity))
+            .or_else(|| self.methods.first())
+            .map(|method| method.as_ref())
+    }
+
+    /// Returns method, which is offered to peer, which has declined some methods and desires
+    /// given ones
+    fn select_desired(&self, desired: &[u8], declined: &[u8]) -> Option<&dyn EapMethod> {
+        self.methods.iter()
+            .find(|method| desired.contains(&method.eap_type()) && !declined.contains(&method.eap_type()))
+            .map(|method| method.as_ref())
+    }
+}
+
+impl fmt::Debug for EapMethodRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EapMethodRegistry")
+            .field("eap_types", &self.eap_types())
+            .finish()
+    }
+}
+
+struct ActiveMethod {
+    eap_type:  u8,
+    session:   Box<dyn EapSession>,
+    // Peer could only decline method with Nak, until it has answered it
+    answered:  bool
+}
+
+struct Session {
+    identity:   Option<String>,
+    method:     Option<ActiveMethod>,
+    // EAP Types, which have been offered to peer
+    offered:    Vec<u8>,
+    // Identifier of the last EAP-Request sent to peer
+    identifier: u8
+}
+
+struct SessionEntry {
+    session: Arc<Mutex<Session>>,
+    expires: Instant
+}
+
+#[derive(Debug)]
+/// Represents EAP authenticator, which resolves Access-Requests, that carry EAP-Message
+///
+/// Every EAP session is identified by State attribute, which authenticator sends in
+/// Access-Challenge and RADIUS Client sends back in next Access-Request; sessions, which peer
+/// doesn't carry on within session timeout, are forgotten. Once EAP method succeeds, its MSK is
+/// sent to RADIUS Client as MS-MPPE-Recv-Key and MS-MPPE-Send-Key (RFC 2548 Section 2.4), so
+/// dictionary has to define State, Vendor-Specific, EAP-Message and Message-Authenticator
+/// attributes
+///
+/// Authenticator is meant to be called by RADIUS Server for Access-Requests, which carry
+/// EAP-Message; replies it returns are ready to be sent back to RADIUS Client
+pub struct EapAuthenticator {
+    host:            Host,
+    registry:        EapMethodRegistry,
+    session_timeout: Duration,
+    max_sessions:    usize,
+    sessions:        Mutex<HashMap<Vec<u8>, SessionEntry>>
+}
+
+impl EapAuthenticator {
+    /// Initialises EapAuthenticator, which offers methods of given registry and creates reply
+    /// attributes from given dictionary
+    pub fn new(dictionary: Dictionary, registry: EapMethodRegistry) -> EapAuthenticator {
+        EapAuthenticator {
+            host:            Host::with_dictionary(dictionary),
+            registry,
+            session_timeout: DEFAULT_SESSION_TIMEOUT,
+            max_sessions:    DEFAULT_MAX_SESSIONS,
+            sessions:        Mutex::new(HashMap::new())
+        }
+    }
+
+    // === Builder for EapAuthenticator ===
+    /// **Optional**
+    ///
+    /// Sets time, within which peer has to answer EAP-Request (60 seconds by default)
+    pub fn set_session_timeout(mut self, session_timeout: Duration) -> EapAuthenticator {
+        self.session_timeout = session_timeout;
+        self
+    }
+
+    /// **Optional**
+    ///
+    /// Sets maximum number of EAP sessions in progress (65536 by default); once it is reached,
+    /// requests, which start new sessions, are discarded
+    pub fn set_max_sessions(mut self, max_sessions: usize) -> EapAuthenticator {
+        self.max_sessions = max_sessions;
+        self
+    }
+    // ===================
+
+    /// Returns registry of offered EAP methods
+    pub fn registry(&self) -> &EapMethodRegistry {
+        &self.registry
+    }
+
+    /// Returns time, within which peer has to answer EAP-Request
+    pub fn session_timeout(&self) -> Duration {
+        self.session_timeout
+    }
+
+    /// Returns maximum number of EAP sessions in progress
+    pub fn max_sessions(&self) -> usize {
+        self.max_sessions
+    }
+
+    /// Returns number of EAP sessions in progress (including the expired ones, which have not
+    /// been forgotten yet)
+    pub fn sessions(&self) -> usize {
+        lock(&self.sessions).len()
+    }
+
+    /// Resolves Access-Request from RADIUS Client with given secret, which carries EAP-Message,
+    /// and returns Access-Challenge, Access-Accept or Access-Reject with EAP packet for peer
+    ///
+    /// Reply carries request's ID, Response Authenticator and Message-Authenticator, so it could
+    /// be sent as is. None is returned for request, which doesn't answer the last EAP-Request of
+    /// its session: such request is to be silently discarded (RFC 3748 Section 4.1)
+    ///
+    /// Returns an error, if request carries no EAP-Message or it is not EAP-Response, if EAP
+    /// method has failed on Server's side or if too many sessions are in progress
+    pub fn handle_request(&self, secret: &str, request: &RadiusPacket) -> Result<Option<RadiusPacket>, RadiusError> {
+        let message = eap_message(request)
+            .ok_or_else(|| RadiusError::EapError { error: String::from("request carries no EAP-Message") })?;
+        let now     = Instant::now();
+
+        let reply = match request.attribute_by_id(STATE_ID) {
+            Some(state) => {
+                let response = parse_response(&message)?;
+                self.continue_session(secret, request, state.value(), &response, now)?
+            },
+            None        => self.start_session(secret, request, &message, now)?
+        };
+
+        match reply {
+            Some(mut reply) => {
+                finalise_reply(&mut reply, secret, request)?;
+                Ok(Some(reply))
+            },
+            None            => Ok(None)
+        }
+    }
+
+    fn start_session(&self, secret: &str, request: &RadiusPacket, message: &[u8], now: Instant) -> Result<Option<RadiusPacket>, RadiusError> {
+        let mut session = Session { identity: None, method: None, offered: Vec::new(), identifier: thread_rng().gen() };
+
+        // EAP-Start (RFC 3579 Section 2.1) is answered with EAP-Request/Identity
+        let step = if message.is_empty() {
+            EapStep::Request(Vec::new())
+        } else {
+            let response = parse_response(message)?;
+            if response.eap_type() != Some(EAP_TYPE_IDENTITY) {
+                return self.reject(response.identifier()).map(Some)
+            }
+            session.identifier = response.identifier();
+            self.select_method(&mut session, parse_identity(response.type_data()))?
+        };
+
+        let state   = thread_rng().gen::<[u8; 16]>().to_vec();
+        let reply = self.reply(secret, request, &mut session, &state, step)?;
+        if is_challenge(&reply) {
+            self.store_session(state, session, now)?;
+        }
+        Ok(Some(reply))
+    }
+
+    fn continue_session(&self, secret: &str, request: &RadiusPacket, state: &[u8], response: &EapPacket, now: Instant) -> Result<Option<RadiusPacket>, RadiusError> {
+        let session = match self.session(state, now) {
+            Some(session) => session,
+            None          => return self.reject(response.identifier()).map(Some)
+        };
+        let mut session = lock(&session);
+
+        if response.identifier() != session.identifier {
+            return Ok(None)
+        }
+
+        let reply = self.process_response(&mut session, response)
+            .and_then(|step| self.reply(secret, request, &mut session, state, step));
+        self.finish_step(state, &reply, now);
+        reply.map(Some)
+    }
+
+    fn process_response(&self, session: &mut Session, response: &EapPacket) -> Result<EapStep, RadiusError> {
+        let eap_type   = response.eap_type().unwrap_or_default();
+        let identifier = session.identifier.wrapping_add(1);
+
+        match session.method.as_mut() {
+            None                                                 => {
+                // Answer to EAP-Request/Identity, which has followed EAP-Start
+                if eap_type != EAP_TYPE_IDENTITY {
+                    return Ok(EapStep::Failure)
+                }
+                self.select_method(session, parse_identity(response.type_data()))
+            },
+            Some(method) if eap_type == EAP_TYPE_NAK && !method.answered => {
+                let identity = session.identity.clone().unwrap_or_default();
+                match self.registry.select_desired(response.type_data(), &session.offered) {
+                    Some(method) => self.start_method(session, method, &identity),
+                    None         => Ok(EapStep::Failure)
+                }
+            },
+            Some(method) if eap_type == method.eap_type          => {
+                method.answered = true;
+                method.session.process(response, identifier)
+            },
+            Some(_)                                              => Ok(EapStep::Failure)
+        }
+    }
+
+    fn select_method(&self, session: &mut Session, identity: String) -> Result<EapStep, RadiusError> {
+        session.identity = Some(identity.clone());
+        match self.registry.select(&identity) {
+            Some(method) => self.start_method(session, method, &identity),
+            None         => Ok(EapStep::Failure)
+        }
+    }
+
+    fn start_method(&self, session: &mut Session, method: &dyn EapMethod, identity: &str) -> Result<EapStep, RadiusError> {
+        let mut method_session = method.start_session(identity)?;
+        let step               = method_session.start(session.identifier.wrapping_add(1))?;
+
+        session.offered.push(method.eap_type());
+        session.method = Some(ActiveMethod { eap_type: method.eap_type(), session: method_session, answered: false });
+        Ok(step)
+    }
+
+    /// Builds reply to request, which carries the step of session
+    fn reply(&self, secret: &str, request: &RadiusPacket, session: &mut Session, state: &[u8], step: EapStep) -> Result<RadiusPacket, RadiusError> {
+        match step {
+            EapStep::Request(type_data) => {
+                let eap_type       = session.method.as_ref().map(|method| method.eap_type).unwrap_or(EAP_TYPE_IDENTITY);
+                session.identifier = session.identifier.wrapping_add(1);
+
+                let eap            = EapPacket::new(EapCode::Request, session.identifier, eap_type, &type_data);
+                let mut attributes = self.eap_attributes(&eap)?;
+                attributes.push(self.host.create_attribute_by_id(STATE_ID, state.to_vec())?);
+                Ok(reply_packet(TypeCode::AccessChallenge, attributes))
+            },
+            EapStep::Success(keys)      => {
+                let eap            = EapPacket::completion(EapCode::Success, session.identifier);
+                let mut attributes = self.eap_attributes(&eap)?;
+                attributes.extend(self.mppe_key_attributes(&keys, secret, request.authenticator())?);
+                Ok(reply_packet(TypeCode::AccessAccept, attributes))
+            },
+            EapStep::Failure            => self.reject(session.identifier)
+        }
+    }
+
+    fn reject(&self, identifier: u8) -> Result<RadiusPacket, RadiusError> {
+        let eap = EapPacket::completion(EapCode::Failure, identifier);
+        Ok(reply_packet(TypeCode::AccessReject, self.eap_attributes(&eap)?))
+    }
+
+    /// Returns EAP-Message attributes, which carry EAP packet, followed by Message-Authenticator
+    /// (RFC 3579 Section 3.2), which is generated by Server
+    fn eap_attributes(&self, eap: &EapPacket) -> Result<Vec<RadiusAttribute>, RadiusError> {
+        let mut attributes = eap.to_bytes()
+            .chunks(MAX_ATTRIBUTE_LENGTH)
+            .map(|chunk| self.host.create_attribute_by_id(EAP_MESSAGE_ID, chunk.to_vec()))
+            .collect::<Result<Vec<RadiusAttribute>, RadiusError>>()?;
+        attributes.push(self.host.create_attribute_by_id(MESSAGE_AUTHENTICATOR_ID, [0; 16].to_vec())?);
+        Ok(attributes)
+    }
+
+    /// Returns MS-MPPE-Recv-Key (first half of MSK) and MS-MPPE-Send-Key (second half of MSK)
+    /// attributes (RFC 3748 Section 7.10 & RFC 3079 Section 3.3)
+    fn mppe_key_attributes(&self, keys: &EapKeys, secret: &str, request_authenticator: &[u8]) -> Result<Vec<RadiusAttribute>, RadiusError> {
+        if keys.msk().len() < 64 {
+            return Err( RadiusError::EapError { error: format!("MSK is {} octets long, while at least 64 are required", keys.msk().len()) } )
+        }
+        let salt: u16 = thread_rng().gen::<u16>() | 0x8000;
+
+        [(MS_MPPE_RECV_KEY, &keys.msk()[..32], salt), (MS_MPPE_SEND_KEY, &keys.msk()[32..64], salt ^ 1)].iter()
+            .map(|(vendor_type, key, salt)| {
+                let value = encrypt_mppe_key(key, secret, request_authenticator, salt.to_be_bytes());
+                self.host.create_attribute_by_id(VENDOR_SPECIFIC_ID, microsoft_attribute(*vendor_type, &value))
+            })
+            .collect()
+    }
+
+    /// Stores new session, which waits for peer's response, under given State
+    fn store_session(&self, state: Vec<u8>, session: Session, now: Instant) -> Result<(), RadiusError> {
+        let mut sessions = lock(&self.sessions);
+
+        if sessions.len() >= self.max_sessions {
+            sessions.retain(|_, entry| entry.expires > now);
+            if sessions.len() >= self.max_sessions {
+                return Err( RadiusError::EapError { error: String::from("too many EAP sessions are in progress") } )
+            }
+        }
+        sessions.insert(state, SessionEntry { session: Arc::new(Mutex::new(session)), expires: now + self.session_timeout });
+        Ok(())
+    }
+
+    /// Returns session of given State, unless it has expired
+    fn session(&self, state: &[u8], now: Instant) -> Option<Arc<Mutex<Session>>> {
+        let mut sessions = lock(&self.sessions);
+
+        match sessions.get(state) {
+            Some(entry) if entry.expires > now => Some(Arc::clone(&entry.session)),
+            Some(_)                            => {
+                sessions.remove(state);
+                None
+            },
+            None                               => None
+        }
+    }
+
+    /// Keeps session, which waits for peer's next response, and forgets the completed one
+    fn finish_step(&self, state: &[u8], reply: &Result<RadiusPacket, RadiusError>, now: Instant) {
+        let mut sessions = lock(&self.sessions);
+
+        match reply {
+            Ok(reply) if is_challenge(reply) => {
+                if let Some(entry) = sessions.get_mut(state) {
+                    entry.expires = now + self.session_timeout;
+                }
+            },
+            _                                => {
+                sessions.remove(state);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for SessionEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionEntry")
+            .field("expires", &self.expires)
+            .finish()
+    }
+}
+
+/// Returns EAP packet, which is carried in EAP-Message attributes of RADIUS packet
+///
+/// Values of all EAP-Message attributes are concatenated in order (RFC 3579 Section 3.1)
+pub fn eap_message(packet: &RadiusPacket) -> Option<Vec<u8>> {
+    let mut attributes = packet.attributes().iter().filter(|attribute| attribute.id() == EAP_MESSAGE_ID).peekable();
+    attributes.peek()?;
+
+    Some(attributes.flat_map(|attribute| attribute.value().iter().copied()).collect())
+}
+
+/// Encrypts MS-MPPE key as defined in RFC 2548 Section 2.4.2 and returns Salt followed by the
+/// encrypted key
+fn encrypt_mppe_key(key: &[u8], secret: &str, request_authenticator: &[u8], salt: [u8; 2]) -> Vec<u8> {
+    let mut plain = vec![key.len() as u8];
+    plain.extend_from_slice(key);
+    plain.resize((plain.len() + 15) / 16 * 16, 0);
+
+    let mut value = salt.to_vec();
+    let mut hash  = Md5::new().chain_update(secret).chain_update(request_authenticator).chain_update(salt).finalize();
+
+    for chunk in plain.chunks(16) {
+        let cipher: Vec<u8> = chunk.iter().zip(hash.iter()).map(|(plain, hash)| plain ^ hash).collect();
+        hash = Md5::new().chain_update(secret).chain_update(&cipher).finalize();
+        value.extend(cipher);
+    }
+    value
+}
+
+fn microsoft_attribute(vendor_type: u8, value: &[u8]) -> Vec<u8> {
+    let mut attribute = MICROSOFT_VENDOR.to_be_bytes().to_vec();
+    attribute.push(vendor_type);
+    attribute.push(2 + value.len() as u8);
+    attribute.extend_from_slice(value);
+    attribute
+}
+
+fn parse_response(message: &[u8]) -> Result<EapPacket, RadiusError> {
+    let packet = EapPacket::from_bytes(message)?;
+    match packet.code() {
+        EapCode::Response => Ok(packet),
+        code              => Err( RadiusError::EapError { error: format!("EAP {:?} is not expected from peer", code) } )
+    }
+}
+
+/// Returns identity of EAP-Response/Identity; options, which follow NUL (RFC 4284), are dropped
+fn parse_identity(type_data: &[u8]) -> String {
+    let identity = type_data.split(|&byte| byte == 0).next().unwrap_or_default();
+    String::from_utf8_lossy(identity).into_owned()
+}
+
+/// Gives reply ID and Response Authenticator of request; Message-Authenticator is generated
+/// while reply still carries Request Authenticator (RFC 3579 Section 3.2)
+fn finalise_reply(reply: &mut RadiusPacket, secret: &str, request: &RadiusPacket) -> Result<(), RadiusError> {
+    reply.override_id(request.id());
+    reply.override_authenticator(request.authenticator().to_vec());
+    reply.generate_message_authenticator(secret)?;
+
+    let bytes         = reply.to_bytes();
+    let authenticator = Md5::new()
+        .chain_update(&bytes[0..4])
+        .chain_update(request.authenticator())
+        .chain_update(&bytes[20..])
+        .chain_update(secret)
+        .finalize();
+    reply.override_authenticator(authenticator.to_vec());
+    Ok(())
+}
+
+fn is_challenge(reply: &RadiusPacket) -> bool {
+    reply.code() == &TypeCode::AccessChallenge
+}
+
+fn reply_packet(code: TypeCode, attributes: Vec<RadiusAttribute>) -> RadiusPacket {
+    let mut reply = RadiusPacket::initialise_packet(code);
+    reply.set_attributes(attributes);
+    reply
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::client::Client;
+    use crate::testing::{ eap_reply, eap_request, mppe_msk, test_dictionary };
+
+    const TEST_TYPE:  u8 = 254;
+    const OTHER_TYPE: u8 = 253;
+
+    // Toy method: peer is asked for a secret, whose length is its own EAP Type
+    struct TestMethod {
+        eap_type: u8,
+        prefix:   &'static str
+    }
+
+    struct TestSession {
+        eap_type: u8
+    }
+
+    impl EapMethod for TestMethod {
+        fn eap_type(&self) -> u8 {
+            self.eap_type
+        }
+
+        fn is_preferred_for(&self, identity: &str) -> bool {
+            identity.starts_with(self.prefix)
+        }
+
+        fn start_session(&self, _identity: &str) -> Result<Box<dyn EapSession>, RadiusError> {
+            Ok(Box::new(TestSession { eap_type: self.eap_type }))
+        }
+    }
+
+    impl EapSession for TestSession {
+        fn start(&mut self, _identifier: u8) -> Result<EapStep, RadiusError> {
+            Ok(EapStep::Request(vec![b'?'; self.eap_type as usize]))
+        }
+
+        fn process(&mut self, response: &EapPacket, _identifier: u8) -> Result<EapStep, RadiusError> {
+            match response.type_data().len() == self.eap_type as usize {
+                true  => Ok(EapStep::Success(EapKeys::new((0..64).collect(), (64..128).collect()))),
+                false => Ok(EapStep::Failure)
+            }
+        }
+    }
+
+    fn test_authenticator() -> EapAuthenticator {
+        let registry = EapMethodRegistry::new()
+            .register(TestMethod { eap_type: TEST_TYPE,  prefix: "test" })
+            .register(TestMethod { eap_type: OTHER_TYPE, prefix: "other" });
+        EapAuthenticator::new(test_dictionary(), registry)
+    }
+
+    fn response(identifier: u8, eap_type: u8, type_data: &[u8]) -> EapPacket {
+        EapPacket::new(EapCode::Response, identifier, eap_type, type_data)
+    }
+
+    #[test]
+    fn test_eap_packet() {
+        let packet = EapPacket::new(EapCode::Response, 7, EAP_TYPE_IDENTITY, b"user");
+        let bytes  = packet.to_bytes();
+
+        assert_eq!(vec![2, 7, 0, 9, 1, b'u', b's', b'e', b'r'], bytes);
+        assert_eq!(packet,                                   EapPacket::from_bytes(&[&bytes[..], &[0, 0]].concat()).unwrap());
+        assert_eq!(Some(EAP_TYPE_IDENTITY),                  packet.eap_type());
+        assert_eq!(b"user",                                  packet.type_data());
+        assert_eq!(EapPacket::completion(EapCode::Success, 7), EapPacket::from_bytes(&[3, 7, 0, 4]).unwrap());
+
+        assert!(EapPacket::from_bytes(&[2, 7, 0, 4]).is_err());
+        assert!(EapPacket::from_bytes(&[2, 7, 0, 10, 1]).is_err());
+        assert!(EapPacket::from_bytes(&[5, 7, 0, 4]).is_err());
+    }
+
+    #[test]
+    fn test_eap_authenticator() {
+        let authenticator = test_authenticator();
+
+        // Method, which prefers identity, is offered regardless of registration order
+        let request          = eap_request(Some(&response(9, EAP_TYPE_IDENTITY, b"other@example.com\0NAIRealms=example.com")), None);
+        let (mut reply, eap) = eap_reply(authenticator.handle_request("secret", &request).unwrap());
+        let state            = reply.attribute_by_id(STATE_ID).unwrap().value().to_vec();
+
+        // Reply is ready to be sent: RADIUS Client accepts its Response Authenticator and
+        // Message-Authenticator, which is generated over Request Authenticator
+        let client    = Client::with_dictionary(test_dictionary()).set_secret(String::from("secret"));
+        let mut bytes = reply.to_bytes();
+        assert!(client.verify_reply(&request, &bytes).is_ok());
+        bytes[4..20].copy_from_slice(request.authenticator());
+        assert!(client.verify_message_authenticator(&bytes).is_ok());
+
+        assert_eq!(&TypeCode::AccessChallenge,                        reply.code());
+        assert_eq!((EapCode::Request, 10, Some(OTHER_TYPE)),          (eap.code(), eap.identifier(), eap.eap_type()));
+        // EAP-Request is split into EAP-Message attributes of at most 253 octets
+        assert_eq!(2,                                                 reply.attributes().iter().filter(|attribute| attribute.id() == EAP_MESSAGE_ID).count());
+        assert_eq!(1,                                                 authenticator.sessions());
+
+        // Response to another EAP-Request is silently discarded
+        let request       = eap_request(Some(&response(9, OTHER_TYPE, &[0; 253])), Some(&state));
+        assert_eq!(None,                                              authenticator.handle_request("secret", &request).unwrap());
+
+        let request       = eap_request(Some(&response(10, OTHER_TYPE, &[0; 253])), Some(&state));
+        let (reply, eap)  = eap_reply(authenticator.handle_request("secret", &request).unwrap());
+
+        assert_eq!(&TypeCode::AccessAccept,                           reply.code());
+        assert_eq!(EapPacket::completion(EapCode::Success, 10),       eap);
+        assert_eq!((0..64).collect::<Vec<u8>>(),                      mppe_msk(&reply, "secret", request.authenticator()));
+        assert_eq!(0,                                                 authenticator.sessions());
+
+        // Completed session is forgotten
+        let (reply, eap)  = eap_reply(authenticator.handle_request("secret", &request).unwrap());
+        assert_eq!(&TypeCode::AccessReject,                           reply.code());
+        assert_eq!(EapPacket::completion(EapCode::Failure, 10),       eap);
+    }
+
+    #[test]
+    fn test_eap_start_and_nak() {
+        let authenticator = test_authenticator();
+
+        let (reply, eap)  = eap_reply(authenticator.handle_request("secret", &eap_request(None, None)).unwrap());
+        let state         = reply.attribute_by_id(STATE_ID).unwrap().value().to_vec();
+        assert_eq!((EapCode::Request, Some(EAP_TYPE_IDENTITY)),       (eap.code(), eap.eap_type()));
+
+        let request       = eap_request(Some(&response(eap.identifier(), EAP_TYPE_IDENTITY, b"user")), Some(&state));
+        let (_, eap)      = eap_reply(authenticator.handle_request("secret", &request).unwrap());
+        assert_eq!(Some(TEST_TYPE),                                   eap.eap_type());
+
+        // Peer declines the first method and gets the one it desires
+        let request       = eap_request(Some(&response(eap.identifier(), EAP_TYPE_NAK, &[TEST_TYPE, OTHER_TYPE])), Some(&state));
+        let (_, eap)      = eap_reply(authenticator.handle_request("secret", &request).unwrap());
+        assert_eq!(Some(OTHER_TYPE),                                  eap.eap_type());
+
+        // Method, which peer has started to answer, could not be declined
+        let request       = eap_request(Some(&response(eap.identifier(), OTHER_TYPE, &[0; 3])), Some(&state));
+        let (reply, eap)  = eap_reply(authenticator.handle_request("secret", &request).unwrap());
+        assert_eq!(&TypeCode::AccessReject,                           reply.code());
+        assert_eq!(EapCode::Failure,                                  eap.code());
+        assert_eq!(0,                                                 authenticator.sessions());
+    }
+
+    #[test]
+    fn test_eap_authenticator_errors() {
+        let authenticator = test_authenticator().set_max_sessions(1);
+        let identity      = response(1, EAP_TYPE_IDENTITY, b"user");
+
+        let mut request   = eap_request(None, None);
+        request.set_attributes(Vec::new());
+        assert!(authenticator.handle_request("secret", &request).is_err());
+
+        let request       = eap_request(Some(&EapPacket::new(EapCode::Request, 1, EAP_TYPE_IDENTITY, b"user")), None);
+        assert!(authenticator.handle_request("secret", &request).is_err());
+
+        eap_reply(authenticator.handle_request("secret", &eap_request(Some(&identity), None)).unwrap());
+        assert!(authenticator.handle_request("secret", &eap_request(Some(&identity), None)).is_err());
+
+        // Expired sessions make room for new ones
+        let authenticator = test_authenticator().set_max_sessions(1).set_session_timeout(Duration::from_secs(0));
+        for _ in 0..2 {
+            eap_reply(authenticator.handle_request("secret", &eap_request(Some(&identity), None)).unwrap());
+        }
+        assert_eq!(1, authenticator.sessions());
+    }
+
+    #[test]
+    fn test_encrypt_mppe_key() {
+        let key   = [0x5a; 32];
+        let value = encrypt_mppe_key(&key, "secret", &[1; 16], [0x80, 0x01]);
+
+        // Salt, followed by 1 octet of key length, key and padding up to 48 octets
+        assert_eq!(2 + 48,       value.len());
+        assert_eq!([0x80, 0x01], value[0..2]);
+        assert_ne!(key[..],      value[3..35]);
+    }
+}
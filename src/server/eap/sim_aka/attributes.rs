@@ -0,0 +1,210 @@
+//! Attributes of EAP-SIM and EAP-AKA messages (RFC 4186 Section 8.1 & RFC 4187 Section 8.1)
+
+
+pub(super) const AT_RAND:              u8 = 1;
+pub(super) const AT_AUTN:              u8 = 2;
+pub(super) const AT_RES:               u8 = 3;
+pub(super) const AT_AUTS:              u8 = 4;
+pub(super) const AT_PADDING:           u8 = 6;
+pub(super) const AT_NONCE_MT:          u8 = 7;
+pub(super) const AT_PERMANENT_ID_REQ:  u8 = 10;
+pub(super) const AT_MAC:               u8 = 11;
+pub(super) const AT_NOTIFICATION:      u8 = 12;
+pub(super) const AT_ANY_ID_REQ:        u8 = 13;
+pub(super) const AT_IDENTITY:          u8 = 14;
+pub(super) const AT_VERSION_LIST:      u8 = 15;
+pub(super) const AT_SELECTED_VERSION:  u8 = 16;
+pub(super) const AT_FULLAUTH_ID_REQ:   u8 = 17;
+pub(super) const AT_COUNTER:           u8 = 19;
+pub(super) const AT_COUNTER_TOO_SMALL: u8 = 20;
+pub(super) const AT_NONCE_S:           u8 = 21;
+pub(super) const AT_CLIENT_ERROR_CODE: u8 = 22;
+pub(super) const AT_KDF_INPUT:         u8 = 23;
+pub(super) const AT_KDF:               u8 = 24;
+pub(super) const AT_IV:                u8 = 129;
+pub(super) const AT_ENCR_DATA:         u8 = 130;
+pub(super) const AT_NEXT_PSEUDONYM:    u8 = 132;
+pub(super) const AT_NEXT_REAUTH_ID:    u8 = 133;
+pub(super) const AT_BIDDING:           u8 = 136;
+
+// Attributes below 128, which are not known, make message malformed; the others are skipped
+const NON_SKIPPABLE_ATTRIBUTES: [u8; 20] = [
+    AT_RAND, AT_AUTN, AT_RES, AT_AUTS, AT_PADDING, AT_NONCE_MT, AT_PERMANENT_ID_REQ, AT_MAC,
+    AT_NOTIFICATION, AT_ANY_ID_REQ, AT_IDENTITY, AT_VERSION_LIST, AT_SELECTED_VERSION,
+    AT_FULLAUTH_ID_REQ, AT_COUNTER, AT_COUNTER_TOO_SMALL, AT_NONCE_S, AT_CLIENT_ERROR_CODE,
+    AT_KDF_INPUT, AT_KDF
+];
+
+pub(super) const MAC_LENGTH:   usize = 16;
+const SUBTYPE_HEADER_LENGTH:   usize = 3;
+
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Represents attributes in order of appearance; every value is kept without Type and Length,
+/// but with padding
+pub(super) struct Attributes {
+    attributes: Vec<(u8, Vec<u8>)>
+}
+
+impl Attributes {
+    pub(super) fn new() -> Attributes {
+        Attributes::default()
+    }
+
+    /// Adds attribute; value is padded with zeros to multiple of 4 octets (including Type and
+    /// Length)
+    pub(super) fn push(mut self, kind: u8, mut value: Vec<u8>) -> Attributes {
+        value.resize((value.len() + 2 + 3) / 4 * 4 - 2, 0);
+        self.attributes.push((kind, value));
+        self
+    }
+
+    /// Adds AT_PADDING, so attributes fill whole AES blocks (RFC 4186 Section 10.12)
+    pub(super) fn pad(self) -> Attributes {
+        match self.to_bytes().len() % 16 {
+            0         => self,
+            remainder => self.push(AT_PADDING, vec![0; 16 - remainder - 2])
+        }
+    }
+
+    /// Returns value of the first attribute of given type
+    pub(super) fn get(&self, kind: u8) -> Option<&[u8]> {
+        self.attributes.iter().find(|(attribute, _)| *attribute == kind).map(|(_, value)| &value[..])
+    }
+
+    pub(super) fn contains(&self, kind: u8) -> bool {
+        self.get(kind).is_some()
+    }
+
+    pub(super) fn to_bytes(&self) -> Vec<u8> {
+        self.attributes.iter()
+            .flat_map(|(kind, value)| [&[*kind, ((value.len() + 2) / 4) as u8], &value[..]].concat())
+            .collect()
+    }
+
+    /// Returns offset of MAC in bytes of attributes
+    pub(super) fn mac_offset(&self) -> Option<usize> {
+        let mut offset = 0;
+        for (kind, value) in &self.attributes {
+            if *kind == AT_MAC {
+                return Some(offset + 4)
+            }
+            offset += value.len() + 2;
+        }
+        None
+    }
+
+    /// Parses attributes; returns None, if they are malformed or carry unknown non-skippable
+    /// attribute
+    pub(super) fn parse(mut bytes: &[u8]) -> Option<Attributes> {
+        let mut attributes = Attributes::new();
+
+        while !bytes.is_empty() {
+            let kind   = bytes[0];
+            let length = *bytes.get(1)? as usize * 4;
+            if length == 0 || length > bytes.len() || (kind < 128 && !NON_SKIPPABLE_ATTRIBUTES.contains(&kind)) {
+                return None
+            }
+            attributes.attributes.push((kind, bytes[2..length].to_vec()));
+            bytes = &bytes[length..];
+        }
+        Some(attributes)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Represents type data of EAP-SIM/EAP-AKA packet: Subtype, Reserved and attributes
+pub(super) struct Message {
+    subtype:    u8,
+    attributes: Attributes
+}
+
+impl Message {
+    pub(super) fn new(subtype: u8, attributes: Attributes) -> Message {
+        Message { subtype, attributes }
+    }
+
+    pub(super) fn subtype(&self) -> u8 {
+        self.subtype
+    }
+
+    pub(super) fn attributes(&self) -> &Attributes {
+        &self.attributes
+    }
+
+    pub(super) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.subtype, 0, 0];
+        bytes.extend(self.attributes.to_bytes());
+        bytes
+    }
+
+    /// Returns offset of MAC in type data
+    pub(super) fn mac_offset(&self) -> Option<usize> {
+        self.attributes.mac_offset().map(|offset| SUBTYPE_HEADER_LENGTH + offset)
+    }
+
+    pub(super) fn parse(type_data: &[u8]) -> Option<Message> {
+        if type_data.len() < SUBTYPE_HEADER_LENGTH {
+            return None
+        }
+        Some(Message { subtype: type_data[0], attributes: Attributes::parse(&type_data[SUBTYPE_HEADER_LENGTH..])? })
+    }
+}
+
+/// Returns value, which starts with two reserved octets
+pub(super) fn reserved(data: &[u8]) -> Vec<u8> {
+    [&[0, 0], data].concat()
+}
+
+/// Returns value, which starts with actual length of data in octets
+pub(super) fn counted(data: &[u8]) -> Vec<u8> {
+    [&(data.len() as u16).to_be_bytes()[..], data].concat()
+}
+
+/// Returns data of given length, which follows two reserved octets
+pub(super) fn fixed(value: &[u8], length: usize) -> Option<&[u8]> {
+    value.get(2..2 + length)
+}
+
+/// Returns data, which follows its actual length
+pub(super) fn counted_data(value: &[u8]) -> Option<&[u8]> {
+    let length = u16_value(value)? as usize;
+    value.get(2..2 + length)
+}
+
+/// Returns value, which is a single 16 bits number
+pub(super) fn u16_value(value: &[u8]) -> Option<u16> {
+    Some(u16::from_be_bytes([*value.first()?, *value.get(1)?]))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attributes() {
+        let attributes = Attributes::new()
+            .push(AT_IDENTITY, counted(b"12345"))
+            .push(AT_MAC,      reserved(&[7; MAC_LENGTH]))
+            .push(AT_COUNTER,  1u16.to_be_bytes().to_vec());
+        let message    = Message::new(11, attributes.clone());
+        let bytes      = message.to_bytes();
+
+        // Identity is padded to multiple of 4 octets
+        assert_eq!(&[11, 0, 0, AT_IDENTITY, 3, 0, 5, b'1', b'2', b'3', b'4', b'5', 0, 0, 0, AT_MAC, 5, 0, 0], &bytes[..19]);
+        assert_eq!(Some(message.clone()),                          Message::parse(&bytes));
+        assert_eq!(Some(19),                                       message.mac_offset());
+        assert_eq!(&[7; MAC_LENGTH],                               &bytes[19..35]);
+        assert_eq!(Some(&b"12345"[..]),                            attributes.get(AT_IDENTITY).and_then(counted_data));
+        assert_eq!(Some(1),                                        attributes.get(AT_COUNTER).and_then(u16_value));
+        assert_eq!(0,                                              attributes.clone().pad().to_bytes().len() % 16);
+        assert_eq!(0,                                              Attributes::new().push(AT_NONCE_S, reserved(&[0; 16])).pad().to_bytes().len() % 16);
+
+        // Unknown attributes are skipped, unless they are non-skippable
+        assert!(Attributes::parse(&[200, 1, 0, 0]).is_some());
+        assert!(Attributes::parse(&[100, 1, 0, 0]).is_none());
+        assert!(Attributes::parse(&[AT_COUNTER, 0]).is_none());
+        assert!(Attributes::parse(&[AT_COUNTER, 2, 0, 0]).is_none());
+    }
+}
@@ -0,0 +1,274 @@
+//! Key derivation, message authentication and encryption of EAP-SIM (RFC 4186 Section 7),
+//! EAP-AKA (RFC 4187 Section 7) and EAP-AKA' (RFC 5448 Section 3)
+
+
+use super::attributes::MAC_LENGTH;
+
+use aes::Aes128;
+use aes::cipher::{ BlockDecryptMut, BlockEncryptMut, KeyIvInit };
+use aes::cipher::block_padding::NoPadding;
+use hmac::{ Hmac, Mac };
+use sha1::{ Digest, Sha1 };
+use sha1::digest::generic_array::GenericArray;
+use sha2::Sha256;
+
+
+type HmacSha1   = Hmac<Sha1>;
+type HmacSha256 = Hmac<Sha256>;
+
+const SHA1_INITIAL_STATE: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+const AKA_PRIME_FC:       u8       = 0x20;
+const AKA_PRIME_LABEL:    &[u8]    = b"EAP-AKA'";
+const AKA_PRIME_REAUTH:   &[u8]    = b"EAP-AKA' re-auth";
+
+pub(super) const KEY_LENGTH: usize = 16;
+const MSK_LENGTH:            usize = 64;
+const EMSK_LENGTH:           usize = 64;
+
+
+#[derive(Clone)]
+/// Represents keys of full authentication; reauthentication key is MK of EAP-SIM/EAP-AKA or K_re
+/// of EAP-AKA'
+pub(super) struct SessionKeys {
+    pub(super) k_encr: Vec<u8>,
+    pub(super) k_aut:  Vec<u8>,
+    pub(super) k_re:   Vec<u8>,
+    pub(super) msk:    Vec<u8>,
+    pub(super) emsk:   Vec<u8>
+}
+
+/// Returns keys of EAP-SIM full authentication
+pub(super) fn sim_keys(identity: &[u8], kcs: &[u8], nonce_mt: &[u8], version_list: &[u8], selected_version: &[u8]) -> SessionKeys {
+    let mk = Sha1::new()
+        .chain_update(identity)
+        .chain_update(kcs)
+        .chain_update(nonce_mt)
+        .chain_update(version_list)
+        .chain_update(selected_version)
+        .finalize()
+        .to_vec();
+    fips_keys(mk)
+}
+
+/// Returns keys of EAP-AKA full authentication
+pub(super) fn aka_keys(identity: &[u8], ck: &[u8], ik: &[u8]) -> SessionKeys {
+    let mk = Sha1::new().chain_update(identity).chain_update(ik).chain_update(ck).finalize().to_vec();
+    fips_keys(mk)
+}
+
+/// Returns keys of EAP-AKA' full authentication, which are derived from CK' and IK'
+pub(super) fn aka_prime_keys(identity: &[u8], ck: &[u8], ik: &[u8], network_name: &[u8], autn: &[u8]) -> SessionKeys {
+    let (ck, ik) = prime_ck_ik(ck, ik, network_name, autn);
+    let keys     = prf_prime(&[&ik[..], &ck[..]].concat(), &[AKA_PRIME_LABEL, identity].concat(), 208);
+
+    SessionKeys {
+        k_encr: keys[..16].to_vec(),
+        k_aut:  keys[16..48].to_vec(),
+        k_re:   keys[48..80].to_vec(),
+        msk:    keys[80..144].to_vec(),
+        emsk:   keys[144..].to_vec()
+    }
+}
+
+/// Returns MSK and EMSK of fast reauthentication
+pub(super) fn reauth_keys(aka_prime: bool, k_re: &[u8], identity: &[u8], counter: u16, nonce_s: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let mut keys = match aka_prime {
+        true  => prf_prime(k_re, &[AKA_PRIME_REAUTH, identity, &counter.to_be_bytes(), nonce_s].concat(), MSK_LENGTH + EMSK_LENGTH),
+        false => {
+            let xkey = Sha1::new()
+                .chain_update(identity)
+                .chain_update(counter.to_be_bytes())
+                .chain_update(nonce_s)
+                .chain_update(k_re)
+                .finalize();
+            fips_prf(&xkey, MSK_LENGTH + EMSK_LENGTH)
+        }
+    };
+    let emsk = keys.split_off(MSK_LENGTH);
+    (keys, emsk)
+}
+
+/// Returns MAC of EAP packet followed by message specific data: HMAC-SHA1-128 or, for EAP-AKA',
+/// HMAC-SHA-256-128
+pub(super) fn mac(aka_prime: bool, k_aut: &[u8], packet: &[u8], extra: &[u8]) -> Vec<u8> {
+    let mut mac = match aka_prime {
+        true  => {
+            let mut mac = HmacSha256::new_from_slice(k_aut).expect("HMAC can take key of any size");
+            mac.update(packet);
+            mac.update(extra);
+            mac.finalize().into_bytes().to_vec()
+        },
+        false => {
+            let mut mac = HmacSha1::new_from_slice(k_aut).expect("HMAC can take key of any size");
+            mac.update(packet);
+            mac.update(extra);
+            mac.finalize().into_bytes().to_vec()
+        }
+    };
+    mac.truncate(MAC_LENGTH);
+    mac
+}
+
+/// Returns true, if MAC of EAP packet, which carries MAC at given offset, is valid; comparison
+/// takes constant time
+pub(super) fn verify_mac(aka_prime: bool, k_aut: &[u8], packet: &[u8], offset: usize, extra: &[u8]) -> bool {
+    let received = match packet.get(offset..offset + MAC_LENGTH) {
+        Some(received) => received,
+        None           => return false
+    };
+    let mut zeroed = packet.to_vec();
+    zeroed[offset..offset + MAC_LENGTH].copy_from_slice(&[0; MAC_LENGTH]);
+
+    let expected = mac(aka_prime, k_aut, &zeroed, extra);
+    expected.iter().zip(received).fold(0, |difference, (expected, received)| difference | (expected ^ received)) == 0
+}
+
+/// Encrypts data, which fills whole AES blocks, with AES-128-CBC (AT_ENCR_DATA)
+pub(super) fn encrypt(k_encr: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut encrypted = data.to_vec();
+    cbc::Encryptor::<Aes128>::new_from_slices(k_encr, iv)
+        .expect("K_encr and IV are 16 octets long")
+        .encrypt_padded_mut::<NoPadding>(&mut encrypted, data.len())
+        .expect("Data fills whole AES blocks");
+    encrypted
+}
+
+/// Decrypts AT_ENCR_DATA; returns None, if it doesn't fill whole AES blocks or IV is invalid
+pub(super) fn decrypt(k_encr: &[u8], iv: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+    let mut decrypted = data.to_vec();
+    let length        = cbc::Decryptor::<Aes128>::new_from_slices(k_encr, iv).ok()?
+        .decrypt_padded_mut::<NoPadding>(&mut decrypted).ok()?
+        .len();
+    decrypted.truncate(length);
+    Some(decrypted)
+}
+
+/// Splits output of FIPS 186-2 PRF into K_encr, K_aut, MSK and EMSK; MK is kept for fast
+/// reauthentication
+fn fips_keys(mk: Vec<u8>) -> SessionKeys {
+    let keys = fips_prf(&mk, 2 * KEY_LENGTH + MSK_LENGTH + EMSK_LENGTH);
+
+    SessionKeys {
+        k_encr: keys[..16].to_vec(),
+        k_aut:  keys[16..32].to_vec(),
+        k_re:   mk,
+        msk:    keys[32..96].to_vec(),
+        emsk:   keys[96..].to_vec()
+    }
+}
+
+/// Pseudo-random function of FIPS 186-2 (with change notice 1), which is keyed by 160 bits XKEY
+/// (RFC 4186 Appendix B)
+fn fips_prf(xkey: &[u8], length: usize) -> Vec<u8> {
+    let mut xkey   = xkey.to_vec();
+    let mut result = Vec::with_capacity(length + 20);
+
+    while result.len() < length {
+        let w = g(&xkey);
+        // XKEY = (1 + XKEY + w) mod 2^160
+        let mut carry = 1u16;
+        for (xkey, w) in xkey.iter_mut().zip(w.iter()).rev() {
+            let sum = *xkey as u16 + *w as u16 + carry;
+            *xkey   = sum as u8;
+            carry   = sum >> 8;
+        }
+        result.extend_from_slice(&w);
+    }
+    result.truncate(length);
+    result
+}
+
+/// SHA-1 compression of XVAL padded with zeros (function G of FIPS 186-2)
+fn g(xval: &[u8]) -> [u8; 20] {
+    let mut state = SHA1_INITIAL_STATE;
+    let mut block = [0; 64];
+    block[..xval.len()].copy_from_slice(xval);
+    sha1::compress(&mut state, &[GenericArray::clone_from_slice(&block)]);
+
+    let mut w = [0; 20];
+    for (chunk, word) in w.chunks_mut(4).zip(state.iter()) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    w
+}
+
+/// PRF' of RFC 5448 Section 3.4, which returns given number of octets
+fn prf_prime(key: &[u8], s: &[u8], length: usize) -> Vec<u8> {
+    let mut result = Vec::with_capacity(length + 32);
+    let mut block  = Vec::new();
+
+    for counter in 1..=u8::MAX {
+        if result.len() >= length {
+            break;
+        }
+        let mut mac = HmacSha256::new_from_slice(key).expect("HMAC can take key of any size");
+        mac.update(&block);
+        mac.update(s);
+        mac.update(&[counter]);
+        block = mac.finalize().into_bytes().to_vec();
+        result.extend_from_slice(&block);
+    }
+    result.truncate(length);
+    result
+}
+
+/// Returns CK' and IK', which bind CK and IK to access network name (RFC 5448 Section 3.3)
+fn prime_ck_ik(ck: &[u8], ik: &[u8], network_name: &[u8], autn: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    // SQN xor AK is the first 6 octets of AUTN
+    let mut mac = HmacSha256::new_from_slice(&[ck, ik].concat()).expect("HMAC can take key of any size");
+    mac.update(&[AKA_PRIME_FC]);
+    mac.update(network_name);
+    mac.update(&(network_name.len() as u16).to_be_bytes());
+    mac.update(&autn[..6]);
+    mac.update(&6u16.to_be_bytes());
+
+    let mut ck = mac.finalize().into_bytes().to_vec();
+    let ik     = ck.split_off(KEY_LENGTH);
+    (ck, ik)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn test_fips_prf() {
+        // Example of FIPS 186-2 Appendix 3.1 (with XSEED of zeros)
+        let xkey = [0xbd, 0x02, 0x9b, 0xbe, 0x7f, 0x51, 0x96, 0x0b, 0xcf, 0x9e, 0xdb, 0x2b, 0x61, 0xf0, 0x6f, 0x0f, 0xeb, 0x5a, 0x38, 0xb6];
+        assert_eq!("2070b3223dba372fde1c0ffc7b2e3b498b2606143c6c18bacb0f6c55babb13788e20d737a3275116", hex(&fips_prf(&xkey, 40)));
+    }
+
+    #[test]
+    fn test_aka_prime_keys() {
+        // Test Case 1 of RFC 5448 Appendix C
+        let ck   = [0x53, 0x49, 0xfb, 0xe0, 0x98, 0x64, 0x9f, 0x94, 0x8f, 0x5d, 0x2e, 0x97, 0x3a, 0x81, 0xc0, 0x0f];
+        let ik   = [0x97, 0x44, 0x87, 0x1a, 0xd3, 0x2b, 0xf9, 0xbb, 0xd1, 0xdd, 0x5c, 0xe5, 0x4e, 0x3e, 0x2e, 0x5a];
+        let autn = [0xbb, 0x52, 0xe9, 0x1c, 0x74, 0x7a, 0xc3, 0xab, 0x2a, 0x5c, 0x23, 0xd1, 0x5e, 0xe3, 0x51, 0xd5];
+
+        let (ck_prime, ik_prime) = prime_ck_ik(&ck, &ik, b"WLAN", &autn);
+        assert_eq!("0093962d0dd84aa5684b045c9edffa04", hex(&ck_prime));
+        assert_eq!("ccfc230ca74fcc96c0a5d61164f5a76c", hex(&ik_prime));
+
+        let keys = aka_prime_keys(b"0555444333222111", &ck, &ik, b"WLAN", &autn);
+        assert_eq!("766fa0a6c317174b812d52fbcd11a179",                                                                                                 hex(&keys.k_encr));
+        assert_eq!("0842ea722ff6835bfa2032499fc3ec23c2f0e388b4f07543ffc677f1696d71ea",                                                                 hex(&keys.k_aut));
+        assert_eq!("cf83aa8bc7e0aced892acc98e76a9b2095b558c7795c7094715cb3393aa7d17a",                                                                 hex(&keys.k_re));
+        assert_eq!("67c42d9aa56c1b79e295e3459fc3d187d42be0bf818d3070e362c5e967a4d544e8ecfe19358ab3039aff03b7c930588c055babee58a02650b067ec4e9347c75a", hex(&keys.msk));
+        assert_eq!("f861703cd775590e16c7679ea3874ada866311de290764d760cf76df647ea01c313f69924bdd7650ca9bac141ea075c4ef9e8029c0e290cdbad5638b63bc23fb", hex(&keys.emsk));
+    }
+
+    #[test]
+    fn test_encryption() {
+        let data      = [0x5a; 32];
+        let encrypted = encrypt(&[1; 16], &[2; 16], &data);
+
+        assert_ne!(&data[..],          &encrypted[..]);
+        assert_eq!(Some(data.to_vec()), decrypt(&[1; 16], &[2; 16], &encrypted));
+        assert_eq!(None,                decrypt(&[1; 16], &[2; 16], &encrypted[..20]));
+    }
+}
@@ -0,0 +1,1303 @@
+//! EAP-SIM (RFC 4186), EAP-AKA (RFC 4187) and EAP-AKA' (RFC 5448) methods, which authenticate
+//! SIM/USIM based peers with authentication vectors of [VectorProvider]
+//!
+//! Methods issue pseudonyms and fast reauthentication identities to peers (in AT_ENCR_DATA of
+//! Challenge), so peers only send their permanent identities (IMSI) once. Issued identities are
+//! kept in memory of the method, one pseudonym and one fast reauthentication identity per
+//! subscriber. Protected result indications and Notifications are not supported; EAP-AKA
+//! synchronization failure ends authentication
+
+
+mod attributes;
+mod keys;
+
+use self::attributes::{
+    counted, counted_data, fixed, reserved, u16_value, Attributes, Message,
+    AT_AUTN, AT_BIDDING, AT_COUNTER, AT_COUNTER_TOO_SMALL, AT_ENCR_DATA, AT_FULLAUTH_ID_REQ,
+    AT_IDENTITY, AT_IV, AT_KDF, AT_KDF_INPUT, AT_MAC, AT_NEXT_PSEUDONYM, AT_NEXT_REAUTH_ID,
+    AT_NONCE_MT, AT_NONCE_S, AT_PERMANENT_ID_REQ, AT_RAND, AT_RES, AT_SELECTED_VERSION,
+    AT_VERSION_LIST, MAC_LENGTH
+};
+use self::keys::{ SessionKeys, KEY_LENGTH };
+use crate::protocol::error::RadiusError;
+use crate::server::eap::{ EapCode, EapKeys, EapMethod, EapPacket, EapSession, EapStep, EAP_TYPE_AKA, EAP_TYPE_AKA_PRIME, EAP_TYPE_SIM };
+use crate::tools::lock;
+
+use rand::{ thread_rng, Rng };
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::sync::{ Arc, Mutex };
+use std::time::{ Duration, Instant };
+
+
+const AKA_CHALLENGE:    u8 = 1;
+const AKA_IDENTITY:     u8 = 5;
+const SIM_START:        u8 = 10;
+const SIM_CHALLENGE:    u8 = 11;
+const REAUTHENTICATION: u8 = 13;
+
+const SIM_VERSION:       [u8; 2] = [0, 1];
+const AKA_PRIME_KDF:     [u8; 2] = [0, 1];
+// D bit of AT_BIDDING: Server supports EAP-AKA' (RFC 5448 Section 4)
+const BIDDING_AKA_PRIME: [u8; 2] = [0x80, 0];
+
+const SIM_TRIPLETS:            usize    = 3;
+const NONCE_LENGTH:            usize    = 16;
+// Code, Identifier, Length and Type of EAP packet
+const EAP_HEADER_LENGTH:       usize    = 5;
+const DEFAULT_REAUTH_LIFETIME: Duration = Duration::from_secs(3600);
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Represents GSM authentication triplet, which EAP-SIM challenges peer with
+pub struct SimTriplet {
+    rand: [u8; 16],
+    sres: [u8; 4],
+    kc:   [u8; 8]
+}
+
+impl SimTriplet {
+    /// Initialises SimTriplet
+    pub fn new(rand: [u8; 16], sres: [u8; 4], kc: [u8; 8]) -> SimTriplet {
+        SimTriplet { rand, sres, kc }
+    }
+
+    /// Returns RAND
+    pub fn rand(&self) -> &[u8; 16] {
+        &self.rand
+    }
+
+    /// Returns SRES
+    pub fn sres(&self) -> &[u8; 4] {
+        &self.sres
+    }
+
+    /// Returns Kc
+    pub fn kc(&self) -> &[u8; 8] {
+        &self.kc
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Represents UMTS authentication vector (quintuplet), which EAP-AKA and EAP-AKA' challenge peer
+/// with
+pub struct AkaQuintuplet {
+    rand: [u8; 16],
+    autn: [u8; 16],
+    xres: Vec<u8>,
+    ck:   [u8; 16],
+    ik:   [u8; 16]
+}
+
+impl AkaQuintuplet {
+    /// Initialises AkaQuintuplet; XRES has to be 4 to 16 octets long
+    pub fn new(rand: [u8; 16], autn: [u8; 16], xres: Vec<u8>, ck: [u8; 16], ik: [u8; 16]) -> AkaQuintuplet {
+        AkaQuintuplet { rand, autn, xres, ck, ik }
+    }
+
+    /// Returns RAND
+    pub fn rand(&self) -> &[u8; 16] {
+        &self.rand
+    }
+
+    /// Returns AUTN
+    pub fn autn(&self) -> &[u8; 16] {
+        &self.autn
+    }
+
+    /// Returns XRES
+    pub fn xres(&self) -> &[u8] {
+        &self.xres
+    }
+
+    /// Returns CK
+    pub fn ck(&self) -> &[u8; 16] {
+        &self.ck
+    }
+
+    /// Returns IK
+    pub fn ik(&self) -> &[u8; 16] {
+        &self.ik
+    }
+}
+
+/// This trait is to be implemented by user to fetch authentication vectors of subscribers (ie
+/// from HLR/HSS)
+///
+/// Subscriber is identified by IMSI, which is taken from peer's permanent identity. Provider,
+/// which doesn't know subscriber, returns None and peer is not authenticated; if provider
+/// returns an error, request is discarded without reply
+pub trait VectorProvider: Send + Sync {
+    /// Returns up to given number of GSM triplets of subscriber for EAP-SIM; at least 2 triplets
+    /// with distinct RANDs are required
+    ///
+    /// Returns None by default
+    fn sim_triplets(&self, _imsi: &str, _count: usize) -> Result<Option<Vec<SimTriplet>>, RadiusError> {
+        Ok(None)
+    }
+
+    /// Returns UMTS authentication vector of subscriber for EAP-AKA and EAP-AKA'
+    ///
+    /// Returns None by default
+    fn aka_quintuplet(&self, _imsi: &str) -> Result<Option<AkaQuintuplet>, RadiusError> {
+        Ok(None)
+    }
+}
+
+impl<P: VectorProvider + ?Sized> VectorProvider for Arc<P> {
+    fn sim_triplets(&self, imsi: &str, count: usize) -> Result<Option<Vec<SimTriplet>>, RadiusError> {
+        (**self).sim_triplets(imsi, count)
+    }
+
+    fn aka_quintuplet(&self, imsi: &str) -> Result<Option<AkaQuintuplet>, RadiusError> {
+        (**self).aka_quintuplet(imsi)
+    }
+}
+
+#[derive(Debug)]
+/// Provides authentication vectors from a file, which is meant for tests and labs
+///
+/// Every line holds a single vector: IMSI, `SIM` or `AKA` and values of the vector in hex.
+/// Empty lines and lines starting with `#` are ignored
+///
+/// ```text
+/// # IMSI          SIM RAND                             SRES     Kc
+/// 232010000000001 SIM 000102030405060708090a0b0c0d0e0f d1d2d3d4 a0a1a2a3a4a5a6a7
+/// # IMSI          AKA RAND                             AUTN                             XRES             CK                               IK
+/// 232010000000001 AKA 101112131415161718191a1b1c1d1e1f 202122232425262728292a2b2c2d2e2f 3031323334353637 404142434445464748494a4b4c4d4e4f 505152535455565758595a5b5c5d5e5f
+/// ```
+///
+/// File is read whenever vectors are requested, so subscribers could be added without
+/// restarting Server; vectors of subscriber are handed out in turn, starting over once all of
+/// them have been used
+pub struct FileVectorProvider {
+    path: PathBuf,
+    next: Mutex<HashMap<(String, bool), usize>>
+}
+
+impl FileVectorProvider {
+    /// Initialises FileVectorProvider, which reads vectors from given file
+    pub fn new<P: AsRef<Path>>(path: P) -> FileVectorProvider {
+        FileVectorProvider { path: path.as_ref().to_path_buf(), next: Mutex::new(HashMap::new()) }
+    }
+
+    /// Returns file path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns up to given number of subscriber's vectors of given kind, which are next in turn
+    fn vectors<T>(&self, imsi: &str, sim: bool, count: usize, parse: fn(&[Vec<u8>]) -> Option<T>) -> Result<Option<Vec<T>>, RadiusError> {
+        let mut vectors = Vec::new();
+
+        for line in fs::read_to_string(&self.path)?.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let fields = line.split_whitespace().collect::<Vec<&str>>();
+            let values = fields.iter().skip(2).map(|field| decode_hex(field)).collect::<Option<Vec<Vec<u8>>>>();
+            let kind   = fields.get(1).map(|kind| kind.to_ascii_uppercase());
+
+            let valid = match (kind.as_deref(), &values) {
+                (Some("SIM"), Some(values)) => parse_triplet(values).is_some(),
+                (Some("AKA"), Some(values)) => parse_quintuplet(values).is_some(),
+                _                           => false
+            };
+            if !valid {
+                return Err( RadiusError::ValidationError { error: format!("Invalid authentication vector: {}", line) } )
+            }
+            if fields[0] == imsi && (kind.as_deref() == Some("SIM")) == sim {
+                vectors.extend(values.as_deref().and_then(parse));
+            }
+        }
+        if vectors.is_empty() {
+            return Ok(None)
+        }
+
+        let mut next  = lock(&self.next);
+        let start     = next.entry((imsi.to_string(), sim)).or_insert(0);
+        let count     = count.min(vectors.len());
+        let first     = *start % vectors.len();
+        *start        = first + count;
+
+        vectors.rotate_left(first);
+        vectors.truncate(count);
+        Ok(Some(vectors))
+    }
+}
+
+impl VectorProvider for FileVectorProvider {
+    fn sim_triplets(&self, imsi: &str, count: usize) -> Result<Option<Vec<SimTriplet>>, RadiusError> {
+        self.vectors(imsi, true, count, parse_triplet)
+    }
+
+    fn aka_quintuplet(&self, imsi: &str) -> Result<Option<AkaQuintuplet>, RadiusError> {
+        Ok(self.vectors(imsi, false, 1, parse_quintuplet)?.and_then(|mut vectors| vectors.pop()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variant {
+    Sim,
+    Aka,
+    AkaPrime
+}
+
+impl Variant {
+    fn eap_type(&self) -> u8 {
+        match self {
+            Variant::Sim      => EAP_TYPE_SIM,
+            Variant::Aka      => EAP_TYPE_AKA,
+            Variant::AkaPrime => EAP_TYPE_AKA_PRIME
+        }
+    }
+
+    /// Returns leading characters of permanent identity, pseudonym and fast reauthentication
+    /// identity (3GPP TS 23.003 Section 19.3)
+    fn prefixes(&self) -> [char; 3] {
+        match self {
+            Variant::Sim      => ['1', '3', '5'],
+            Variant::Aka      => ['0', '2', '4'],
+            Variant::AkaPrime => ['6', '7', '8']
+        }
+    }
+
+    fn challenge(&self) -> u8 {
+        match self {
+            Variant::Sim => SIM_CHALLENGE,
+            _            => AKA_CHALLENGE
+        }
+    }
+
+    fn is_aka_prime(&self) -> bool {
+        *self == Variant::AkaPrime
+    }
+}
+
+#[derive(Clone)]
+/// Holds keys of the last full authentication, which fast reauthentication reuses
+struct ReauthContext {
+    imsi:    String,
+    k_encr:  Vec<u8>,
+    k_aut:   Vec<u8>,
+    k_re:    Vec<u8>,
+    counter: u16,
+    expires: Instant
+}
+
+#[derive(Default)]
+struct IdentityStore {
+    // Pseudonym => IMSI
+    pseudonyms: HashMap<String, String>,
+    reauth_ids: HashMap<String, ReauthContext>,
+    // IMSI => pseudonym and fast reauthentication identity, which have been issued last
+    issued:     HashMap<String, (Option<String>, Option<String>)>
+}
+
+enum PeerIdentity {
+    Permanent(String),
+    Reauth(ReauthContext),
+    Unknown
+}
+
+impl IdentityStore {
+    /// Returns IMSI of permanent identity or known pseudonym, or context of fast reauthentication
+    /// identity, which could only be used once
+    fn resolve(&mut self, variant: Variant, identity: &str, now: Instant) -> PeerIdentity {
+        let username                       = identity.split('@').next().unwrap_or_default();
+        let [permanent, pseudonym, reauth] = variant.prefixes();
+        let mut characters                 = username.chars();
+
+        match characters.next() {
+            Some(prefix) if prefix == permanent && !characters.as_str().is_empty() => PeerIdentity::Permanent(characters.as_str().to_string()),
+            Some(prefix) if prefix == pseudonym                                     => match self.pseudonyms.get(username) {
+                Some(imsi) => PeerIdentity::Permanent(imsi.clone()),
+                None       => PeerIdentity::Unknown
+            },
+            Some(prefix) if prefix == reauth                                        => match self.reauth_ids.remove(username) {
+                Some(context) if context.expires > now => PeerIdentity::Reauth(context),
+                _                                      => PeerIdentity::Unknown
+            },
+            _                                                                       => PeerIdentity::Unknown
+        }
+    }
+
+    /// Stores identities, which subscriber has been issued, in place of the previous ones
+    fn commit(&mut self, imsi: &str, pseudonym: Option<String>, reauth: Option<(String, ReauthContext)>) {
+        let issued = self.issued.entry(imsi.to_string()).or_default();
+
+        if let Some(pseudonym) = pseudonym {
+            if let Some(previous) = issued.0.replace(pseudonym.clone()) {
+                self.pseudonyms.remove(&previous);
+            }
+            self.pseudonyms.insert(pseudonym, imsi.to_string());
+        }
+        if let Some((reauth_id, context)) = reauth {
+            if let Some(previous) = issued.1.replace(reauth_id.clone()) {
+                self.reauth_ids.remove(&previous);
+            }
+            self.reauth_ids.insert(reauth_id, context);
+        }
+    }
+}
+
+#[derive(Clone)]
+struct SimAka {
+    variant:         Variant,
+    provider:        Arc<dyn VectorProvider>,
+    network_name:    String,
+    pseudonyms:      bool,
+    fast_reauth:     bool,
+    reauth_lifetime: Duration,
+    bidding:         bool,
+    identities:      Arc<Mutex<IdentityStore>>
+}
+
+impl SimAka {
+    fn new(variant: Variant, provider: Arc<dyn VectorProvider>, network_name: String) -> SimAka {
+        SimAka {
+            variant,
+            provider,
+            network_name,
+            pseudonyms:      true,
+            fast_reauth:     true,
+            reauth_lifetime: DEFAULT_REAUTH_LIFETIME,
+            bidding:         false,
+            identities:      Arc::new(Mutex::new(IdentityStore::default()))
+        }
+    }
+
+    fn is_preferred_for(&self, identity: &str) -> bool {
+        identity.chars().next().map_or(false, |prefix| self.variant.prefixes().contains(&prefix))
+    }
+
+    fn start_session(&self, identity: &str) -> Box<dyn EapSession> {
+        Box::new(SimAkaSession { method: self.clone(), identity: identity.to_string(), state: SessionState::Done })
+    }
+}
+
+#[derive(Clone)]
+/// Represents EAP-SIM method (RFC 4186), which could be registered with
+/// [EapMethodRegistry](crate::server::eap::EapMethodRegistry)
+///
+/// Method is preferred for EAP-SIM identities (ones, which start with `1`, `3` or `5`)
+pub struct EapSim {
+    method: SimAka
+}
+
+impl EapSim {
+    /// Initialises EapSim, which takes GSM triplets from given provider
+    pub fn new<P: VectorProvider + 'static>(provider: P) -> EapSim {
+        EapSim { method: SimAka::new(Variant::Sim, Arc::new(provider), String::new()) }
+    }
+
+    // === Builder for EapSim ===
+    /// **Optional**
+    ///
+    /// Sets, whether pseudonyms are issued to peers (true by default)
+    pub fn set_pseudonyms(mut self, pseudonyms: bool) -> EapSim {
+        self.method.pseudonyms = pseudonyms;
+        self
+    }
+
+    /// **Optional**
+    ///
+    /// Sets, whether fast reauthentication identities are issued to peers (true by default)
+    pub fn set_fast_reauth(mut self, fast_reauth: bool) -> EapSim {
+        self.method.fast_reauth = fast_reauth;
+        self
+    }
+
+    /// **Optional**
+    ///
+    /// Sets time, within which peer could reauthenticate after full authentication (1 hour by
+    /// default)
+    pub fn set_reauth_lifetime(mut self, reauth_lifetime: Duration) -> EapSim {
+        self.method.reauth_lifetime = reauth_lifetime;
+        self
+    }
+    // ===================
+
+    /// Returns true, if pseudonyms are issued to peers
+    pub fn pseudonyms(&self) -> bool {
+        self.method.pseudonyms
+    }
+
+    /// Returns true, if fast reauthentication identities are issued to peers
+    pub fn fast_reauth(&self) -> bool {
+        self.method.fast_reauth
+    }
+
+    /// Returns time, within which peer could reauthenticate after full authentication
+    pub fn reauth_lifetime(&self) -> Duration {
+        self.method.reauth_lifetime
+    }
+}
+
+impl fmt::Debug for EapSim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EapSim")
+            .field("pseudonyms",      &self.method.pseudonyms)
+            .field("fast_reauth",     &self.method.fast_reauth)
+            .field("reauth_lifetime", &self.method.reauth_lifetime)
+            .finish()
+    }
+}
+
+impl EapMethod for EapSim {
+    fn eap_type(&self) -> u8 {
+        EAP_TYPE_SIM
+    }
+
+    fn is_preferred_for(&self, identity: &str) -> bool {
+        self.method.is_preferred_for(identity)
+    }
+
+    fn start_session(&self, identity: &str) -> Result<Box<dyn EapSession>, RadiusError> {
+        Ok(self.method.start_session(identity))
+    }
+}
+
+#[derive(Clone)]
+/// Represents EAP-AKA (RFC 4187) or EAP-AKA' (RFC 5448) method, which could be registered with
+/// [EapMethodRegistry](crate::server::eap::EapMethodRegistry)
+///
+/// Method is preferred for its own identities: ones, which start with `0`, `2` or `4` for
+/// EAP-AKA and with `6`, `7` or `8` for EAP-AKA'
+pub struct EapAka {
+    method: SimAka
+}
+
+impl EapAka {
+    /// Initialises EAP-AKA method, which takes authentication vectors from given provider
+    pub fn new<P: VectorProvider + 'static>(provider: P) -> EapAka {
+        EapAka { method: SimAka::new(Variant::Aka, Arc::new(provider), String::new()) }
+    }
+
+    /// Initialises EAP-AKA' method, which takes authentication vectors from given provider and
+    /// binds keys to given access network name (ie `WLAN`, 3GPP TS 24.302 Section 8.1.1)
+    pub fn new_prime<P: VectorProvider + 'static>(provider: P, network_name: String) -> EapAka {
+        EapAka { method: SimAka::new(Variant::AkaPrime, Arc::new(provider), network_name) }
+    }
+
+    // === Builder for EapAka ===
+    /// **Optional**
+    ///
+    /// Sets, whether pseudonyms are issued to peers (true by default)
+    pub fn set_pseudonyms(mut self, pseudonyms: bool) -> EapAka {
+        self.method.pseudonyms = pseudonyms;
+        self
+    }
+
+    /// **Optional**
+    ///
+    /// Sets, whether fast reauthentication identities are issued to peers (true by default)
+    pub fn set_fast_reauth(mut self, fast_reauth: bool) -> EapAka {
+        self.method.fast_reauth = fast_reauth;
+        self
+    }
+
+    /// **Optional**
+    ///
+    /// Sets time, within which peer could reauthenticate after full authentication (1 hour by
+    /// default)
+    pub fn set_reauth_lifetime(mut self, reauth_lifetime: Duration) -> EapAka {
+        self.method.reauth_lifetime = reauth_lifetime;
+        self
+    }
+
+    /// **Optional**
+    ///
+    /// Sets, whether EAP-AKA' is offered by Server as well (false by default); if so, EAP-AKA
+    /// challenges carry AT_BIDDING, so peers, which support EAP-AKA', detect bidding down attack
+    /// (RFC 5448 Section 4). It has no effect on EAP-AKA' method
+    pub fn set_aka_prime_offered(mut self, offered: bool) -> EapAka {
+        self.method.bidding = offered;
+        self
+    }
+    // ===================
+
+    /// Returns access network name of EAP-AKA' method or None for EAP-AKA one
+    pub fn network_name(&self) -> Option<&str> {
+        match self.method.variant {
+            Variant::AkaPrime => Some(&self.method.network_name),
+            _                 => None
+        }
+    }
+
+    /// Returns true, if pseudonyms are issued to peers
+    pub fn pseudonyms(&self) -> bool {
+        self.method.pseudonyms
+    }
+
+    /// Returns true, if fast reauthentication identities are issued to peers
+    pub fn fast_reauth(&self) -> bool {
+        self.method.fast_reauth
+    }
+
+    /// Returns time, within which peer could reauthenticate after full authentication
+    pub fn reauth_lifetime(&self) -> Duration {
+        self.method.reauth_lifetime
+    }
+
+    /// Returns true, if EAP-AKA' is offered by Server as well
+    pub fn aka_prime_offered(&self) -> bool {
+        self.method.bidding
+    }
+}
+
+impl fmt::Debug for EapAka {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EapAka")
+            .field("network_name",      &self.network_name())
+            .field("pseudonyms",        &self.method.pseudonyms)
+            .field("fast_reauth",       &self.method.fast_reauth)
+            .field("reauth_lifetime",   &self.method.reauth_lifetime)
+            .field("aka_prime_offered", &self.method.bidding)
+            .finish()
+    }
+}
+
+impl EapMethod for EapAka {
+    fn eap_type(&self) -> u8 {
+        self.method.variant.eap_type()
+    }
+
+    fn is_preferred_for(&self, identity: &str) -> bool {
+        self.method.is_preferred_for(identity)
+    }
+
+    fn start_session(&self, identity: &str) -> Result<Box<dyn EapSession>, RadiusError> {
+        Ok(self.method.start_session(identity))
+    }
+}
+
+struct NextIdentities {
+    pseudonym: Option<String>,
+    reauth_id: Option<String>
+}
+
+enum SessionState {
+    // EAP-Request/SIM/Start or EAP-Request/AKA-Identity has been sent
+    Identity  { id_request: Option<u8>, imsi: Option<String> },
+    // Expected is n*SRES for EAP-SIM and XRES for EAP-AKA
+    Challenge { imsi: String, keys: SessionKeys, expected: Vec<u8>, next: NextIdentities },
+    Reauth    { context: ReauthContext, nonce_s: [u8; NONCE_LENGTH], next_reauth_id: String },
+    Done
+}
+
+enum IdentityAnswer {
+    Imsi(String),
+    Request(u8),
+    Failure
+}
+
+struct SimAkaSession {
+    method:   SimAka,
+    // The last identity, which peer has sent; keys are derived from it
+    identity: String,
+    state:    SessionState
+}
+
+impl EapSession for SimAkaSession {
+    fn start(&mut self, identifier: u8) -> Result<EapStep, RadiusError> {
+        let peer = lock(&self.method.identities).resolve(self.method.variant, &self.identity, Instant::now());
+
+        match peer {
+            PeerIdentity::Reauth(context) if self.method.fast_reauth => Ok(self.reauthenticate(context, identifier)),
+            PeerIdentity::Permanent(imsi)                            => self.full_authentication(Some(imsi), None, identifier),
+            _                                                        => self.full_authentication(None, Some(AT_FULLAUTH_ID_REQ), identifier)
+        }
+    }
+
+    fn process(&mut self, response: &EapPacket, identifier: u8) -> Result<EapStep, RadiusError> {
+        let message = match Message::parse(response.type_data()) {
+            Some(message) => message,
+            None          => return Ok(EapStep::Failure)
+        };
+        let variant = self.method.variant;
+
+        // Client-Error, EAP-AKA Authentication-Reject and Synchronization-Failure end authentication
+        match (std::mem::replace(&mut self.state, SessionState::Done), message.subtype()) {
+            (SessionState::Identity { id_request, imsi }, SIM_START) if variant == Variant::Sim   => self.process_start(id_request, imsi, &message, identifier),
+            (SessionState::Identity { id_request, .. }, AKA_IDENTITY) if variant != Variant::Sim => {
+                match self.identity_answer(id_request, None, message.attributes()) {
+                    IdentityAnswer::Imsi(imsi)       => self.aka_challenge(imsi, identifier),
+                    IdentityAnswer::Request(request) => self.full_authentication(None, Some(request), identifier),
+                    IdentityAnswer::Failure          => Ok(EapStep::Failure)
+                }
+            },
+            (SessionState::Challenge { imsi, keys, expected, next }, subtype) if subtype == variant.challenge() => {
+                Ok(self.process_challenge(response, &message, imsi, keys, expected, next))
+            },
+            (SessionState::Reauth { context, nonce_s, next_reauth_id }, REAUTHENTICATION)         => {
+                self.process_reauth(response, &message, context, nonce_s, next_reauth_id, identifier)
+            },
+            _                                                                                     => Ok(EapStep::Failure)
+        }
+    }
+}
+
+impl SimAkaSession {
+    /// Asks peer for identity (and EAP-SIM peer for NONCE_MT) or challenges EAP-AKA peer, whose
+    /// IMSI is known
+    fn full_authentication(&mut self, imsi: Option<String>, id_request: Option<u8>, identifier: u8) -> Result<EapStep, RadiusError> {
+        let (subtype, mut attributes) = match (self.method.variant, imsi) {
+            (Variant::Sim, imsi) => {
+                self.state = SessionState::Identity { id_request, imsi };
+                (SIM_START, Attributes::new().push(AT_VERSION_LIST, counted(&SIM_VERSION)))
+            },
+            (_, Some(imsi))      => return self.aka_challenge(imsi, identifier),
+            (_, None)            => {
+                self.state = SessionState::Identity { id_request, imsi: None };
+                (AKA_IDENTITY, Attributes::new())
+            }
+        };
+        if let Some(id_request) = id_request {
+            attributes = attributes.push(id_request, reserved(&[]));
+        }
+        Ok(self.request(identifier, Message::new(subtype, attributes), None))
+    }
+
+    /// Returns IMSI of peer, which has answered identity request (if any), or identity request,
+    /// which is to be sent next
+    fn identity_answer(&mut self, id_request: Option<u8>, imsi: Option<String>, attributes: &Attributes) -> IdentityAnswer {
+        let id_request = match (id_request, imsi) {
+            (Some(id_request), _) => id_request,
+            (None, Some(imsi))    => return IdentityAnswer::Imsi(imsi),
+            (None, None)          => return IdentityAnswer::Failure
+        };
+        let identity = attributes.get(AT_IDENTITY)
+            .and_then(counted_data)
+            .and_then(|identity| std::str::from_utf8(identity).ok());
+        match identity {
+            Some(identity) => self.identity = identity.to_string(),
+            None           => return IdentityAnswer::Failure
+        }
+
+        // Peer, whose pseudonym is not known, is asked for its permanent identity
+        match lock(&self.method.identities).resolve(self.method.variant, &self.identity, Instant::now()) {
+            PeerIdentity::Permanent(imsi)         => IdentityAnswer::Imsi(imsi),
+            _ if id_request == AT_FULLAUTH_ID_REQ => IdentityAnswer::Request(AT_PERMANENT_ID_REQ),
+            _                                     => IdentityAnswer::Failure
+        }
+    }
+
+    fn process_start(&mut self, id_request: Option<u8>, imsi: Option<String>, message: &Message, identifier: u8) -> Result<EapStep, RadiusError> {
+        let imsi = match self.identity_answer(id_request, imsi, message.attributes()) {
+            IdentityAnswer::Imsi(imsi)       => imsi,
+            IdentityAnswer::Request(request) => return self.full_authentication(None, Some(request), identifier),
+            IdentityAnswer::Failure          => return Ok(EapStep::Failure)
+        };
+        let nonce_mt = message.attributes().get(AT_NONCE_MT).and_then(|value| fixed(value, NONCE_LENGTH));
+        let version  = message.attributes().get(AT_SELECTED_VERSION);
+
+        match (nonce_mt, version) {
+            (Some(nonce_mt), Some(version)) if version == SIM_VERSION => self.sim_challenge(imsi, nonce_mt, identifier),
+            _                                                         => Ok(EapStep::Failure)
+        }
+    }
+
+    fn sim_challenge(&mut self, imsi: String, nonce_mt: &[u8], identifier: u8) -> Result<EapStep, RadiusError> {
+        let mut triplets = match self.method.provider.sim_triplets(&imsi, SIM_TRIPLETS)? {
+            Some(triplets) => triplets,
+            None           => return Ok(EapStep::Failure)
+        };
+        triplets.truncate(SIM_TRIPLETS);
+
+        let distinct = triplets.iter().enumerate().all(|(index, triplet)| triplets[..index].iter().all(|other| other.rand != triplet.rand));
+        if triplets.len() < 2 || !distinct {
+            return Err( RadiusError::EapError { error: format!("EAP-SIM requires at least 2 triplets with distinct RANDs, while {} have been provided", triplets.len()) } )
+        }
+
+        let rands: Vec<u8> = triplets.iter().flat_map(|triplet| triplet.rand).collect();
+        let kcs:   Vec<u8> = triplets.iter().flat_map(|triplet| triplet.kc).collect();
+        let sres:  Vec<u8> = triplets.iter().flat_map(|triplet| triplet.sres).collect();
+        let keys           = keys::sim_keys(self.identity.as_bytes(), &kcs, nonce_mt, &SIM_VERSION, &SIM_VERSION);
+
+        let (attributes, next) = self.next_identities(Attributes::new().push(AT_RAND, reserved(&rands)), &keys);
+        let attributes         = attributes.push(AT_MAC, reserved(&[0; MAC_LENGTH]));
+        let step               = self.request(identifier, Message::new(SIM_CHALLENGE, attributes), Some((&keys.k_aut, nonce_mt)));
+
+        self.state = SessionState::Challenge { imsi, keys, expected: sres, next };
+        Ok(step)
+    }
+
+    fn aka_challenge(&mut self, imsi: String, identifier: u8) -> Result<EapStep, RadiusError> {
+        let vector = match self.method.provider.aka_quintuplet(&imsi)? {
+            Some(vector) => vector,
+            None         => return Ok(EapStep::Failure)
+        };
+        if !(4..=16).contains(&vector.xres.len()) {
+            return Err( RadiusError::EapError { error: format!("XRES is {} octets long, while 4 to 16 are allowed", vector.xres.len()) } )
+        }
+
+        let mut attributes = Attributes::new()
+            .push(AT_RAND, reserved(&vector.rand))
+            .push(AT_AUTN, reserved(&vector.autn));
+        let keys           = match self.method.variant {
+            Variant::AkaPrime => {
+                attributes = attributes
+                    .push(AT_KDF_INPUT, counted(self.method.network_name.as_bytes()))
+                    .push(AT_KDF,       AKA_PRIME_KDF.to_vec());
+                keys::aka_prime_keys(self.identity.as_bytes(), &vector.ck, &vector.ik, self.method.network_name.as_bytes(), &vector.autn)
+            },
+            _                 => {
+                if self.method.bidding {
+                    attributes = attributes.push(AT_BIDDING, BIDDING_AKA_PRIME.to_vec());
+                }
+                keys::aka_keys(self.identity.as_bytes(), &vector.ck, &vector.ik)
+            }
+        };
+
+        let (attributes, next) = self.next_identities(attributes, &keys);
+        let attributes         = attributes.push(AT_MAC, reserved(&[0; MAC_LENGTH]));
+        let step               = self.request(identifier, Message::new(AKA_CHALLENGE, attributes), Some((&keys.k_aut, &[])));
+
+        self.state = SessionState::Challenge { imsi, keys, expected: vector.xres, next };
+        Ok(step)
+    }
+
+    fn process_challenge(&mut self, response: &EapPacket, message: &Message, imsi: String, keys: SessionKeys, expected: Vec<u8>, next: NextIdentities) -> EapStep {
+        let authentic = match self.method.variant {
+            Variant::Sim => self.verify(response, message, &keys.k_aut, &expected),
+            _            => {
+                let res = message.attributes().get(AT_RES).and_then(res_data);
+                self.verify(response, message, &keys.k_aut, &[]) && res == Some(&expected[..])
+            }
+        };
+        if !authentic {
+            return EapStep::Failure
+        }
+
+        let reauth = next.reauth_id.map(|reauth_id| (reauth_id, ReauthContext {
+            imsi:    imsi.clone(),
+            k_encr:  keys.k_encr.clone(),
+            k_aut:   keys.k_aut.clone(),
+            k_re:    keys.k_re.clone(),
+            counter: 1,
+            expires: Instant::now() + self.method.reauth_lifetime
+        }));
+        lock(&self.method.identities).commit(&imsi, next.pseudonym, reauth);
+        EapStep::Success(EapKeys::new(keys.msk, keys.emsk))
+    }
+
+    fn reauthenticate(&mut self, context: ReauthContext, identifier: u8) -> EapStep {
+        let nonce_s        = thread_rng().gen::<[u8; NONCE_LENGTH]>();
+        let next_reauth_id = new_identity(self.method.variant.prefixes()[2]);
+        let encrypted      = Attributes::new()
+            .push(AT_COUNTER,        context.counter.to_be_bytes().to_vec())
+            .push(AT_NONCE_S,        reserved(&nonce_s))
+            .push(AT_NEXT_REAUTH_ID, counted(next_reauth_id.as_bytes()));
+
+        let attributes = push_encrypted(Attributes::new(), &context.k_encr, encrypted).push(AT_MAC, reserved(&[0; MAC_LENGTH]));
+        let step       = self.request(identifier, Message::new(REAUTHENTICATION, attributes), Some((&context.k_aut, &[])));
+
+        self.state = SessionState::Reauth { context, nonce_s, next_reauth_id };
+        step
+    }
+
+    fn process_reauth(&mut self, response: &EapPacket, message: &Message, context: ReauthContext, nonce_s: [u8; NONCE_LENGTH], next_reauth_id: String, identifier: u8) -> Result<EapStep, RadiusError> {
+        if !self.verify(response, message, &context.k_aut, &nonce_s) {
+            return Ok(EapStep::Failure)
+        }
+        let encrypted = match decrypt_attributes(message.attributes(), &context.k_encr) {
+            Some(encrypted) if encrypted.get(AT_COUNTER).and_then(u16_value) == Some(context.counter) => encrypted,
+            _                                                                                       => return Ok(EapStep::Failure)
+        };
+        // Peer, which has already seen greater counter, is authenticated in full
+        if encrypted.contains(AT_COUNTER_TOO_SMALL) {
+            return self.full_authentication(None, Some(AT_FULLAUTH_ID_REQ), identifier)
+        }
+
+        let (msk, emsk) = keys::reauth_keys(self.method.variant.is_aka_prime(), &context.k_re, self.identity.as_bytes(), context.counter, &nonce_s);
+        if let Some(counter) = context.counter.checked_add(1) {
+            let imsi = context.imsi.clone();
+            lock(&self.method.identities).commit(&imsi, None, Some((next_reauth_id, ReauthContext { counter, ..context })));
+        }
+        Ok(EapStep::Success(EapKeys::new(msk, emsk)))
+    }
+
+    /// Adds encrypted pseudonym and fast reauthentication identity, which are issued to peer
+    fn next_identities(&self, attributes: Attributes, keys: &SessionKeys) -> (Attributes, NextIdentities) {
+        let [_, pseudonym, reauth_id] = self.method.variant.prefixes();
+        let next = NextIdentities {
+            pseudonym: Some(new_identity(pseudonym)).filter(|_| self.method.pseudonyms),
+            reauth_id: Some(new_identity(reauth_id)).filter(|_| self.method.fast_reauth)
+        };
+
+        let mut encrypted = Attributes::new();
+        if let Some(pseudonym) = &next.pseudonym {
+            encrypted = encrypted.push(AT_NEXT_PSEUDONYM, counted(pseudonym.as_bytes()));
+        }
+        if let Some(reauth_id) = &next.reauth_id {
+            encrypted = encrypted.push(AT_NEXT_REAUTH_ID, counted(reauth_id.as_bytes()));
+        }
+        match encrypted.to_bytes().is_empty() {
+            true  => (attributes, next),
+            false => (push_encrypted(attributes, &keys.k_encr, encrypted), next)
+        }
+    }
+
+    /// Returns EAP-Request step; MAC is calculated over EAP packet, which is to carry given
+    /// Identifier, and message specific data
+    fn request(&self, identifier: u8, message: Message, mac: Option<(&[u8], &[u8])>) -> EapStep {
+        let mut type_data = message.to_bytes();
+
+        if let (Some((k_aut, extra)), Some(offset)) = (mac, message.mac_offset()) {
+            let packet = EapPacket::new(EapCode::Request, identifier, self.method.variant.eap_type(), &type_data).to_bytes();
+            let mac    = keys::mac(self.method.variant.is_aka_prime(), k_aut, &packet, extra);
+            type_data[offset..offset + MAC_LENGTH].copy_from_slice(&mac);
+        }
+        EapStep::Request(type_data)
+    }
+
+    /// Returns true, if response carries valid MAC
+    fn verify(&self, response: &EapPacket, message: &Message, k_aut: &[u8], extra: &[u8]) -> bool {
+        match message.mac_offset() {
+            Some(offset) => keys::verify_mac(self.method.variant.is_aka_prime(), k_aut, &response.to_bytes(), EAP_HEADER_LENGTH + offset, extra),
+            None         => false
+        }
+    }
+}
+
+/// Adds AT_IV and AT_ENCR_DATA, which carries given attributes
+fn push_encrypted(attributes: Attributes, k_encr: &[u8], encrypted: Attributes) -> Attributes {
+    let iv   = thread_rng().gen::<[u8; KEY_LENGTH]>();
+    let data = keys::encrypt(k_encr, &iv, &encrypted.pad().to_bytes());
+    attributes.push(AT_IV, reserved(&iv)).push(AT_ENCR_DATA, reserved(&data))
+}
+
+/// Returns attributes, which AT_ENCR_DATA carries
+fn decrypt_attributes(attributes: &Attributes, k_encr: &[u8]) -> Option<Attributes> {
+    let iv   = attributes.get(AT_IV).and_then(|value| fixed(value, KEY_LENGTH))?;
+    let data = attributes.get(AT_ENCR_DATA)?.get(2..)?;
+    Attributes::parse(&keys::decrypt(k_encr, iv, data)?)
+}
+
+/// Returns RES of AT_RES, whose length is given in bits
+fn res_data(value: &[u8]) -> Option<&[u8]> {
+    let bits = u16_value(value)? as usize;
+    match bits % 8 {
+        0 => value.get(2..2 + bits / 8),
+        _ => None
+    }
+}
+
+/// Returns new pseudonym or fast reauthentication identity with given leading character
+fn new_identity(prefix: char) -> String {
+    let random = thread_rng().gen::<[u8; 12]>();
+    std::iter::once(prefix.to_string()).chain(random.iter().map(|byte| format!("{:02x}", byte))).collect()
+}
+
+fn decode_hex(field: &str) -> Option<Vec<u8>> {
+    if field.len() % 2 != 0 || !field.is_ascii() {
+        return None
+    }
+    (0..field.len()).step_by(2).map(|index| u8::from_str_radix(&field[index..index + 2], 16).ok()).collect()
+}
+
+fn parse_triplet(values: &[Vec<u8>]) -> Option<SimTriplet> {
+    match values {
+        [rand, sres, kc] => Some(SimTriplet::new(rand[..].try_into().ok()?, sres[..].try_into().ok()?, kc[..].try_into().ok()?)),
+        _                => None
+    }
+}
+
+fn parse_quintuplet(values: &[Vec<u8>]) -> Option<AkaQuintuplet> {
+    match values {
+        [rand, autn, xres, ck, ik] if (4..=16).contains(&xres.len()) => {
+            Some(AkaQuintuplet::new(rand[..].try_into().ok()?, autn[..].try_into().ok()?, xres.clone(), ck[..].try_into().ok()?, ik[..].try_into().ok()?))
+        },
+        _                                                            => None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::eap::{ EapAuthenticator, EapMethodRegistry, EAP_TYPE_IDENTITY };
+    use crate::testing::{ eap_reply, eap_request, mppe_msk, test_dictionary };
+
+    const IMSI:    &str = "232010000000001";
+    const VECTORS: &str = "\
+# IMSI          SIM RAND                             SRES     Kc
+232010000000001 SIM 000102030405060708090a0b0c0d0e0f d1d2d3d4 a0a1a2a3a4a5a6a7
+232010000000001 SIM 101112131415161718191a1b1c1d1e1f e1e2e3e4 b0b1b2b3b4b5b6b7
+232010000000001 SIM 202122232425262728292a2b2c2d2e2f f1f2f3f4 c0c1c2c3c4c5c6c7
+
+# IMSI          AKA RAND                             AUTN                             XRES             CK                               IK
+232010000000001 AKA 303132333435363738393a3b3c3d3e3f 404142434445464748494a4b4c4d4e4f 5051525354555657 606162636465666768696a6b6c6d6e6f 707172737475767778797a7b7c7d7e7f
+232010000000001 aka 808182838485868788898a8b8c8d8e8f 909192939495969798999a9b9c9d9e9f a0a1a2a3         b0b1b2b3b4b5b6b7b8b9babbbcbdbebf c0c1c2c3c4c5c6c7c8c9cacbcccdcecf
+";
+
+    // Provider, which has a single triplet and fails to fetch quintuplets
+    struct BrokenProvider;
+
+    impl VectorProvider for BrokenProvider {
+        fn sim_triplets(&self, _imsi: &str, _count: usize) -> Result<Option<Vec<SimTriplet>>, RadiusError> {
+            Ok(Some(sim_card().0.into_iter().take(1).collect()))
+        }
+
+        fn aka_quintuplet(&self, _imsi: &str) -> Result<Option<AkaQuintuplet>, RadiusError> {
+            Err(RadiusError::EapError { error: String::from("HSS is unavailable") })
+        }
+    }
+
+    // Peer side of EAP-SIM/EAP-AKA, whose SIM holds the same vectors as provider
+    struct TestPeer {
+        variant:   Variant,
+        imsi:      &'static str,
+        // The last identity peer has sent
+        identity:  String,
+        pseudonym: Option<String>,
+        // Fast reauthentication identity, keys of full authentication and the last counter
+        reauth:    Option<(String, SessionKeys, u16)>,
+        nonce_mt:  [u8; NONCE_LENGTH],
+        keys:      Option<EapKeys>,
+        // Peer answers challenge with wrong SRES/RES
+        corrupt:   bool,
+        bidding:   bool
+    }
+
+    impl TestPeer {
+        fn new(variant: Variant) -> TestPeer {
+            TestPeer { variant, imsi: IMSI, identity: String::new(), pseudonym: None, reauth: None, nonce_mt: [7; NONCE_LENGTH], keys: None, corrupt: false, bidding: false }
+        }
+
+        fn permanent(&self) -> String {
+            format!("{}{}@example.com", self.variant.prefixes()[0], self.imsi)
+        }
+
+        /// Returns identity of EAP-Response/Identity: fast reauthentication identity, pseudonym
+        /// or permanent identity
+        fn identity(&mut self) -> String {
+            self.identity = match (&self.reauth, &self.pseudonym) {
+                (Some((reauth_id, _, _)), _) => format!("{}@example.com", reauth_id),
+                (None, Some(pseudonym))      => format!("{}@example.com", pseudonym),
+                (None, None)                 => self.permanent()
+            };
+            self.identity.clone()
+        }
+
+        fn respond(&mut self, request: &EapPacket) -> EapPacket {
+            let message    = Message::parse(request.type_data()).unwrap();
+            let attributes = message.attributes();
+            let aka_prime  = self.variant.is_aka_prime();
+            let packet     = request.to_bytes();
+            let offset     = message.mac_offset().map(|offset| EAP_HEADER_LENGTH + offset);
+
+            let (response, mac) = match message.subtype() {
+                SIM_START | AKA_IDENTITY => {
+                    let mut response = Attributes::new();
+                    if attributes.contains(AT_FULLAUTH_ID_REQ) || attributes.contains(AT_PERMANENT_ID_REQ) {
+                        self.identity = match (&self.pseudonym, attributes.contains(AT_FULLAUTH_ID_REQ)) {
+                            (Some(pseudonym), true) => format!("{}@example.com", pseudonym),
+                            _                       => self.permanent()
+                        };
+                        response = response.push(AT_IDENTITY, counted(self.identity.as_bytes()));
+                    }
+                    if message.subtype() == SIM_START {
+                        response = response
+                            .push(AT_NONCE_MT,         reserved(&self.nonce_mt))
+                            .push(AT_SELECTED_VERSION, SIM_VERSION.to_vec());
+                    }
+                    (response, None)
+                },
+                SIM_CHALLENGE            => {
+                    let triplets = attributes.get(AT_RAND).unwrap()[2..].chunks(16)
+                        .map(|rand| sim_card().0.into_iter().find(|triplet| triplet.rand() == rand).unwrap())
+                        .collect::<Vec<SimTriplet>>();
+                    let kcs      = triplets.iter().flat_map(|triplet| triplet.kc).collect::<Vec<u8>>();
+                    let mut sres = triplets.iter().flat_map(|triplet| triplet.sres).collect::<Vec<u8>>();
+                    sres[0]     ^= self.corrupt as u8;
+
+                    let keys = keys::sim_keys(self.identity.as_bytes(), &kcs, &self.nonce_mt, &SIM_VERSION, &SIM_VERSION);
+                    assert!(keys::verify_mac(false, &keys.k_aut, &packet, offset.unwrap(), &self.nonce_mt));
+
+                    let k_aut = keys.k_aut.clone();
+                    self.accept(attributes, keys);
+                    (Attributes::new().push(AT_MAC, reserved(&[0; MAC_LENGTH])), Some((k_aut, sres)))
+                },
+                AKA_CHALLENGE            => {
+                    let rand    = fixed(attributes.get(AT_RAND).unwrap(), 16).unwrap();
+                    let vector  = sim_card().1.into_iter().find(|vector| vector.rand() == rand).unwrap();
+                    assert_eq!(Some(&vector.autn()[..]), fixed(attributes.get(AT_AUTN).unwrap(), 16));
+                    self.bidding = attributes.get(AT_BIDDING) == Some(&BIDDING_AKA_PRIME[..]);
+
+                    let keys = match aka_prime {
+                        true  => {
+                            assert_eq!(Some(&b"WLAN"[..]), attributes.get(AT_KDF_INPUT).and_then(counted_data));
+                            keys::aka_prime_keys(self.identity.as_bytes(), vector.ck(), vector.ik(), b"WLAN", vector.autn())
+                        },
+                        false => keys::aka_keys(self.identity.as_bytes(), vector.ck(), vector.ik())
+                    };
+                    assert!(keys::verify_mac(aka_prime, &keys.k_aut, &packet, offset.unwrap(), &[]));
+
+                    let mut res = vector.xres().to_vec();
+                    res[0]     ^= self.corrupt as u8;
+                    let k_aut   = keys.k_aut.clone();
+                    self.accept(attributes, keys);
+
+                    let response = Attributes::new()
+                        .push(AT_RES, [&((res.len() * 8) as u16).to_be_bytes()[..], &res].concat())
+                        .push(AT_MAC, reserved(&[0; MAC_LENGTH]));
+                    (response, Some((k_aut, Vec::new())))
+                },
+                _                        => {
+                    let (_, keys, counter) = self.reauth.take().unwrap();
+                    assert!(keys::verify_mac(aka_prime, &keys.k_aut, &packet, offset.unwrap(), &[]));
+
+                    let encrypted      = decrypt_attributes(attributes, &keys.k_encr).unwrap();
+                    let server_counter = encrypted.get(AT_COUNTER).and_then(u16_value).unwrap();
+                    let nonce_s        = fixed(encrypted.get(AT_NONCE_S).unwrap(), NONCE_LENGTH).unwrap().to_vec();
+                    let mut inner      = Attributes::new().push(AT_COUNTER, server_counter.to_be_bytes().to_vec());
+
+                    if server_counter <= counter {
+                        inner = inner.push(AT_COUNTER_TOO_SMALL, reserved(&[]));
+                    } else {
+                        let (msk, emsk) = keys::reauth_keys(aka_prime, &keys.k_re, self.identity.as_bytes(), server_counter, &nonce_s);
+                        self.keys       = Some(EapKeys::new(msk, emsk));
+                        self.reauth     = encrypted.get(AT_NEXT_REAUTH_ID)
+                            .and_then(counted_data)
+                            .map(|reauth_id| (String::from_utf8(reauth_id.to_vec()).unwrap(), keys.clone(), server_counter));
+                    }
+                    let k_aut = keys.k_aut.clone();
+                    (push_encrypted(Attributes::new(), &keys.k_encr, inner).push(AT_MAC, reserved(&[0; MAC_LENGTH])), Some((k_aut, nonce_s)))
+                }
+            };
+
+            let message       = Message::new(message.subtype(), response);
+            let mut type_data = message.to_bytes();
+            if let Some((k_aut, extra)) = mac {
+                let offset = message.mac_offset().unwrap();
+                let packet = EapPacket::new(EapCode::Response, request.identifier(), self.variant.eap_type(), &type_data).to_bytes();
+                type_data[offset..offset + MAC_LENGTH].copy_from_slice(&keys::mac(aka_prime, &k_aut, &packet, &extra));
+            }
+            EapPacket::new(EapCode::Response, request.identifier(), self.variant.eap_type(), &type_data)
+        }
+
+        /// Takes identities, which Server has issued, and keys of full authentication
+        fn accept(&mut self, attributes: &Attributes, keys: SessionKeys) {
+            let encrypted = decrypt_attributes(attributes, &keys.k_encr).unwrap_or_default();
+            let identity  = |kind| encrypted.get(kind).and_then(counted_data).map(|identity| String::from_utf8(identity.to_vec()).unwrap());
+
+            if let Some(pseudonym) = identity(AT_NEXT_PSEUDONYM) {
+                self.pseudonym = Some(pseudonym);
+            }
+            self.reauth = identity(AT_NEXT_REAUTH_ID).map(|reauth_id| (reauth_id, keys.clone(), 0));
+            self.keys   = Some(EapKeys::new(keys.msk, keys.emsk));
+        }
+    }
+
+    fn sim_card() -> (Vec<SimTriplet>, Vec<AkaQuintuplet>) {
+        let mut card = (Vec::new(), Vec::new());
+
+        for line in VECTORS.lines().filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let fields = line.split_whitespace().collect::<Vec<&str>>();
+            let values = fields[2..].iter().map(|field| decode_hex(field).unwrap()).collect::<Vec<Vec<u8>>>();
+            match fields[1] {
+                "SIM" => card.0.push(parse_triplet(&values).unwrap()),
+                _     => card.1.push(parse_quintuplet(&values).unwrap())
+            }
+        }
+        card
+    }
+
+    fn provider(name: &str) -> FileVectorProvider {
+        let path = std::env::temp_dir().join(format!("radius-vectors-{}-{}", name, std::process::id()));
+        fs::write(&path, VECTORS).unwrap();
+        FileVectorProvider::new(path)
+    }
+
+    fn methods(name: &str) -> Vec<(Variant, Box<dyn EapMethod>)> {
+        vec![
+            (Variant::Sim,      Box::new(EapSim::new(provider(&format!("{}-sim", name))))),
+            (Variant::Aka,      Box::new(EapAka::new(provider(&format!("{}-aka", name))))),
+            (Variant::AkaPrime, Box::new(EapAka::new_prime(provider(&format!("{}-aka-prime", name)), String::from("WLAN"))))
+        ]
+    }
+
+    /// Runs session between Server and peer and returns Server's last step with Subtypes of
+    /// EAP-Requests
+    fn authenticate(method: &dyn EapMethod, peer: &mut TestPeer) -> Result<(EapStep, Vec<u8>), RadiusError> {
+        let mut session    = method.start_session(&peer.identity())?;
+        let mut identifier = 1;
+        let mut step       = session.start(identifier)?;
+        let mut subtypes   = Vec::new();
+
+        while let EapStep::Request(type_data) = step {
+            subtypes.push(type_data[0]);
+            let response = peer.respond(&EapPacket::new(EapCode::Request, identifier, method.eap_type(), &type_data));
+            identifier  += 1;
+            step         = session.process(&response, identifier)?;
+        }
+        Ok((step, subtypes))
+    }
+
+    fn assert_success(step: EapStep, peer: &TestPeer) {
+        match step {
+            EapStep::Success(keys) => {
+                assert_eq!(Some(&keys), peer.keys.as_ref());
+                assert_eq!(64,          keys.msk().len());
+            },
+            step                   => panic!("Peer has not been authenticated: {:?}", step)
+        }
+    }
+
+    #[test]
+    fn test_file_vector_provider() {
+        let provider = provider("provider");
+        let (triplets, quintuplets) = sim_card();
+
+        // Vectors are handed out in turn
+        assert_eq!(Some(triplets[..2].to_vec()),                    provider.sim_triplets(IMSI, 2).unwrap());
+        assert_eq!(Some(vec![triplets[2].clone(), triplets[0].clone()]), provider.sim_triplets(IMSI, 2).unwrap());
+        assert_eq!(Some(triplets.clone()),                          provider.sim_triplets(IMSI, 5).unwrap().map(|mut vectors| { vectors.rotate_right(1); vectors }));
+        assert_eq!(Some(quintuplets[0].clone()),                    provider.aka_quintuplet(IMSI).unwrap());
+        assert_eq!(Some(quintuplets[1].clone()),                    provider.aka_quintuplet(IMSI).unwrap());
+        assert_eq!(None,                                            provider.sim_triplets("232010000000002", 3).unwrap());
+
+        fs::write(provider.path(), "232010000000001 SIM 0001 d1d2d3d4 a0a1a2a3a4a5a6a7\n").unwrap();
+        assert!(provider.sim_triplets(IMSI, 3).is_err());
+        fs::remove_file(provider.path()).unwrap();
+        assert!(provider.aka_quintuplet(IMSI).is_err());
+    }
+
+    #[test]
+    fn test_eap_sim_aka() {
+        for (variant, method) in methods("full") {
+            let (full_authentication, identity_request) = match variant {
+                Variant::Sim => (vec![SIM_START, SIM_CHALLENGE], vec![SIM_START, SIM_CHALLENGE]),
+                _            => (vec![AKA_CHALLENGE],            vec![AKA_IDENTITY, AKA_CHALLENGE])
+            };
+            let mut peer = TestPeer::new(variant);
+            assert!(method.is_preferred_for(&peer.permanent()));
+
+            // Peer, which is known by permanent identity, gets pseudonym and reauthentication identity
+            let (step, subtypes) = authenticate(method.as_ref(), &mut peer).unwrap();
+            assert_eq!(full_authentication, subtypes);
+            assert_success(step, &peer);
+            assert!(peer.pseudonym.is_some() && peer.reauth.is_some());
+
+            // Fast reauthentication is carried on with the next reauthentication identity
+            for _ in 0..2 {
+                let (step, subtypes) = authenticate(method.as_ref(), &mut peer).unwrap();
+                assert_eq!(vec![REAUTHENTICATION], subtypes);
+                assert_success(step, &peer);
+            }
+            assert_eq!(Some(2), peer.reauth.as_ref().map(|(_, _, counter)| *counter));
+
+            // Reauthentication identity could only be used once
+            let used             = peer.reauth.clone();
+            authenticate(method.as_ref(), &mut peer).unwrap();
+            peer.reauth          = used;
+            let (step, subtypes) = authenticate(method.as_ref(), &mut peer).unwrap();
+            assert_eq!(identity_request, subtypes);
+            assert_success(step, &peer);
+
+            // Pseudonym stands for permanent identity
+            peer.reauth          = None;
+            let (step, subtypes) = authenticate(method.as_ref(), &mut peer).unwrap();
+            assert_eq!(full_authentication, subtypes);
+            assert_success(step, &peer);
+            assert!(peer.identity.starts_with(variant.prefixes()[1]));
+        }
+    }
+
+    #[test]
+    fn test_eap_sim_aka_identities() {
+        for (variant, method) in methods("identities") {
+            let (start, challenge) = match variant {
+                Variant::Sim => (SIM_START, SIM_CHALLENGE),
+                _            => (AKA_IDENTITY, AKA_CHALLENGE)
+            };
+
+            // Peer, whose identity is not known, is asked for full authentication identity and
+            // then for permanent one
+            let mut peer          = TestPeer::new(variant);
+            peer.pseudonym        = Some(String::from("anonymous"));
+            let (step, subtypes)  = authenticate(method.as_ref(), &mut peer).unwrap();
+            assert_eq!(vec![start, start, challenge], subtypes);
+            assert_eq!(peer.permanent(), peer.identity);
+            assert_success(step, &peer);
+
+            // Unknown pseudonym is followed by permanent identity request
+            let mut peer          = TestPeer::new(variant);
+            peer.pseudonym        = Some(format!("{}unknown", variant.prefixes()[1]));
+            let (step, subtypes)  = authenticate(method.as_ref(), &mut peer).unwrap();
+            assert_eq!(vec![start, start, challenge], subtypes);
+            assert_success(step, &peer);
+
+            // Peer, which has seen greater counter, is authenticated in full
+            peer.reauth.as_mut().unwrap().2 = 5;
+            let (step, subtypes)  = authenticate(method.as_ref(), &mut peer).unwrap();
+            assert_eq!(vec![REAUTHENTICATION, start, challenge], subtypes);
+            assert_success(step, &peer);
+        }
+    }
+
+    #[test]
+    fn test_eap_sim_aka_failures() {
+        for (variant, method) in methods("failures") {
+            // Wrong SRES/RES
+            let mut peer    = TestPeer::new(variant);
+            peer.corrupt    = true;
+            assert_eq!(EapStep::Failure, authenticate(method.as_ref(), &mut peer).unwrap().0);
+
+            // Unknown subscriber
+            let mut peer    = TestPeer::new(variant);
+            peer.imsi       = "232010000000002";
+            assert_eq!(EapStep::Failure, authenticate(method.as_ref(), &mut peer).unwrap().0);
+
+            // Expired reauthentication identity
+            let method      = match variant {
+                Variant::Sim => Box::new(EapSim::new(provider("expired-sim")).set_reauth_lifetime(Duration::from_secs(0))) as Box<dyn EapMethod>,
+                _            => Box::new(EapAka::new_prime(provider("expired-aka"), String::from("WLAN")).set_reauth_lifetime(Duration::from_secs(0)))
+            };
+            let mut peer    = TestPeer::new(if variant == Variant::Sim { Variant::Sim } else { Variant::AkaPrime });
+            assert_success(authenticate(method.as_ref(), &mut peer).unwrap().0, &peer);
+            let (_, subtypes) = authenticate(method.as_ref(), &mut peer).unwrap();
+            assert_ne!(Some(&REAUTHENTICATION), subtypes.first());
+        }
+
+        // Single triplet and provider's failure are Server's errors
+        assert!(authenticate(&EapSim::new(BrokenProvider), &mut TestPeer::new(Variant::Sim)).is_err());
+        assert!(authenticate(&EapAka::new(BrokenProvider), &mut TestPeer::new(Variant::Aka)).is_err());
+
+        // Identities are not issued, once they are disabled
+        let method   = EapAka::new(provider("disabled")).set_pseudonyms(false).set_fast_reauth(false).set_aka_prime_offered(true);
+        let mut peer = TestPeer::new(Variant::Aka);
+        assert_success(authenticate(&method, &mut peer).unwrap().0, &peer);
+        assert!(peer.pseudonym.is_none() && peer.reauth.is_none());
+        assert!(peer.bidding);
+    }
+
+    #[test]
+    fn test_eap_sim_aka_authenticator() {
+        let registry      = EapMethodRegistry::new()
+            .register(EapSim::new(provider("authenticator-sim")))
+            .register(EapAka::new(provider("authenticator-aka")))
+            .register(EapAka::new_prime(provider("authenticator-aka-prime"), String::from("WLAN")));
+        let authenticator = EapAuthenticator::new(test_dictionary(), registry);
+        let mut peer      = TestPeer::new(Variant::AkaPrime);
+
+        let identity             = peer.identity();
+        let mut request          = eap_request(Some(&EapPacket::new(EapCode::Response, 1, EAP_TYPE_IDENTITY, identity.as_bytes())), None);
+        let (mut reply, mut eap) = eap_reply(authenticator.handle_request("secret", &request).unwrap());
+
+        while eap.code() == EapCode::Request {
+            assert_eq!(Some(EAP_TYPE_AKA_PRIME), eap.eap_type());
+
+            let response = peer.respond(&eap);
+            let state    = reply.attribute_by_id(24).unwrap().value().to_vec();
+            request      = eap_request(Some(&response), Some(&state));
+            (reply, eap) = eap_reply(authenticator.handle_request("secret", &request).unwrap());
+        }
+
+        assert_eq!(EapCode::Success,         eap.code());
+        assert_eq!(peer.keys.unwrap().msk(), &mppe_msk(&reply, "secret", request.authenticator())[..]);
+    }
+}
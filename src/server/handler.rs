@@ -0,0 +1,104 @@
+//! RADIUS Server request handler, which works with parsed and verified RADIUS packets
+
+
+use crate::protocol::error::RadiusError;
+use crate::protocol::radius_packet::{ RadiusMsgType, RadiusPacket, TypeCode };
+use crate::server::client_table::ClientEntry;
+
+use std::net::SocketAddr;
+use std::time::Instant;
+
+
+#[derive(Debug, Clone)]
+/// Represents everything RADIUS Server knows about the request, apart from the request itself
+pub struct RequestContext {
+    source:   SocketAddr,
+    msg_type: RadiusMsgType,
+    client:   ClientEntry,
+    received: Instant
+}
+
+impl RequestContext {
+    /// Initialises request context
+    pub fn new(source: SocketAddr, msg_type: RadiusMsgType, client: ClientEntry, received: Instant) -> RequestContext {
+        RequestContext { source, msg_type, client, received }
+    }
+
+    /// Returns address, request has been received from
+    pub fn source(&self) -> SocketAddr {
+        self.source
+    }
+
+    /// Returns RADIUS Message Type of the socket, request has been received on
+    pub fn msg_type(&self) -> RadiusMsgType {
+        self.msg_type
+    }
+
+    /// Returns client, request's source has been matched against
+    pub fn client(&self) -> &ClientEntry {
+        &self.client
+    }
+
+    /// Returns time at which request has been received
+    pub fn received(&self) -> Instant {
+        self.received
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Represents what RADIUS Server should do with the request, once it has been handled
+pub enum HandlerOutcome {
+    /// Reply is sent back to RADIUS Client
+    ///
+    /// Reply gets request's ID and Response Authenticator (and Message-Authenticator, if reply
+    /// carries one or request has carried one) from RADIUS Server, so only its code and
+    /// attributes are to be set
+    Reply(RadiusPacket),
+    /// Request is silently discarded
+    Drop
+}
+
+/// This trait is to be implemented by user to resolve RADIUS requests, received by
+/// [Server::serve()](crate::server::server::Server::serve)
+///
+/// Handler only receives requests from allowed clients, which passed verification; it is called
+/// from several threads at once. Status-Server requests are answered by Server itself and never
+/// reach handler
+pub trait RadiusHandler {
+    /// Resolves RADIUS request
+    ///
+    /// If handler returns an error, request is discarded and error is passed to
+    /// [handle_error](RadiusHandler::handle_error)
+    fn handle_request(&self, context: &RequestContext, request: &RadiusPacket) -> Result<HandlerOutcome, RadiusError>;
+
+    /// Is called whenever request is discarded because of an error: request has failed
+    /// verification, handler has returned an error or reply could not be built
+    ///
+    /// Does nothing by default
+    fn handle_error(&self, _context: &RequestContext, _error: RadiusError) {}
+
+    /// Is called whenever request from given source, which is not a known client, is discarded
+    /// because [client resolver](crate::server::server::Server::set_client_resolver) has failed;
+    /// calls are rate limited the same way as the ones to [handle_error](RadiusHandler::handle_error)
+    ///
+    /// Does nothing by default
+    fn handle_resolver_error(&self, _source: SocketAddr, _error: RadiusError) {}
+}
+
+/// Returns true, if request code is expected on the socket of given RADIUS Message Type
+pub(crate) fn is_expected_request(msg_type: RadiusMsgType, code: &TypeCode) -> bool {
+    match msg_type {
+        RadiusMsgType::AUTH => matches!(code, TypeCode::AccessRequest | TypeCode::StatusServer),
+        RadiusMsgType::ACCT => matches!(code, TypeCode::AccountingRequest | TypeCode::StatusServer),
+        RadiusMsgType::COA  => matches!(code, TypeCode::CoARequest | TypeCode::DisconnectRequest)
+    }
+}
+
+/// Records time handler has taken to resolve request, which it has started at given instant
+#[cfg(feature = "metrics")]
+pub(crate) fn record_handler_duration(context: &RequestContext, request: &RadiusPacket, started: Instant) {
+    use crate::metrics;
+
+    let labels = [metrics::socket_label(context.msg_type()), metrics::code_label(request.code())];
+    metrics::registry().observe(&metrics::SERVER_HANDLER_DURATION, &labels, started.elapsed().as_secs_f64());
+}
@@ -4,36 +4,40 @@
 use crate::protocol::error::RadiusError;
 
 
+#[cfg(feature = "async-radius")]
+use crate::protocol::radius_packet::RadiusPacket;
+#[cfg(feature = "async-radius")]
+use crate::server::handler::{ HandlerOutcome, RequestContext };
 #[cfg(all(feature = "async-radius"))]
 use async_trait::async_trait;
+#[cfg(feature = "async-radius")]
+use std::net::SocketAddr;
 #[cfg(all(feature = "async-radius"))]
 #[async_trait]
 /// This trait is to be implemented by user, if they are planning to resolve AUTH, ACCT or CoA
 /// RADIUS requests for Async RADIUS Server
+///
+/// Handler only receives requests from allowed clients, which passed verification, and could be
+/// resolving many requests at once; Status-Server requests are answered by Server itself. See [AsyncServer](crate::server::async_server::AsyncServer)
 pub trait AsyncServerTrait {
-    /// Main function, that starts and keeps server running
+    /// Resolves RADIUS request
     ///
-    /// For example see `examples/async_radius_server.rs`
-    async fn run(&mut self) -> Result<(), RadiusError>;
+    /// If handler returns an error, request is discarded and error is passed to
+    /// [handle_error](AsyncServerTrait::handle_error)
+    async fn handle_request(&self, context: &RequestContext, request: &RadiusPacket) -> Result<HandlerOutcome, RadiusError>;
 
-    /// Function is responsible for resolving AUTH RADIUS request
-    ///
-    /// For example see `examples/async_radius_server.rs`
-    async fn handle_auth_request(&self) -> Result<(), RadiusError> {
-        Ok(())
-    }
-    /// Function is responsible for resolving AUTH RADIUS request
+    /// Is called whenever request is discarded because of an error: request has failed
+    /// verification, handler has returned an error or reply could not be built
     ///
-    /// For example see `examples/async_radius_server.rs`
-    async fn handle_acct_request(&self) -> Result<(), RadiusError> {
-        Ok(())
-    }
-    /// Function is responsible for resolving AUTH RADIUS request
+    /// Does nothing by default
+    fn handle_error(&self, _context: &RequestContext, _error: RadiusError) {}
+
+    /// Is called whenever request from given source, which is not a known client, is discarded
+    /// because [client resolver](crate::server::server::Server::set_client_resolver) has failed;
+    /// calls are rate limited the same way as the ones to [handle_error](AsyncServerTrait::handle_error)
     ///
-    /// For example see `examples/async_radius_server.rs`
-    async fn handle_coa_request(&self) -> Result<(), RadiusError> {
-        Ok(())
-    }
+    /// Does nothing by default
+    fn handle_resolver_error(&self, _source: SocketAddr, _error: RadiusError) {}
 }
 
 /// This trait is to be implemented by user, if they are planning to resolve AUTH, ACCT or CoA
@@ -64,4 +68,19 @@ pub trait SyncServerTrait {
     }
 }
 
+pub mod client_resolver;
+pub mod client_table;
+pub mod eap;
+pub mod handler;
+pub mod overload;
+pub mod rate_limit;
+pub mod reply_cache;
 pub mod server;
+pub mod statistics;
+pub mod udp_server;
+#[cfg(feature = "async-radius")]
+pub mod async_server;
+#[cfg(feature = "tokio")]
+pub mod tokio_server;
+
+mod work_queue;
@@ -0,0 +1,128 @@
+//! Admission control of RADIUS Server: request queues, drop policies and counters of shed
+//! requests
+
+
+use crate::protocol::radius_packet::{ RadiusMsgType, TypeCode };
+
+use std::fmt;
+use std::sync::atomic::{ AtomicU64, Ordering };
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Represents queue, in which verified request waits for worker
+///
+/// Status-Server queue is always served first; AUTH, CoA and ACCT queues are then served by
+/// weighted round-robin with weights 4, 2 and 1, so accounting storm mostly delays accounting,
+/// while accounting still makes progress during authentication storm
+pub enum RequestQueue {
+    /// Status-Server requests, received on any socket
+    StatusServer,
+    /// Access-Request
+    Auth,
+    /// CoA-Request and Disconnect-Request
+    CoA,
+    /// Accounting-Request
+    Acct
+}
+
+impl RequestQueue {
+    /// All queues in [index](RequestQueue::index) order
+    pub const ALL: [RequestQueue; 4] = [RequestQueue::StatusServer, RequestQueue::Auth, RequestQueue::CoA, RequestQueue::Acct];
+
+    /// Returns queue for request with given code, which has been received on the socket of given
+    /// RADIUS Message Type
+    pub fn of(msg_type: RadiusMsgType, code: &TypeCode) -> RequestQueue {
+        match (code, msg_type) {
+            (TypeCode::StatusServer, _)    => RequestQueue::StatusServer,
+            (_, RadiusMsgType::AUTH)       => RequestQueue::Auth,
+            (_, RadiusMsgType::COA)        => RequestQueue::CoA,
+            (_, RadiusMsgType::ACCT)       => RequestQueue::Acct
+        }
+    }
+
+    pub(crate) fn index(self) -> usize {
+        match self {
+            RequestQueue::StatusServer => 0,
+            RequestQueue::Auth         => 1,
+            RequestQueue::CoA          => 2,
+            RequestQueue::Acct         => 3
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Represents which request is shed, when request arrives to a full queue
+pub enum DropPolicy {
+    /// Request, which has just arrived, is shed (default)
+    DropNewest,
+    /// Request, which has waited in queue for the longest time, is shed; it is the most likely
+    /// one to have been given up on by NAS
+    DropOldest
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Represents why request has been shed by RADIUS Server
+pub enum ShedReason {
+    /// Request has arrived to a full queue (or has been pushed out of it, with DropOldest)
+    QueueFull,
+    /// Request has reached deadline while waiting in queue, so was not resolved
+    Expired,
+    /// Request has been resolved after deadline, so its reply was discarded
+    Late
+}
+
+impl fmt::Display for ShedReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShedReason::QueueFull => write!(f, "queue is full"),
+            ShedReason::Expired   => write!(f, "request has reached deadline before it was resolved"),
+            ShedReason::Late      => write!(f, "request has been resolved after deadline")
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+/// Counts requests, which have been shed by RADIUS Server because of overload, per queue
+pub struct OverloadStats {
+    queue_full: [AtomicU64; 4],
+    expired:    [AtomicU64; 4],
+    late:       [AtomicU64; 4]
+}
+
+impl OverloadStats {
+    /// Returns number of requests, which have been shed because their queue was full
+    pub fn queue_full(&self, queue: RequestQueue) -> u64 {
+        self.queue_full[queue.index()].load(Ordering::Relaxed)
+    }
+
+    /// Returns number of requests, which have reached deadline while waiting in queue, so were
+    /// not resolved
+    pub fn expired(&self, queue: RequestQueue) -> u64 {
+        self.expired[queue.index()].load(Ordering::Relaxed)
+    }
+
+    /// Returns number of requests, which have been resolved after deadline, so their replies
+    /// were discarded
+    pub fn late(&self, queue: RequestQueue) -> u64 {
+        self.late[queue.index()].load(Ordering::Relaxed)
+    }
+
+    /// Returns number of all requests, which have been shed
+    pub fn total(&self) -> u64 {
+        RequestQueue::ALL.iter()
+            .map(|&queue| self.queue_full(queue) + self.expired(queue) + self.late(queue))
+            .sum()
+    }
+
+    pub(crate) fn record_queue_full(&self, queue: RequestQueue) {
+        self.queue_full[queue.index()].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_expired(&self, queue: RequestQueue) {
+        self.expired[queue.index()].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_late(&self, queue: RequestQueue) {
+        self.late[queue.index()].fetch_add(1, Ordering::Relaxed);
+    }
+}
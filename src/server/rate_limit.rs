@@ -0,0 +1,402 @@
+//! Token-bucket rate limiting of incoming RADIUS requests, which protects RADIUS Server from
+//! floods
+
+
+use crate::server::client_table::ClientEntry;
+use crate::tools::lock;
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::time::Instant;
+
+
+const MAX_BUCKETS:     usize = 65536;
+// Number of least recently used buckets, which are forgotten at once, if table is still full
+const EVICTED_BUCKETS: usize = MAX_BUCKETS / 16;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Represents rate limit: number of requests per second, with bursts up to given size
+pub struct RateLimit {
+    rate:  u32,
+    burst: u32
+}
+
+impl RateLimit {
+    /// Initialises RateLimit of given number of requests per second and burst size (at least 1)
+    pub fn new(rate: u32, burst: u32) -> RateLimit {
+        RateLimit { rate, burst: std::cmp::max(burst, 1) }
+    }
+
+    /// Returns number of requests per second
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    /// Returns burst size
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Represents what RADIUS Server does with request, which exceeds rate limit
+pub enum RateLimitAction {
+    /// Request is silently discarded (default)
+    Drop,
+    /// Access-Request is answered with Access-Reject, CoA-Request and Disconnect-Request with NAK;
+    /// other requests are silently discarded
+    Reject
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    limit:   RateLimit,
+    tokens:  f64,
+    updated: Instant
+}
+
+impl TokenBucket {
+    fn new(limit: &RateLimit, now: Instant) -> TokenBucket {
+        TokenBucket { limit: *limit, tokens: f64::from(limit.burst), updated: now }
+    }
+
+    fn tokens(&self, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * f64::from(self.limit.rate)).min(f64::from(self.limit.burst))
+    }
+
+    fn refill(&mut self, now: Instant) {
+        self.tokens  = self.tokens(now);
+        self.updated = now;
+    }
+
+    fn is_full(&self, now: Instant) -> bool {
+        self.tokens(now) >= f64::from(self.limit.burst)
+    }
+
+    fn has_token(&self) -> bool {
+        self.tokens >= 1.0
+    }
+
+    fn take(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.has_token() {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct LimiterState {
+    sources: HashMap<IpAddr, TokenBucket>,
+    clients: HashMap<(IpAddr, u8), TokenBucket>,
+    unknown: Option<TokenBucket>,
+    errors:  Option<TokenBucket>
+}
+
+#[derive(Debug, Default)]
+/// Counts requests, which have been limited by RateLimiter
+pub struct RateLimitStats {
+    source_limited:    AtomicU64,
+    client_limited:    AtomicU64,
+    unknown_limited:   AtomicU64,
+    errors_suppressed: AtomicU64
+}
+
+impl RateLimitStats {
+    /// Returns number of requests, which have exceeded per source IP limit
+    pub fn source_limited(&self) -> u64 {
+        self.source_limited.load(Ordering::Relaxed)
+    }
+
+    /// Returns number of requests, which have exceeded per client limit
+    pub fn client_limited(&self) -> u64 {
+        self.client_limited.load(Ordering::Relaxed)
+    }
+
+    /// Returns number of requests from unknown addresses, which have not been passed to client
+    /// resolver
+    pub fn unknown_limited(&self) -> u64 {
+        self.unknown_limited.load(Ordering::Relaxed)
+    }
+
+    /// Returns number of requests, which have failed verification and have not been reported to
+    /// handler
+    pub fn errors_suppressed(&self) -> u64 {
+        self.errors_suppressed.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+/// Represents rate limits, which RADIUS Server applies to incoming requests
+///
+/// Requests from known clients are limited per source IP and per client entry (every entry has
+/// its own bucket, even if it covers a network). Requests from unknown addresses and requests,
+/// which fail verification, are limited separately: the former are not passed to
+/// [client resolver](crate::server::server::Server::set_client_resolver), the latter are not
+/// reported to handler, so handler's logs stay usable under attack
+///
+/// By default nothing is limited
+pub struct RateLimiter {
+    source_limit:  Option<RateLimit>,
+    client_limit:  Option<RateLimit>,
+    unknown_limit: Option<RateLimit>,
+    error_limit:   Option<RateLimit>,
+    action:        RateLimitAction,
+    state:         Mutex<LimiterState>,
+    stats:         RateLimitStats
+}
+
+impl Default for RateLimiter {
+    fn default() -> RateLimiter {
+        RateLimiter::new()
+    }
+}
+
+impl RateLimiter {
+    /// Initialises RateLimiter, which doesn't limit anything
+    pub fn new() -> RateLimiter {
+        RateLimiter {
+            source_limit:  None,
+            client_limit:  None,
+            unknown_limit: None,
+            error_limit:   None,
+            action:        RateLimitAction::Drop,
+            state:         Mutex::new(LimiterState::default()),
+            stats:         RateLimitStats::default()
+        }
+    }
+
+    // === Builder for RateLimiter ===
+    /// Sets limit of requests from every source IP
+    pub fn set_source_limit(mut self, limit: Option<RateLimit>) -> RateLimiter {
+        self.source_limit = limit;
+        self
+    }
+
+    /// Sets limit of requests from every client entry, unless entry has
+    /// [its own limit](crate::server::client_table::ClientEntry::set_rate_limit)
+    pub fn set_client_limit(mut self, limit: Option<RateLimit>) -> RateLimiter {
+        self.client_limit = limit;
+        self
+    }
+
+    /// Sets limit of requests from unknown addresses, which are passed to client resolver (all
+    /// addresses share the same bucket)
+    pub fn set_unknown_client_limit(mut self, limit: Option<RateLimit>) -> RateLimiter {
+        self.unknown_limit = limit;
+        self
+    }
+
+    /// Sets limit of requests, which failed verification and are reported to handler (all
+    /// clients share the same bucket)
+    pub fn set_error_report_limit(mut self, limit: Option<RateLimit>) -> RateLimiter {
+        self.error_limit = limit;
+        self
+    }
+
+    /// Sets what is done with request, which exceeds source IP or client limit
+    pub fn set_action(mut self, action: RateLimitAction) -> RateLimiter {
+        self.action = action;
+        self
+    }
+    // ===================
+
+    /// Returns limit of requests from every source IP
+    pub fn source_limit(&self) -> Option<RateLimit> {
+        self.source_limit
+    }
+
+    /// Returns limit of requests from every client entry
+    pub fn client_limit(&self) -> Option<RateLimit> {
+        self.client_limit
+    }
+
+    /// Returns limit of requests from unknown addresses
+    pub fn unknown_client_limit(&self) -> Option<RateLimit> {
+        self.unknown_limit
+    }
+
+    /// Returns limit of reported verification failures
+    pub fn error_report_limit(&self) -> Option<RateLimit> {
+        self.error_limit
+    }
+
+    /// Returns what is done with request, which exceeds limit
+    pub fn action(&self) -> RateLimitAction {
+        self.action
+    }
+
+    /// Returns counters of limited requests
+    pub fn stats(&self) -> &RateLimitStats {
+        &self.stats
+    }
+
+    /// Takes token for request from known client and returns action, if request exceeds limit
+    ///
+    /// Tokens are taken from source IP and client buckets only if both of them allow request
+    pub(crate) fn check(&self, source: IpAddr, client: &ClientEntry) -> Option<RateLimitAction> {
+        let now       = Instant::now();
+        let mut state = lock(&self.state);
+        let LimiterState { sources, clients, .. } = &mut *state;
+
+        let source_bucket = self.source_limit.as_ref().map(|limit| bucket(sources, source, limit, now));
+        if source_bucket.as_ref().map_or(false, |bucket| !bucket.has_token()) {
+            self.stats.source_limited.fetch_add(1, Ordering::Relaxed);
+            return Some(self.action)
+        }
+        let client_bucket = client.rate_limit().or(self.client_limit).map(|limit| bucket(clients, (client.network(), client.prefix_len()), &limit, now));
+        if client_bucket.as_ref().map_or(false, |bucket| !bucket.has_token()) {
+            self.stats.client_limited.fetch_add(1, Ordering::Relaxed);
+            return Some(self.action)
+        }
+
+        for bucket in source_bucket.into_iter().chain(client_bucket) {
+            bucket.tokens -= 1.0;
+        }
+        None
+    }
+
+    /// Takes token for request from unknown address and returns true, if it could be resolved
+    pub(crate) fn allow_unknown(&self) -> bool {
+        let allowed = self.take_shared(self.unknown_limit.as_ref(), |state| &mut state.unknown);
+        if !allowed {
+            self.stats.unknown_limited.fetch_add(1, Ordering::Relaxed);
+        }
+        allowed
+    }
+
+    /// Takes token for verification failure and returns true, if it could be reported
+    pub(crate) fn allow_error_report(&self) -> bool {
+        let allowed = self.take_shared(self.error_limit.as_ref(), |state| &mut state.errors);
+        if !allowed {
+            self.stats.errors_suppressed.fetch_add(1, Ordering::Relaxed);
+        }
+        allowed
+    }
+
+    fn take_shared(&self, limit: Option<&RateLimit>, bucket: impl Fn(&mut LimiterState) -> &mut Option<TokenBucket>) -> bool {
+        let limit = match limit {
+            Some(limit) => limit,
+            None        => return true
+        };
+        let now       = Instant::now();
+        let mut state = lock(&self.state);
+
+        let bucket   = bucket(&mut state).get_or_insert_with(|| TokenBucket::new(limit, now));
+        bucket.limit = *limit;
+        bucket.take(now)
+    }
+}
+
+/// Returns refilled bucket of given key, which follows given limit
+///
+/// If table is full, buckets, which carry no state, are forgotten first; then buckets, which
+/// have not been used for the longest time, so busy (flooding) sources keep their buckets
+fn bucket<'a, K: Eq + Hash + Copy>(buckets: &'a mut HashMap<K, TokenBucket>, key: K, limit: &RateLimit, now: Instant) -> &'a mut TokenBucket {
+    if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(&key) {
+        buckets.retain(|_, bucket| !bucket.is_full(now));
+        if buckets.len() >= MAX_BUCKETS {
+            let mut updated: Vec<(Instant, K)> = buckets.iter().map(|(key, bucket)| (bucket.updated, *key)).collect();
+            updated.select_nth_unstable_by_key(EVICTED_BUCKETS - 1, |(updated, _)| *updated);
+            for (_, key) in &updated[..EVICTED_BUCKETS] {
+                buckets.remove(key);
+            }
+        }
+    }
+    let bucket   = buckets.entry(key).or_insert_with(|| TokenBucket::new(limit, now));
+    bucket.limit = *limit;
+    bucket.refill(now);
+    bucket
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new()
+            .set_source_limit(Some(RateLimit::new(0, 3)))
+            .set_client_limit(Some(RateLimit::new(0, 2)))
+            .set_error_report_limit(Some(RateLimit::new(0, 1)))
+            .set_action(RateLimitAction::Reject);
+
+        let network = ClientEntry::new("10.0.0.0/8", String::from("secret")).unwrap();
+        let nas     = ClientEntry::new("10.0.0.9",   String::from("secret")).unwrap().set_rate_limit(Some(RateLimit::new(0, 5)));
+
+        assert_eq!(None,                          limiter.check("10.0.0.1".parse().unwrap(), &network));
+        assert_eq!(None,                          limiter.check("10.0.0.2".parse().unwrap(), &network));
+        // Client entry is limited, even though every source IP is below its own limit
+        assert_eq!(Some(RateLimitAction::Reject), limiter.check("10.0.0.3".parse().unwrap(), &network));
+
+        // Client's own limit overrides limiter's one, but source IP limit still applies
+        for _ in 0..3 {
+            assert_eq!(None, limiter.check("10.0.0.9".parse().unwrap(), &nas));
+        }
+        assert_eq!(Some(RateLimitAction::Reject), limiter.check("10.0.0.9".parse().unwrap(), &nas));
+
+        assert!(limiter.allow_unknown());
+        assert!(limiter.allow_error_report());
+        assert!(!limiter.allow_error_report());
+
+        assert_eq!(1, limiter.stats().source_limited());
+        assert_eq!(1, limiter.stats().client_limited());
+        assert_eq!(0, limiter.stats().unknown_limited());
+        assert_eq!(1, limiter.stats().errors_suppressed());
+    }
+
+    #[test]
+    fn test_rate_limiter_takes_both_tokens_or_none() {
+        let limiter = RateLimiter::new()
+            .set_source_limit(Some(RateLimit::new(0, 2)))
+            .set_client_limit(Some(RateLimit::new(0, 1)));
+
+        let source = "10.0.0.1".parse().unwrap();
+        let first  = ClientEntry::new("10.0.0.0/24", String::from("secret")).unwrap();
+        let second = ClientEntry::new("10.0.0.1",    String::from("secret")).unwrap();
+
+        assert_eq!(None,                        limiter.check(source, &first));
+        // Rejected by client limit, so source IP token is kept
+        assert_eq!(Some(RateLimitAction::Drop), limiter.check(source, &first));
+        assert_eq!(None,                        limiter.check(source, &second));
+        assert_eq!(Some(RateLimitAction::Drop), limiter.check(source, &second));
+
+        assert_eq!(1, limiter.stats().source_limited());
+        assert_eq!(1, limiter.stats().client_limited());
+    }
+
+    #[test]
+    fn test_bucket_eviction() {
+        let start   = Instant::now();
+        let flood   = RateLimit::new(0, 1);
+        let client  = RateLimit::new(0, 5);
+        let mut buckets: HashMap<u32, TokenBucket> = HashMap::new();
+
+        // Flooding source uses its bucket after every other source
+        for key in 0..MAX_BUCKETS as u32 {
+            let now = start + Duration::from_millis(u64::from(key));
+            assert!(bucket(&mut buckets, key, &client, now).take(now));
+        }
+        let now = start + Duration::from_secs(3600);
+        assert!(bucket(&mut buckets, 0, &flood, now).take(now));
+        assert!(!bucket(&mut buckets, 0, &flood, now).take(now));
+
+        // Table is full: no bucket is full with its own limit, so least recently used ones are
+        // forgotten, while flooding source stays limited
+        assert!(bucket(&mut buckets, MAX_BUCKETS as u32, &flood, now).take(now));
+        assert_eq!(MAX_BUCKETS - EVICTED_BUCKETS + 1, buckets.len());
+        assert!(!buckets.contains_key(&1));
+        assert!(buckets.contains_key(&(MAX_BUCKETS as u32 - 1)));
+        assert!(!bucket(&mut buckets, 0, &flood, now).take(now));
+    }
+}
@@ -0,0 +1,166 @@
+//! Cache of replies to recent requests, which lets RADIUS Server answer retransmitted requests
+//! without resolving them again (RFC 5080 Section 2.2.2)
+
+
+use crate::protocol::radius_packet::RadiusPacket;
+use crate::tools::lock;
+
+use std::collections::{ HashMap, VecDeque };
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{ Duration, Instant };
+
+
+const DEFAULT_MAX_ENTRIES: usize = 65536;
+
+
+type RequestKey = (SocketAddr, u8, Vec<u8>);
+
+#[derive(Debug, Clone, PartialEq)]
+/// Represents what RADIUS Server knows about the request, which it has received before
+pub(crate) enum CachedRequest {
+    /// Request is still being resolved
+    InProgress,
+    /// Request has been resolved; None means request has been discarded without reply
+    Replied(Option<Vec<u8>>)
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: HashMap<RequestKey, (CachedRequest, Instant)>,
+    // Keys in order of arrival, which is also the order of expiry
+    expiry:  VecDeque<(RequestKey, Instant)>
+}
+
+#[derive(Debug)]
+/// Represents cache of replies, keyed by request's source address, port, ID and Request
+/// Authenticator
+///
+/// Request, which arrives again within the window, is treated as retransmission: if it has been
+/// resolved, cached reply is sent back again; if it is still being resolved, it is silently
+/// discarded
+pub struct ReplyCache {
+    window:      Duration,
+    max_entries: usize,
+    state:       Mutex<CacheState>
+}
+
+impl ReplyCache {
+    /// Initialises ReplyCache, which remembers requests for given window and holds at most 65536
+    /// requests
+    pub fn new(window: Duration) -> ReplyCache {
+        ReplyCache {
+            window,
+            max_entries: DEFAULT_MAX_ENTRIES,
+            state:       Mutex::new(CacheState::default())
+        }
+    }
+
+    // === Builder for ReplyCache ===
+    /// Sets maximum number of cached requests (at least 1); once it is reached, the oldest
+    /// requests are forgotten before their window ends
+    pub fn set_max_entries(mut self, max_entries: usize) -> ReplyCache {
+        self.max_entries = std::cmp::max(max_entries, 1);
+        self
+    }
+    // ===================
+
+    /// Returns for how long requests are remembered
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Returns maximum number of cached requests
+    pub fn max_entries(&self) -> usize {
+        self.max_entries
+    }
+
+    /// Returns number of cached requests
+    pub fn len(&self) -> usize {
+        lock(&self.state).entries.len()
+    }
+
+    /// Returns true, if there are no cached requests
+    pub fn is_empty(&self) -> bool {
+        lock(&self.state).entries.is_empty()
+    }
+
+    /// Returns cached state of the request, if it is a duplicate; otherwise remembers request as
+    /// being resolved and returns None
+    pub(crate) fn start(&self, source: SocketAddr, request: &RadiusPacket) -> Option<CachedRequest> {
+        let key       = ReplyCache::key(source, request);
+        let now       = Instant::now();
+        let mut state = lock(&self.state);
+
+        while matches!(state.expiry.front(), Some((_, expires)) if *expires <= now) || state.entries.len() >= self.max_entries {
+            match state.expiry.pop_front() {
+                // Request could have been abandoned and started again since, then it expires later
+                Some((key, expires)) => if matches!(state.entries.get(&key), Some((_, entry_expires)) if *entry_expires == expires) {
+                    state.entries.remove(&key);
+                },
+                None                 => break
+            }
+        }
+
+        if let Some((cached, _)) = state.entries.get(&key) {
+            return Some(cached.clone())
+        }
+        let expires = now + self.window;
+        state.entries.insert(key.clone(), (CachedRequest::InProgress, expires));
+        state.expiry.push_back((key, expires));
+        None
+    }
+
+    /// Remembers reply to the request, which has been resolved
+    pub(crate) fn finish(&self, source: SocketAddr, request: &RadiusPacket, reply: Option<Vec<u8>>) {
+        if let Some((cached, _)) = lock(&self.state).entries.get_mut(&ReplyCache::key(source, request)) {
+            *cached = CachedRequest::Replied(reply);
+        }
+    }
+
+    /// Forgets request, which could not be resolved, so its retransmission is resolved again
+    pub(crate) fn abandon(&self, source: SocketAddr, request: &RadiusPacket) {
+        // Key stays in expiry queue and is skipped, once it is popped
+        lock(&self.state).entries.remove(&ReplyCache::key(source, request));
+    }
+
+    fn key(source: SocketAddr, request: &RadiusPacket) -> RequestKey {
+        (source, request.id(), request.authenticator().to_vec())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::radius_packet::TypeCode;
+
+    #[test]
+    fn test_reply_cache() {
+        let cache   = ReplyCache::new(Duration::from_secs(60)).set_max_entries(2);
+        let source  = "127.0.0.1:50000".parse().unwrap();
+        let first   = RadiusPacket::initialise_packet(TypeCode::AccessRequest);
+        let second  = RadiusPacket::initialise_packet(TypeCode::AccountingRequest);
+        let third   = RadiusPacket::initialise_packet(TypeCode::AccessRequest);
+
+        assert_eq!(None,                            cache.start(source, &first));
+        assert_eq!(Some(CachedRequest::InProgress), cache.start(source, &first));
+
+        cache.finish(source, &first, Some(vec![2, 1, 0, 20]));
+        assert_eq!(Some(CachedRequest::Replied(Some(vec![2, 1, 0, 20]))), cache.start(source, &first));
+        // The same request from another port is not a duplicate
+        assert_eq!(None, cache.start("127.0.0.1:50001".parse().unwrap(), &first));
+
+        // The oldest request is forgotten, once cache is full
+        assert_eq!(None, cache.start(source, &second));
+        assert_eq!(2,    cache.len());
+        assert_eq!(None, cache.start(source, &first));
+
+        cache.abandon(source, &first);
+        assert_eq!(None, cache.start(source, &first));
+
+        let cache = ReplyCache::new(Duration::from_millis(0));
+        assert_eq!(None, cache.start(source, &third));
+        assert_eq!(None, cache.start(source, &third));
+    }
+}
@@ -5,15 +5,43 @@ use crate::protocol::host::Host;
 use crate::protocol::radius_packet::{ RadiusAttribute, RadiusMsgType, RadiusPacket, TypeCode };
 use crate::protocol::dictionary::Dictionary;
 use crate::protocol::error::RadiusError;
+use crate::server::client_resolver::{ ClientCache, ClientResolver };
+#[cfg(feature = "async-radius")]
+use crate::server::client_resolver::AsyncClientResolver;
+use crate::server::client_table::{ ClientEntry, ClientTable };
+use crate::server::handler::{ is_expected_request, RadiusHandler, RequestContext };
+use crate::server::rate_limit::{ RateLimitAction, RateLimiter };
+use crate::server::reply_cache::{ CachedRequest, ReplyCache };
+use crate::server::statistics::{ Event, ServerStatistics };
+use crate::server::udp_server::UdpServer;
+
+#[cfg(feature = "metrics")]
+use crate::metrics;
 
 use md5::{ Digest, Md5 };
+use std::borrow::Cow;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+
+const VENDOR_SPECIFIC_ID:       u8 = 26;
+const EAP_MESSAGE_ID:           u8 = 79;
+const MESSAGE_AUTHENTICATOR_ID: u8 = 80;
+
+const DEFAULT_REPLY_CACHE_WINDOW: u64 = 5;
 
 
 #[derive(Debug)]
 /// Represents RADIUS Generic Server instance
 pub struct Server {
     host:          Host,
+    clients:       ClientTable,
     allowed_hosts: Vec<String>,
+    allowed:       ClientTable,
+    resolver:      ClientCache,
+    reply_cache:   Option<ReplyCache>,
+    rate_limiter:  RateLimiter,
+    statistics:    ServerStatistics,
     server:        String,
     secret:        String,
     retries:       u16,
@@ -30,7 +58,13 @@ impl Server {
 
         Server {
             host,
+            clients:       ClientTable::new(),
             allowed_hosts: Vec::new(),
+            allowed:       ClientTable::new(),
+            resolver:      ClientCache::new(),
+            reply_cache:   Some(ReplyCache::new(Duration::from_secs(DEFAULT_REPLY_CACHE_WINDOW))),
+            rate_limiter:  RateLimiter::new(),
+            statistics:    ServerStatistics::new(),
             server:        String::from(""),
             secret:        String::from(""),
             retries:       1,
@@ -48,17 +82,82 @@ impl Server {
 
     /// **Required**
     ///
-    /// Sets secret which is used to encode/decode RADIUS packet
+    /// Sets secret which is used to encode/decode RADIUS packet of allowed hosts
     pub fn set_secret(mut self, secret: String) -> Server {
-        self.secret = secret;
+        self.secret  = secret;
+        self.allowed = self.allowed_clients();
         self
     }
 
-    /// **Required**
+    /// **Required/Optional**
     ///
-    /// Sets allowed hosts, from where Server would be allowed to accept RADIUS requests
+    /// Sets allowed hosts, from where Server would be allowed to accept RADIUS requests; allowed
+    /// hosts share Server's secret
+    ///
+    /// Either allowed hosts or [client table](Server::set_client_table) is required
     pub fn set_allowed_hosts(mut self, allowed_hosts: Vec<String>) -> Server {
         self.allowed_hosts = allowed_hosts;
+        self.allowed       = self.allowed_clients();
+        self
+    }
+
+    /// **Required/Optional**
+    ///
+    /// Sets table of RADIUS Clients, from where Server would be allowed to accept RADIUS
+    /// requests, each with its own secret
+    ///
+    /// Clients from the table take precedence over allowed hosts
+    pub fn set_client_table(mut self, clients: ClientTable) -> Server {
+        self.clients = clients;
+        self
+    }
+
+    /// **Optional**
+    ///
+    /// Sets resolver, which is consulted when request arrives from address, which is neither in
+    /// client table nor in allowed hosts
+    pub fn set_client_resolver<R: ClientResolver + 'static>(mut self, resolver: R) -> Server {
+        self.resolver.set_resolver(Box::new(resolver));
+        self
+    }
+
+    /// **Optional**
+    ///
+    /// Sets resolver, which is awaited by [AsyncServer](crate::server::async_server::AsyncServer)
+    /// when request arrives from address, which is neither in client table nor in allowed hosts
+    ///
+    /// AsyncServer, which has no async resolver, calls [client resolver](Server::set_client_resolver)
+    /// on the runtime worker; async resolver keeps the worker free, while resolver waits for
+    /// external source. Both resolvers share the same cache
+    #[cfg(feature = "async-radius")]
+    pub fn set_async_client_resolver<R: AsyncClientResolver + 'static>(mut self, resolver: R) -> Server {
+        self.resolver.set_async_resolver(Box::new(resolver));
+        self
+    }
+
+    /// **Optional**
+    ///
+    /// Sets for how long resolved clients and unknown addresses are cached, otherwise you would
+    /// have default values of 300 and 30 seconds
+    pub fn set_client_cache_ttl(mut self, ttl: Duration, negative_ttl: Duration) -> Server {
+        self.resolver.set_ttl(ttl, negative_ttl);
+        self
+    }
+
+    /// **Optional**
+    ///
+    /// Sets cache, which detects retransmitted requests, or disables it with None; otherwise you
+    /// would have a cache with default window of 5 seconds
+    pub fn set_reply_cache(mut self, reply_cache: Option<ReplyCache>) -> Server {
+        self.reply_cache = reply_cache;
+        self
+    }
+
+    /// **Optional**
+    ///
+    /// Sets rate limits of incoming requests, otherwise nothing is limited
+    pub fn set_rate_limiter(mut self, rate_limiter: RateLimiter) -> Server {
+        self.rate_limiter = rate_limiter;
         self
     }
 
@@ -112,6 +211,26 @@ impl Server {
         &self.allowed_hosts
     }
 
+    /// Returns client table
+    pub fn client_table(&self) -> &ClientTable {
+        &self.clients
+    }
+
+    /// Returns reply cache, if it is enabled
+    pub fn reply_cache(&self) -> Option<&ReplyCache> {
+        self.reply_cache.as_ref()
+    }
+
+    /// Returns rate limiter
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    /// Returns counters of authentication and accounting requests, which Server has received
+    pub fn statistics(&self) -> &ServerStatistics {
+        &self.statistics
+    }
+
     /// Creates RADIUS packet attribute by name, that is defined in dictionary file
     ///
     /// For example, see [Client](crate::client::client::Client::create_attribute_by_name)
@@ -126,30 +245,42 @@ impl Server {
         self.host.create_attribute_by_id(attribute_id, value)
     }
 
-    /// Creates reply RADIUS packet
+    /// Creates reply RADIUS packet, signed with secret of allowed hosts
     ///
     /// Similar to [Client's create_packet()](crate::client::client::Client::create_packet), however also sets correct packet ID and authenticator
     pub fn create_reply_packet(&self, reply_code: TypeCode, attributes: Vec<RadiusAttribute>, request: &mut [u8]) -> RadiusPacket {
+        self.create_reply_packet_with_secret(&self.secret, reply_code, attributes, request)
+    }
+
+    /// Creates reply RADIUS packet, signed with secret of given client
+    ///
+    /// Same as [create_reply_packet](Server::create_reply_packet), but for clients from
+    /// [client table](Server::set_client_table)
+    pub fn create_client_reply_packet(&self, client: &ClientEntry, reply_code: TypeCode, attributes: Vec<RadiusAttribute>, request: &mut [u8]) -> RadiusPacket {
+        self.create_reply_packet_with_secret(client.secret(), reply_code, attributes, request)
+    }
+
+    fn create_reply_packet_with_secret(&self, secret: &str, reply_code: TypeCode, attributes: Vec<RadiusAttribute>, request: &mut [u8]) -> RadiusPacket {
         let mut reply_packet = RadiusPacket::initialise_packet(reply_code);
         reply_packet.set_attributes(attributes);
 
         // We can only create new authenticator after we set reply packet ID to the request's ID
         reply_packet.override_id(request[1]);
 
-        let authenticator = self.create_reply_authenticator(&reply_packet.to_bytes(), &request[4..20]);
+        let authenticator = self.create_reply_authenticator(secret, &reply_packet.to_bytes(), &request[4..20]);
         reply_packet.override_authenticator(authenticator);
 
         reply_packet
     }
 
-    fn create_reply_authenticator(&self, raw_reply_packet: &[u8], request_authenticator: &[u8]) -> Vec<u8> {
+    fn create_reply_authenticator(&self, secret: &str, raw_reply_packet: &[u8], request_authenticator: &[u8]) -> Vec<u8> {
         // We need to create authenticator as MD5 hash (similar to how client verifies server reply)
         let mut md5_hasher    = Md5::new();
 
         md5_hasher.update(&raw_reply_packet[0..4]); // Append reply's   type code, reply ID and reply length
         md5_hasher.update(&request_authenticator);  // Append request's authenticator
         md5_hasher.update(&raw_reply_packet[20..]); // Append reply's   attributes
-        md5_hasher.update(&self.secret.as_bytes()); // Append server's  secret. Possibly it should be client's secret, which sould be stored together with allowed hostnames ?
+        md5_hasher.update(secret.as_bytes());       // Append client's  secret
         // ----------------
 
         md5_hasher.finalize().to_vec()
@@ -185,16 +316,285 @@ impl Server {
     /// Checks if host from where Server received RADIUS request is allowed host, meaning RADIUS
     /// Server can process such request
     pub fn host_allowed(&self, remote_host: &std::net::SocketAddr) -> bool {
-        let remote_host_name            = remote_host.to_string();
-        let remote_host_name: Vec<&str> = remote_host_name.split(':').collect();
+        self.client(remote_host).is_some()
+    }
 
-        self.allowed_hosts.iter().any(|host| host==remote_host_name[0]) 
+    /// Returns client, which matches host from where Server received RADIUS request
+    ///
+    /// [Client table](Server::set_client_table) is searched first, then allowed hosts and then
+    /// [client resolver](Server::set_client_resolver) is consulted, unless
+    /// [rate limiter](Server::set_rate_limiter) doesn't allow it; resolver's error is treated as
+    /// unknown host
+    pub fn client(&self, remote_host: &SocketAddr) -> Option<Cow<'_, ClientEntry>> {
+        self.resolve_client(remote_host).ok().flatten()
     }
+
+    /// Same as [client](Server::client), but returns client resolver's error
+    pub(crate) fn resolve_client(&self, remote_host: &SocketAddr) -> Result<Option<Cow<'_, ClientEntry>>, RadiusError> {
+        let remote_ip = remote_host.ip();
+
+        match self.clients.lookup(&remote_ip).or_else(|| self.allowed.lookup(&remote_ip)) {
+            Some(client) => Ok(Some(Cow::Borrowed(client))),
+            None         => Ok(self.resolver.resolve(&remote_ip, || self.rate_limiter.allow_unknown())?.map(Cow::Owned))
+        }
+    }
+
+    /// Same as [resolve_client](Server::resolve_client), but awaits
+    /// [async client resolver](Server::set_async_client_resolver), if there is one
+    #[cfg(feature = "async-radius")]
+    pub(crate) async fn resolve_client_async(&self, remote_host: &SocketAddr) -> Result<Option<Cow<'_, ClientEntry>>, RadiusError> {
+        let remote_ip = remote_host.ip();
+
+        match self.clients.lookup(&remote_ip).or_else(|| self.allowed.lookup(&remote_ip)) {
+            Some(client) => Ok(Some(Cow::Borrowed(client))),
+            None         => Ok(self.resolver.resolve_async(&remote_ip, || self.rate_limiter.allow_unknown()).await?.map(Cow::Owned))
+        }
+    }
+
+    fn allowed_clients(&self) -> ClientTable {
+        // Allowed hosts, which are not IP addresses, could never match and are skipped
+        self.allowed_hosts.iter()
+            .filter_map(|host| ClientEntry::new(host, self.secret.clone()).ok())
+            .fold(ClientTable::new(), |table, client| table.add_client(client))
+    }
+
+    /// Verifies incoming RADIUS request and returns it as RadiusPacket
+    ///
+    /// Request is parsed and its attribute values are verified. Request Authenticator of
+    /// Accounting-Request, CoA-Request and Disconnect-Request is verified (RFC 2866 Section 3 &
+    /// RFC 5176 Section 2.3); Message-Authenticator is verified, if present, and is required in
+    /// Status-Server (RFC 5997 Section 3) and in requests, which carry EAP-Message (RFC 3579
+    /// Section 3.2), as well as in Access-Request from client, which requires it
+    pub fn process_request(&self, client: &ClientEntry, request: &[u8]) -> Result<RadiusPacket, RadiusError> {
+        self.check_request(client, request).map_err(|(_, error)| error)
+    }
+
+    // Same as process_request, but also tells which MIB counter verification failure belongs to
+    fn check_request(&self, client: &ClientEntry, request: &[u8]) -> Result<RadiusPacket, (Event, RadiusError)> {
+        let packet = self.initialise_packet_from_bytes(request).map_err(|error| (Event::Malformed, error))?;
+        let length = u16::from_be_bytes([request[2], request[3]]) as usize;
+        let bytes  = &request[..length];
+
+        let has_request_authenticator = matches!(packet.code(), TypeCode::AccountingRequest | TypeCode::CoARequest | TypeCode::DisconnectRequest);
+
+        if has_request_authenticator {
+            let mut md5_hasher = Md5::new();
+
+            md5_hasher.update(&bytes[0..4]);               // Append request type code, request ID and request length
+            md5_hasher.update([0; 16]);                    // Append zeroed authenticator
+            md5_hasher.update(&bytes[20..]);               // Append request attributes
+            md5_hasher.update(client.secret().as_bytes()); // Append client's secret
+
+            if md5_hasher.finalize().as_slice() != packet.authenticator() {
+                return Err( (Event::BadAuthenticator, RadiusError::ValidationError { error: String::from("Packet authenticator mismatch") }) )
+            }
+        }
+
+        if packet.attribute_by_id(MESSAGE_AUTHENTICATOR_ID).is_some() {
+            // Request Authenticator, which is calculated over the whole packet, is zeroed, when
+            // Message-Authenticator is calculated (RFC 3579 Section 3.2 & RFC 5176 Section 3.3)
+            let mut zeroed = bytes.to_vec();
+            if has_request_authenticator {
+                zeroed[4..20].copy_from_slice(&[0; 16]);
+            }
+            self.host.verify_message_authenticator(client.secret(), &zeroed).map_err(|error| (Event::BadAuthenticator, error))?;
+        } else if packet.code() == &TypeCode::StatusServer || packet.attribute_by_id(EAP_MESSAGE_ID).is_some()
+            || (packet.code() == &TypeCode::AccessRequest && client.require_message_authenticator()) {
+            return Err( (Event::BadAuthenticator, RadiusError::ValidationError { error: String::from("Request has no Message-Authenticator") }) )
+        }
+
+        self.verify_request_attributes(bytes).map_err(|error| (Event::Malformed, error))?;
+        Ok(packet)
+    }
+
+    /// Finalises reply to verified request from given client and returns it as raw bytes
+    ///
+    /// Reply gets request's ID, Message-Authenticator (if reply carries one or request has carried
+    /// one, as required by RFC 3579 Section 3.2) and Response Authenticator
+    pub fn finalise_reply_packet(&self, client: &ClientEntry, request: &RadiusPacket, reply: &mut RadiusPacket) -> Result<Vec<u8>, RadiusError> {
+        reply.override_id(request.id());
+
+        if reply.attribute_by_id(MESSAGE_AUTHENTICATOR_ID).is_none() && request.attribute_by_id(MESSAGE_AUTHENTICATOR_ID).is_some() {
+            let mut attributes = reply.attributes().to_vec();
+            attributes.push(self.create_attribute_by_id(MESSAGE_AUTHENTICATOR_ID, [0; 16].to_vec())?);
+            reply.set_attributes(attributes);
+        }
+        if reply.attribute_by_id(MESSAGE_AUTHENTICATOR_ID).is_some() {
+            // Reply's Message-Authenticator is calculated with Request Authenticator in place of
+            // Response Authenticator
+            reply.override_authenticator(request.authenticator().to_vec());
+            reply.generate_message_authenticator(client.secret())?;
+        }
+
+        let authenticator = self.create_reply_authenticator(client.secret(), &reply.to_bytes(), request.authenticator());
+        reply.override_authenticator(authenticator);
+
+        Ok(reply.to_bytes())
+    }
+
+    /// Applies rate limits to request, which has been received from known client, and verifies it
+    ///
+    /// Request, which exceeds rate limits, is either dropped or rejected; rejection is returned
+    /// as reply, which is to be sent back straight away
+    ///
+    /// Request is counted in [statistics](Server::statistics), unless it is Status-Server
+    pub(crate) fn admit_request(&self, context: &RequestContext, request: &[u8]) -> Result<Admission, RadiusError> {
+        let code   = request.first().and_then(|code| TypeCode::from_u8(*code).ok());
+        let record = |event: Event| if code != Some(TypeCode::StatusServer) {
+            self.record(context.msg_type(), Some(context.client()), code.as_ref(), event);
+        };
+        record(Event::Request);
+
+        let action = self.rate_limiter.check(context.source().ip(), context.client());
+        if action == Some(RateLimitAction::Drop) {
+            record(Event::Dropped);
+            return Ok(Admission::Reply(None))
+        }
+
+        let packet = self.check_request(context.client(), request).map_err(|(event, error)| {
+            record(event);
+            error
+        })?;
+        if !is_expected_request(context.msg_type(), packet.code()) {
+            record(Event::UnknownType);
+            return Err( RadiusError::ValidationError { error: format!("{:?} is not expected on {:?} socket", packet.code(), context.msg_type()) } )
+        }
+
+        if action.is_none() {
+            return Ok(Admission::Request(packet))
+        }
+        let reject_code = match packet.code() {
+            TypeCode::AccessRequest     => TypeCode::AccessReject,
+            TypeCode::CoARequest        => TypeCode::CoANAK,
+            TypeCode::DisconnectRequest => TypeCode::DisconnectNAK,
+            _                           => {
+                record(Event::Dropped);
+                return Ok(Admission::Reply(None))
+            }
+        };
+        let mut reply = RadiusPacket::initialise_packet(reject_code);
+        let reply     = self.finalise_reply_packet(context.client(), &packet, &mut reply)?;
+        record(Event::Reply(reject_code));
+        Ok(Admission::Reply(Some(reply)))
+    }
+
+    /// Builds reply to verified Status-Server request (RFC 5997 Section 3): Access-Accept, if it
+    /// has been received on AUTH socket, or Accounting-Response, if on ACCT socket
+    ///
+    /// If request carries FreeRADIUS-Statistics-Type attribute, reply carries requested counters
+    /// as FreeRADIUS Vendor-Specific attributes
+    pub(crate) fn status_server_reply(&self, context: &RequestContext, request: &RadiusPacket) -> Result<Option<Vec<u8>>, RadiusError> {
+        let reply_code = match context.msg_type() {
+            RadiusMsgType::AUTH => TypeCode::AccessAccept,
+            RadiusMsgType::ACCT => TypeCode::AccountingResponse,
+            RadiusMsgType::COA  => return Ok(None)
+        };
+        let attributes = self.statistics.status_attributes(request).into_iter()
+            .map(|value| self.create_attribute_by_id(VENDOR_SPECIFIC_ID, value))
+            .collect::<Result<Vec<RadiusAttribute>, RadiusError>>()?;
+
+        let mut reply = RadiusPacket::initialise_packet(reply_code);
+        reply.set_attributes(attributes);
+        self.finalise_reply_packet(context.client(), request, &mut reply).map(Some)
+    }
+
+    /// Counts request, which has been received from unknown address on the socket of given RADIUS
+    /// Message Type
+    pub(crate) fn record_invalid_request(&self, msg_type: RadiusMsgType, request: &[u8]) {
+        let code = request.first().and_then(|code| TypeCode::from_u8(*code).ok());
+        self.record(msg_type, None, code.as_ref(), Event::Invalid);
+    }
+
+    /// Counts verified request, which has been discarded before it was resolved
+    pub(crate) fn record_dropped_request(&self, context: &RequestContext, request: &RadiusPacket) {
+        if request.code() != &TypeCode::StatusServer {
+            self.record(context.msg_type(), Some(context.client()), Some(request.code()), Event::Dropped);
+        }
+    }
+
+    /// Returns true, if request, which has failed verification, could be reported to handler
+    pub(crate) fn report_invalid_request(&self) -> bool {
+        self.rate_limiter.allow_error_report()
+    }
+
+    /// Checks if verified request is a retransmission of the request, which
+    /// Server has received before, and if so returns what should be sent back (None, if request
+    /// is still being resolved or has been discarded)
+    ///
+    /// Otherwise request is remembered as being resolved and its result is to be passed to
+    /// [finish_request](Server::finish_request)
+    pub(crate) fn duplicate_request(&self, context: &RequestContext, request: &RadiusPacket) -> Option<Option<Vec<u8>>> {
+        let cached = self.reply_cache.as_ref()?.start(context.source(), request)?;
+        self.record(context.msg_type(), Some(context.client()), Some(request.code()), Event::Duplicate);
+
+        match cached {
+            CachedRequest::InProgress     => Some(None),
+            CachedRequest::Replied(reply) => Some(reply)
+        }
+    }
+
+    /// Remembers the result of resolving verified request, so its retransmissions get the same
+    /// reply; failed request is forgotten
+    pub(crate) fn finish_request(&self, context: &RequestContext, request: &RadiusPacket, result: &Result<Option<Vec<u8>>, RadiusError>) {
+        let event = match result {
+            Ok(Some(reply)) => TypeCode::from_u8(reply[0]).map(Event::Reply).unwrap_or(Event::Dropped),
+            _               => Event::Dropped
+        };
+        self.record(context.msg_type(), Some(context.client()), Some(request.code()), event);
+
+        if let Some(reply_cache) = &self.reply_cache {
+            match result {
+                Ok(reply) => reply_cache.finish(context.source(), request, reply.clone()),
+                Err(_)    => reply_cache.abandon(context.source(), request)
+            }
+        }
+    }
+
+    /// Forgets verified request, which has been resolved, but whose reply is not going to be sent,
+    /// and counts it as dropped
+    pub(crate) fn abandon_request(&self, context: &RequestContext, request: &RadiusPacket) {
+        self.record(context.msg_type(), Some(context.client()), Some(request.code()), Event::Dropped);
+
+        if let Some(reply_cache) = &self.reply_cache {
+            reply_cache.abandon(context.source(), request);
+        }
+    }
+
+    fn record(&self, msg_type: RadiusMsgType, client: Option<&ClientEntry>, code: Option<&TypeCode>, event: Event) {
+        #[cfg(feature = "metrics")]
+        if let Some(outcome) = event.label() {
+            let code   = code.map(metrics::code_label).unwrap_or("unknown");
+            let client = client.map(ClientEntry::shortname).unwrap_or("");
+            metrics::registry().increment_counter(&metrics::SERVER_REQUESTS, &[metrics::socket_label(msg_type), code, client, outcome]);
+        }
+        #[cfg(not(feature = "metrics"))]
+        let _ = code;
+
+        self.statistics.record(msg_type, client, event);
+    }
+
+    /// Binds AUTH, ACCT and CoA sockets on Server's hostname and ports and serves requests with
+    /// given handler, until socket error occurs
+    ///
+    /// See [UdpServer](crate::server::udp_server::UdpServer) for details
+    pub fn serve<H: RadiusHandler + Sync>(self, handler: H) -> Result<(), RadiusError> {
+        UdpServer::with_server(self)?.serve(&handler)
+    }
+}
+
+/// Represents what RADIUS Server does with incoming request, once it has passed rate limits and
+/// verification
+pub(crate) enum Admission {
+    /// Request is to be resolved by handler
+    Request(RadiusPacket),
+    /// Request is not to be resolved; reply (if any) is to be sent back straight away
+    Reply(Option<Vec<u8>>)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{ test_client, test_server };
 
     #[test]
     fn test_add_allowed_hosts_and_add_request_handler() {
@@ -206,4 +606,131 @@ mod tests {
 
         assert_eq!(server.allowed_hosts().len(), 1);
     }
+
+    #[test]
+    fn test_client_table() {
+        let clients = ClientTable::new()
+            .add_client(ClientEntry::new("10.0.0.0/8", String::from("network-secret")).unwrap())
+            .add_client(ClientEntry::new("10.0.0.1",   String::from("nas-secret")).unwrap().set_require_message_authenticator(true));
+
+        let server = test_server("secret")
+            .set_allowed_hosts(vec![String::from("::1"), String::from("localhost")])
+            .set_client_table(clients);
+
+        assert_eq!("network-secret", server.client(&"10.0.0.2:1812".parse().unwrap()).unwrap().secret());
+        assert_eq!("secret",         server.client(&"[::1]:1812".parse().unwrap()).unwrap().secret());
+        assert!(server.host_allowed(&"[::1]:1812".parse().unwrap()));
+        assert!(!server.host_allowed(&"127.0.0.1:1812".parse().unwrap()));
+
+        let nas         = test_client(1812, "nas-secret");
+        let client      = &server.client(&"10.0.0.1:1812".parse().unwrap()).unwrap();
+        // Access-Request without Message-Authenticator is discarded, if client requires one
+        let mut request = nas.create_auth_packet();
+        request.set_attributes(vec![nas.create_attribute_by_name("User-Name", b"testing".to_vec()).unwrap()]);
+        assert!(server.process_request(client, &request.to_bytes()).is_err());
+
+        let mut request = nas.create_pap_packet("testing", "password").unwrap();
+        let packet    = server.process_request(client, &request.to_bytes()).unwrap();
+        let mut reply = RadiusPacket::initialise_packet(TypeCode::AccessAccept);
+        let reply     = server.finalise_reply_packet(client, &packet, &mut reply).unwrap();
+        assert_eq!(&TypeCode::AccessAccept, nas.process_reply(&request, &reply).unwrap().code());
+
+        let other_client = &server.client(&"10.0.0.2:1812".parse().unwrap()).unwrap();
+        assert!(server.process_request(other_client, &request.to_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_coa_request_with_message_authenticator() {
+        let server = test_server("secret")
+            .set_allowed_hosts(vec![String::from("127.0.0.1")]);
+
+        let nas         = test_client(1812, "secret");
+        let client      = &server.client(&"127.0.0.1:3799".parse().unwrap()).unwrap();
+        let mut request = nas.create_coa_packet();
+        request.set_attributes(vec![
+            nas.create_attribute_by_name("User-Name", b"testing".to_vec()).unwrap(),
+            nas.create_attribute_by_name("Message-Authenticator", [0; 16].to_vec()).unwrap()
+        ]);
+        request.generate_request_authenticator(nas.secret()).unwrap();
+
+        let packet    = server.process_request(client, &request.to_bytes()).unwrap();
+        let mut reply = RadiusPacket::initialise_packet(TypeCode::CoAACK);
+        let reply     = server.finalise_reply_packet(client, &packet, &mut reply).unwrap();
+        assert_eq!(&TypeCode::CoAACK, nas.process_reply(&request, &reply).unwrap().code());
+    }
+
+    #[test]
+    fn test_admit_request_with_rate_limit() {
+        let server = test_server("secret")
+            .set_allowed_hosts(vec![String::from("127.0.0.1")])
+            .set_rate_limiter(RateLimiter::new()
+                .set_source_limit(Some(crate::server::rate_limit::RateLimit::new(0, 1)))
+                .set_action(RateLimitAction::Reject));
+
+        let nas        = test_client(1812, "secret");
+        let source     = "127.0.0.1:50000".parse().unwrap();
+        let client     = server.client(&source).unwrap().into_owned();
+        let context    = RequestContext::new(source, RadiusMsgType::AUTH, client, std::time::Instant::now());
+
+        let mut request = nas.create_pap_packet("testing", "password").unwrap();
+        assert!(matches!(server.admit_request(&context, &request.to_bytes()), Ok(Admission::Request(_))));
+
+        let mut request = nas.create_pap_packet("testing", "password").unwrap();
+        match server.admit_request(&context, &request.to_bytes()) {
+            Ok(Admission::Reply(Some(reply))) => assert_eq!(&TypeCode::AccessReject, nas.process_reply(&request, &reply).unwrap().code()),
+            _                                 => assert!(false)
+        }
+        assert_eq!(1, server.rate_limiter().stats().source_limited());
+    }
+
+    #[test]
+    fn test_status_server_and_statistics() {
+        let server = test_server("secret")
+            .set_allowed_hosts(vec![String::from("127.0.0.1")]);
+
+        let nas        = test_client(1812, "secret");
+        let source     = "127.0.0.1:50000".parse().unwrap();
+        let client     = server.client(&source).unwrap().into_owned();
+        let context    = RequestContext::new(source, RadiusMsgType::AUTH, client, std::time::Instant::now());
+
+        let mut request = nas.create_pap_packet("testing", "password").unwrap();
+        let packet      = match server.admit_request(&context, &request.to_bytes()) {
+            Ok(Admission::Request(packet)) => packet,
+            _                              => panic!("Access-Request has not been admitted")
+        };
+        assert!(server.duplicate_request(&context, &packet).is_none());
+        let mut reply = RadiusPacket::initialise_packet(TypeCode::AccessAccept);
+        let reply     = server.finalise_reply_packet(context.client(), &packet, &mut reply).map(Some);
+        server.finish_request(&context, &packet, &reply);
+        assert_eq!(Some(reply.unwrap()), server.duplicate_request(&context, &packet));
+
+        let other_nas   = test_client(1812, "other-secret");
+        let mut request = other_nas.create_pap_packet("testing", "password").unwrap();
+        assert!(server.admit_request(&context, &request.to_bytes()).is_err());
+
+        // Status-Server asks for authentication counters (FreeRADIUS-Statistics-Type = Authentication)
+        let mut request = nas.create_packet(TypeCode::StatusServer);
+        request.set_attributes(vec![
+            nas.create_attribute_by_id(VENDOR_SPECIFIC_ID, vec![0, 0, 0x2c, 0x50, 127, 6, 0, 0, 0, 1]).unwrap(),
+            nas.create_attribute_by_id(MESSAGE_AUTHENTICATOR_ID, [0; 16].to_vec()).unwrap()
+        ]);
+        request.generate_message_authenticator(nas.secret()).unwrap();
+        let packet = match server.admit_request(&context, &request.to_bytes()) {
+            Ok(Admission::Request(packet)) => packet,
+            _                              => panic!("Status-Server has not been admitted")
+        };
+        let reply = server.status_server_reply(&context, &packet).unwrap().unwrap();
+        let reply = nas.process_reply(&request, &reply).unwrap();
+        assert_eq!(&TypeCode::AccessAccept, reply.code());
+        assert_eq!(10, reply.attributes().iter().filter(|attribute| attribute.id() == VENDOR_SPECIFIC_ID).count());
+        assert!(reply.attributes().iter().any(|attribute| attribute.value() == [0, 0, 0x2c, 0x50, 128, 6, 0, 0, 0, 2]));
+
+        let auth = server.statistics().auth();
+        assert_eq!(2, auth.access_requests());
+        assert_eq!(1, auth.dup_requests());
+        assert_eq!(1, auth.access_accepts());
+        assert_eq!(1, auth.bad_authenticators());
+        assert_eq!(0, server.statistics().acct().requests());
+        assert_eq!(2, server.statistics().client(&source.ip()).unwrap().auth().access_requests());
+    }
 }
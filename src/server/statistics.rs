@@ -0,0 +1,485 @@
+//! Counters of RADIUS Server, which follow RADIUS Authentication Server MIB (RFC 4669) and
+//! RADIUS Accounting Server MIB (RFC 4671), and their FreeRADIUS-Statistics representation,
+//! which is returned in reply to Status-Server (RFC 5997)
+
+
+use crate::protocol::radius_packet::{ RadiusMsgType, RadiusPacket, TypeCode };
+use crate::server::client_table::ClientEntry;
+use crate::tools::lock;
+
+use std::collections::HashMap;
+use std::net::{ IpAddr, Ipv4Addr };
+use std::sync::Mutex;
+
+
+const MAX_CLIENTS: usize = 65536;
+
+const VENDOR_SPECIFIC_ID: u8  = 26;
+const FREERADIUS_VENDOR:  u32 = 11344;
+
+// FreeRADIUS-Statistics-Type and its flags
+const STATISTICS_TYPE:    u8  = 127;
+const STATISTICS_AUTH:    u32 = 0x01;
+const STATISTICS_ACCT:    u32 = 0x02;
+const STATISTICS_CLIENT:  u32 = 0x20;
+
+// FreeRADIUS-Stats-Client-IP-Address
+const STATS_CLIENT_IP:    u8  = 167;
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Represents counters of authentication requests (RFC 4669)
+pub struct AuthStats {
+    access_requests:    u64,
+    dup_requests:       u64,
+    access_accepts:     u64,
+    access_rejects:     u64,
+    access_challenges:  u64,
+    malformed_requests: u64,
+    bad_authenticators: u64,
+    dropped_requests:   u64,
+    unknown_types:      u64,
+    invalid_requests:   u64
+}
+
+impl AuthStats {
+    /// Returns number of packets received on authentication port (radiusAuthServTotalAccessRequests)
+    pub fn access_requests(&self) -> u64 {
+        self.access_requests
+    }
+
+    /// Returns number of duplicate Access-Requests (radiusAuthServTotalDupAccessRequests)
+    pub fn dup_requests(&self) -> u64 {
+        self.dup_requests
+    }
+
+    /// Returns number of sent Access-Accepts (radiusAuthServTotalAccessAccepts)
+    pub fn access_accepts(&self) -> u64 {
+        self.access_accepts
+    }
+
+    /// Returns number of sent Access-Rejects (radiusAuthServTotalAccessRejects)
+    pub fn access_rejects(&self) -> u64 {
+        self.access_rejects
+    }
+
+    /// Returns number of sent Access-Challenges (radiusAuthServTotalAccessChallenges)
+    pub fn access_challenges(&self) -> u64 {
+        self.access_challenges
+    }
+
+    /// Returns number of malformed Access-Requests (radiusAuthServTotalMalformedAccessRequests)
+    pub fn malformed_requests(&self) -> u64 {
+        self.malformed_requests
+    }
+
+    /// Returns number of Access-Requests with invalid Message-Authenticator
+    /// (radiusAuthServTotalBadAuthenticators)
+    pub fn bad_authenticators(&self) -> u64 {
+        self.bad_authenticators
+    }
+
+    /// Returns number of requests, which have been silently discarded for other reasons
+    /// (radiusAuthServTotalPacketsDropped)
+    pub fn dropped_requests(&self) -> u64 {
+        self.dropped_requests
+    }
+
+    /// Returns number of packets of unknown type (radiusAuthServTotalUnknownTypes)
+    pub fn unknown_types(&self) -> u64 {
+        self.unknown_types
+    }
+
+    /// Returns number of packets received from unknown addresses
+    /// (radiusAuthServTotalInvalidRequests); always 0 for a single client
+    pub fn invalid_requests(&self) -> u64 {
+        self.invalid_requests
+    }
+
+    fn count(&mut self, event: &Event) {
+        match event {
+            Event::Request                             => self.access_requests    += 1,
+            Event::Duplicate                           => self.dup_requests       += 1,
+            Event::Malformed                           => self.malformed_requests += 1,
+            Event::BadAuthenticator                    => self.bad_authenticators += 1,
+            Event::Dropped                             => self.dropped_requests   += 1,
+            Event::UnknownType                         => self.unknown_types      += 1,
+            Event::Invalid                             => self.invalid_requests   += 1,
+            Event::Reply(TypeCode::AccessAccept)       => self.access_accepts     += 1,
+            Event::Reply(TypeCode::AccessReject)       => self.access_rejects     += 1,
+            Event::Reply(TypeCode::AccessChallenge)    => self.access_challenges  += 1,
+            Event::Reply(_)                            => {}
+        }
+    }
+
+    fn to_attributes(self) -> Vec<(u8, u64)> {
+        vec![
+            (128, self.access_requests),
+            (129, self.access_accepts),
+            (130, self.access_rejects),
+            (131, self.access_challenges),
+            (132, self.access_accepts + self.access_rejects + self.access_challenges),
+            (133, self.dup_requests),
+            (134, self.malformed_requests),
+            (135, self.invalid_requests),
+            (136, self.dropped_requests),
+            (137, self.unknown_types)
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Represents counters of accounting requests (RFC 4671)
+pub struct AcctStats {
+    requests:           u64,
+    dup_requests:       u64,
+    responses:          u64,
+    malformed_requests: u64,
+    bad_authenticators: u64,
+    dropped_requests:   u64,
+    unknown_types:      u64,
+    invalid_requests:   u64
+}
+
+impl AcctStats {
+    /// Returns number of packets received on accounting port (radiusAccServTotalRequests)
+    pub fn requests(&self) -> u64 {
+        self.requests
+    }
+
+    /// Returns number of duplicate Accounting-Requests (radiusAccServTotalDupRequests)
+    pub fn dup_requests(&self) -> u64 {
+        self.dup_requests
+    }
+
+    /// Returns number of sent Accounting-Responses (radiusAccServTotalResponses)
+    pub fn responses(&self) -> u64 {
+        self.responses
+    }
+
+    /// Returns number of malformed Accounting-Requests (radiusAccServTotalMalformedRequests)
+    pub fn malformed_requests(&self) -> u64 {
+        self.malformed_requests
+    }
+
+    /// Returns number of Accounting-Requests with invalid Request Authenticator
+    /// (radiusAccServTotalBadAuthenticators)
+    pub fn bad_authenticators(&self) -> u64 {
+        self.bad_authenticators
+    }
+
+    /// Returns number of requests, which have been silently discarded for other reasons
+    /// (radiusAccServTotalPacketsDropped)
+    pub fn dropped_requests(&self) -> u64 {
+        self.dropped_requests
+    }
+
+    /// Returns number of packets of unknown type (radiusAccServTotalUnknownTypes)
+    pub fn unknown_types(&self) -> u64 {
+        self.unknown_types
+    }
+
+    /// Returns number of packets received from unknown addresses
+    /// (radiusAccServTotalInvalidRequests); always 0 for a single client
+    pub fn invalid_requests(&self) -> u64 {
+        self.invalid_requests
+    }
+
+    fn count(&mut self, event: &Event) {
+        match event {
+            Event::Request                             => self.requests           += 1,
+            Event::Duplicate                           => self.dup_requests       += 1,
+            Event::Malformed                           => self.malformed_requests += 1,
+            Event::BadAuthenticator                    => self.bad_authenticators += 1,
+            Event::Dropped                             => self.dropped_requests   += 1,
+            Event::UnknownType                         => self.unknown_types      += 1,
+            Event::Invalid                             => self.invalid_requests   += 1,
+            Event::Reply(TypeCode::AccountingResponse) => self.responses          += 1,
+            Event::Reply(_)                            => {}
+        }
+    }
+
+    fn to_attributes(self) -> Vec<(u8, u64)> {
+        vec![
+            (148, self.requests),
+            (149, self.responses),
+            (150, self.dup_requests),
+            (151, self.malformed_requests),
+            (152, self.invalid_requests),
+            (153, self.dropped_requests),
+            (154, self.unknown_types)
+        ]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Represents counters of requests from a single client
+pub struct ClientStatistics {
+    client: ClientEntry,
+    auth:   AuthStats,
+    acct:   AcctStats
+}
+
+impl ClientStatistics {
+    /// Returns client, which counters belong to
+    pub fn client(&self) -> &ClientEntry {
+        &self.client
+    }
+
+    /// Returns counters of authentication requests from the client
+    pub fn auth(&self) -> AuthStats {
+        self.auth
+    }
+
+    /// Returns counters of accounting requests from the client
+    pub fn acct(&self) -> AcctStats {
+        self.acct
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Represents what has happened to request, as counted by ServerStatistics
+pub(crate) enum Event {
+    /// Request has been received from known client
+    Request,
+    /// Request has been a retransmission of the one received before
+    Duplicate,
+    /// Request could not be parsed or its attributes are invalid
+    Malformed,
+    /// Request Authenticator or Message-Authenticator of request is invalid
+    BadAuthenticator,
+    /// Request has been silently discarded for other reasons
+    Dropped,
+    /// Request's code is not expected on the socket it has been received on
+    UnknownType,
+    /// Request has been received from unknown address
+    Invalid,
+    /// Reply with given code has been sent back
+    Reply(TypeCode)
+}
+
+#[cfg(feature = "metrics")]
+impl Event {
+    /// Returns label of request's outcome, if event completes request
+    pub(crate) fn label(&self) -> Option<&'static str> {
+        match self {
+            Event::Request          => None,
+            Event::Duplicate        => Some("duplicate"),
+            Event::Malformed        => Some("malformed"),
+            Event::BadAuthenticator => Some("bad_authenticator"),
+            Event::Dropped          => Some("dropped"),
+            Event::UnknownType      => Some("unknown_type"),
+            Event::Invalid          => Some("invalid"),
+            Event::Reply(code)      => Some(crate::metrics::code_label(code))
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct StatisticsState {
+    auth:    AuthStats,
+    acct:    AcctStats,
+    clients: HashMap<(IpAddr, u8), ClientStatistics>
+}
+
+#[derive(Debug, Default)]
+/// Holds counters of authentication and accounting requests, which RADIUS Server has received,
+/// in total and per client
+///
+/// Status-Server requests are not counted; requests received on CoA socket are not counted
+/// either, as there is no MIB for them. Per client counters are kept for at most 65536 clients,
+/// the rest are only counted in total
+pub struct ServerStatistics {
+    state: Mutex<StatisticsState>
+}
+
+impl ServerStatistics {
+    /// Initialises ServerStatistics with all counters set to 0
+    pub fn new() -> ServerStatistics {
+        ServerStatistics::default()
+    }
+
+    /// Returns total counters of authentication requests
+    pub fn auth(&self) -> AuthStats {
+        lock(&self.state).auth
+    }
+
+    /// Returns total counters of accounting requests
+    pub fn acct(&self) -> AcctStats {
+        lock(&self.state).acct
+    }
+
+    /// Returns counters of the most specific client, which network contains given address
+    pub fn client(&self, address: &IpAddr) -> Option<ClientStatistics> {
+        lock(&self.state).clients.values()
+            .filter(|statistics| statistics.client.contains(address))
+            .max_by_key(|statistics| statistics.client.prefix_len())
+            .cloned()
+    }
+
+    /// Returns counters of all clients, which have sent requests
+    pub fn clients(&self) -> Vec<ClientStatistics> {
+        lock(&self.state).clients.values().cloned().collect()
+    }
+
+    /// Counts event, which has happened to request from given client on the socket of given
+    /// RADIUS Message Type
+    pub(crate) fn record(&self, msg_type: RadiusMsgType, client: Option<&ClientEntry>, event: Event) {
+        if msg_type == RadiusMsgType::COA {
+            return
+        }
+        let mut state = lock(&self.state);
+        let state     = &mut *state;
+
+        let client = match client {
+            Some(client) => {
+                let key = (client.network(), client.prefix_len());
+                if state.clients.len() < MAX_CLIENTS || state.clients.contains_key(&key) {
+                    Some(state.clients.entry(key).or_insert_with(|| ClientStatistics { client: client.clone(), auth: AuthStats::default(), acct: AcctStats::default() }))
+                } else {
+                    None
+                }
+            },
+            None         => None
+        };
+
+        match msg_type {
+            RadiusMsgType::AUTH => {
+                state.auth.count(&event);
+                if let Some(client) = client {
+                    client.auth.count(&event);
+                }
+            },
+            RadiusMsgType::ACCT => {
+                state.acct.count(&event);
+                if let Some(client) = client {
+                    client.acct.count(&event);
+                }
+            },
+            RadiusMsgType::COA  => {}
+        }
+    }
+
+    /// Returns values of FreeRADIUS Vendor-Specific attributes, which carry counters requested
+    /// by FreeRADIUS-Statistics-Type attribute of Status-Server request
+    ///
+    /// If Client flag is set, counters of the client given by FreeRADIUS-Stats-Client-IP-Address
+    /// are returned instead of total ones
+    pub(crate) fn status_attributes(&self, request: &RadiusPacket) -> Vec<Vec<u8>> {
+        let requested = freeradius_attributes(request);
+        let stats_type = match requested.iter().find(|(id, _)| *id == STATISTICS_TYPE) {
+            Some((_, value)) if value.len() == 4 => u32::from_be_bytes([value[0], value[1], value[2], value[3]]),
+            _                                    => return Vec::new()
+        };
+
+        let (auth, acct, client_ip) = if stats_type & STATISTICS_CLIENT != 0 {
+            let client_ip = match requested.iter().find(|(id, _)| *id == STATS_CLIENT_IP) {
+                Some((_, value)) if value.len() == 4 => Ipv4Addr::new(value[0], value[1], value[2], value[3]),
+                _                                    => return Vec::new()
+            };
+            match self.client(&IpAddr::V4(client_ip)) {
+                Some(statistics) => (statistics.auth, statistics.acct, Some(client_ip)),
+                None             => return Vec::new()
+            }
+        } else {
+            let state = lock(&self.state);
+            (state.auth, state.acct, None)
+        };
+
+        let mut counters = Vec::new();
+        if stats_type & STATISTICS_AUTH != 0 {
+            counters.extend(auth.to_attributes());
+        }
+        if stats_type & STATISTICS_ACCT != 0 {
+            counters.extend(acct.to_attributes());
+        }
+
+        let mut attributes: Vec<Vec<u8>> = client_ip.into_iter()
+            .map(|client_ip| vendor_attribute(STATS_CLIENT_IP, &client_ip.octets()))
+            .collect();
+        // Counters are 32 bit integers, so they wrap around like MIB Counter32 does
+        attributes.extend(counters.into_iter().map(|(id, counter)| vendor_attribute(id, &(counter as u32).to_be_bytes())));
+        attributes
+    }
+}
+
+fn freeradius_attributes(packet: &RadiusPacket) -> Vec<(u8, &[u8])> {
+    let mut attributes = Vec::new();
+
+    for attribute in packet.attributes().iter().filter(|attribute| attribute.id() == VENDOR_SPECIFIC_ID) {
+        let value = attribute.value();
+        if value.len() < 4 || value[0..4] != FREERADIUS_VENDOR.to_be_bytes() {
+            continue;
+        }
+
+        let mut rest = &value[4..];
+        while rest.len() >= 2 && rest[1] >= 2 && rest.len() >= rest[1] as usize {
+            attributes.push((rest[0], &rest[2..rest[1] as usize]));
+            rest = &rest[rest[1] as usize..];
+        }
+    }
+    attributes
+}
+
+fn vendor_attribute(id: u8, value: &[u8]) -> Vec<u8> {
+    let mut attribute = FREERADIUS_VENDOR.to_be_bytes().to_vec();
+    attribute.push(id);
+    attribute.push(2 + value.len() as u8);
+    attribute.extend_from_slice(value);
+    attribute
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::test_dictionary;
+    use crate::protocol::radius_packet::RadiusAttribute;
+
+    #[test]
+    fn test_server_statistics() {
+        let statistics = ServerStatistics::new();
+        let network    = ClientEntry::new("10.0.0.0/8", String::from("secret")).unwrap();
+        let nas        = ClientEntry::new("10.0.0.1",   String::from("secret")).unwrap();
+
+        statistics.record(RadiusMsgType::AUTH, Some(&network), Event::Request);
+        statistics.record(RadiusMsgType::AUTH, Some(&network), Event::Reply(TypeCode::AccessAccept));
+        statistics.record(RadiusMsgType::AUTH, Some(&nas),     Event::Request);
+        statistics.record(RadiusMsgType::AUTH, Some(&nas),     Event::BadAuthenticator);
+        statistics.record(RadiusMsgType::AUTH, None,           Event::Invalid);
+        statistics.record(RadiusMsgType::ACCT, Some(&nas),     Event::Request);
+        statistics.record(RadiusMsgType::ACCT, Some(&nas),     Event::Duplicate);
+        statistics.record(RadiusMsgType::COA,  Some(&nas),     Event::Request);
+
+        assert_eq!(2, statistics.auth().access_requests());
+        assert_eq!(1, statistics.auth().access_accepts());
+        assert_eq!(1, statistics.auth().bad_authenticators());
+        assert_eq!(1, statistics.auth().invalid_requests());
+        assert_eq!(1, statistics.acct().requests());
+        assert_eq!(1, statistics.acct().dup_requests());
+        assert_eq!(2, statistics.clients().len());
+
+        let nas_statistics = statistics.client(&"10.0.0.1".parse().unwrap()).unwrap();
+        assert_eq!(&nas,  nas_statistics.client());
+        assert_eq!(1,     nas_statistics.auth().access_requests());
+        assert_eq!(0,     nas_statistics.auth().access_accepts());
+        assert_eq!(1,     nas_statistics.acct().dup_requests());
+        assert_eq!(1,     statistics.client(&"10.0.0.2".parse().unwrap()).unwrap().auth().access_accepts());
+        assert!(statistics.client(&"11.0.0.1".parse().unwrap()).is_none());
+
+        let dictionary  = test_dictionary();
+        let mut request = RadiusPacket::initialise_packet(TypeCode::StatusServer);
+        request.set_attributes(vec![RadiusAttribute::create_by_id(&dictionary, VENDOR_SPECIFIC_ID, vendor_attribute(STATISTICS_TYPE, &(STATISTICS_AUTH | STATISTICS_CLIENT).to_be_bytes())).unwrap(),
+                                    RadiusAttribute::create_by_id(&dictionary, VENDOR_SPECIFIC_ID, vendor_attribute(STATS_CLIENT_IP, &[10, 0, 0, 1])).unwrap()]);
+
+        let attributes = statistics.status_attributes(&request);
+        assert_eq!(11,                                   attributes.len());
+        assert_eq!(vendor_attribute(STATS_CLIENT_IP, &[10, 0, 0, 1]), attributes[0]);
+        assert_eq!(vendor_attribute(128, &[0, 0, 0, 1]), attributes[1]);
+
+        let mut request = RadiusPacket::initialise_packet(TypeCode::StatusServer);
+        assert!(statistics.status_attributes(&request).is_empty());
+        request.set_attributes(vec![RadiusAttribute::create_by_id(&dictionary, VENDOR_SPECIFIC_ID, vendor_attribute(STATISTICS_TYPE, &(STATISTICS_AUTH | STATISTICS_ACCT).to_be_bytes())).unwrap()]);
+        assert_eq!(17, statistics.status_attributes(&request).len());
+    }
+}
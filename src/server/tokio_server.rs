@@ -0,0 +1,193 @@
+//! RADIUS Async UDP Server implementation, built on top of Tokio runtime
+
+
+use crate::protocol::error::RadiusError;
+use crate::protocol::radius_packet::{ RadiusMsgType, TypeCode };
+use crate::server::AsyncServerTrait;
+use crate::server::async_server::AsyncServer;
+use crate::server::server::Server;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+
+
+const MAX_PACKET_SIZE:         usize = 4096;
+const DEFAULT_MAX_CONCURRENCY: usize = 1024;
+
+
+#[derive(Debug)]
+/// Represents ready-to-use Async RADIUS Server, which receives packets over UDP using Tokio
+///
+/// Binds AUTH and ACCT sockets (and CoA socket, if CoA port is configured) on
+/// [Server's](crate::server::server::Server) hostname and ports, and only accepts requests from
+/// Server's allowed hosts
+pub struct TokioServer {
+    base_server:     Arc<Server>,
+    auth_socket:     Arc<UdpSocket>,
+    acct_socket:     Arc<UdpSocket>,
+    coa_socket:      Option<Arc<UdpSocket>>,
+    max_concurrency: usize
+}
+
+impl TokioServer {
+    /// Initialises TokioServer from configured Server and binds AUTH, ACCT and CoA sockets
+    ///
+    /// CoA socket is only bound, if CoA port is set on Server (it is 0 by default)
+    pub async fn with_server(base_server: Server) -> Result<TokioServer, RadiusError> {
+        let auth_socket = TokioServer::bind(&base_server, TypeCode::AccessRequest).await?;
+        let acct_socket = TokioServer::bind(&base_server, TypeCode::AccountingRequest).await?;
+        let coa_socket  = match base_server.port(&TypeCode::CoARequest) {
+            Some(0) | None => None,
+            Some(_)        => Some(Arc::new(TokioServer::bind(&base_server, TypeCode::CoARequest).await?))
+        };
+
+        Ok(TokioServer {
+            base_server:     Arc::new(base_server),
+            auth_socket:     Arc::new(auth_socket),
+            acct_socket:     Arc::new(acct_socket),
+            coa_socket,
+            max_concurrency: DEFAULT_MAX_CONCURRENCY
+        })
+    }
+
+    // === Builder for TokioServer ===
+    /// Sets maximum number of requests, which are resolved at once (1024 by default)
+    ///
+    /// Once the limit is reached, server stops receiving datagrams until one of the requests is
+    /// resolved
+    pub fn set_max_concurrency(mut self, max_concurrency: usize) -> TokioServer {
+        self.max_concurrency = max_concurrency;
+        self
+    }
+    // ===================
+
+    /// Returns underlying Server
+    pub fn server(&self) -> &Server {
+        &self.base_server
+    }
+
+    /// Returns maximum number of requests, which are resolved at once
+    pub fn max_concurrency(&self) -> usize {
+        self.max_concurrency
+    }
+
+    /// Returns address to which socket for given RADIUS Message Type is bound
+    ///
+    /// Error is returned for CoA, if CoA socket is not bound
+    pub fn local_addr(&self, msg_type: RadiusMsgType) -> Result<SocketAddr, RadiusError> {
+        let socket = self.socket(msg_type).ok_or_else(|| RadiusError::SocketInvalidConnectionError { error: String::from("CoA socket is not bound, as CoA port is not set") })?;
+        Ok(socket.local_addr()?)
+    }
+
+    /// Starts serving requests with given handler and keeps server running until socket error
+    /// occurs
+    ///
+    /// Each request is resolved in its own Tokio task; see
+    /// [AsyncServer::handle_datagram](crate::server::async_server::AsyncServer::handle_datagram)
+    /// on how requests are verified
+    pub async fn serve<H: AsyncServerTrait + Send + Sync + 'static>(&self, handler: H) -> Result<(), RadiusError> {
+        let server = AsyncServer::new(Arc::clone(&self.base_server), handler).set_max_concurrency(self.max_concurrency);
+
+        tokio::try_join!(
+            self.listen(RadiusMsgType::AUTH, &server),
+            self.listen(RadiusMsgType::ACCT, &server),
+            self.listen(RadiusMsgType::COA,  &server)
+        )?;
+        Ok(())
+    }
+
+    async fn listen<H: AsyncServerTrait + Send + Sync + 'static>(&self, msg_type: RadiusMsgType, server: &AsyncServer<H>) -> Result<(), RadiusError> {
+        let socket = match self.socket(msg_type) {
+            Some(socket) => socket,
+            None         => return Ok(())
+        };
+
+        loop {
+            let mut request      = vec![0u8; MAX_PACKET_SIZE];
+            let (amount, source) = socket.recv_from(&mut request).await?;
+            request.truncate(amount);
+
+            let permit = server.acquire().await;
+            let server = server.clone();
+            let socket = Arc::clone(socket);
+
+            tokio::spawn(async move {
+                if let Some(reply) = server.handle_datagram(permit, source, msg_type, &request).await {
+                    // There is nobody to report failed send to, so reply is lost the same way as
+                    // a datagram dropped on the wire
+                    let _ = socket.send_to(&reply, source).await;
+                }
+            });
+        }
+    }
+
+    fn socket(&self, msg_type: RadiusMsgType) -> Option<&Arc<UdpSocket>> {
+        match msg_type {
+            RadiusMsgType::AUTH => Some(&self.auth_socket),
+            RadiusMsgType::ACCT => Some(&self.acct_socket),
+            RadiusMsgType::COA  => self.coa_socket.as_ref()
+        }
+    }
+
+    async fn bind(server: &Server, code: TypeCode) -> Result<UdpSocket, RadiusError> {
+        let port = server.port(&code).unwrap_or(0);
+        Ok(UdpSocket::bind((server.server(), port)).await?)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tokio_client::TokioClient;
+    use crate::protocol::radius_packet::RadiusPacket;
+    use crate::server::handler::{ HandlerOutcome, RequestContext };
+    use crate::testing::{ test_client, test_server };
+
+    use async_trait::async_trait;
+
+    struct TestHandler;
+
+    #[async_trait]
+    impl AsyncServerTrait for TestHandler {
+        async fn handle_request(&self, context: &RequestContext, _request: &RadiusPacket) -> Result<HandlerOutcome, RadiusError> {
+            assert_eq!("127.0.0.1", context.client().shortname());
+            Ok(HandlerOutcome::Reply(RadiusPacket::initialise_packet(TypeCode::AccessAccept)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_serve_replies_to_allowed_host() {
+        let server = test_server("secret")
+            .set_server(String::from("127.0.0.1"))
+            .set_allowed_hosts(vec![String::from("127.0.0.1")]);
+
+        let server    = TokioServer::with_server(server).await.unwrap().set_max_concurrency(1);
+        let auth_port = server.local_addr(RadiusMsgType::AUTH).unwrap().port();
+
+        tokio::spawn(async move { server.serve(TestHandler).await });
+
+        let client = TokioClient::with_client(test_client(auth_port, "secret"));
+
+        for _ in 0..2 {
+            let mut packet = client.client().create_pap_packet("testing", "password").unwrap();
+            let reply      = client.send_and_receive_reply(&mut packet).await.unwrap();
+            assert_eq!(&TypeCode::AccessAccept, reply.code());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_coa_socket_is_only_bound_with_coa_port() {
+        let server = TokioServer::with_server(test_server("secret").set_server(String::from("127.0.0.1"))).await.unwrap();
+        assert!(server.local_addr(RadiusMsgType::COA).is_err());
+
+        let coa_port = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let server   = test_server("secret")
+            .set_server(String::from("127.0.0.1"))
+            .set_port(RadiusMsgType::COA, coa_port);
+
+        let server = TokioServer::with_server(server).await.unwrap();
+        assert_eq!(coa_port, server.local_addr(RadiusMsgType::COA).unwrap().port());
+    }
+}
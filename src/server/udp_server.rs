@@ -0,0 +1,517 @@
+//! RADIUS Blocking UDP Server implementation
+
+
+use crate::protocol::error::RadiusError;
+use crate::protocol::radius_packet::{ RadiusMsgType, RadiusPacket, TypeCode };
+use crate::server::handler::{ HandlerOutcome, RadiusHandler, RequestContext };
+use crate::server::overload::{ DropPolicy, OverloadStats, RequestQueue, ShedReason };
+use crate::server::server::{ Admission, Server };
+use crate::server::work_queue::WorkQueue;
+
+use std::io::ErrorKind;
+use std::net::{ SocketAddr, UdpSocket };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::thread;
+use std::time::{ Duration, Instant };
+
+
+const MAX_PACKET_SIZE:    usize    = 4096;
+const STOP_POLL_TIMEOUT:  Duration = Duration::from_millis(500);
+const DEFAULT_QUEUE_SIZE: usize    = 1024;
+
+
+#[derive(Debug)]
+/// Represents ready-to-use blocking RADIUS Server, which receives packets over UDP
+///
+/// Binds AUTH, ACCT and CoA sockets on [Server's](crate::server::server::Server) hostname and
+/// ports, and only accepts requests from Server's allowed hosts
+pub struct UdpServer {
+    base_server: Server,
+    auth_socket: UdpSocket,
+    acct_socket: UdpSocket,
+    coa_socket:  UdpSocket,
+    workers:     usize,
+    queue_sizes: [usize; 4],
+    drop_policy: DropPolicy,
+    deadline:    Option<Duration>,
+    stats:       OverloadStats,
+    stopped:     AtomicBool
+}
+
+impl UdpServer {
+    /// Initialises UdpServer from configured Server and binds AUTH, ACCT and CoA sockets
+    pub fn with_server(base_server: Server) -> Result<UdpServer, RadiusError> {
+        let auth_socket = UdpServer::bind(&base_server, TypeCode::AccessRequest)?;
+        let acct_socket = UdpServer::bind(&base_server, TypeCode::AccountingRequest)?;
+        let coa_socket  = UdpServer::bind(&base_server, TypeCode::CoARequest)?;
+
+        Ok(UdpServer {
+            base_server,
+            auth_socket,
+            acct_socket,
+            coa_socket,
+            workers:     0,
+            queue_sizes: [DEFAULT_QUEUE_SIZE; 4],
+            drop_policy: DropPolicy::DropNewest,
+            deadline:    None,
+            stats:       OverloadStats::default(),
+            stopped:     AtomicBool::new(false)
+        })
+    }
+
+    // === Builder for UdpServer ===
+    /// Sets number of worker threads, which resolve requests; otherwise requests are resolved one
+    /// by one by the thread, which has received them
+    ///
+    /// With workers, receiver threads only verify requests and put them into bounded
+    /// [queue](RequestQueue), from which workers take them by queue weight
+    pub fn set_workers(mut self, workers: usize) -> UdpServer {
+        self.workers = workers;
+        self
+    }
+
+    /// Sets size of given queue, otherwise you would have a default value of 1024
+    ///
+    /// Once queue is full, requests are shed according to [drop policy](UdpServer::set_drop_policy)
+    /// and reported to handler, so flood of one type of requests doesn't delay others
+    pub fn set_queue_size(mut self, queue: RequestQueue, queue_size: usize) -> UdpServer {
+        self.queue_sizes[queue.index()] = queue_size;
+        self
+    }
+
+    /// Sets which request is shed, when request arrives to a full queue, otherwise you would have
+    /// a default value of DropNewest
+    pub fn set_drop_policy(mut self, drop_policy: DropPolicy) -> UdpServer {
+        self.drop_policy = drop_policy;
+        self
+    }
+
+    /// Sets for how long request could be processed since it has been received
+    ///
+    /// Request, which reaches deadline while waiting in queue, is not resolved; reply, which is
+    /// ready after deadline, is discarded, as NAS has likely given up on it by then. Both are
+    /// reported to handler
+    pub fn set_deadline(mut self, deadline: Option<Duration>) -> UdpServer {
+        self.deadline = deadline;
+        self
+    }
+    // ===================
+
+    /// Returns underlying Server
+    pub fn server(&self) -> &Server {
+        &self.base_server
+    }
+
+    /// Returns number of worker threads
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Returns size of given queue
+    pub fn queue_size(&self, queue: RequestQueue) -> usize {
+        self.queue_sizes[queue.index()]
+    }
+
+    /// Returns drop policy
+    pub fn drop_policy(&self) -> DropPolicy {
+        self.drop_policy
+    }
+
+    /// Returns deadline of request processing
+    pub fn deadline(&self) -> Option<Duration> {
+        self.deadline
+    }
+
+    /// Returns counters of requests, which have been shed because of overload
+    pub fn overload_stats(&self) -> &OverloadStats {
+        &self.stats
+    }
+
+    /// Returns address to which socket for given RADIUS Message Type is bound
+    pub fn local_addr(&self, msg_type: RadiusMsgType) -> Result<SocketAddr, RadiusError> {
+        Ok(self.socket(msg_type).local_addr()?)
+    }
+
+    /// Starts serving requests and keeps server running until socket receive error occurs or server
+    /// is [stopped](UdpServer::stop)
+    ///
+    /// Replies, which could not be sent, are reported to handler and do not stop the server
+    ///
+    /// Each socket is served by its own thread. Requests from hosts, which are not allowed, are
+    /// silently discarded; requests, which fail [verification](Server::process_request) or are
+    /// not expected on the socket they arrived to, are discarded and reported to handler
+    ///
+    /// Should handler panic, server is stopped and error is returned
+    ///
+    /// Server, which has been stopped before it is served, returns straight away; once serve
+    /// returns, server could be served again
+    pub fn serve<H: RadiusHandler + Sync>(&self, handler: &H) -> Result<(), RadiusError> {
+        let queue = WorkQueue::new(self.queue_sizes, self.drop_policy);
+
+        let result = thread::scope(|scope| {
+            let queue = &queue;
+            // Once any thread is done, whether it has failed or panicked, other threads are
+            // stopped as well, so its error is returned straight away
+            let mut threads: Vec<_> = [RadiusMsgType::AUTH, RadiusMsgType::ACCT, RadiusMsgType::COA].iter()
+                .map(|&msg_type| scope.spawn(move || {
+                    let _stop = StopGuard(self);
+                    self.listen(msg_type, handler, queue)
+                }))
+                .collect();
+            threads.extend((0..self.workers).map(|_| scope.spawn(move || {
+                let _stop = StopGuard(self);
+                self.work(handler, queue);
+                Ok(())
+            })));
+
+            // Every thread is joined before result is returned, so none of panics is missed
+            let results: Vec<Result<(), RadiusError>> = threads.into_iter()
+                .map(|thread| thread.join().unwrap_or_else(|panic| Err(RadiusError::HandlerPanicError { error: panic_message(panic) })))
+                .collect();
+            results.into_iter().collect()
+        });
+        // Stop is only cleared once every thread is done, so stop issued before or while server
+        // starts is not lost
+        self.stopped.store(false, Ordering::Relaxed);
+        result
+    }
+
+    /// Stops serving requests; [serve](UdpServer::serve) returns within half a second
+    ///
+    /// Requests, which are waiting in queues, are discarded
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    fn listen<H: RadiusHandler>(&self, msg_type: RadiusMsgType, handler: &H, queue: &WorkQueue<(RequestContext, RadiusPacket)>) -> Result<(), RadiusError> {
+        let socket      = self.socket(msg_type);
+        let mut request = [0u8; MAX_PACKET_SIZE];
+        socket.set_read_timeout(Some(STOP_POLL_TIMEOUT))?;
+
+        while !self.stopped.load(Ordering::Relaxed) {
+            let (amount, source) = match socket.recv_from(&mut request) {
+                Ok(received)                                                                              => received,
+                Err(error) if error.kind() == ErrorKind::WouldBlock || error.kind() == ErrorKind::TimedOut => continue,
+                Err(error)                                                                                => return Err(RadiusError::SocketConnectionError(error))
+            };
+
+            let client = match self.base_server.resolve_client(&source) {
+                Ok(Some(client)) => client.into_owned(),
+                Ok(None)         => {
+                    self.base_server.record_invalid_request(msg_type, &request[..amount]);
+                    continue;
+                },
+                Err(error)       => {
+                    self.base_server.record_invalid_request(msg_type, &request[..amount]);
+                    if self.base_server.report_invalid_request() {
+                        handler.handle_resolver_error(source, error);
+                    }
+                    continue;
+                }
+            };
+            let context = RequestContext::new(source, msg_type, client, Instant::now());
+
+            let packet = match self.base_server.admit_request(&context, &request[..amount]) {
+                Ok(Admission::Request(packet)) => packet,
+                Ok(Admission::Reply(reply))    => {
+                    if let Some(reply) = reply {
+                        self.send_reply(&context, &reply, handler);
+                    }
+                    continue;
+                },
+                Err(error)                     => {
+                    if self.base_server.report_invalid_request() {
+                        handler.handle_error(&context, error);
+                    }
+                    continue;
+                }
+            };
+
+            if self.workers > 0 {
+                let request_queue = RequestQueue::of(msg_type, packet.code());
+
+                if let Some((context, packet)) = queue.push(request_queue, (context, packet)) {
+                    self.stats.record_queue_full(request_queue);
+                    self.base_server.record_dropped_request(&context, &packet);
+                    handler.handle_error(&context, RadiusError::RequestShedError { queue: request_queue, reason: ShedReason::QueueFull });
+                }
+            } else {
+                self.respond(&context, &packet, handler);
+            }
+        }
+        Ok(())
+    }
+
+    fn work<H: RadiusHandler>(&self, handler: &H, queue: &WorkQueue<(RequestContext, RadiusPacket)>) {
+        while !self.stopped.load(Ordering::Relaxed) {
+            if let Some((context, packet)) = queue.pop(STOP_POLL_TIMEOUT) {
+                self.respond(&context, &packet, handler);
+            }
+        }
+    }
+
+    fn respond<H: RadiusHandler>(&self, context: &RequestContext, packet: &RadiusPacket, handler: &H) {
+        let request_queue = RequestQueue::of(context.msg_type(), packet.code());
+
+        if self.is_overdue(context) {
+            self.stats.record_expired(request_queue);
+            self.base_server.record_dropped_request(context, packet);
+            handler.handle_error(context, RadiusError::RequestShedError { queue: request_queue, reason: ShedReason::Expired });
+            return
+        }
+
+        match self.resolve(context, packet, handler) {
+            Ok(Some(reply)) => self.send_reply(context, &reply, handler),
+            Ok(None)        => {},
+            Err(error)      => handler.handle_error(context, error)
+        }
+    }
+
+    fn send_reply<H: RadiusHandler>(&self, context: &RequestContext, reply: &[u8], handler: &H) {
+        // Reply is sent from the socket request has arrived to, so NAS accepts it. Failure to send
+        // single reply (ie no route to NAS or full socket buffer) is reported, but does not stop
+        // the server; NAS would retransmit the request anyway
+        if let Err(error) = self.socket(context.msg_type()).send_to(reply, context.source()) {
+            handler.handle_error(context, RadiusError::SocketConnectionError(error));
+        }
+    }
+
+    fn resolve<H: RadiusHandler>(&self, context: &RequestContext, packet: &RadiusPacket, handler: &H) -> Result<Option<Vec<u8>>, RadiusError> {
+        if packet.code() == &TypeCode::StatusServer {
+            return self.base_server.status_server_reply(context, packet)
+        }
+        if let Some(reply) = self.base_server.duplicate_request(context, packet) {
+            return Ok(reply)
+        }
+
+        #[cfg(feature = "metrics")]
+        let started = Instant::now();
+        let outcome = handler.handle_request(context, packet);
+        #[cfg(feature = "metrics")]
+        crate::server::handler::record_handler_duration(context, packet, started);
+
+        let reply = match outcome {
+            Ok(HandlerOutcome::Reply(mut reply)) => self.base_server.finalise_reply_packet(context.client(), packet, &mut reply).map(Some),
+            Ok(HandlerOutcome::Drop)             => Ok(None),
+            Err(error)                           => Err(error)
+        };
+        if reply.is_ok() && self.is_overdue(context) {
+            // Late reply is not sent, so it is neither counted as reply nor cached: retransmission
+            // of the request is resolved anew
+            let request_queue = RequestQueue::of(context.msg_type(), packet.code());
+
+            self.stats.record_late(request_queue);
+            self.base_server.abandon_request(context, packet);
+            return Err( RadiusError::RequestShedError { queue: request_queue, reason: ShedReason::Late } )
+        }
+        self.base_server.finish_request(context, packet, &reply);
+        reply
+    }
+
+    fn is_overdue(&self, context: &RequestContext) -> bool {
+        matches!(self.deadline, Some(deadline) if context.received().elapsed() >= deadline)
+    }
+
+    fn socket(&self, msg_type: RadiusMsgType) -> &UdpSocket {
+        match msg_type {
+            RadiusMsgType::AUTH => &self.auth_socket,
+            RadiusMsgType::ACCT => &self.acct_socket,
+            RadiusMsgType::COA  => &self.coa_socket
+        }
+    }
+
+    fn bind(server: &Server, code: TypeCode) -> Result<UdpSocket, RadiusError> {
+        let port = server.port(&code).unwrap_or(0);
+        Ok(UdpSocket::bind((server.server(), port))?)
+    }
+}
+
+/// Returns message, handler has panicked with
+fn panic_message(panic: Box<dyn std::any::Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic)  => panic.downcast_ref::<&str>().map(|message| message.to_string()).unwrap_or_default()
+    }
+}
+
+/// Stops UdpServer, once thread, which serves it, is done or unwinds
+struct StopGuard<'a>(&'a UdpServer);
+
+impl Drop for StopGuard<'_> {
+    fn drop(&mut self) {
+        self.0.stop();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::udp_client::UdpClient;
+    use crate::protocol::radius_packet::RadiusPacket;
+    use crate::testing::{ test_client, test_dictionary };
+
+    use std::sync::Mutex;
+
+    struct TestHandler {
+        errors: Mutex<Vec<String>>
+    }
+
+    impl RadiusHandler for TestHandler {
+        fn handle_request(&self, context: &RequestContext, request: &RadiusPacket) -> Result<HandlerOutcome, RadiusError> {
+            assert_eq!("127.0.0.1", context.client().shortname());
+
+            match request.code() {
+                TypeCode::AccessRequest     => Ok(HandlerOutcome::Reply(RadiusPacket::initialise_packet(TypeCode::AccessAccept))),
+                TypeCode::AccountingRequest => Ok(HandlerOutcome::Reply(RadiusPacket::initialise_packet(TypeCode::AccountingResponse))),
+                _                           => Ok(HandlerOutcome::Drop)
+            }
+        }
+
+        fn handle_error(&self, _context: &RequestContext, error: RadiusError) {
+            self.errors.lock().unwrap().push(error.to_string());
+        }
+    }
+
+    fn udp_server() -> UdpServer {
+        let server     = Server::with_dictionary(test_dictionary())
+            .set_server(String::from("127.0.0.1"))
+            .set_secret(String::from("secret"))
+            .set_allowed_hosts(vec![String::from("127.0.0.1")]);
+
+        UdpServer::with_server(server).unwrap()
+    }
+
+    fn serve_and_verify(server: UdpServer) {
+        let auth_port = server.local_addr(RadiusMsgType::AUTH).unwrap().port();
+        let acct_port = server.local_addr(RadiusMsgType::ACCT).unwrap().port();
+        let handler   = TestHandler { errors: Mutex::new(Vec::new()) };
+
+        thread::scope(|scope| {
+            let serving = scope.spawn(|| server.serve(&handler));
+
+            let client_with_secret = |secret: &str| {
+                UdpClient::with_client(test_client(auth_port, secret).set_retries(1).set_timeout(1).set_port(RadiusMsgType::ACCT, acct_port)).unwrap()
+            };
+
+            let mut client = client_with_secret("secret");
+            let mut packet = client.client().create_pap_packet("testing", "password").unwrap();
+            let reply      = client.send_and_receive_reply(&mut packet).unwrap();
+            assert_eq!(&TypeCode::AccessAccept, reply.code());
+            assert!(reply.message_authenticator().is_ok());
+
+            let mut packet = client.client().create_acct_packet();
+            assert_eq!(&TypeCode::AccountingResponse, client.send_and_receive_reply(&mut packet).unwrap().code());
+
+            // Accounting-Request with wrong secret fails verification and is discarded
+            let mut other_client = client_with_secret("other-secret");
+            let mut packet       = other_client.client().create_acct_packet();
+            assert!(other_client.send_and_receive_reply(&mut packet).is_err());
+
+            server.stop();
+            serving.join().unwrap().unwrap();
+        });
+
+        assert_eq!(vec![String::from("Verification failed for incoming Radius packet: Packet authenticator mismatch")], *handler.errors.lock().unwrap());
+    }
+
+    #[test]
+    fn test_serve() {
+        serve_and_verify(udp_server());
+    }
+
+    #[test]
+    fn test_serve_with_workers() {
+        let server = udp_server()
+            .set_workers(2)
+            .set_queue_size(RequestQueue::Acct, 1)
+            .set_drop_policy(DropPolicy::DropOldest)
+            .set_deadline(Some(Duration::from_secs(1)));
+
+        assert_eq!(1,    server.queue_size(RequestQueue::Acct));
+        assert_eq!(1024, server.queue_size(RequestQueue::Auth));
+        serve_and_verify(server);
+    }
+
+    struct SlowHandler {
+        errors: Mutex<Vec<RadiusError>>
+    }
+
+    impl RadiusHandler for SlowHandler {
+        fn handle_request(&self, _context: &RequestContext, _request: &RadiusPacket) -> Result<HandlerOutcome, RadiusError> {
+            thread::sleep(Duration::from_millis(200));
+            Ok(HandlerOutcome::Reply(RadiusPacket::initialise_packet(TypeCode::AccessAccept)))
+        }
+
+        fn handle_error(&self, _context: &RequestContext, error: RadiusError) {
+            self.errors.lock().unwrap().push(error);
+        }
+    }
+
+    #[test]
+    fn test_serve_discards_late_reply() {
+        let server    = udp_server().set_workers(1).set_deadline(Some(Duration::from_millis(100)));
+        let auth_port = server.local_addr(RadiusMsgType::AUTH).unwrap().port();
+        let handler   = SlowHandler { errors: Mutex::new(Vec::new()) };
+
+        thread::scope(|scope| {
+            let serving = scope.spawn(|| server.serve(&handler));
+
+            let mut client = UdpClient::with_client(test_client(auth_port, "secret").set_retries(1).set_timeout(1)).unwrap();
+
+            let mut packet = client.client().create_pap_packet("testing", "password").unwrap();
+            assert!(client.send_and_receive_reply(&mut packet).is_err());
+
+            server.stop();
+            serving.join().unwrap().unwrap();
+        });
+
+        assert_eq!(1, server.overload_stats().late(RequestQueue::Auth));
+        assert_eq!(1, server.overload_stats().total());
+        assert_eq!(1, server.server().statistics().auth().dropped_requests());
+        assert_eq!(0, server.server().statistics().auth().access_accepts());
+
+        let errors = handler.errors.lock().unwrap();
+        assert!(matches!(errors[..], [RadiusError::RequestShedError { queue: RequestQueue::Auth, reason: ShedReason::Late }]));
+        assert_eq!("Auth request has been shed: request has been resolved after deadline", errors[0].to_string());
+    }
+
+    struct PanickingHandler;
+
+    impl RadiusHandler for PanickingHandler {
+        fn handle_request(&self, _context: &RequestContext, _request: &RadiusPacket) -> Result<HandlerOutcome, RadiusError> {
+            panic!("handler has failed")
+        }
+    }
+
+    #[test]
+    fn test_serve_stops_when_handler_panics() {
+        // Handler panics on listener thread without workers and on worker thread with them
+        for workers in [0, 2] {
+            let server    = udp_server().set_workers(workers);
+            let auth_port = server.local_addr(RadiusMsgType::AUTH).unwrap().port();
+
+            thread::scope(|scope| {
+                let serving = scope.spawn(|| server.serve(&PanickingHandler));
+
+                let mut client = UdpClient::with_client(test_client(auth_port, "secret").set_retries(1).set_timeout(1)).unwrap();
+                let mut packet = client.client().create_pap_packet("testing", "password").unwrap();
+                assert!(client.send_and_receive_reply(&mut packet).is_err());
+
+                match serving.join().unwrap() {
+                    Err(RadiusError::HandlerPanicError { error }) => assert_eq!("handler has failed", error),
+                    _                                             => assert!(false)
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn test_stop_before_serve() {
+        let server = udp_server();
+
+        server.stop();
+        server.serve(&PanickingHandler).unwrap();
+        assert!(!server.stopped.load(Ordering::Relaxed));
+    }
+}
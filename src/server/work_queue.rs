@@ -0,0 +1,154 @@
+//! Bounded queues, through which UdpServer's receiver threads dispatch requests to worker threads
+
+
+use crate::server::overload::{ DropPolicy, RequestQueue };
+use crate::tools::lock;
+
+use std::collections::VecDeque;
+use std::sync::{ Condvar, Mutex };
+use std::time::Duration;
+
+
+// Number of requests taken from AUTH, CoA and ACCT queue in a single round; Status-Server queue is
+// not weighted, as it is always served first
+const WEIGHTS: [usize; 4] = [0, 4, 2, 1];
+
+
+#[derive(Debug)]
+/// Holds separate bounded queue for every [RequestQueue], so flood of one type of requests only
+/// fills its own queue; queues are served by weighted round-robin, with Status-Server first
+pub(crate) struct WorkQueue<T> {
+    capacities:  [usize; 4],
+    drop_policy: DropPolicy,
+    queues:      Mutex<Queues<T>>,
+    ready:       Condvar
+}
+
+#[derive(Debug)]
+struct Queues<T> {
+    items:   [VecDeque<T>; 4],
+    credits: [usize; 4]
+}
+
+impl<T> Queues<T> {
+    fn is_empty(&self) -> bool {
+        self.items.iter().all(VecDeque::is_empty)
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        if let Some(item) = self.items[0].pop_front() {
+            return Some(item)
+        }
+        // Non-empty queue, which has used up its credits, waits until every other non-empty queue
+        // has used up its credits as well, then new round starts
+        let index = match self.next_index() {
+            Some(index) => index,
+            None        => {
+                self.credits = WEIGHTS;
+                self.next_index()?
+            }
+        };
+        self.credits[index] -= 1;
+        self.items[index].pop_front()
+    }
+
+    fn next_index(&self) -> Option<usize> {
+        (1..self.items.len()).find(|&index| self.credits[index] > 0 && !self.items[index].is_empty())
+    }
+}
+
+impl<T> WorkQueue<T> {
+    /// Initialises WorkQueue with capacities of queues in [RequestQueue::index] order
+    pub(crate) fn new(capacities: [usize; 4], drop_policy: DropPolicy) -> WorkQueue<T> {
+        WorkQueue {
+            capacities,
+            drop_policy,
+            queues: Mutex::new(Queues { items: [VecDeque::new(), VecDeque::new(), VecDeque::new(), VecDeque::new()], credits: WEIGHTS }),
+            ready:  Condvar::new()
+        }
+    }
+
+    /// Adds item to given queue and returns item, which has been shed according to drop policy,
+    /// if queue was full
+    pub(crate) fn push(&self, queue: RequestQueue, item: T) -> Option<T> {
+        let index      = queue.index();
+        let mut queues = lock(&self.queues);
+
+        let shed = if queues.items[index].len() < self.capacities[index] {
+            None
+        } else {
+            match self.drop_policy {
+                DropPolicy::DropOldest if self.capacities[index] > 0 => queues.items[index].pop_front(),
+                _                                                    => return Some(item)
+            }
+        };
+        queues.items[index].push_back(item);
+        self.ready.notify_one();
+        shed
+    }
+
+    /// Takes item from the queue, which is next to be served, waiting up to timeout for one to
+    /// arrive
+    pub(crate) fn pop(&self, timeout: Duration) -> Option<T> {
+        let queues     = lock(&self.queues);
+        let mut queues = if queues.is_empty() {
+            self.ready.wait_timeout(queues, timeout).unwrap_or_else(|poisoned| poisoned.into_inner()).0
+        } else {
+            queues
+        };
+
+        queues.pop()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_work_queue() {
+        let queue = WorkQueue::new([1, 1, 1, 2], DropPolicy::DropNewest);
+
+        assert_eq!(None,           queue.push(RequestQueue::Acct, "acct-1"));
+        assert_eq!(None,           queue.push(RequestQueue::Acct, "acct-2"));
+        assert_eq!(Some("acct-3"), queue.push(RequestQueue::Acct, "acct-3"));
+        // Full ACCT queue doesn't affect other queues
+        assert_eq!(None,           queue.push(RequestQueue::Auth,         "auth-1"));
+        assert_eq!(None,           queue.push(RequestQueue::StatusServer, "status-1"));
+
+        assert_eq!(Some("status-1"), queue.pop(Duration::from_millis(10)));
+        assert_eq!(Some("auth-1"),   queue.pop(Duration::from_millis(10)));
+        assert_eq!(Some("acct-1"),   queue.pop(Duration::from_millis(10)));
+        assert_eq!(Some("acct-2"),   queue.pop(Duration::from_millis(10)));
+        assert_eq!(None,             queue.pop(Duration::from_millis(10)));
+
+        let queue = WorkQueue::new([1, 1, 1, 2], DropPolicy::DropOldest);
+        assert_eq!(None,           queue.push(RequestQueue::Acct, "acct-1"));
+        assert_eq!(None,           queue.push(RequestQueue::Acct, "acct-2"));
+        assert_eq!(Some("acct-1"), queue.push(RequestQueue::Acct, "acct-3"));
+        assert_eq!(Some("acct-2"), queue.pop(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn test_work_queue_weights() {
+        let queue = WorkQueue::new([1, 8, 1, 2], DropPolicy::DropNewest);
+
+        for request in 0..8 {
+            assert_eq!(None, queue.push(RequestQueue::Auth, request));
+        }
+        assert_eq!(Some(8), queue.push(RequestQueue::Auth, 8));
+        assert_eq!(None,    queue.push(RequestQueue::Acct, 100));
+        assert_eq!(None,    queue.push(RequestQueue::Acct, 101));
+
+        // ACCT request is served after every 4 AUTH requests, even though AUTH queue is full
+        let served: Vec<_> = (0..10).map(|_| queue.pop(Duration::from_millis(10)).unwrap()).collect();
+        assert_eq!(vec![0, 1, 2, 3, 100, 4, 5, 6, 7, 101], served);
+
+        // Status-Server is always served first
+        assert_eq!(None, queue.push(RequestQueue::Auth,         0));
+        assert_eq!(None, queue.push(RequestQueue::StatusServer, 200));
+        assert_eq!(Some(200), queue.pop(Duration::from_millis(10)));
+        assert_eq!(Some(0),   queue.pop(Duration::from_millis(10)));
+    }
+}
@@ -0,0 +1,153 @@
+//! Fixtures shared by unit tests: dictionary, RADIUS Client & Server, which share secret, toy
+//! RADIUS Servers, which answer requests received on a socket, and EAP conversations carried over
+//! RADIUS
+
+
+use crate::client::client::Client;
+use crate::protocol::dictionary::Dictionary;
+use crate::protocol::radius_packet::{ RadiusAttribute, RadiusMsgType, RadiusPacket, TypeCode };
+use crate::server::eap::{ eap_message, EapPacket };
+use crate::server::server::Server;
+
+use md5::{ Digest, Md5 };
+
+use std::net::{ SocketAddr, UdpSocket };
+use std::thread;
+
+
+const MAX_PACKET_SIZE: usize = 4096;
+
+
+const STATE_ID:           u8  = 24;
+const VENDOR_SPECIFIC_ID: u8  = 26;
+const EAP_MESSAGE_ID:     u8  = 79;
+const MICROSOFT_VENDOR:   u32 = 311;
+const MS_MPPE_SEND_KEY:   u8  = 16;
+const MS_MPPE_RECV_KEY:   u8  = 17;
+
+
+/// Reply toy RADIUS Server sends back; None means request is "lost"
+pub(crate) type Reply = Option<(TypeCode, Vec<RadiusAttribute>)>;
+
+/// Returns dictionary, which tests are run with
+pub(crate) fn test_dictionary() -> Dictionary {
+    Dictionary::from_file("./dict_examples/integration_dict").unwrap()
+}
+
+/// Returns Client, which sends authentication requests to given port of localhost; ports of other
+/// RADIUS Message Types are left to the test
+pub(crate) fn test_client(port: u16, secret: &str) -> Client {
+    Client::with_dictionary(test_dictionary())
+        .set_server(String::from("127.0.0.1"))
+        .set_secret(String::from(secret))
+        .set_port(RadiusMsgType::AUTH, port)
+}
+
+/// Returns Server with given secret
+pub(crate) fn test_server(secret: &str) -> Server {
+    Server::with_dictionary(test_dictionary()).set_secret(String::from(secret))
+}
+
+/// Builds reply to raw request the way RADIUS Server would
+pub(crate) fn reply_to(server: &Server, request: &mut [u8], code: TypeCode, attributes: Vec<RadiusAttribute>) -> Vec<u8> {
+    server.create_reply_packet(code, attributes, request).to_bytes()
+}
+
+/// Spawns thread, which receives given number of requests on socket, answers them with replies
+/// built by `reply` and returns received requests (including "lost" ones)
+pub(crate) fn serve_requests<F>(socket: UdpSocket, secret: &str, requests: usize, reply: F) -> thread::JoinHandle<Vec<RadiusPacket>>
+where
+    F: Fn(&Server, &RadiusPacket, SocketAddr) -> Reply + Send + 'static
+{
+    let secret = secret.to_string();
+
+    thread::spawn(move || {
+        let server       = test_server(&secret);
+        let mut received = Vec::with_capacity(requests);
+
+        while received.len() < requests {
+            let mut request      = [0u8; MAX_PACKET_SIZE];
+            let (amount, source) = socket.recv_from(&mut request).unwrap();
+            let packet           = server.initialise_packet_from_bytes(&request[..amount]).unwrap();
+
+            if let Some((code, attributes)) = reply(&server, &packet, source) {
+                socket.send_to(&reply_to(&server, &mut request[..amount], code, attributes), source).unwrap();
+            }
+            received.push(packet);
+        }
+        received
+    })
+}
+
+/// Spawns task, which keeps answering requests received on socket with replies built by `reply`
+#[cfg(feature = "tokio")]
+pub(crate) fn spawn_server<F>(socket: tokio::net::UdpSocket, secret: &str, reply: F) -> tokio::task::JoinHandle<()>
+where
+    F: Fn(&Server, &RadiusPacket, SocketAddr) -> Reply + Send + 'static
+{
+    let secret = secret.to_string();
+
+    tokio::spawn(async move {
+        let server = test_server(&secret);
+
+        loop {
+            let mut request      = [0u8; MAX_PACKET_SIZE];
+            let (amount, source) = socket.recv_from(&mut request).await.unwrap();
+            let packet           = server.initialise_packet_from_bytes(&request[..amount]).unwrap();
+
+            if let Some((code, attributes)) = reply(&server, &packet, source) {
+                socket.send_to(&reply_to(&server, &mut request[..amount], code, attributes), source).await.unwrap();
+            }
+        }
+    })
+}
+
+/// Builds Access-Request, which carries given EAP packet (or EAP-Start, if there is none) and
+/// State, if it is given
+pub(crate) fn eap_request(eap: Option<&EapPacket>, state: Option<&[u8]>) -> RadiusPacket {
+    let dictionary     = test_dictionary();
+    let bytes          = eap.map(|eap| eap.to_bytes()).unwrap_or_default();
+    let mut attributes = match bytes.is_empty() {
+        true  => vec![RadiusAttribute::create_by_id(&dictionary, EAP_MESSAGE_ID, Vec::new()).unwrap()],
+        false => bytes.chunks(253).map(|chunk| RadiusAttribute::create_by_id(&dictionary, EAP_MESSAGE_ID, chunk.to_vec()).unwrap()).collect()
+    };
+    if let Some(state) = state {
+        attributes.push(RadiusAttribute::create_by_id(&dictionary, STATE_ID, state.to_vec()).unwrap());
+    }
+
+    let mut request = RadiusPacket::initialise_packet(TypeCode::AccessRequest);
+    request.set_attributes(attributes);
+    request
+}
+
+/// Returns reply, which EAP authenticator has built, and EAP packet it carries
+pub(crate) fn eap_reply(reply: Option<RadiusPacket>) -> (RadiusPacket, EapPacket) {
+    match reply {
+        Some(reply) => {
+            let eap = EapPacket::from_bytes(&eap_message(&reply).unwrap()).unwrap();
+            (reply, eap)
+        },
+        None        => panic!("EAP authenticator has dropped request")
+    }
+}
+
+/// Returns MSK, which has been sent in MS-MPPE-Recv-Key and MS-MPPE-Send-Key of reply to request
+/// with given Request Authenticator (RFC 2548 Section 2.4.2)
+pub(crate) fn mppe_msk(reply: &RadiusPacket, secret: &str, request_authenticator: &[u8]) -> Vec<u8> {
+    let key = |vendor_type: u8| {
+        let attribute = reply.attributes().iter()
+            .find(|attribute| attribute.id() == VENDOR_SPECIFIC_ID && attribute.value()[0..4] == MICROSOFT_VENDOR.to_be_bytes() && attribute.value()[4] == vendor_type)
+            .unwrap();
+        let value     = &attribute.value()[6..];
+
+        let mut plain = Vec::new();
+        let mut hash  = Md5::new().chain_update(secret).chain_update(request_authenticator).chain_update(&value[0..2]).finalize();
+        for chunk in value[2..].chunks(16) {
+            plain.extend(chunk.iter().zip(hash.iter()).map(|(cipher, hash)| cipher ^ hash));
+            hash = Md5::new().chain_update(secret).chain_update(chunk).finalize();
+        }
+        plain[1..=plain[0] as usize].to_vec()
+    };
+
+    [key(MS_MPPE_RECV_KEY), key(MS_MPPE_SEND_KEY)].concat()
+}
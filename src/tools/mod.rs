@@ -9,6 +9,7 @@ use std::convert::TryInto;
 use std::fmt::Write;
 use std::net::{ Ipv4Addr, Ipv6Addr };
 use std::str::FromStr;
+use std::sync::{ Mutex, MutexGuard };
 
 use crate::protocol::error::RadiusError;
 
@@ -400,6 +401,13 @@ fn u16_to_be_bytes(u16_data: u16) -> [u8;2] {
 pub(crate) fn u16_from_be_bytes(bytes: &[u8]) -> u16 {
     u16::from_be_bytes(bytes.try_into().expect("slice with incorrect length"))
 }
+
+/// Locks mutex, ignoring poisoning: state, guarded by crate's mutexes, is always left consistent
+/// between statements, so it is safe to keep using it even if some thread panicked while holding
+/// the lock
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
 // -----------------------------------------
 
 